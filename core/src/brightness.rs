@@ -0,0 +1,165 @@
+use crate::{
+    hal::{Clock, SystemClock},
+    timezone,
+};
+use anyhow::{bail, Result};
+use chrono::{Datelike, Timelike};
+use serde::{Deserialize, Serialize};
+
+/// 曲线上的一个点
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BrightnessPoint {
+    /// 本地时间，距零点的分钟数
+    pub minutes: u16,
+    /// 亮度百分比
+    pub brightness: u8,
+}
+
+/// 未指定亮度开灯时的默认亮度曲线，点之间线性插值，跨零点循环
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BrightnessCurve {
+    pub enabled: bool,
+    pub points: Vec<BrightnessPoint>,
+}
+
+impl Default for BrightnessCurve {
+    fn default() -> Self {
+        let point = |hour: u16, brightness| BrightnessPoint {
+            minutes: hour * 60,
+            brightness,
+        };
+        Self {
+            enabled: true,
+            // 白天全亮，夜间调暗
+            points: vec![point(6, 30), point(8, 100), point(19, 100), point(22, 30)],
+        }
+    }
+}
+
+/// 系统时间早于该年份说明还没有同步过时间
+const MIN_VALID_YEAR: i32 = 2024;
+
+/// 亮度等级的最大值，Hue等协议使用0-254的亮度等级
+pub const MAX_LEVEL: u8 = 254;
+
+/// 亮度等级转换为百分比，非零等级至少为1%
+pub fn level_to_percent(level: u8) -> u8 {
+    if level == 0 {
+        return 0;
+    }
+    let level = level.min(MAX_LEVEL) as u32;
+    ((level * 100 + MAX_LEVEL as u32 / 2) / MAX_LEVEL as u32).max(1) as u8
+}
+
+pub fn percent_to_level(percent: u8) -> u8 {
+    let percent = percent.min(100) as u32;
+    ((percent * MAX_LEVEL as u32 + 50) / 100) as u8
+}
+
+impl BrightnessCurve {
+    pub fn validate(&self) -> Result<()> {
+        for point in &self.points {
+            if point.minutes >= 24 * 60 || point.brightness > 100 {
+                bail!("Invalid brightness point {:?}", point);
+            }
+        }
+        Ok(())
+    }
+
+    /// 当前时间对应的默认亮度百分比
+    pub fn current(&self) -> u8 {
+        self.current_with(&SystemClock)
+    }
+
+    /// 指定时钟的当前时间对应的默认亮度百分比
+    pub fn current_with(&self, clock: &impl Clock) -> u8 {
+        let now = clock.now().with_timezone(&timezone::offset());
+        if !self.enabled || now.year() < MIN_VALID_YEAR {
+            return 100;
+        }
+        self.at((now.hour() * 60 + now.minute()) as u16)
+    }
+
+    fn at(&self, minutes: u16) -> u8 {
+        let mut points = self.points.clone();
+        points.sort_by_key(|point| point.minutes);
+        let (Some(first), Some(last)) = (points.first(), points.last()) else {
+            return 100;
+        };
+        // 找到当前时间前后的两个点，首尾相连
+        let (from, to) = match points.iter().position(|point| point.minutes > minutes) {
+            Some(0) | None => (last, first),
+            Some(index) => (&points[index - 1], &points[index]),
+        };
+        let day = 24 * 60;
+        let span = (to.minutes + day - from.minutes) % day;
+        if span == 0 {
+            return from.brightness;
+        }
+        let elapsed = (minutes + day - from.minutes) % day;
+        let t = elapsed as f32 / span as f32;
+        (from.brightness as f32 + (to.brightness as f32 - from.brightness as f32) * t).round() as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::ManualClock;
+    use chrono::{TimeDelta, TimeZone, Utc};
+
+    fn clock(hour: u32, minute: u32) -> ManualClock {
+        ManualClock::new(Utc.with_ymd_and_hms(2025, 1, 1, hour, minute, 0).unwrap())
+    }
+
+    fn curve(points: &[(u16, u8)]) -> BrightnessCurve {
+        BrightnessCurve {
+            enabled: true,
+            points: points
+                .iter()
+                .map(|&(minutes, brightness)| BrightnessPoint {
+                    minutes,
+                    brightness,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn interpolates_between_points() {
+        let curve = BrightnessCurve::default();
+        assert_eq!(curve.current_with(&clock(7, 0)), 65);
+        assert_eq!(curve.current_with(&clock(12, 0)), 100);
+        assert_eq!(curve.current_with(&clock(22, 0)), 30);
+    }
+
+    #[test]
+    fn wraps_around_midnight() {
+        let curve = curve(&[(22 * 60, 20), (6 * 60, 60)]);
+        assert_eq!(curve.current_with(&clock(2, 0)), 40);
+        assert_eq!(curve.current_with(&clock(23, 0)), 25);
+    }
+
+    #[test]
+    fn follows_clock() {
+        let curve = BrightnessCurve::default();
+        let clock = clock(6, 0);
+        assert_eq!(curve.current_with(&clock), 30);
+        clock.advance(TimeDelta::hours(1));
+        assert_eq!(curve.current_with(&clock), 65);
+    }
+
+    #[test]
+    fn full_brightness_when_disabled_or_unsynced() {
+        let disabled = BrightnessCurve {
+            enabled: false,
+            ..Default::default()
+        };
+        assert_eq!(disabled.current_with(&clock(2, 0)), 100);
+        let unsynced = ManualClock::new(Utc.with_ymd_and_hms(1970, 1, 1, 2, 0, 0).unwrap());
+        assert_eq!(BrightnessCurve::default().current_with(&unsynced), 100);
+        assert_eq!(curve(&[]).current_with(&clock(2, 0)), 100);
+    }
+}
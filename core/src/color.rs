@@ -0,0 +1,269 @@
+//! 颜色计算，渲染效果与指示灯共用
+
+use rgb::RGB8;
+use serde::{Deserialize, Serialize};
+
+/// 色度坐标的最大值，对应1.0
+pub const MAX_XY: u16 = 65279;
+/// 支持的色温范围，单位：mired，对应6500K-2000K
+pub const MIN_MIREDS: u16 = 153;
+pub const MAX_MIREDS: u16 = 500;
+
+/// 渐变插值的颜色空间，RGB线性插值在饱和色之间会出现发灰的中间色
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorSpace {
+    #[default]
+    Rgb,
+    /// 色相沿较短的方向旋转，中间色保持饱和
+    Hsv,
+    /// 感知均匀的颜色空间，亮度过渡最自然
+    Oklab,
+}
+
+// 调整颜色亮度
+pub fn adjust_brightness(rgb: RGB8, brightness: f32) -> RGB8 {
+    let factor = brightness.clamp(0.0, 1.0); // 确保亮度因子在有效范围内
+
+    // 调整每个颜色分量
+    let new_r = (rgb.r as f32) * factor;
+    let new_g = (rgb.g as f32) * factor;
+    let new_b = (rgb.b as f32) * factor;
+
+    // 将结果转换回u8类型，同时确保不会溢出
+    let new_r = new_r.clamp(0.0, 255.0) as u8;
+    let new_g = new_g.clamp(0.0, 255.0) as u8;
+    let new_b = new_b.clamp(0.0, 255.0) as u8;
+
+    RGB8::new(new_r, new_g, new_b)
+}
+
+// sin周期变化
+pub fn cycle_value_sin(t: f32) -> f32 {
+    ((t * std::f32::consts::PI).sin() + 1.0) / 2.0
+}
+
+// HSV转RGB，h为色相(0-360)，s和v取值0-1
+pub fn hsv_to_rgb(h: f32, s: f32, v: f32) -> RGB8 {
+    let h = h.rem_euclid(360.0);
+    let s = s.clamp(0.0, 1.0);
+    let v = v.clamp(0.0, 1.0);
+
+    let c = v * s;
+    let x = c * (1.0 - ((h / 60.0) % 2.0 - 1.0).abs());
+    let m = v - c;
+    let (r, g, b) = match (h / 60.0) as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    RGB8::new(
+        ((r + m) * 255.0).round() as u8,
+        ((g + m) * 255.0).round() as u8,
+        ((b + m) * 255.0).round() as u8,
+    )
+}
+
+// HSL转RGB，h为色相(0-360)，s和l取值0-1，l为0.5时是最饱和的颜色
+pub fn hsl_to_rgb(h: f32, s: f32, l: f32) -> RGB8 {
+    let s = s.clamp(0.0, 1.0);
+    let l = l.clamp(0.0, 1.0);
+    let v = l + s * l.min(1.0 - l);
+    let s = if v == 0.0 { 0.0 } else { 2.0 * (1.0 - l / v) };
+    hsv_to_rgb(h, s, v)
+}
+
+// 一维值噪声，返回0-1之间平滑变化的伪随机值
+pub fn noise1d(x: f32) -> f32 {
+    fn hash(n: i32) -> f32 {
+        let n = (n << 13) ^ n;
+        let n = n
+            .wrapping_mul(n.wrapping_mul(n).wrapping_mul(15731).wrapping_add(789221))
+            .wrapping_add(1376312589);
+        (n & 0x7fffffff) as f32 / 0x7fffffff as f32
+    }
+    let i = x.floor();
+    let f = x - i;
+    // smoothstep插值，避免折线感
+    let t = f * f * (3.0 - 2.0 * f);
+    let a = hash(i as i32);
+    let b = hash(i as i32 + 1);
+    a + (b - a) * t
+}
+
+// // 线性周期变化
+// pub fn cycle_value<'a>(value: &'a mut f32, step: f32) -> impl (FnMut() -> f32) + 'a {
+//     let mut operator = 1.0;
+//     move || {
+//         if *value >= 1.0 {
+//             operator = -1.0;
+//         }
+//         if *value <= 0.0 {
+//             operator = 1.0;
+//         }
+//         *value += operator * step;
+//         *value
+//     }
+// }
+
+// 线性变化颜色
+pub fn blend_colors(color1: RGB8, color2: RGB8, ratio: f32, space: ColorSpace) -> RGB8 {
+    match space {
+        ColorSpace::Rgb => blend_rgb(color1, color2, ratio),
+        ColorSpace::Hsv => blend_hsv(color1, color2, ratio),
+        ColorSpace::Oklab => blend_oklab(color1, color2, ratio),
+    }
+}
+
+fn blend_rgb(color1: RGB8, color2: RGB8, ratio: f32) -> RGB8 {
+    let blend = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * ratio).clamp(0.0, 255.0) as u8;
+    RGB8::new(
+        blend(color1.r, color2.r),
+        blend(color1.g, color2.g),
+        blend(color1.b, color2.b),
+    )
+}
+
+// RGB转HSV，h为色相(0-360)，s和v取值0-1
+pub fn rgb_to_hsv(rgb: RGB8) -> (f32, f32, f32) {
+    let r = rgb.r as f32 / 255.0;
+    let g = rgb.g as f32 / 255.0;
+    let b = rgb.b as f32 / 255.0;
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+    let h = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    let s = if max == 0.0 { 0.0 } else { delta / max };
+    (h, s, max)
+}
+
+fn blend_hsv(color1: RGB8, color2: RGB8, ratio: f32) -> RGB8 {
+    let (h1, s1, v1) = rgb_to_hsv(color1);
+    let (h2, s2, v2) = rgb_to_hsv(color2);
+    // 灰色没有色相，使用另一个颜色的色相，避免经过无关的颜色
+    let h1 = if s1 == 0.0 { h2 } else { h1 };
+    let h2 = if s2 == 0.0 { h1 } else { h2 };
+    // 沿较短的方向旋转色相
+    let mut delta = h2 - h1;
+    if delta > 180.0 {
+        delta -= 360.0;
+    } else if delta < -180.0 {
+        delta += 360.0;
+    }
+    hsv_to_rgb(
+        h1 + delta * ratio,
+        s1 + (s2 - s1) * ratio,
+        v1 + (v2 - v1) * ratio,
+    )
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let value = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (value * 255.0).round() as u8
+}
+
+// sRGB转OKLab
+fn rgb_to_oklab(rgb: RGB8) -> [f32; 3] {
+    let r = srgb_to_linear(rgb.r);
+    let g = srgb_to_linear(rgb.g);
+    let b = srgb_to_linear(rgb.b);
+    let l = (0.41222146 * r + 0.53633255 * g + 0.051445995 * b).cbrt();
+    let m = (0.2119035 * r + 0.6806995 * g + 0.10739696 * b).cbrt();
+    let s = (0.08830246 * r + 0.28171885 * g + 0.6299787 * b).cbrt();
+    [
+        0.21045426 * l + 0.7936178 * m - 0.004072047 * s,
+        1.9779985 * l - 2.4285922 * m + 0.4505937 * s,
+        0.025904037 * l + 0.78277177 * m - 0.80867577 * s,
+    ]
+}
+
+// OKLab转sRGB
+fn oklab_to_rgb([lightness, a, b]: [f32; 3]) -> RGB8 {
+    let l = (lightness + 0.39633778 * a + 0.21580376 * b).powi(3);
+    let m = (lightness - 0.105561346 * a - 0.06385417 * b).powi(3);
+    let s = (lightness - 0.08948418 * a - 1.2914855 * b).powi(3);
+    RGB8::new(
+        linear_to_srgb(4.0767417 * l - 3.3077116 * m + 0.23096994 * s),
+        linear_to_srgb(-1.268438 * l + 2.6097574 * m - 0.34131938 * s),
+        linear_to_srgb(-0.0041960863 * l - 0.7034186 * m + 1.7076147 * s),
+    )
+}
+
+fn blend_oklab(color1: RGB8, color2: RGB8, ratio: f32) -> RGB8 {
+    let lab1 = rgb_to_oklab(color1);
+    let lab2 = rgb_to_oklab(color2);
+    oklab_to_rgb([0, 1, 2].map(|index| lab1[index] + (lab2[index] - lab1[index]) * ratio))
+}
+
+/// 色度坐标转换为sRGB，按最大分量归一化
+pub fn xy_to_rgb(x: u16, y: u16) -> RGB8 {
+    let x = x.min(MAX_XY) as f32 / MAX_XY as f32;
+    let y = (y.min(MAX_XY) as f32 / MAX_XY as f32).max(0.0001);
+    // 亮度Y取1
+    let big_x = x / y;
+    let big_z = (1.0 - x - y) / y;
+    let r = 3.2406 * big_x - 1.5372 - 0.4986 * big_z;
+    let g = -0.9689 * big_x + 1.8758 + 0.0415 * big_z;
+    let b = 0.0557 * big_x - 0.2040 + 1.0570 * big_z;
+    let (r, g, b) = (r.max(0.0), g.max(0.0), b.max(0.0));
+    let max = r.max(g).max(b).max(f32::EPSILON);
+    RGB8::new(
+        linear_to_srgb(r / max),
+        linear_to_srgb(g / max),
+        linear_to_srgb(b / max),
+    )
+}
+
+/// 色温转换为近似的RGB颜色，超出支持范围时取边界值
+pub fn mireds_to_rgb(mireds: u16) -> RGB8 {
+    let kelvin = 1_000_000.0 / mireds.clamp(MIN_MIREDS, MAX_MIREDS) as f32;
+    // Tanner Helland的黑体辐射拟合公式
+    let t = kelvin / 100.0;
+    let r = if t <= 66.0 {
+        255.0
+    } else {
+        329.699 * (t - 60.0).powf(-0.133_204_76)
+    };
+    let g = if t <= 66.0 {
+        99.470_8 * t.ln() - 161.119_57
+    } else {
+        288.122_17 * (t - 60.0).powf(-0.075_514_85)
+    };
+    let b = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.517_73 * (t - 10.0).ln() - 305.044_8
+    };
+    RGB8::new(
+        r.clamp(0.0, 255.0).round() as u8,
+        g.clamp(0.0, 255.0).round() as u8,
+        b.clamp(0.0, 255.0).round() as u8,
+    )
+}
@@ -0,0 +1,190 @@
+use crate::timezone;
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, TimeDelta, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// 精简的cron表达式：`分 时 日 月 周`
+///
+/// 每个字段支持 `*`、数字、范围 `1-5`、列表 `1,3,5` 和步长 `*/15`，
+/// 周字段中0和7都表示周日。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Schedule {
+    source: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    day_any: bool,
+    weekday_any: bool,
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64> {
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>()?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            bail!("Invalid step in `{field}`");
+        }
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (start.parse::<u32>()?, end.parse::<u32>()?)
+        } else {
+            let start = range.parse::<u32>()?;
+            // `5/10` 表示从5开始每10个单位
+            if part.contains('/') {
+                (start, max)
+            } else {
+                (start, start)
+            }
+        };
+        if start < min || end > max || start > end {
+            bail!("Out of range value in `{field}`");
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+impl FromStr for Schedule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        if fields.len() != 5 {
+            bail!("Cron schedule must have 5 fields");
+        }
+        let mut weekdays = parse_field(fields[4], 0, 7)?;
+        // 7也表示周日
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        let schedule = Self {
+            source: fields.join(" "),
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            weekdays,
+            day_any: fields[2] == "*",
+            weekday_any: fields[4] == "*",
+        };
+        // 确保表达式能够触发，如 `0 0 30 2 *` 永远不会执行
+        schedule.next_after(Utc::now().with_timezone(&timezone::offset()))?;
+        Ok(schedule)
+    }
+}
+
+impl TryFrom<String> for Schedule {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<Schedule> for String {
+    fn from(value: Schedule) -> Self {
+        value.source
+    }
+}
+
+impl Schedule {
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        if self.months & (1 << date.month()) == 0 {
+            return false;
+        }
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        // 与标准cron一致：日和周都有限制时满足其一即可
+        if self.day_any || self.weekday_any {
+            day && weekday
+        } else {
+            day || weekday
+        }
+    }
+
+    /// 获取指定时间之后的下一次触发时间，按传入时间所在的时区计算
+    pub fn next_after(&self, time: DateTime<FixedOffset>) -> Result<DateTime<FixedOffset>> {
+        let start = time
+            .with_second(0)
+            .and_then(|t| t.with_nanosecond(0))
+            .ok_or(anyhow!("Invalid time"))?
+            + TimeDelta::minutes(1);
+        let mut date = start.date_naive();
+        // 最多向后查找5年，覆盖闰年2月29日的情况
+        for _ in 0..366 * 5 {
+            if self.matches_date(date) {
+                let (from_hour, from_minute) = if date == start.date_naive() {
+                    (start.hour(), start.minute())
+                } else {
+                    (0, 0)
+                };
+                for hour in (from_hour..24).filter(|h| self.hours & (1 << h) != 0) {
+                    let first_minute = if hour == from_hour { from_minute } else { 0 };
+                    if let Some(minute) = (first_minute..60).find(|m| self.minutes & (1 << m) != 0)
+                    {
+                        return date
+                            .and_hms_opt(hour, minute, 0)
+                            .and_then(|t| t.and_local_timezone(*time.offset()).single())
+                            .ok_or(anyhow!("Invalid time"));
+                    }
+                }
+            }
+            date = date.succ_opt().ok_or(anyhow!("Invalid time"))?;
+        }
+        Err(anyhow!("No matching time for `{}`", self.source))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(time).unwrap()
+    }
+
+    #[test]
+    fn every_two_hours_on_weekends() {
+        let schedule: Schedule = "0 */2 * * 0,6".parse().unwrap();
+        // 2024-06-07为周五
+        let cases = [
+            ("2024-06-07T23:30:00+08:00", "2024-06-08T00:00:00+08:00"),
+            ("2024-06-08T00:00:00+08:00", "2024-06-08T02:00:00+08:00"),
+            ("2024-06-08T01:59:59+08:00", "2024-06-08T02:00:00+08:00"),
+            ("2024-06-08T22:10:00+08:00", "2024-06-09T00:00:00+08:00"),
+            ("2024-06-09T22:00:00+08:00", "2024-06-15T00:00:00+08:00"),
+        ];
+        for (now, next) in cases {
+            assert_eq!(schedule.next_after(at(now)).unwrap(), at(next), "{now}");
+        }
+    }
+
+    #[test]
+    fn sunday_can_be_seven() {
+        let zero: Schedule = "0 */2 * * 0,6".parse().unwrap();
+        let seven: Schedule = "0 */2 * * 6,7".parse().unwrap();
+        let now = at("2024-06-09T13:00:00+08:00");
+        assert_eq!(
+            zero.next_after(now).unwrap(),
+            seven.next_after(now).unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_schedule() {
+        assert!("0 */2 * *".parse::<Schedule>().is_err());
+        assert!("0 */0 * * 0,6".parse::<Schedule>().is_err());
+        assert!("0 24 * * 0,6".parse::<Schedule>().is_err());
+        assert!("0 0 30 2 *".parse::<Schedule>().is_err());
+    }
+}
@@ -0,0 +1,438 @@
+//! 灯光效果的逐帧计算，与灯带驱动和渲染定时无关
+//!
+//! 固件按帧率调用[`Effect::frame`]并写入灯带，主机上可以直接检查生成的画面。
+
+use crate::{
+    color::{adjust_brightness, blend_colors, hsv_to_rgb, noise1d, ColorSpace},
+    scene::{
+        Candle, Color, ColorDuration, Fire, FireState, Gradient, GradientColorItem, Meteor, Music,
+        Rainbow, Sos, Strobe,
+    },
+};
+use anyhow::{anyhow, Result};
+use rgb::RGB8;
+use std::time::{Duration, Instant};
+
+/// 一帧音频的分析结果，各项取值0-1
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AudioFrame {
+    pub level: f32,
+    pub bass: f32,
+    pub mid: f32,
+    pub treble: f32,
+}
+
+/// 效果运行中读取的外部输入，固件中由渲染修饰器提供
+pub trait EffectInput: Send {
+    /// 速度倍率
+    fn speed(&self) -> f32;
+
+    /// 最新一帧音频，没有麦克风时为None
+    fn audio_frame(&self) -> Option<AudioFrame>;
+}
+
+/// 灯光效果，渲染器每一帧调用一次，返回整条灯带的颜色
+pub trait Effect: Send {
+    /// t为效果开始后经过的时间，已按速度倍率缩放，单位：秒
+    fn frame(&mut self, t: f32) -> Vec<RGB8>;
+
+    /// 画面不随时间变化，修饰器也不变化时只需要渲染一次
+    fn is_static(&self) -> bool {
+        false
+    }
+}
+
+/// 根据场景颜色创建效果，调色板需要先解析
+pub fn create<I>(color: Color, input: &I) -> Result<Box<dyn Effect>>
+where
+    I: EffectInput + Clone + 'static,
+{
+    Ok(match color {
+        Color::Solid(solid) => Box::new(SolidEffect(solid.color)),
+        Color::Gradient(gradient) if gradient.linear => Box::new(LinearGradient::new(&gradient)),
+        Color::Gradient(gradient) => Box::new(SteppedGradient::new(gradient.colors)),
+        Color::Palette(_) => return Err(anyhow!("Palette must be resolved before use")),
+        Color::Candle(candle) => Box::new(CandleEffect(candle)),
+        Color::Music(music) => Box::new(MusicEffect::new(music, input.clone())),
+        Color::Meteor(meteor) => Box::new(MeteorEffect::new(meteor)),
+        Color::Fire(fire) => Box::new(FireEffect::new(fire)),
+        Color::Strobe(strobe) => Box::new(StrobeEffect::new(strobe, input.clone())),
+        Color::Sos(sos) => Box::new(SosEffect::new(sos)),
+        Color::Rainbow(rainbow) => Box::new(RainbowEffect(rainbow)),
+    })
+}
+
+struct SolidEffect(RGB8);
+
+impl Effect for SolidEffect {
+    fn frame(&mut self, _t: f32) -> Vec<RGB8> {
+        vec![self.0]
+    }
+
+    fn is_static(&self) -> bool {
+        true
+    }
+}
+
+/// 在相邻颜色之间线性过渡
+struct LinearGradient {
+    durations: Vec<ColorDuration>,
+    space: ColorSpace,
+    total: f32,
+}
+
+impl LinearGradient {
+    fn new(gradient: &Gradient) -> Self {
+        let durations = gradient.get_color_durations();
+        let total = durations.iter().map(|d| d.duration.as_secs_f32()).sum();
+        Self {
+            durations,
+            space: gradient.space,
+            total,
+        }
+    }
+}
+
+impl Effect for LinearGradient {
+    fn frame(&mut self, t: f32) -> Vec<RGB8> {
+        let mut elapsed = if self.total > 0.0 {
+            t % self.total
+        } else {
+            0.0
+        };
+        for item in &self.durations {
+            let duration = item.duration.as_secs_f32();
+            if elapsed < duration {
+                let color = blend_colors(
+                    item.start_color,
+                    item.end_color,
+                    elapsed / duration,
+                    self.space,
+                );
+                return vec![color];
+            }
+            elapsed -= duration;
+        }
+        // 没有颜色的渐变显示为黑色
+        vec![self
+            .durations
+            .first()
+            .map_or(RGB8::default(), |item| item.end_color)]
+    }
+}
+
+/// 每种颜色保持指定时间后直接切换
+struct SteppedGradient {
+    colors: Vec<GradientColorItem>,
+    total: f32,
+}
+
+impl SteppedGradient {
+    fn new(colors: Vec<GradientColorItem>) -> Self {
+        let total = colors.iter().map(|c| c.duration.max(0.0)).sum();
+        Self { colors, total }
+    }
+}
+
+impl Effect for SteppedGradient {
+    fn frame(&mut self, t: f32) -> Vec<RGB8> {
+        let mut elapsed = if self.total > 0.0 {
+            t % self.total
+        } else {
+            0.0
+        };
+        for item in &self.colors {
+            if elapsed < item.duration {
+                return vec![item.color];
+            }
+            elapsed -= item.duration.max(0.0);
+        }
+        vec![self
+            .colors
+            .first()
+            .map_or(RGB8::default(), |item| item.color)]
+    }
+}
+
+struct CandleEffect(Candle);
+
+impl Effect for CandleEffect {
+    fn frame(&mut self, t: f32) -> Vec<RGB8> {
+        let candle = &self.0;
+        let intensity = candle.intensity.clamp(0.0, 1.0);
+        // 火焰变暗时偏向更深的橙红色
+        let ember = RGB8::new(candle.base_color.r, candle.base_color.g / 3, 0);
+        // 叠加快慢两层噪声，模拟火焰的摇曳和细碎跳动
+        let flicker = noise1d(t * 2.0) * 0.7 + noise1d(t * 9.0 + 100.0) * 0.3;
+        let brightness = 1.0 - intensity * flicker;
+        let color = blend_colors(
+            candle.base_color,
+            ember,
+            intensity * flicker * 0.5,
+            ColorSpace::Rgb,
+        );
+        vec![adjust_brightness(color, brightness)]
+    }
+}
+
+/// 音乐律动，颜色跟随频段分布，亮度跟随音量
+struct MusicEffect<I> {
+    music: Music,
+    input: I,
+    /// 平滑后的色相和亮度，避免画面跳变
+    hue: f32,
+    brightness: f32,
+}
+
+impl<I> MusicEffect<I> {
+    fn new(music: Music, input: I) -> Self {
+        let brightness = music.min_brightness;
+        Self {
+            music,
+            input,
+            hue: 0.0,
+            brightness,
+        }
+    }
+}
+
+impl<I: EffectInput> Effect for MusicEffect<I> {
+    fn frame(&mut self, _t: f32) -> Vec<RGB8> {
+        let music = &self.music;
+        let frame = self.input.audio_frame().unwrap_or_default();
+        let total = frame.bass + frame.mid + frame.treble;
+        if total > 0.0 {
+            // 低音偏红，中音偏绿，高音偏蓝
+            let target = (frame.mid * 120.0 + frame.treble * 240.0) / total;
+            self.hue += (target - self.hue) * 0.2;
+        }
+        let level = (frame.level * music.sensitivity).clamp(0.0, 1.0);
+        let target = music.min_brightness + (1.0 - music.min_brightness) * level;
+        // 变亮快、变暗慢，跟上鼓点
+        self.brightness +=
+            (target - self.brightness) * if target > self.brightness { 0.6 } else { 0.15 };
+        vec![hsv_to_rgb(self.hue, music.saturation, self.brightness)]
+    }
+}
+
+struct MeteorEffect {
+    meteor: Meteor,
+    colors: Vec<RGB8>,
+}
+
+impl MeteorEffect {
+    fn new(meteor: Meteor) -> Self {
+        let colors = if meteor.colors.is_empty() {
+            vec![RGB8::new(255, 255, 255)]
+        } else {
+            meteor.colors.clone()
+        };
+        Self { meteor, colors }
+    }
+}
+
+impl Effect for MeteorEffect {
+    fn frame(&mut self, t: f32) -> Vec<RGB8> {
+        let pixels = self.meteor.pixels.max(1) as usize;
+        let tail = self.meteor.tail.max(1) as f32;
+        // 流星头完全划出灯带后从头开始并换下一个颜色
+        let cycle = pixels as f32 + tail;
+        let distance = t * self.meteor.speed.max(0.0);
+        let head = distance % cycle;
+        let color = self.colors[(distance / cycle) as usize % self.colors.len()];
+        (0..pixels)
+            .map(|i| {
+                let position = if self.meteor.reverse {
+                    pixels - 1 - i
+                } else {
+                    i
+                };
+                let distance = head - position as f32;
+                // 尾巴按平方衰减，越远越暗
+                let brightness = if (0.0..tail).contains(&distance) {
+                    (1.0 - distance / tail).powi(2)
+                } else {
+                    0.0
+                };
+                adjust_brightness(color, brightness)
+            })
+            .collect()
+    }
+}
+
+struct FireEffect {
+    fire: Fire,
+    state: FireState,
+    last: f32,
+}
+
+impl FireEffect {
+    fn new(fire: Fire) -> Self {
+        let state = FireState::new(fire.pixels as usize);
+        Self {
+            fire,
+            state,
+            last: 0.0,
+        }
+    }
+}
+
+impl Effect for FireEffect {
+    fn frame(&mut self, t: f32) -> Vec<RGB8> {
+        self.state.step(&self.fire, t - self.last);
+        self.last = t;
+        self.state.frame(&self.fire)
+    }
+}
+
+/// 频闪按实际时间计算相位，速度倍率再大频率也不会超过上限
+struct StrobeEffect<I> {
+    strobe: Strobe,
+    input: I,
+    /// 当前周期内经过的时间
+    phase: Duration,
+    last: Instant,
+}
+
+impl<I> StrobeEffect<I> {
+    fn new(strobe: Strobe, input: I) -> Self {
+        Self {
+            strobe,
+            input,
+            phase: Duration::ZERO,
+            last: Instant::now(),
+        }
+    }
+}
+
+impl<I: EffectInput> Effect for StrobeEffect<I> {
+    fn frame(&mut self, _t: f32) -> Vec<RGB8> {
+        // 每帧重新计算，速度调整后立即生效
+        let (on, off) = self.strobe.periods(self.input.speed());
+        self.phase += self.last.elapsed();
+        self.last = Instant::now();
+        let period = on + off;
+        if self.phase >= period {
+            self.phase = Duration::from_secs_f32(self.phase.as_secs_f32() % period.as_secs_f32());
+        }
+        if self.phase < on {
+            vec![self.strobe.color]
+        } else {
+            vec![RGB8::default()]
+        }
+    }
+}
+
+struct SosEffect {
+    sos: Sos,
+    pattern: Vec<(bool, u32)>,
+    units: u32,
+}
+
+impl SosEffect {
+    fn new(sos: Sos) -> Self {
+        let pattern = Sos::pattern();
+        let units = pattern.iter().map(|&(_, units)| units).sum();
+        Self {
+            sos,
+            pattern,
+            units,
+        }
+    }
+}
+
+impl Effect for SosEffect {
+    fn frame(&mut self, t: f32) -> Vec<RGB8> {
+        let unit = self.sos.unit_ms.clamp(50, 1000) as f32 / 1000.0;
+        let mut position = (t / unit) as u32 % self.units;
+        for &(on, units) in &self.pattern {
+            if position < units {
+                return vec![if on { self.sos.color } else { RGB8::default() }];
+            }
+            position -= units;
+        }
+        vec![RGB8::default()]
+    }
+}
+
+struct RainbowEffect(Rainbow);
+
+impl Effect for RainbowEffect {
+    fn frame(&mut self, t: f32) -> Vec<RGB8> {
+        let rainbow = &self.0;
+        let hue = (t * rainbow.speed) % 360.0;
+        vec![hsv_to_rgb(hue, rainbow.saturation, rainbow.brightness)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone)]
+    struct Input;
+
+    impl EffectInput for Input {
+        fn speed(&self) -> f32 {
+            1.0
+        }
+
+        fn audio_frame(&self) -> Option<AudioFrame> {
+            None
+        }
+    }
+
+    fn effect(color: serde_json::Value) -> Box<dyn Effect> {
+        create(serde_json::from_value(color).unwrap(), &Input).unwrap()
+    }
+
+    #[test]
+    fn stepped_gradient_cycles() {
+        let mut effect = effect(serde_json::json!({
+            "type": "gradient",
+            "colors": [
+                { "color": { "r": 255, "g": 0, "b": 0 }, "duration": 1.0 },
+                { "color": { "r": 0, "g": 0, "b": 255 }, "duration": 2.0 },
+            ],
+        }));
+        assert_eq!(effect.frame(0.5), vec![RGB8::new(255, 0, 0)]);
+        assert_eq!(effect.frame(1.5), vec![RGB8::new(0, 0, 255)]);
+        assert_eq!(effect.frame(3.5), vec![RGB8::new(255, 0, 0)]);
+        assert!(!effect.is_static());
+    }
+
+    #[test]
+    fn solid_is_static() {
+        let mut effect = effect(serde_json::json!({
+            "type": "solid",
+            "color": { "r": 1, "g": 2, "b": 3 },
+        }));
+        assert_eq!(effect.frame(10.0), vec![RGB8::new(1, 2, 3)]);
+        assert!(effect.is_static());
+    }
+
+    #[test]
+    fn meteor_covers_strip() {
+        let mut effect = effect(serde_json::json!({
+            "type": "meteor",
+            "pixels": 10,
+            "tail": 3,
+            "speed": 1.0,
+        }));
+        let frame = effect.frame(2.0);
+        assert_eq!(frame.len(), 10);
+        // 流星头在第2颗，之后的灯珠还没有被点亮
+        assert_eq!(frame[2], RGB8::new(255, 255, 255));
+        assert_eq!(frame[3], RGB8::default());
+    }
+
+    #[test]
+    fn unresolved_palette_is_error() {
+        let color = serde_json::from_value(serde_json::json!({
+            "type": "palette",
+            "palette": "ocean",
+        }))
+        .unwrap();
+        assert!(create(color, &Input).is_err());
+    }
+}
@@ -0,0 +1,76 @@
+//! 硬件抽象：灯带、存储与时钟
+//!
+//! 固件中由ESP-IDF的驱动实现，测试中使用`mock`模块的内存实现，
+//! 不接硬件也能在主机上测试依赖这些接口的逻辑。
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rgb::RGB8;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// 灯带驱动，不同型号的灯带实现该接口
+pub trait LedStrip: Send {
+    /// 发送一帧已校准的颜色
+    fn write(&mut self, pixels: &[RGB8]) -> Result<()>;
+}
+
+/// 按键保存数据的存储
+pub trait Storage: Send + Sync {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+    fn set(&self, key: &str, data: &[u8]) -> Result<()>;
+    /// 返回键是否存在
+    fn remove(&self, key: &str) -> Result<bool>;
+}
+
+/// 读取JSON格式保存的数据
+pub fn read_json<T: DeserializeOwned>(storage: &impl Storage, key: &str) -> Result<Option<T>> {
+    storage
+        .get(key)?
+        .map(|data| Ok(serde_json::from_slice(&data)?))
+        .transpose()
+}
+
+pub fn write_json<T: Serialize>(storage: &impl Storage, key: &str, value: &T) -> Result<()> {
+    storage.set(key, &serde_json::to_vec(value)?)
+}
+
+/// 当前时间的来源
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// 系统时间，固件中由SNTP校准
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MemoryStorage;
+
+    #[test]
+    fn json_round_trip() {
+        let storage = MemoryStorage::default();
+        assert_eq!(read_json::<Vec<u8>>(&storage, "key").unwrap(), None);
+        write_json(&storage, "key", &vec![1u8, 2, 3]).unwrap();
+        assert_eq!(
+            read_json::<Vec<u8>>(&storage, "key").unwrap(),
+            Some(vec![1, 2, 3])
+        );
+        assert!(storage.remove("key").unwrap());
+        assert!(!storage.remove("key").unwrap());
+    }
+
+    #[test]
+    fn invalid_json_is_error() {
+        let storage = MemoryStorage::default();
+        storage.set("key", b"{").unwrap();
+        assert!(read_json::<Vec<u8>>(&storage, "key").is_err());
+    }
+}
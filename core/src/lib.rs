@@ -0,0 +1,29 @@
+//! SmartBrite固件中与硬件无关的部分：场景与效果的逐帧计算、调色板、分享码、分块传输协议、
+//! 定时规则、固件版本检查以及红外、铃声、实时串流等格式解析。
+//!
+//! 只依赖`std`，可以在主机上编译，供App、模拟器或测试工具复用：
+//!
+//! ```sh
+//! cargo build -p smart-brite-core --target x86_64-unknown-linux-gnu
+//! ```
+//!
+//! 仓库根目录的`.cargo/config.toml`默认目标为ESP32-C3，在主机上编译时需要显式指定`--target`。
+//! 测试同样需要指定目标：`cargo test -p smart-brite-core --target x86_64-unknown-linux-gnu`。
+
+pub mod brightness;
+pub mod color;
+pub mod cron;
+pub mod effect;
+pub mod hal;
+#[cfg(test)]
+mod mock;
+pub mod nec;
+pub mod ota;
+pub mod palette;
+pub mod protocol;
+pub mod realtime;
+pub mod rtttl;
+pub mod scene;
+pub mod share;
+pub mod time_task;
+pub mod timezone;
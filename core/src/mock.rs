@@ -0,0 +1,84 @@
+//! [`hal`](crate::hal)中各接口和传输连接的内存实现，只在测试中使用
+
+use crate::{
+    hal::{Clock, Storage},
+    protocol::{msg::NotifyMessage, session::Transport, DataFromBytes},
+};
+use anyhow::Result;
+use chrono::{DateTime, TimeDelta, Utc};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+/// 保存在内存中的存储
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    pub entries: Arc<Mutex<BTreeMap<String, Vec<u8>>>>,
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.entries.lock().unwrap().get(key).cloned())
+    }
+
+    fn set(&self, key: &str, data: &[u8]) -> Result<()> {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), data.to_vec());
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<bool> {
+        Ok(self.entries.lock().unwrap().remove(key).is_some())
+    }
+}
+
+/// 手动调整的时钟
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    now: Arc<Mutex<DateTime<Utc>>>,
+}
+
+impl ManualClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    pub fn advance(&self, delta: TimeDelta) {
+        *self.now.lock().unwrap() += delta;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// 记录发送的回复
+#[derive(Debug, Default)]
+pub struct RecordingTransport {
+    pub replies: Mutex<Vec<(u16, Vec<u8>)>>,
+}
+
+impl RecordingTransport {
+    /// 取出最近一条回复
+    pub fn last(&self) -> Option<(u16, NotifyMessage)> {
+        let (conn_handle, bytes) = self.replies.lock().unwrap().last()?.clone();
+        let (message, _) = NotifyMessage::from_data(&bytes)?;
+        Some((conn_handle, message))
+    }
+}
+
+impl Transport for RecordingTransport {
+    fn reply(&self, conn_handle: u16, message: NotifyMessage) {
+        self.replies
+            .lock()
+            .unwrap()
+            .push((conn_handle, message.bytes()));
+    }
+}
@@ -0,0 +1,54 @@
+//! NEC红外协议解码
+
+/// NEC协议各段的时长，单位：微秒
+const LEADER_MARK: u32 = 9000;
+const LEADER_SPACE: u32 = 4500;
+const REPEAT_SPACE: u32 = 2250;
+const BIT_MARK: u32 = 560;
+const ZERO_SPACE: u32 = 560;
+const ONE_SPACE: u32 = 1690;
+
+/// 解码结果
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NecCode {
+    Code(u32),
+    /// 按住按键时发送的重复码
+    Repeat,
+}
+
+/// 误差在25%以内即认为匹配
+fn near(value: u32, expected: u32) -> bool {
+    value.abs_diff(expected) <= expected / 4
+}
+
+/// 解码NEC帧，输入为每个脉冲的（高/低电平时长，随后的间隔时长）
+pub fn decode_nec(pulses: &[(u32, u32)]) -> Option<NecCode> {
+    let (&(mark, space), bits) = pulses.split_first()?;
+    if !near(mark, LEADER_MARK) {
+        return None;
+    }
+    if near(space, REPEAT_SPACE) {
+        return Some(NecCode::Repeat);
+    }
+    if !near(space, LEADER_SPACE) || bits.len() < 32 {
+        return None;
+    }
+    let mut code = 0u32;
+    for (index, &(mark, space)) in bits[..32].iter().enumerate() {
+        if !near(mark, BIT_MARK) {
+            return None;
+        }
+        if near(space, ONE_SPACE) {
+            code |= 1 << index;
+        } else if !near(space, ZERO_SPACE) {
+            return None;
+        }
+    }
+    // 命令字节与其反码校验，地址可能是16位扩展地址，不做校验
+    let command = (code >> 16) as u8;
+    let inverse = (code >> 24) as u8;
+    if command != !inverse {
+        return None;
+    }
+    Some(NecCode::Code(code))
+}
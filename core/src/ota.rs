@@ -0,0 +1,84 @@
+//! OTA固件镜像的版本检查，写入之前从镜像头部读取版本号，拒绝降级
+
+use anyhow::{anyhow, bail, Result};
+
+const IMAGE_MAGIC: u8 = 0xe9;
+/// 应用描述位于镜像头（24字节）和第一个段头（8字节）之后
+const APP_DESC_OFFSET: usize = 32;
+const APP_DESC_MAGIC: u32 = 0xabcd_5432;
+/// 版本号在镜像中的偏移，前面是应用描述的魔数、安全版本和保留字段
+const VERSION_OFFSET: usize = APP_DESC_OFFSET + 16;
+const VERSION_LEN: usize = 32;
+/// 读取版本号需要的镜像头部长度
+pub const HEADER_LEN: usize = VERSION_OFFSET + VERSION_LEN;
+
+/// 读取镜像应用描述中的版本号
+pub fn image_version(header: &[u8]) -> Result<&str> {
+    let header = header
+        .get(..HEADER_LEN)
+        .ok_or(anyhow!("Image header too short"))?;
+    let magic = u32::from_le_bytes(header[APP_DESC_OFFSET..APP_DESC_OFFSET + 4].try_into()?);
+    if header[0] != IMAGE_MAGIC || magic != APP_DESC_MAGIC {
+        bail!("Not a firmware image");
+    }
+    let version = &header[VERSION_OFFSET..];
+    let end = version.iter().position(|&b| b == 0).unwrap_or(VERSION_LEN);
+    Ok(std::str::from_utf8(&version[..end])?)
+}
+
+/// 解析`主版本.次版本.修订号`，忽略`-`或`+`之后的部分
+fn parse_version(version: &str) -> Option<[u32; 3]> {
+    let core = version.split(['-', '+']).next()?;
+    let mut parts = core.split('.').map(|part| part.parse().ok());
+    let version = [parts.next()??, parts.next()??, parts.next()??];
+    parts.next().is_none().then_some(version)
+}
+
+/// 新固件的版本不能低于当前固件，相同版本允许重新安装
+pub fn check_upgrade(running: &str, new: &str) -> Result<()> {
+    let (Some(current), Some(next)) = (parse_version(running), parse_version(new)) else {
+        bail!("Invalid firmware version {new:?}, running {running:?}");
+    };
+    if next < current {
+        bail!("Downgrade from {running} to {new} is not allowed");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(version: &str) -> Vec<u8> {
+        let mut header = vec![0u8; HEADER_LEN];
+        header[0] = IMAGE_MAGIC;
+        header[APP_DESC_OFFSET..APP_DESC_OFFSET + 4].copy_from_slice(&APP_DESC_MAGIC.to_le_bytes());
+        header[VERSION_OFFSET..VERSION_OFFSET + version.len()].copy_from_slice(version.as_bytes());
+        header
+    }
+
+    #[test]
+    fn reads_image_version() {
+        assert_eq!(image_version(&header("1.2.3")).unwrap(), "1.2.3");
+        assert!(image_version(&header("1.2.3")[..HEADER_LEN - 1]).is_err());
+        let mut invalid = header("1.2.3");
+        invalid[APP_DESC_OFFSET] = 0;
+        assert!(image_version(&invalid).is_err());
+    }
+
+    #[test]
+    fn rejects_downgrade() {
+        assert!(check_upgrade("0.2.0", "0.10.0").is_ok());
+        assert!(check_upgrade("0.2.0", "0.2.0").is_ok());
+        assert!(check_upgrade("0.2.0", "0.2.1-beta").is_ok());
+        assert!(check_upgrade("0.2.0", "0.1.9").is_err());
+        assert!(check_upgrade("1.0.0", "0.9.9").is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_version() {
+        assert!(check_upgrade("0.2.0", "latest").is_err());
+        assert!(check_upgrade("0.2.0", "0.2").is_err());
+        assert!(check_upgrade("0.2.0", "0.2.0.1").is_err());
+    }
+}
@@ -0,0 +1,142 @@
+use crate::{
+    color::ColorSpace,
+    scene::{Color, Gradient, GradientColorItem},
+};
+use anyhow::{anyhow, bail, Result};
+use rgb::RGB8;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// 调色板，可被多个场景共享
+pub type Palette = Vec<GradientColorItem>;
+
+pub type Palettes = HashMap<String, Palette>;
+
+/// 通过名称引用调色板，在开灯时才解析为渐变
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PaletteRef {
+    pub palette: String,
+    #[serde(default)]
+    pub linear: bool,
+    #[serde(default)]
+    pub space: ColorSpace,
+}
+
+/// 内置的色盲友好调色板，通过`builtin:okabeIto`等名称引用，用户调色板不能使用该前缀
+const BUILTIN_PREFIX: &str = "builtin:";
+
+/// 内置调色板的名称和依次显示的颜色
+type BuiltinPalette = (&'static str, &'static [(u8, u8, u8)]);
+
+const BUILTIN_PALETTES: &[BuiltinPalette] = &[
+    // Okabe-Ito配色，各种色觉类型都能区分
+    (
+        "okabeIto",
+        &[
+            (230, 159, 0),
+            (86, 180, 233),
+            (0, 158, 115),
+            (240, 228, 66),
+            (0, 114, 178),
+            (213, 94, 0),
+            (204, 121, 167),
+        ],
+    ),
+    // 蓝-橙对比，红绿色盲可区分
+    (
+        "blueOrange",
+        &[(0, 114, 178), (86, 180, 233), (240, 228, 66), (230, 159, 0)],
+    ),
+    // cividis色阶，按亮度递增
+    (
+        "cividis",
+        &[
+            (0, 32, 76),
+            (65, 77, 107),
+            (124, 123, 120),
+            (188, 175, 111),
+            (255, 234, 70),
+        ],
+    ),
+];
+
+/// 每个颜色在内置调色板中的持续时间，单位：秒
+const BUILTIN_DURATION: f32 = 3.0;
+
+fn builtin_palette(name: &str) -> Option<Palette> {
+    let name = name.strip_prefix(BUILTIN_PREFIX)?;
+    BUILTIN_PALETTES
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(_, colors)| {
+            colors
+                .iter()
+                .map(|&(r, g, b)| GradientColorItem {
+                    color: RGB8::new(r, g, b),
+                    duration: BUILTIN_DURATION,
+                })
+                .collect()
+        })
+}
+
+impl PaletteRef {
+    pub fn resolve(&self, palettes: &Palettes) -> Result<Gradient> {
+        let colors = builtin_palette(&self.palette)
+            .or_else(|| palettes.get(&self.palette).cloned())
+            .ok_or(anyhow!("Palette `{}` not found", self.palette))?;
+        Ok(Gradient {
+            colors,
+            linear: self.linear,
+            space: self.space,
+        })
+    }
+}
+
+pub fn validate_palettes(palettes: &Palettes) -> Result<()> {
+    for (name, colors) in palettes {
+        if name.starts_with(BUILTIN_PREFIX) {
+            bail!("Palette name `{name}` is reserved");
+        }
+        if colors.is_empty() {
+            bail!("Palette `{name}` is empty");
+        }
+    }
+    Ok(())
+}
+
+impl Color {
+    /// 将引用的调色板解析为实际颜色
+    pub fn resolve(&self, palettes: &Palettes) -> Result<Color> {
+        match self {
+            Color::Palette(palette_ref) => Ok(Color::Gradient(palette_ref.resolve(palettes)?)),
+            // 流星效果引用调色板时，使用调色板中的颜色
+            Color::Meteor(meteor) => {
+                let mut meteor = meteor.clone();
+                if let Some(palette) = meteor.palette.take() {
+                    let gradient = PaletteRef {
+                        palette,
+                        linear: false,
+                        space: ColorSpace::default(),
+                    }
+                    .resolve(palettes)?;
+                    meteor.colors = gradient.colors.iter().map(|item| item.color).collect();
+                }
+                Ok(Color::Meteor(meteor))
+            }
+            Color::Fire(fire) => {
+                let mut fire = fire.clone();
+                if let Some(palette) = fire.palette.take() {
+                    let gradient = PaletteRef {
+                        palette,
+                        linear: false,
+                        space: ColorSpace::default(),
+                    }
+                    .resolve(palettes)?;
+                    fire.colors = gradient.colors.iter().map(|item| item.color).collect();
+                }
+                Ok(Color::Fire(fire))
+            }
+            color => Ok(color.clone()),
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use super::{read_u32, DataFromBytes};
+
+#[derive(Debug, Clone)]
+pub struct ChunkMetaData {
+    pub id: u32,
+    pub start: u32,
+    pub chunk_size: u32,
+}
+
+impl DataFromBytes for ChunkMetaData {
+    fn from_data(value: &[u8]) -> Option<(Self, &[u8])> {
+        let (id, value) = read_u32(value)?;
+        let (start, value) = read_u32(value)?;
+        let (chunk_size, value) = read_u32(value)?;
+        Some((
+            Self {
+                id,
+                start,
+                chunk_size,
+            },
+            value,
+        ))
+    }
+    fn bytes(&self) -> Vec<u8> {
+        let mut data = vec![];
+        data.extend(self.id.to_ne_bytes());
+        data.extend(self.start.to_ne_bytes());
+        data.extend(self.chunk_size.to_ne_bytes());
+        data
+    }
+}
+
+/// 数据使用deflate压缩，`total_size`为压缩后的长度
+pub const FLAG_DEFLATE: u8 = 1;
+
+#[derive(Debug, Clone)]
+pub struct MetaData {
+    pub id: u32,
+    pub total_size: u32,
+    /// 协议版本2新增，旧版客户端不发送该字节
+    pub flags: u8,
+}
+
+impl DataFromBytes for MetaData {
+    fn from_data(value: &[u8]) -> Option<(Self, &[u8])> {
+        let (id, value) = read_u32(value)?;
+        let (total_size, value) = read_u32(value)?;
+        let (flags, value) = match value.split_first() {
+            Some((&flags, rest)) => (flags, rest),
+            None => (0, value),
+        };
+        Some((
+            Self {
+                id,
+                total_size,
+                flags,
+            },
+            value,
+        ))
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        let mut data = vec![];
+        data.extend(self.id.to_ne_bytes());
+        data.extend(self.total_size.to_ne_bytes());
+        // 没有标志时保持旧格式，兼容旧版客户端
+        if self.flags != 0 {
+            data.push(self.flags);
+        }
+        data
+    }
+}
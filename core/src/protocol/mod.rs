@@ -0,0 +1,26 @@
+//! 分块传输协议的消息格式和会话状态，与BLE协议栈无关
+
+pub mod meta_date;
+pub mod msg;
+pub mod session;
+
+/// 二进制消息的编解码
+pub trait DataFromBytes
+where
+    Self: Sized,
+{
+    /// 解析开头的消息并返回剩余数据，长度不足时返回None
+    fn from_data(value: &[u8]) -> Option<(Self, &[u8])>;
+    fn bytes(&self) -> Vec<u8>;
+}
+
+/// 读取开头的`u32`，长度不足时返回None
+fn read_u32(bytes: &[u8]) -> Option<(u32, &[u8])> {
+    let (value, rest) = bytes.split_first_chunk::<4>()?;
+    Some((u32::from_ne_bytes(*value), rest))
+}
+
+/// 设备支持的传输协议版本范围，分块格式变化时递增
+/// 版本2：`MetaData`增加标志字节，支持压缩传输
+pub const PROTOCOL_VERSION: u8 = 2;
+pub const MIN_PROTOCOL_VERSION: u8 = 1;
@@ -0,0 +1,293 @@
+use super::{
+    meta_date::{ChunkMetaData, MetaData},
+    read_u32, DataFromBytes,
+};
+
+#[derive(Debug)]
+pub enum ReadMessage {
+    StartRead,
+    ReadReceive {
+        next_start: u32,
+    },
+    ReadFinish,
+    StartWrite(MetaData),
+    Write(ChunkMetaData),
+    Ping {
+        nonce: u32,
+    },
+    /// 客户端告知支持的协议版本
+    Hello {
+        version: u8,
+    },
+    /// 无法识别的消息，可能来自更新的协议版本
+    Unknown(u8),
+}
+
+impl DataFromBytes for ReadMessage {
+    fn from_data(bytes: &[u8]) -> Option<(Self, &[u8])> {
+        let (&code, bytes) = bytes.split_first()?;
+        Some(match code {
+            0 => (ReadMessage::StartRead, bytes),
+            1 => {
+                let (next_start, bytes) = read_u32(bytes)?;
+                (ReadMessage::ReadReceive { next_start }, bytes)
+            }
+            2 => (ReadMessage::ReadFinish, bytes),
+            3 => {
+                let (meta_date, bytes) = MetaData::from_data(bytes)?;
+                (ReadMessage::StartWrite(meta_date), bytes)
+            }
+            4 => {
+                let (chunk_meta_date, bytes) = ChunkMetaData::from_data(bytes)?;
+                (ReadMessage::Write(chunk_meta_date), bytes)
+            }
+            5 => {
+                let (nonce, bytes) = read_u32(bytes)?;
+                (ReadMessage::Ping { nonce }, bytes)
+            }
+            6 => {
+                let (&version, bytes) = bytes.split_first()?;
+                (ReadMessage::Hello { version }, bytes)
+            }
+            code => (ReadMessage::Unknown(code), &[]),
+        })
+    }
+    fn bytes(&self) -> Vec<u8> {
+        match self {
+            ReadMessage::StartRead => vec![0],
+            ReadMessage::ReadReceive { next_start } => {
+                let mut bytes = vec![1];
+                bytes.extend(next_start.to_ne_bytes());
+                bytes
+            }
+            ReadMessage::ReadFinish => vec![2],
+            ReadMessage::StartWrite(meta_date) => {
+                let mut bytes = vec![3];
+                bytes.extend(meta_date.bytes());
+                bytes
+            }
+            ReadMessage::Write(chunk_meta_date) => {
+                let mut bytes = vec![4];
+                bytes.extend(chunk_meta_date.bytes());
+                bytes
+            }
+            ReadMessage::Ping { nonce } => {
+                let mut bytes = vec![5];
+                bytes.extend(nonce.to_ne_bytes());
+                bytes
+            }
+            ReadMessage::Hello { version } => vec![6, *version],
+            ReadMessage::Unknown(code) => vec![*code],
+        }
+    }
+}
+
+pub enum NotifyMessage {
+    DataUpdate,
+    ReadReady(MetaData),
+    WriteReady {
+        mtu: u16,
+    },
+    WriteReceive {
+        next_start: u32,
+    },
+    WriteFinish,
+    Error(String),
+    Pong {
+        nonce: u32,
+    },
+    /// 回复握手，告知设备支持的协议版本范围
+    Version {
+        min: u8,
+        max: u8,
+    },
+}
+
+impl DataFromBytes for NotifyMessage {
+    fn from_data(bytes: &[u8]) -> Option<(Self, &[u8])> {
+        let (&code, bytes) = bytes.split_first()?;
+        Some(match code {
+            0 => (NotifyMessage::WriteFinish, bytes),
+            1 => (NotifyMessage::DataUpdate, bytes),
+            2 => {
+                let (meta_data, bytes) = MetaData::from_data(bytes)?;
+                (NotifyMessage::ReadReady(meta_data), bytes)
+            }
+            3 => {
+                let (mtu, bytes) = bytes.split_first_chunk::<2>()?;
+                (
+                    NotifyMessage::WriteReady {
+                        mtu: u16::from_ne_bytes(*mtu),
+                    },
+                    bytes,
+                )
+            }
+            4 => {
+                let (next_start, bytes) = read_u32(bytes)?;
+                (NotifyMessage::WriteReceive { next_start }, bytes)
+            }
+            5 => (
+                NotifyMessage::Error(String::from_utf8_lossy(bytes).to_string()),
+                &[],
+            ),
+            6 => {
+                let (nonce, bytes) = read_u32(bytes)?;
+                (NotifyMessage::Pong { nonce }, bytes)
+            }
+            7 => {
+                let ([min, max], bytes) = bytes.split_first_chunk::<2>()?;
+                (
+                    NotifyMessage::Version {
+                        min: *min,
+                        max: *max,
+                    },
+                    bytes,
+                )
+            }
+            // 更新的设备可能发送未知的通知
+            _ => return None,
+        })
+    }
+    fn bytes(&self) -> Vec<u8> {
+        match self {
+            NotifyMessage::WriteFinish => vec![0],
+            NotifyMessage::DataUpdate => vec![1],
+            NotifyMessage::ReadReady(meta_data) => {
+                let mut bytes = vec![2];
+                bytes.extend(meta_data.bytes());
+                bytes
+            }
+            NotifyMessage::WriteReady { mtu } => {
+                let mut bytes = vec![3];
+                bytes.extend(mtu.to_ne_bytes());
+                bytes
+            }
+            NotifyMessage::WriteReceive { next_start } => {
+                let mut bytes = vec![4];
+                bytes.extend(next_start.to_ne_bytes());
+                bytes
+            }
+            NotifyMessage::Error(err) => {
+                let mut bytes = vec![5];
+                bytes.extend(err.as_bytes());
+                bytes
+            }
+            NotifyMessage::Pong { nonce } => {
+                let mut bytes = vec![6];
+                bytes.extend(nonce.to_ne_bytes());
+                bytes
+            }
+            NotifyMessage::Version { min, max } => vec![7, *min, *max],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::meta_date::FLAG_DEFLATE;
+
+    #[test]
+    fn read_message_round_trip() {
+        let message = ReadMessage::StartWrite(MetaData {
+            id: 7,
+            total_size: 300,
+            flags: FLAG_DEFLATE,
+        });
+        let mut bytes = message.bytes();
+        bytes.extend(b"rest");
+        let Some((ReadMessage::StartWrite(meta), rest)) = ReadMessage::from_data(&bytes) else {
+            panic!("unexpected message");
+        };
+        assert_eq!(
+            (meta.id, meta.total_size, meta.flags),
+            (7, 300, FLAG_DEFLATE)
+        );
+        assert_eq!(rest, b"rest");
+
+        let bytes = ReadMessage::Write(ChunkMetaData {
+            id: 7,
+            start: 200,
+            chunk_size: 100,
+        })
+        .bytes();
+        let Some((ReadMessage::Write(chunk), _)) = ReadMessage::from_data(&bytes) else {
+            panic!("unexpected message");
+        };
+        assert_eq!((chunk.id, chunk.start, chunk.chunk_size), (7, 200, 100));
+    }
+
+    #[test]
+    fn legacy_meta_data_has_no_flags() {
+        let meta = MetaData {
+            id: 1,
+            total_size: 10,
+            flags: 0,
+        };
+        // 旧版客户端不发送标志字节
+        let bytes = meta.bytes();
+        assert_eq!(bytes.len(), 8);
+        let (meta, rest) = MetaData::from_data(&bytes).unwrap();
+        assert_eq!(meta.flags, 0);
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn notify_message_round_trip() {
+        let bytes = NotifyMessage::Version { min: 1, max: 2 }.bytes();
+        let Some((NotifyMessage::Version { min, max }, _)) = NotifyMessage::from_data(&bytes)
+        else {
+            panic!("unexpected message");
+        };
+        assert_eq!((min, max), (1, 2));
+    }
+
+    #[test]
+    fn truncated_read_message_is_none() {
+        let full = [
+            ReadMessage::ReadReceive { next_start: 20 }.bytes(),
+            ReadMessage::Ping { nonce: 42 }.bytes(),
+            ReadMessage::Hello { version: 2 }.bytes(),
+            ReadMessage::StartWrite(MetaData {
+                id: 1,
+                total_size: 10,
+                flags: 0,
+            })
+            .bytes(),
+            ReadMessage::Write(ChunkMetaData {
+                id: 1,
+                start: 0,
+                chunk_size: 10,
+            })
+            .bytes(),
+        ];
+        assert!(ReadMessage::from_data(&[]).is_none());
+        for bytes in full {
+            assert!(ReadMessage::from_data(&bytes).is_some());
+            for len in 1..bytes.len() {
+                assert!(ReadMessage::from_data(&bytes[..len]).is_none(), "{bytes:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_notify_message_is_none() {
+        let full = [
+            NotifyMessage::WriteReady { mtu: 247 }.bytes(),
+            NotifyMessage::WriteReceive { next_start: 20 }.bytes(),
+            NotifyMessage::Pong { nonce: 42 }.bytes(),
+            NotifyMessage::Version { min: 1, max: 2 }.bytes(),
+        ];
+        assert!(NotifyMessage::from_data(&[]).is_none());
+        assert!(NotifyMessage::from_data(&[99]).is_none());
+        for bytes in full {
+            assert!(NotifyMessage::from_data(&bytes).is_some());
+            for len in 1..bytes.len() {
+                assert!(
+                    NotifyMessage::from_data(&bytes[..len]).is_none(),
+                    "{bytes:?}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,480 @@
+//! 分块传输的会话状态，每个连接一个会话，多个客户端可以同时读写，互不影响
+//!
+//! 回复通过[`Transport`]发送，固件中由BLE特征实现，测试中记录下发送的消息即可。
+
+use super::{
+    meta_date::{ChunkMetaData, MetaData, FLAG_DEFLATE},
+    msg::{NotifyMessage, ReadMessage},
+    DataFromBytes, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+use anyhow::{anyhow, Result};
+use miniz_oxide::{deflate::compress_to_vec, inflate::decompress_to_vec_with_limit};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// 超过该长度的数据才尝试压缩
+const COMPRESS_THRESHOLD: usize = 64;
+/// 解压后数据的最大长度，防止恶意数据耗尽内存
+const MAX_DECOMPRESSED_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum State {
+    Reading,
+    Writing,
+}
+
+/// 向客户端发送回复的连接
+pub trait Transport {
+    /// 只回复发起传输的连接
+    fn reply(&self, conn_handle: u16, message: NotifyMessage);
+}
+
+/// 压缩后更小时才使用压缩数据
+fn compress(data: Vec<u8>) -> (Vec<u8>, u8) {
+    if data.len() > COMPRESS_THRESHOLD {
+        let compressed = compress_to_vec(&data, 6);
+        if compressed.len() < data.len() {
+            return (compressed, FLAG_DEFLATE);
+        }
+    }
+    (data, 0)
+}
+
+fn decompress(data: Vec<u8>, flags: u8) -> Result<Vec<u8>> {
+    if flags & FLAG_DEFLATE == 0 {
+        return Ok(data);
+    }
+    decompress_to_vec_with_limit(&data, MAX_DECOMPRESSED_SIZE)
+        .map_err(|e| anyhow!("Decompress failed: {:?}", e.status))
+}
+
+/// 单个连接的传输会话
+struct Session {
+    state: Option<State>,
+    /// 客户端握手时声明的协议版本，未握手的旧版客户端视为版本1
+    client_version: u8,
+    last_active: Instant,
+    mtu: u16,
+    read_meta_data: Option<MetaData>,
+    /// 本次读取发送的数据，可能是压缩后的
+    read_buffer: Vec<u8>,
+    start: u32,
+    write_meta_data: Option<MetaData>,
+    write_buffer: Vec<u8>,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            state: None,
+            client_version: MIN_PROTOCOL_VERSION,
+            last_active: Instant::now(),
+            mtu: 0,
+            read_meta_data: None,
+            read_buffer: vec![],
+            start: 0,
+            write_meta_data: None,
+            write_buffer: vec![],
+        }
+    }
+}
+
+impl Session {
+    fn version_supported(&self) -> bool {
+        (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&self.client_version)
+    }
+
+    fn abort_write(&mut self) {
+        if matches!(self.state, Some(State::Writing)) {
+            self.state = None;
+            self.write_meta_data = None;
+            self.write_buffer = vec![];
+        }
+    }
+}
+
+/// 按连接句柄区分的所有会话
+pub struct Sessions {
+    sessions: HashMap<u16, Session>,
+    /// 下一次读取的标识
+    next_id: u32,
+}
+
+impl Sessions {
+    /// `id_seed`为第一次读取的标识，之后逐次递增，使用随机数避免与重启前的传输混淆
+    pub fn new(id_seed: u32) -> Self {
+        Self {
+            sessions: HashMap::new(),
+            next_id: id_seed,
+        }
+    }
+
+    /// 记录连接的MTU，返回是否为新连接
+    pub fn connect(&mut self, conn_handle: u16, mtu: u16) -> bool {
+        let new_session = !self.sessions.contains_key(&conn_handle);
+        self.sessions.entry(conn_handle).or_default().mtu = mtu;
+        new_session
+    }
+
+    /// 连接断开时移除会话，不需要等待超时
+    pub fn remove(&mut self, conn_handle: u16) {
+        self.sessions.remove(&conn_handle);
+    }
+
+    /// 整体状态，任一会话在写入即为写入，否则任一会话在读取即为读取
+    pub fn state(&self) -> Option<State> {
+        let states = || self.sessions.values().filter_map(|session| session.state);
+        if states().any(|state| state == State::Writing) {
+            Some(State::Writing)
+        } else if states().any(|state| state == State::Reading) {
+            Some(State::Reading)
+        } else {
+            None
+        }
+    }
+
+    /// 处理客户端写入的一条消息，写入完成时返回解压后的数据
+    ///
+    /// `data`在开始读取时调用，返回发送给客户端的数据；`child_locked`为true时拒绝写入
+    pub fn handle(
+        &mut self,
+        transport: &impl Transport,
+        conn_handle: u16,
+        value: &[u8],
+        child_locked: bool,
+        data: impl FnOnce() -> Vec<u8>,
+    ) -> Option<Result<Vec<u8>>> {
+        let Some((message, recv_data)) = ReadMessage::from_data(value) else {
+            transport.reply(conn_handle, NotifyMessage::Error("Invalid message".into()));
+            return None;
+        };
+        let session = self.sessions.entry(conn_handle).or_default();
+        session.last_active = Instant::now();
+        if matches!(message, ReadMessage::StartRead | ReadMessage::StartWrite(_))
+            && !session.version_supported()
+        {
+            transport.reply(
+                conn_handle,
+                NotifyMessage::Error(format!(
+                    "Unsupported protocol version {}, expected {}-{}",
+                    session.client_version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+                )),
+            );
+            return None;
+        }
+        if matches!(message, ReadMessage::StartWrite(_) | ReadMessage::Write(_)) && child_locked {
+            // 写入途中开启儿童锁时放弃本次写入
+            session.abort_write();
+            transport.reply(
+                conn_handle,
+                NotifyMessage::Error("Child lock enabled".into()),
+            );
+            return None;
+        }
+        match message {
+            ReadMessage::Ping { nonce } => {
+                transport.reply(conn_handle, NotifyMessage::Pong { nonce });
+            }
+            ReadMessage::Hello { version } => {
+                session.client_version = version;
+                transport.reply(
+                    conn_handle,
+                    NotifyMessage::Version {
+                        min: MIN_PROTOCOL_VERSION,
+                        max: PROTOCOL_VERSION,
+                    },
+                );
+            }
+            ReadMessage::Unknown(code) => {
+                transport.reply(
+                    conn_handle,
+                    NotifyMessage::Error(format!("Unsupported message {code}")),
+                );
+            }
+            ReadMessage::StartRead => {
+                let id = self.next_id;
+                self.next_id = self.next_id.wrapping_add(1);
+                let (data, flags) = if session.client_version >= 2 {
+                    compress(data())
+                } else {
+                    (data(), 0)
+                };
+                let meta_data = MetaData {
+                    id,
+                    total_size: data.len() as u32,
+                    flags,
+                };
+                session.state = Some(State::Reading);
+                session.read_buffer = data;
+                session.read_meta_data = Some(meta_data.clone());
+                session.start = 0;
+                transport.reply(conn_handle, NotifyMessage::ReadReady(meta_data));
+            }
+            ReadMessage::ReadReceive { next_start } => {
+                session.start = next_start;
+            }
+            ReadMessage::ReadFinish => {
+                session.state = None;
+                session.read_buffer = vec![];
+            }
+            ReadMessage::StartWrite(meta_data) => {
+                session.write_meta_data = Some(meta_data);
+                session.write_buffer = vec![];
+                session.state = Some(State::Writing);
+                transport.reply(conn_handle, NotifyMessage::WriteReady { mtu: session.mtu });
+            }
+            ReadMessage::Write(chunk_meta_data) => match session.write_meta_data.clone() {
+                Some(write_meta_data)
+                    if matches!(session.state, Some(State::Writing))
+                        && write_meta_data.id == chunk_meta_data.id =>
+                {
+                    let next_start = chunk_meta_data.start + chunk_meta_data.chunk_size;
+                    session.write_buffer.extend(recv_data);
+                    if next_start < write_meta_data.total_size {
+                        transport.reply(conn_handle, NotifyMessage::WriteReceive { next_start });
+                    } else {
+                        // 写入完成重置状态
+                        session.state = None;
+                        session.write_meta_data = None;
+                        return Some(decompress(
+                            std::mem::take(&mut session.write_buffer),
+                            write_meta_data.flags,
+                        ));
+                    }
+                }
+                _ => transport.reply(conn_handle, NotifyMessage::Error("写入失败".into())),
+            },
+        }
+        None
+    }
+
+    /// 读取会话的下一块数据，不在读取状态或已读完时返回None
+    pub fn read_chunk(&mut self, conn_handle: u16, mtu: u16) -> Option<Vec<u8>> {
+        let session = self.sessions.get_mut(&conn_handle)?;
+        session.last_active = Instant::now();
+        if !matches!(session.state, Some(State::Reading)) {
+            return None;
+        }
+        let meta_data = session.read_meta_data.clone()?;
+        let start = session.start;
+        if start >= meta_data.total_size {
+            return None;
+        }
+        let chunk_meta = ChunkMetaData {
+            id: meta_data.id,
+            start,
+            chunk_size: (mtu as u32)
+                .saturating_sub(12)
+                .min(meta_data.total_size - start),
+        };
+        // 客户端发来的`next_start`超出数据范围时不返回数据
+        let chunk = session
+            .read_buffer
+            .get(start as usize..(start + chunk_meta.chunk_size) as usize)?;
+        let mut chunk_meta_bytes = chunk_meta.bytes();
+        chunk_meta_bytes.extend(chunk);
+        Some(chunk_meta_bytes)
+    }
+
+    /// 传输进行中且超过`timeout`无响应的连接
+    pub fn expired(&self, now: Instant, timeout: Duration) -> Vec<u16> {
+        self.sessions
+            .iter()
+            .filter(|(_, session)| {
+                session.state.is_some() && now.duration_since(session.last_active) > timeout
+            })
+            .map(|(conn_handle, _)| *conn_handle)
+            .collect()
+    }
+
+    /// 结束连接正在进行的传输，返回是否确实有传输被结束
+    pub fn cancel(&mut self, conn_handle: u16) -> bool {
+        self.sessions
+            .get_mut(&conn_handle)
+            .and_then(|session| session.state.take())
+            .is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::RecordingTransport;
+
+    const CONN: u16 = 1;
+
+    fn start_write(id: u32, total_size: u32, flags: u8) -> Vec<u8> {
+        ReadMessage::StartWrite(MetaData {
+            id,
+            total_size,
+            flags,
+        })
+        .bytes()
+    }
+
+    fn write(id: u32, start: u32, chunk: &[u8]) -> Vec<u8> {
+        let mut bytes = ReadMessage::Write(ChunkMetaData {
+            id,
+            start,
+            chunk_size: chunk.len() as u32,
+        })
+        .bytes();
+        bytes.extend(chunk);
+        bytes
+    }
+
+    fn handle(
+        sessions: &mut Sessions,
+        transport: &RecordingTransport,
+        message: &[u8],
+    ) -> Option<Result<Vec<u8>>> {
+        sessions.handle(transport, CONN, message, false, || b"device data".to_vec())
+    }
+
+    #[test]
+    fn chunked_write() {
+        let transport = RecordingTransport::default();
+        let mut sessions = Sessions::new(0);
+        sessions.connect(CONN, 100);
+        assert!(handle(&mut sessions, &transport, &start_write(7, 10, 0)).is_none());
+        assert!(matches!(
+            transport.last(),
+            Some((CONN, NotifyMessage::WriteReady { mtu: 100 }))
+        ));
+        assert_eq!(sessions.state(), Some(State::Writing));
+
+        assert!(handle(&mut sessions, &transport, &write(7, 0, b"hello ")).is_none());
+        assert!(matches!(
+            transport.last(),
+            Some((_, NotifyMessage::WriteReceive { next_start: 6 }))
+        ));
+        let data = handle(&mut sessions, &transport, &write(7, 6, b"hue!")).unwrap();
+        assert_eq!(data.unwrap(), b"hello hue!");
+        assert_eq!(sessions.state(), None);
+    }
+
+    #[test]
+    fn chunk_with_wrong_id_is_rejected() {
+        let transport = RecordingTransport::default();
+        let mut sessions = Sessions::new(0);
+        handle(&mut sessions, &transport, &start_write(7, 10, 0));
+        assert!(handle(&mut sessions, &transport, &write(8, 0, b"0123456789")).is_none());
+        assert!(matches!(
+            transport.last(),
+            Some((_, NotifyMessage::Error(_)))
+        ));
+    }
+
+    #[test]
+    fn chunked_read() {
+        let transport = RecordingTransport::default();
+        let mut sessions = Sessions::new(41);
+        // 每块最多MTU减去12字节的分块头
+        sessions.connect(CONN, 18);
+        assert_eq!(sessions.read_chunk(CONN, 18), None);
+        handle(&mut sessions, &transport, &ReadMessage::StartRead.bytes());
+        let Some((_, NotifyMessage::ReadReady(meta))) = transport.last() else {
+            panic!("unexpected reply");
+        };
+        assert_eq!((meta.id, meta.total_size, meta.flags), (41, 11, 0));
+        assert_eq!(sessions.state(), Some(State::Reading));
+
+        let mut received: Vec<u8> = vec![];
+        while let Some(chunk) = sessions.read_chunk(CONN, 18) {
+            let (chunk_meta, data) = ChunkMetaData::from_data(&chunk).unwrap();
+            assert_eq!(chunk_meta.start as usize, received.len());
+            received.extend(data);
+            let next_start = ReadMessage::ReadReceive {
+                next_start: received.len() as u32,
+            };
+            handle(&mut sessions, &transport, &next_start.bytes());
+        }
+        assert_eq!(received, b"device data");
+        handle(&mut sessions, &transport, &ReadMessage::ReadFinish.bytes());
+        assert_eq!(sessions.state(), None);
+    }
+
+    #[test]
+    fn compressed_round_trip() {
+        let transport = RecordingTransport::default();
+        let mut sessions = Sessions::new(0);
+        handle(
+            &mut sessions,
+            &transport,
+            &ReadMessage::Hello { version: 2 }.bytes(),
+        );
+        let data = vec![b'a'; 200];
+        sessions.handle(
+            &transport,
+            CONN,
+            &ReadMessage::StartRead.bytes(),
+            false,
+            || data.clone(),
+        );
+        let Some((_, NotifyMessage::ReadReady(meta))) = transport.last() else {
+            panic!("unexpected reply");
+        };
+        assert_eq!(meta.flags, FLAG_DEFLATE);
+        assert!((meta.total_size as usize) < data.len());
+
+        let (compressed, flags) = compress(data.clone());
+        let size = compressed.len() as u32;
+        handle(&mut sessions, &transport, &start_write(1, size, flags));
+        let written = handle(&mut sessions, &transport, &write(1, 0, &compressed)).unwrap();
+        assert_eq!(written.unwrap(), data);
+    }
+
+    #[test]
+    fn child_lock_aborts_write() {
+        let transport = RecordingTransport::default();
+        let mut sessions = Sessions::new(0);
+        handle(&mut sessions, &transport, &start_write(7, 10, 0));
+        let chunk = write(7, 0, b"0123456789");
+        assert!(sessions
+            .handle(&transport, CONN, &chunk, true, Vec::new)
+            .is_none());
+        assert!(matches!(
+            transport.last(),
+            Some((_, NotifyMessage::Error(_)))
+        ));
+        assert_eq!(sessions.state(), None);
+    }
+
+    #[test]
+    fn rejects_unsupported_version_and_invalid_message() {
+        let transport = RecordingTransport::default();
+        let mut sessions = Sessions::new(0);
+        handle(&mut sessions, &transport, &[]);
+        assert!(matches!(
+            transport.last(),
+            Some((_, NotifyMessage::Error(_)))
+        ));
+        handle(
+            &mut sessions,
+            &transport,
+            &ReadMessage::Hello { version: 9 }.bytes(),
+        );
+        handle(&mut sessions, &transport, &ReadMessage::StartRead.bytes());
+        assert!(matches!(
+            transport.last(),
+            Some((_, NotifyMessage::Error(_)))
+        ));
+        assert_eq!(sessions.state(), None);
+    }
+
+    #[test]
+    fn expired_transfer_is_cancelled() {
+        let transport = RecordingTransport::default();
+        let mut sessions = Sessions::new(0);
+        handle(&mut sessions, &transport, &ReadMessage::StartRead.bytes());
+        let timeout = Duration::from_secs(10);
+        assert!(sessions.expired(Instant::now(), timeout).is_empty());
+        let later = Instant::now() + timeout + Duration::from_secs(1);
+        assert_eq!(sessions.expired(later, timeout), vec![CONN]);
+        assert!(sessions.cancel(CONN));
+        assert!(!sessions.cancel(CONN));
+        assert!(sessions.expired(later, timeout).is_empty());
+    }
+}
@@ -0,0 +1,167 @@
+//! 实时串流协议的数据包解析，与WLED兼容，电脑氛围灯软件和灯光控制台可以直接逐像素推送
+//!
+//! - WLED UDP实时协议：DRGB、DRGBW、DNRGB，第二个字节为停止接收后恢复原状态的秒数
+//! - DDP：一帧可以分多个包发送，带`PUSH`标志的包到达后才显示
+//! - E1.31（sACN）和Art-Net：只解析DMX数据，通道的用法由固件的配置决定
+
+use rgb::RGB8;
+use std::{net::Ipv4Addr, time::Duration};
+
+/// WLED UDP实时协议的端口
+pub const WLED_PORT: u16 = 21324;
+pub const DDP_PORT: u16 = 4048;
+pub const E131_PORT: u16 = 5568;
+pub const ARTNET_PORT: u16 = 6454;
+/// 一个universe的DMX通道数
+pub const DMX_CHANNELS: usize = 512;
+/// 数据包未指定超时时，停止接收后恢复原状态的时间
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(2500);
+
+const DRGB: u8 = 2;
+const DRGBW: u8 = 3;
+const DNRGB: u8 = 4;
+/// WLED协议中表示一直保持的超时
+const NO_TIMEOUT: u8 = 255;
+
+const DDP_VERSION_MASK: u8 = 0xc0;
+const DDP_VERSION_1: u8 = 0x40;
+const DDP_PUSH: u8 = 0x01;
+const DDP_QUERY: u8 = 0x02;
+const DDP_TIMECODE: u8 = 0x10;
+const DDP_HEADER_LEN: usize = 10;
+/// 带时间码时头部多4字节
+const DDP_TIMECODE_LEN: usize = 4;
+/// 默认输出设备，其余ID用于配置和状态查询
+const DDP_DEFAULT_OUTPUT: u8 = 1;
+
+const E131_PACKET_ID: &[u8; 12] = b"ASC-E1.17\0\0\0";
+const E131_ROOT_VECTOR: u32 = 0x04;
+const E131_FRAMING_VECTOR: u32 = 0x02;
+/// 选项中的预览数据和流结束标志，这两种包都不显示
+const E131_PREVIEW: u8 = 0x80;
+const E131_TERMINATED: u8 = 0x40;
+/// DMX数据前的起始码所在位置
+const E131_START_CODE: usize = 125;
+
+const ARTNET_ID: &[u8; 8] = b"Art-Net\0";
+const ARTNET_OP_DMX: u16 = 0x5000;
+const ARTNET_HEADER_LEN: usize = 18;
+
+/// 解析后的像素数据，从`start`开始依次写入
+#[derive(Debug, Clone, PartialEq)]
+pub struct PixelUpdate {
+    pub start: usize,
+    pub pixels: Vec<RGB8>,
+    /// 是否立即显示
+    pub push: bool,
+    /// 停止接收后多久恢复原状态，`None`表示一直保持
+    pub timeout: Option<Duration>,
+}
+
+/// 按通道数取出RGB，RGBW的白色通道直接丢弃
+fn to_rgb(data: &[u8], channels: usize) -> Vec<RGB8> {
+    data.chunks_exact(channels)
+        .map(|pixel| RGB8::new(pixel[0], pixel[1], pixel[2]))
+        .collect()
+}
+
+/// 解析WLED UDP实时协议，不支持按索引逐个写入的WARLS
+pub fn parse_wled(packet: &[u8]) -> Option<PixelUpdate> {
+    let (&protocol, rest) = packet.split_first()?;
+    let (&timeout, data) = rest.split_first()?;
+    let (start, pixels) = match protocol {
+        DRGB => (0, to_rgb(data, 3)),
+        DRGBW => (0, to_rgb(data, 4)),
+        DNRGB => {
+            let start = u16::from_be_bytes([*data.first()?, *data.get(1)?]);
+            (start as usize, to_rgb(&data[2..], 3))
+        }
+        _ => return None,
+    };
+    Some(PixelUpdate {
+        start,
+        pixels,
+        push: true,
+        timeout: (timeout != NO_TIMEOUT).then(|| Duration::from_secs(timeout as u64)),
+    })
+}
+
+/// 解析DDP数据包，查询和发往其他ID的包返回`None`
+pub fn parse_ddp(packet: &[u8]) -> Option<PixelUpdate> {
+    let header = packet.get(..DDP_HEADER_LEN)?;
+    let flags = header[0];
+    if flags & DDP_VERSION_MASK != DDP_VERSION_1
+        || flags & DDP_QUERY != 0
+        || header[3] != DDP_DEFAULT_OUTPUT
+    {
+        return None;
+    }
+    // 数据类型的第3-5位，0表示未指定，按RGB处理
+    let channels = match (header[2] >> 3) & 0x07 {
+        0 | 1 => 3,
+        3 => 4,
+        _ => return None,
+    };
+    let offset = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    let len = u16::from_be_bytes([header[8], header[9]]) as usize;
+    let data_start = if flags & DDP_TIMECODE != 0 {
+        DDP_HEADER_LEN + DDP_TIMECODE_LEN
+    } else {
+        DDP_HEADER_LEN
+    };
+    let data = packet.get(data_start..data_start + len)?;
+    Some(PixelUpdate {
+        // 偏移量以字节为单位
+        start: offset / channels,
+        pixels: to_rgb(data, channels),
+        push: flags & DDP_PUSH != 0,
+        timeout: Some(DEFAULT_TIMEOUT),
+    })
+}
+
+/// 一个universe的DMX数据，`data[0]`为第1个通道
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DmxPacket<'a> {
+    pub universe: u16,
+    pub data: &'a [u8],
+}
+
+/// E1.31每个universe对应的组播地址
+pub fn e131_multicast_addr(universe: u16) -> Ipv4Addr {
+    let [high, low] = universe.to_be_bytes();
+    Ipv4Addr::new(239, 255, high, low)
+}
+
+fn be_u32(data: &[u8]) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(..4)?.try_into().ok()?))
+}
+
+/// 解析E1.31数据包，只接受起始码为0的DMX数据
+pub fn parse_e131(packet: &[u8]) -> Option<DmxPacket<'_>> {
+    if packet.get(4..16)? != E131_PACKET_ID
+        || be_u32(packet.get(18..)?)? != E131_ROOT_VECTOR
+        || be_u32(packet.get(40..)?)? != E131_FRAMING_VECTOR
+        || *packet.get(112)? & (E131_PREVIEW | E131_TERMINATED) != 0
+        || *packet.get(E131_START_CODE)? != 0
+    {
+        return None;
+    }
+    let universe = u16::from_be_bytes([packet[113], packet[114]]);
+    // 属性数量包含起始码
+    let count = u16::from_be_bytes([packet[123], packet[124]]) as usize;
+    let data = packet.get(E131_START_CODE + 1..E131_START_CODE + count.max(1))?;
+    Some(DmxPacket { universe, data })
+}
+
+/// 解析Art-Net的ArtDmx包，其他操作码（如ArtPoll）返回`None`
+pub fn parse_artnet(packet: &[u8]) -> Option<DmxPacket<'_>> {
+    let header = packet.get(..ARTNET_HEADER_LEN)?;
+    if &header[..8] != ARTNET_ID || u16::from_le_bytes([header[8], header[9]]) != ARTNET_OP_DMX {
+        return None;
+    }
+    // 子网、universe和网络号组成15位的端口地址
+    let universe = u16::from_le_bytes([header[14], header[15]]) & 0x7fff;
+    let len = u16::from_be_bytes([header[16], header[17]]) as usize;
+    let data = packet.get(ARTNET_HEADER_LEN..ARTNET_HEADER_LEN + len.min(DMX_CHANNELS))?;
+    Some(DmxPacket { universe, data })
+}
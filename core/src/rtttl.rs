@@ -0,0 +1,117 @@
+//! RTTTL铃声解析
+
+use anyhow::{anyhow, bail, Result};
+use std::time::Duration;
+
+/// 内置旋律，RTTTL格式
+const MELODIES: &[(&str, &str)] = &[
+    ("chime", "chime:d=8,o=6,b=140:c,e,g,4c7"),
+    (
+        "sunrise",
+        "sunrise:d=4,o=5,b=90:8c,8e,8g,c6,8p,8g,8a,8b,2c6",
+    ),
+    (
+        "alarm",
+        "alarm:d=16,o=6,b=200:c,p,c,p,c,p,4p,c,p,c,p,c,p,4p",
+    ),
+];
+
+/// 单个音符，频率为0表示休止
+#[derive(Debug, Clone, Copy)]
+pub struct Note {
+    pub frequency: u32,
+    pub duration: Duration,
+}
+
+/// 根据名称查找内置旋律，包含`:`时按RTTTL字符串处理
+pub fn melody(name: &str) -> Result<Vec<Note>> {
+    if name.contains(':') {
+        return parse_rtttl(name);
+    }
+    let (_, rtttl) = MELODIES
+        .iter()
+        .find(|(melody, _)| *melody == name)
+        .ok_or(anyhow!("Melody `{name}` not found"))?;
+    parse_rtttl(rtttl)
+}
+
+/// 解析RTTTL：`名称:d=4,o=5,b=120:8c6,e,p,4g.`
+pub fn parse_rtttl(rtttl: &str) -> Result<Vec<Note>> {
+    let mut sections = rtttl.splitn(3, ':');
+    let (Some(_), Some(defaults), Some(notes)) =
+        (sections.next(), sections.next(), sections.next())
+    else {
+        bail!("Invalid RTTTL");
+    };
+
+    let (mut duration, mut octave, mut bpm) = (4u32, 6u32, 63u32);
+    for item in defaults.split(',').filter(|item| !item.trim().is_empty()) {
+        let (key, value) = item
+            .trim()
+            .split_once('=')
+            .ok_or(anyhow!("Invalid RTTTL default `{item}`"))?;
+        let value = value.parse::<u32>()?;
+        match key {
+            "d" => duration = value,
+            "o" => octave = value,
+            "b" => bpm = value,
+            _ => bail!("Invalid RTTTL default `{item}`"),
+        }
+    }
+    if duration == 0 || bpm == 0 {
+        bail!("Invalid RTTTL defaults");
+    }
+    // 全音符的时长
+    let whole_ms = 60_000 * 4 / bpm;
+
+    notes
+        .split(',')
+        .map(str::trim)
+        .filter(|note| !note.is_empty())
+        .map(|note| {
+            let note = note.to_ascii_lowercase();
+            let digits = note.chars().take_while(|c| c.is_ascii_digit()).count();
+            let note_duration = if digits > 0 {
+                note[..digits].parse::<u32>()?
+            } else {
+                duration
+            };
+            if note_duration == 0 {
+                bail!("Invalid note `{note}`");
+            }
+            let mut chars = note[digits..].chars().peekable();
+            let semitone = match chars.next() {
+                Some('c') => Some(0),
+                Some('d') => Some(2),
+                Some('e') => Some(4),
+                Some('f') => Some(5),
+                Some('g') => Some(7),
+                Some('a') => Some(9),
+                Some('b') | Some('h') => Some(11),
+                Some('p') => None,
+                _ => bail!("Invalid note `{note}`"),
+            };
+            let sharp = chars.next_if_eq(&'#').is_some();
+            let mut dotted = chars.next_if_eq(&'.').is_some();
+            let note_octave = match chars.next_if(|c| c.is_ascii_digit()) {
+                Some(c) => c.to_digit(10).unwrap_or(octave),
+                None => octave,
+            };
+            dotted |= chars.next_if_eq(&'.').is_some();
+
+            let mut ms = whole_ms / note_duration;
+            if dotted {
+                ms += ms / 2;
+            }
+            let frequency = semitone.map_or(0, |semitone| {
+                // 以A4=440Hz为基准的十二平均律
+                let n = note_octave as i32 * 12 + semitone + sharp as i32 - (4 * 12 + 9);
+                (440.0 * 2f32.powf(n as f32 / 12.0)).round() as u32
+            });
+            Ok(Note {
+                frequency,
+                duration: Duration::from_millis(ms as u64),
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,379 @@
+use crate::{
+    color::{blend_colors, noise1d, ColorSpace},
+    palette::PaletteRef,
+};
+use anyhow::Result;
+use rgb::RGB8;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Solid {
+    pub color: RGB8,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GradientColorItem {
+    pub color: RGB8,
+    pub duration: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Gradient {
+    pub colors: Vec<GradientColorItem>,
+    #[serde(default)]
+    pub linear: bool,
+    /// 线性渐变插值使用的颜色空间
+    #[serde(default)]
+    pub space: ColorSpace,
+}
+
+/// 彩虹效果，色相持续旋转
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Rainbow {
+    /// 色相旋转速度，单位：度/秒
+    pub speed: f32,
+    #[serde(default = "default_one")]
+    pub saturation: f32,
+    #[serde(default = "default_one")]
+    pub brightness: f32,
+}
+
+/// 烛光效果，在基础颜色上随机闪烁
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Candle {
+    pub base_color: RGB8,
+    /// 闪烁强度，取值0-1
+    #[serde(default = "default_candle_intensity")]
+    pub intensity: f32,
+}
+
+/// 流星效果，灯带上移动的亮点拖着逐渐变暗的尾巴
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Meteor {
+    /// 灯带的灯珠数量
+    #[serde(default = "default_meteor_pixels")]
+    pub pixels: u16,
+    /// 尾巴长度，单位：灯珠
+    #[serde(default = "default_meteor_tail")]
+    pub tail: u16,
+    /// 移动速度，单位：灯珠/秒
+    #[serde(default = "default_meteor_speed")]
+    pub speed: f32,
+    /// 反向移动
+    #[serde(default)]
+    pub reverse: bool,
+    /// 每划过一次换下一个颜色
+    #[serde(default)]
+    pub colors: Vec<RGB8>,
+    /// 使用调色板中的颜色，开灯时解析到`colors`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub palette: Option<String>,
+}
+
+fn default_meteor_pixels() -> u16 {
+    30
+}
+
+fn default_meteor_tail() -> u16 {
+    8
+}
+
+fn default_meteor_speed() -> f32 {
+    20.0
+}
+
+/// 火焰效果，灯带一端不断产生热量并向另一端扩散、冷却，热度经调色板映射为颜色
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Fire {
+    /// 灯带的灯珠数量
+    #[serde(default = "default_meteor_pixels")]
+    pub pixels: u16,
+    /// 火焰跳动的速度倍率
+    #[serde(default = "default_one")]
+    pub speed: f32,
+    /// 冷却速度，取值0-1，越大火焰越矮
+    #[serde(default = "default_fire_cooling")]
+    pub cooling: f32,
+    /// 从另一端开始燃烧
+    #[serde(default)]
+    pub reverse: bool,
+    /// 从冷到热的颜色，为空时使用默认的火焰颜色
+    #[serde(default)]
+    pub colors: Vec<RGB8>,
+    /// 使用调色板中的颜色，开灯时解析到`colors`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub palette: Option<String>,
+}
+
+fn default_fire_cooling() -> f32 {
+    0.5
+}
+
+/// 默认的火焰颜色：黑、暗红、橙、黄、白
+const FIRE_COLORS: [RGB8; 5] = [
+    RGB8::new(0, 0, 0),
+    RGB8::new(160, 20, 0),
+    RGB8::new(255, 80, 0),
+    RGB8::new(255, 180, 20),
+    RGB8::new(255, 255, 180),
+];
+
+/// 火焰效果的热度图，每个灯珠的热度为0-1
+#[derive(Debug, Clone)]
+pub struct FireState {
+    heat: Vec<f32>,
+    t: f32,
+}
+
+impl FireState {
+    pub fn new(pixels: usize) -> Self {
+        Self {
+            heat: vec![0.0; pixels.max(1)],
+            t: 0.0,
+        }
+    }
+
+    /// 按经过的时间推进一步：冷却、向上扩散，并用噪声在底部点火
+    pub fn step(&mut self, fire: &Fire, dt: f32) {
+        let dt = dt * fire.speed.max(0.0);
+        self.t += dt;
+        let len = self.heat.len();
+        let cooling = fire.cooling.clamp(0.0, 1.0) * 3.0;
+        for (i, heat) in self.heat.iter_mut().enumerate() {
+            // 离火源越远冷却越快，噪声让火苗高低不一
+            let height = i as f32 / len as f32;
+            let noise = noise1d(i as f32 * 0.7 + self.t * 3.0);
+            *heat = (*heat - cooling * dt * (0.3 + noise) * (0.5 + height)).max(0.0);
+        }
+        // 热量从火源向外扩散
+        let spread = (dt * 20.0).min(1.0);
+        for i in (2..len).rev() {
+            let target = (self.heat[i - 1] + self.heat[i - 2] * 2.0) / 3.0;
+            self.heat[i] += (target - self.heat[i]) * spread;
+        }
+        if len > 1 {
+            let target = (self.heat[0] + self.heat[1]) / 2.0;
+            self.heat[1] += (target - self.heat[1]) * spread;
+        }
+        // 火源附近随机点火
+        let base = (len / 8).max(1);
+        for (i, heat) in self.heat.iter_mut().take(base).enumerate() {
+            let spark = noise1d(self.t * 4.0 + i as f32 * 13.0).powi(2);
+            *heat = (*heat + spark * dt * 8.0).min(1.0);
+        }
+    }
+
+    /// 把热度映射为颜色
+    pub fn frame(&self, fire: &Fire) -> Vec<RGB8> {
+        let colors: &[RGB8] = if fire.colors.is_empty() {
+            &FIRE_COLORS
+        } else {
+            &fire.colors
+        };
+        let mut frame: Vec<RGB8> = self
+            .heat
+            .iter()
+            .map(|&heat| {
+                if colors.len() == 1 {
+                    return blend_colors(RGB8::default(), colors[0], heat, ColorSpace::Rgb);
+                }
+                let position = heat.clamp(0.0, 1.0) * (colors.len() - 1) as f32;
+                let index = (position as usize).min(colors.len() - 2);
+                blend_colors(
+                    colors[index],
+                    colors[index + 1],
+                    position - index as f32,
+                    ColorSpace::Rgb,
+                )
+            })
+            .collect();
+        if fire.reverse {
+            frame.reverse();
+        }
+        frame
+    }
+}
+
+/// 音乐律动效果，需要麦克风：音量控制亮度，低、中、高音的比例决定色相
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Music {
+    /// 灵敏度倍率，越大越容易达到最亮
+    #[serde(default = "default_one")]
+    pub sensitivity: f32,
+    /// 安静时的亮度，取值0-1
+    #[serde(default = "default_music_min_brightness")]
+    pub min_brightness: f32,
+    #[serde(default = "default_one")]
+    pub saturation: f32,
+}
+
+/// 频闪的最高频率，单位：Hz
+pub const MAX_STROBE_FREQUENCY: f32 = 20.0;
+/// 开启光敏保护时的最高频率，低于常见的光敏性癫痫诱发阈值
+pub const SAFE_STROBE_FREQUENCY: f32 = 3.0;
+
+/// 频闪效果，按固定频率亮灭
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Strobe {
+    pub color: RGB8,
+    /// 每秒闪烁的次数，超过上限时按上限闪烁
+    pub frequency: f32,
+    /// 每个周期中点亮的比例，取值0-1
+    #[serde(default = "default_strobe_duty")]
+    pub duty: f32,
+    /// 光敏保护，开启时频率不超过`SAFE_STROBE_FREQUENCY`
+    #[serde(default = "default_true")]
+    pub photosensitive_guard: bool,
+}
+
+impl Strobe {
+    /// 按速度倍率调整后的点亮和熄灭时间，频率限制在允许范围内
+    pub fn periods(&self, speed: f32) -> (Duration, Duration) {
+        let max = if self.photosensitive_guard {
+            SAFE_STROBE_FREQUENCY
+        } else {
+            MAX_STROBE_FREQUENCY
+        };
+        let frequency = (self.frequency * speed).clamp(0.1, max);
+        let period = 1.0 / frequency;
+        let duty = self.duty.clamp(0.05, 0.95);
+        (
+            Duration::from_secs_f32(period * duty),
+            Duration::from_secs_f32(period * (1.0 - duty)),
+        )
+    }
+}
+
+/// 摩尔斯电码SOS求救信号，循环播放
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Sos {
+    pub color: RGB8,
+    /// 一个点的时长，单位：毫秒
+    #[serde(default = "default_sos_unit_ms")]
+    pub unit_ms: u32,
+}
+
+impl Sos {
+    /// 一次SOS的亮灭序列，时长以点的时长为单位：点亮1、划亮3，
+    /// 符号间隔1、字母间隔3、重复前间隔7
+    pub fn pattern() -> Vec<(bool, u32)> {
+        let letter = |on: u32| [(true, on), (false, 1), (true, on), (false, 1), (true, on)];
+        let mut pattern = vec![];
+        for (index, on) in [1, 3, 1].into_iter().enumerate() {
+            if index > 0 {
+                pattern.push((false, 3));
+            }
+            pattern.extend(letter(on));
+        }
+        pattern.push((false, 7));
+        pattern
+    }
+}
+
+fn default_strobe_duty() -> f32 {
+    0.5
+}
+
+fn default_sos_unit_ms() -> u32 {
+    200
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_music_min_brightness() -> f32 {
+    0.05
+}
+
+fn default_candle_intensity() -> f32 {
+    0.5
+}
+
+fn default_one() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Clone)]
+pub struct ColorDuration {
+    pub start_color: RGB8,
+    pub end_color: RGB8,
+    pub duration: Duration,
+}
+
+impl Gradient {
+    pub fn get_color_durations(&self) -> Vec<ColorDuration> {
+        let mut last_color = self.colors.last().unwrap();
+        let color_durations = self
+            .colors
+            .iter()
+            .map(|g| {
+                let color_duration = ColorDuration {
+                    start_color: last_color.color,
+                    end_color: g.color,
+                    duration: Duration::from_secs_f32(g.duration),
+                };
+                last_color = g;
+                color_duration
+            })
+            .collect();
+        color_durations
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum Color {
+    Solid(Solid),
+    Gradient(Gradient),
+    Rainbow(Rainbow),
+    Palette(PaletteRef),
+    Candle(Candle),
+    Music(Music),
+    Meteor(Meteor),
+    Strobe(Strobe),
+    Sos(Sos),
+    Fire(Fire),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Scene {
+    pub name: String,
+    pub auto_on: bool,
+    /// 根据环境光调整效果速度和亮度，需要光线传感器
+    #[serde(default)]
+    pub ambient_aware: bool,
+    #[serde(flatten)]
+    pub color: Color,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self {
+            name: "Default".to_string(),
+            auto_on: false,
+            ambient_aware: false,
+            color: Color::Solid(Solid {
+                color: RGB8::new(255, 255, 255),
+            }),
+        }
+    }
+}
+
+impl Scene {
+    pub fn from_u8(data: &[u8]) -> Result<Self> {
+        Ok(serde_json::from_slice(data)?)
+    }
+
+    pub fn to_u8(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+}
@@ -0,0 +1,34 @@
+use crate::{palette::Palettes, scene::Scene};
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use miniz_oxide::{deflate::compress_to_vec, inflate::decompress_to_vec_with_limit};
+
+/// 分享字符串的前缀，包含格式版本
+const PREFIX: &str = "sb1.";
+/// 解压后场景数据的最大长度
+const MAX_SCENE_SIZE: usize = 8 * 1024;
+
+/// 将场景编码为URL安全的字符串，供App生成分享二维码
+///
+/// 格式为`sb1.`加上压缩后JSON的base64url编码。引用的调色板会先解析为渐变，
+/// 接收方不需要有同名调色板。
+pub fn encode(scene: &Scene, palettes: &Palettes) -> Result<String> {
+    let scene = Scene {
+        color: scene.color.resolve(palettes)?,
+        ..scene.clone()
+    };
+    let data = compress_to_vec(&scene.to_u8()?, 10);
+    Ok(format!("{PREFIX}{}", URL_SAFE_NO_PAD.encode(data)))
+}
+
+/// 解析分享字符串
+pub fn decode(value: &str) -> Result<Scene> {
+    let data = value
+        .trim()
+        .strip_prefix(PREFIX)
+        .ok_or(anyhow!("Unsupported share string"))?;
+    let data = URL_SAFE_NO_PAD.decode(data)?;
+    let data = decompress_to_vec_with_limit(&data, MAX_SCENE_SIZE)
+        .map_err(|e| anyhow!("Decompress failed: {:?}", e.status))?;
+    Scene::from_u8(&data)
+}
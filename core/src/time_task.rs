@@ -0,0 +1,315 @@
+//! 定时任务的重复规则和下一次执行时间的计算
+//!
+//! 固件负责等待和执行，这里只根据时钟和时区算出还要等待多久，测试中可以使用手动调整的时钟。
+
+use crate::{cron::Schedule, hal::Clock, timezone};
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+
+/// 超过执行时间太久（如同步时间导致时间跳变）则视为错过，不再执行
+pub const MISSED_TOLERANCE_SECS: i64 = 60;
+
+/// 获取延迟执行时间
+pub trait GetDelta {
+    fn get_delta(&self, clock: &impl Clock) -> Result<TimeDelta>;
+}
+
+/// 跳过例外日期，step为任务的重复周期
+fn skip_except(
+    mut time: DateTime<FixedOffset>,
+    step: TimeDelta,
+    except: &[NaiveDate],
+) -> Result<DateTime<FixedOffset>> {
+    for _ in 0..=except.len() {
+        if !except.contains(&time.date_naive()) {
+            return Ok(time);
+        }
+        time += step;
+    }
+    bail!("All dates are excepted")
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum TimeFrequency {
+    Once(OnceTask),
+    Day(DayTask),
+    Week(WeekTask),
+    Dates(DatesTask),
+    Cron(CronTask),
+    Countdown(CountdownTask),
+}
+
+impl GetDelta for TimeFrequency {
+    fn get_delta(&self, clock: &impl Clock) -> Result<TimeDelta> {
+        match self {
+            TimeFrequency::Once(task) => task.get_delta(clock),
+            TimeFrequency::Day(task) => task.get_delta(clock),
+            TimeFrequency::Week(task) => task.get_delta(clock),
+            TimeFrequency::Dates(task) => task.get_delta(clock),
+            TimeFrequency::Cron(task) => task.get_delta(clock),
+            TimeFrequency::Countdown(task) => task.get_delta(clock),
+        }
+    }
+}
+
+impl TimeFrequency {
+    /// 按与执行时相同的规则计算下一次执行时间，已过期的一次性任务和倒计时返回None
+    pub fn next_fire(&self, clock: &impl Clock) -> Option<DateTime<Utc>> {
+        let delta = self.get_delta(clock).ok()?;
+        (delta >= TimeDelta::zero()).then(|| clock.now() + delta)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OnceTask {
+    pub end_time: DateTime<Utc>,
+    /// 断电期间错过执行时间时，启动后立即补执行，否则标记为过期
+    #[serde(default)]
+    pub catch_up: bool,
+}
+
+impl GetDelta for OnceTask {
+    fn get_delta(&self, clock: &impl Clock) -> Result<TimeDelta> {
+        let now = clock.now();
+        Ok(self.end_time.signed_duration_since(now))
+    }
+}
+
+impl OnceTask {
+    /// 是否在断电期间错过了执行时间
+    pub fn missed(&self, clock: &impl Clock) -> Result<bool> {
+        Ok(self.get_delta(clock)? < -TimeDelta::seconds(MISSED_TOLERANCE_SECS))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DayTask {
+    pub delay: DateTime<Utc>,
+    /// 不执行的日期，如节假日
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub except: Vec<NaiveDate>,
+}
+
+impl GetDelta for DayTask {
+    fn get_delta(&self, clock: &impl Clock) -> Result<TimeDelta> {
+        // delay中的时间按本地时间处理
+        let now = clock.now().with_timezone(&timezone::offset());
+        let time = now
+            .with_time(self.delay.time())
+            .single()
+            .ok_or(anyhow!("Invalid time"))?;
+
+        let time = if time > now {
+            time
+        } else {
+            time + TimeDelta::days(1)
+        };
+        let time = skip_except(time, TimeDelta::days(1), &self.except)?;
+        Ok(time.signed_duration_since(now))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WeekTask {
+    pub day_of_week: u32,
+    pub delay: DateTime<Utc>,
+    /// 不执行的日期，如节假日
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub except: Vec<NaiveDate>,
+}
+
+impl GetDelta for WeekTask {
+    fn get_delta(&self, clock: &impl Clock) -> Result<TimeDelta> {
+        let now = clock.now().with_timezone(&timezone::offset());
+        let weekday = now.weekday().number_from_monday();
+        let days_until_target = (self.day_of_week + 7 - weekday) % 7;
+        let time = now
+            .with_time(self.delay.time())
+            .single()
+            .ok_or(anyhow!("Invalid time"))?
+            + TimeDelta::days(days_until_target as i64);
+
+        let time = if time > now {
+            time
+        } else {
+            time + TimeDelta::days(7)
+        };
+        let time = skip_except(time, TimeDelta::days(7), &self.except)?;
+        Ok(time.signed_duration_since(now))
+    }
+}
+
+/// 在指定的几个日期执行，所有日期都过去后移除
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatesTask {
+    pub dates: Vec<NaiveDate>,
+    pub delay: DateTime<Utc>,
+}
+
+impl DatesTask {
+    /// 下一次执行时间，所有日期都已过去时返回None
+    pub fn next(&self, clock: &impl Clock) -> Result<Option<DateTime<FixedOffset>>> {
+        self.next_after(clock.now().with_timezone(&timezone::offset()))
+    }
+
+    fn next_after(&self, now: DateTime<FixedOffset>) -> Result<Option<DateTime<FixedOffset>>> {
+        let mut next = None;
+        for date in &self.dates {
+            let time = date
+                .and_time(self.delay.time())
+                .and_local_timezone(timezone::offset())
+                .single()
+                .ok_or(anyhow!("Invalid time"))?;
+            if time > now && next.map_or(true, |next| time < next) {
+                next = Some(time);
+            }
+        }
+        Ok(next)
+    }
+}
+
+impl GetDelta for DatesTask {
+    fn get_delta(&self, clock: &impl Clock) -> Result<TimeDelta> {
+        let now = clock.now().with_timezone(&timezone::offset());
+        let next = self.next_after(now)?.ok_or(anyhow!("No remaining dates"))?;
+        Ok(next.signed_duration_since(now))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CronTask {
+    pub schedule: Schedule,
+}
+
+impl GetDelta for CronTask {
+    fn get_delta(&self, clock: &impl Clock) -> Result<TimeDelta> {
+        let now = clock.now().with_timezone(&timezone::offset());
+        Ok(self.schedule.next_after(now)?.signed_duration_since(now))
+    }
+}
+
+/// 倒计时任务，到期后执行一次
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CountdownTask {
+    pub end_time: DateTime<Utc>,
+}
+
+impl GetDelta for CountdownTask {
+    fn get_delta(&self, clock: &impl Clock) -> Result<TimeDelta> {
+        let now = clock.now();
+        Ok(self.end_time.signed_duration_since(now))
+    }
+}
+
+impl CountdownTask {
+    pub fn new(seconds: u32, clock: &impl Clock) -> Self {
+        Self {
+            end_time: clock.now() + TimeDelta::seconds(seconds as i64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::ManualClock;
+
+    // 未设置时区，本地时间与UTC相同
+    fn at(time: &str) -> DateTime<Utc> {
+        time.parse().unwrap()
+    }
+
+    fn date(date: &str) -> NaiveDate {
+        date.parse().unwrap()
+    }
+
+    #[test]
+    fn day_task_skips_except() {
+        // 2024-01-01为周一
+        let clock = ManualClock::new(at("2024-01-01T10:00:00Z"));
+        let mut task = DayTask {
+            delay: at("2000-01-01T08:00:00Z"),
+            except: vec![],
+        };
+        assert_eq!(task.get_delta(&clock).unwrap(), TimeDelta::hours(22));
+        task.except.push(date("2024-01-02"));
+        assert_eq!(task.get_delta(&clock).unwrap(), TimeDelta::hours(46));
+        clock.advance(TimeDelta::hours(-3));
+        assert_eq!(task.get_delta(&clock).unwrap(), TimeDelta::hours(1));
+    }
+
+    #[test]
+    fn week_task_waits_for_weekday() {
+        let clock = ManualClock::new(at("2024-01-01T10:00:00Z"));
+        let task = WeekTask {
+            day_of_week: 3,
+            delay: at("2000-01-01T09:00:00Z"),
+            except: vec![],
+        };
+        assert_eq!(task.get_delta(&clock).unwrap(), TimeDelta::hours(47));
+        // 当天已经过了执行时间，等到下一周
+        clock.advance(TimeDelta::days(2));
+        assert_eq!(
+            task.get_delta(&clock).unwrap(),
+            TimeDelta::days(7) - TimeDelta::hours(1)
+        );
+    }
+
+    #[test]
+    fn dates_task_ends_after_last_date() {
+        let clock = ManualClock::new(at("2024-01-01T10:00:00Z"));
+        let frequency = TimeFrequency::Dates(DatesTask {
+            dates: vec![date("2024-01-03"), date("2024-01-01")],
+            delay: at("2000-01-01T08:00:00Z"),
+        });
+        assert_eq!(
+            frequency.next_fire(&clock),
+            Some(at("2024-01-03T08:00:00Z"))
+        );
+        clock.advance(TimeDelta::days(2));
+        assert!(frequency.get_delta(&clock).is_err());
+        assert_eq!(frequency.next_fire(&clock), None);
+    }
+
+    #[test]
+    fn cron_task_uses_clock() {
+        let clock = ManualClock::new(at("2024-06-07T23:30:00Z"));
+        let task = CronTask {
+            schedule: "0 */2 * * 0,6".parse().unwrap(),
+        };
+        assert_eq!(task.get_delta(&clock).unwrap(), TimeDelta::minutes(30));
+    }
+
+    #[test]
+    fn once_task_missed_after_tolerance() {
+        let clock = ManualClock::new(at("2024-01-01T10:00:00Z"));
+        let task = OnceTask {
+            end_time: at("2024-01-01T10:05:00Z"),
+            catch_up: false,
+        };
+        let frequency = TimeFrequency::Once(task.clone());
+        assert!(!task.missed(&clock).unwrap());
+        assert_eq!(frequency.next_fire(&clock), Some(task.end_time));
+        clock.advance(TimeDelta::minutes(6));
+        assert!(!task.missed(&clock).unwrap());
+        assert_eq!(frequency.next_fire(&clock), None);
+        clock.advance(TimeDelta::minutes(1));
+        assert!(task.missed(&clock).unwrap());
+    }
+
+    #[test]
+    fn countdown_counts_down() {
+        let clock = ManualClock::new(at("2024-01-01T10:00:00Z"));
+        let task = CountdownTask::new(90, &clock);
+        clock.advance(TimeDelta::seconds(30));
+        assert_eq!(task.get_delta(&clock).unwrap(), TimeDelta::seconds(60));
+    }
+}
@@ -0,0 +1,22 @@
+use chrono::FixedOffset;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+/// 时区偏移的取值范围，单位：分钟
+pub const MIN_OFFSET: i32 = -12 * 60;
+pub const MAX_OFFSET: i32 = 14 * 60;
+
+/// 当前时区相对UTC的偏移，单位：分钟
+static OFFSET_MINUTES: AtomicI32 = AtomicI32::new(0);
+
+pub fn set_offset_minutes(minutes: i32) {
+    OFFSET_MINUTES.store(minutes.clamp(MIN_OFFSET, MAX_OFFSET), Ordering::Relaxed);
+}
+
+pub fn offset_minutes() -> i32 {
+    OFFSET_MINUTES.load(Ordering::Relaxed)
+}
+
+/// 获取当前时区，定时任务按该时区的本地时间计算
+pub fn offset() -> FixedOffset {
+    FixedOffset::east_opt(offset_minutes() * 60).unwrap_or(FixedOffset::east_opt(0).unwrap())
+}
@@ -0,0 +1,172 @@
+use crate::{
+    ble::BleControl,
+    executor::Executor,
+    report::{self, ErrorCode, Module},
+    store::AdvertisingConfig,
+};
+use esp32_nimble::{utilities::mutex::Mutex, BLEAdvertising, BLEDevice};
+use esp_idf_svc::{
+    sys::{esp, esp_ble_power_type_t_ESP_BLE_PWR_TYPE_ADV, esp_ble_tx_power_set},
+    timer::EspTaskTimerService,
+};
+use futures::task::SpawnExt;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// 无连接持续时间与对应的广播间隔，时间越长间隔越大，
+/// 第一级使用配置中的快速广播间隔
+const STAGES: [(Duration, u32); 4] = [
+    (Duration::ZERO, 0),
+    (Duration::from_secs(60), 500),
+    (Duration::from_secs(5 * 60), 1000),
+    (Duration::from_secs(30 * 60), 2000),
+];
+/// 最后一级改为间歇广播：每个周期只广播一段时间
+const BURST_PERIOD: Duration = Duration::from_secs(60);
+const BURST_ON: Duration = Duration::from_secs(10);
+/// 检查间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 广播间隔单位为0.625毫秒
+fn interval_units(ms: u32) -> u16 {
+    (ms * 1000 / 625).min(u16::MAX as u32) as u16
+}
+
+/// 设置广播间隔，正在广播时重新启动使其生效
+pub fn set_interval(advertising: &Mutex<BLEAdvertising>, ms: u32) -> anyhow::Result<()> {
+    let mut advertising = advertising.lock();
+    let running = advertising.is_advertising();
+    if running {
+        advertising.stop()?;
+    }
+    // 最大间隔略大于最小间隔，便于协议栈调度
+    advertising
+        .min_interval(interval_units(ms))
+        .max_interval(interval_units(ms + ms / 4));
+    if running {
+        advertising.start()?;
+    }
+    Ok(())
+}
+
+/// 设置广播发射功率，ESP32-C3的功率级别从-24dBm开始，步进3dBm
+fn set_tx_power(dbm: i8) -> anyhow::Result<()> {
+    let level = ((dbm as i32 + 24) / 3) as _;
+    esp!(unsafe { esp_ble_tx_power_set(esp_ble_power_type_t_ESP_BLE_PWR_TYPE_ADV, level) })?;
+    Ok(())
+}
+
+/// 空闲时逐步降低广播频率以节省功耗，有连接或按下按键时恢复快速广播
+#[derive(Clone)]
+pub struct Backoff {
+    config: Arc<Mutex<AdvertisingConfig>>,
+    idle_since: Arc<Mutex<Instant>>,
+    /// 当前所处的级别
+    stage: Arc<Mutex<usize>>,
+}
+
+impl Backoff {
+    pub fn new(config: Arc<Mutex<AdvertisingConfig>>) -> Self {
+        Self {
+            config,
+            idle_since: Arc::new(Mutex::new(Instant::now())),
+            stage: Arc::new(Mutex::new(0)),
+        }
+    }
+
+    /// 某一级的广播间隔，不小于配置的快速广播间隔
+    fn interval(&self, stage: usize) -> u32 {
+        STAGES[stage].1.max(self.config.lock().interval_ms)
+    }
+
+    /// 应用当前配置并从快速广播重新开始，启动或修改配置时调用
+    pub fn apply(&self, advertising: &Mutex<BLEAdvertising>) -> anyhow::Result<()> {
+        self.reset();
+        *self.stage.lock() = 0;
+        set_tx_power(self.config.lock().tx_power)?;
+        set_interval(advertising, self.interval(0))?;
+        self.start_if_allowed(advertising)
+    }
+
+    /// 重新开始计时，连接、断开或按下按键时调用
+    pub fn reset(&self) {
+        *self.idle_since.lock() = Instant::now();
+    }
+
+    /// 立即恢复快速广播
+    pub fn wake(&self, advertising: &Mutex<BLEAdvertising>) -> anyhow::Result<()> {
+        self.reset();
+        let stage = std::mem::take(&mut *self.stage.lock());
+        if stage == 0 {
+            return Ok(());
+        }
+        set_interval(advertising, self.interval(0))?;
+        self.start_if_allowed(advertising)
+    }
+
+    fn update(&self, advertising: &Mutex<BLEAdvertising>) -> anyhow::Result<()> {
+        if BLEDevice::take().get_server().connected_count() > 0 {
+            self.reset();
+        }
+        let idle = self.idle_since.lock().elapsed();
+        let stage = STAGES
+            .iter()
+            .rposition(|(after, _)| idle >= *after)
+            .unwrap_or(0);
+        let previous = std::mem::replace(&mut *self.stage.lock(), stage);
+        if stage != previous {
+            log::debug!("advertising interval {}ms", self.interval(stage));
+            set_interval(advertising, self.interval(stage))?;
+            if stage < STAGES.len() - 1 {
+                self.start_if_allowed(advertising)?;
+            }
+        }
+        if stage == STAGES.len() - 1 {
+            let since = idle.saturating_sub(STAGES[stage].0);
+            let on = since.as_secs() % BURST_PERIOD.as_secs() < BURST_ON.as_secs();
+            let running = advertising.lock().is_advertising();
+            if on && !running {
+                self.start_if_allowed(advertising)?;
+            } else if !on && running {
+                advertising.lock().stop()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 连接数未满时才广播，配置为连接后不广播时有连接即停止广播
+    pub fn start_if_allowed(&self, advertising: &Mutex<BLEAdvertising>) -> anyhow::Result<()> {
+        let connected = BLEDevice::take().get_server().connected_count();
+        let allowed = connected < esp_idf_svc::sys::CONFIG_BT_NIMBLE_MAX_CONNECTIONS as _
+            && (connected == 0 || self.config.lock().advertise_when_connected);
+        let mut advertising = advertising.lock();
+        if allowed && !advertising.is_advertising() {
+            advertising.start()?;
+        } else if !allowed && advertising.is_advertising() {
+            advertising.stop()?;
+        }
+        Ok(())
+    }
+}
+
+/// 启动广播退避任务
+pub fn start_backoff(ble_control: BleControl, pool: &Executor) -> anyhow::Result<()> {
+    let mut async_timer = EspTaskTimerService::new()?.timer_async()?;
+    pool.spawn(async move {
+        while async_timer.after(CHECK_INTERVAL).await.is_ok() {
+            if let Err(e) = ble_control
+                .advertising_backoff
+                .update(ble_control.advertising)
+            {
+                report::error(
+                    Module::Advertising,
+                    ErrorCode::Internal,
+                    format!("advertising backoff error: {e}"),
+                );
+            }
+        }
+    })?;
+    Ok(())
+}
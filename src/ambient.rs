@@ -0,0 +1,28 @@
+use crate::event_bus::{self, SensorEvent};
+use esp32_nimble::utilities::mutex::Mutex;
+use std::sync::Arc;
+
+/// 环境光亮度，取值0-1，没有光线传感器时为None
+#[derive(Clone, Default)]
+pub struct Ambient {
+    level: Arc<Mutex<Option<f32>>>,
+}
+
+impl Ambient {
+    pub fn level(&self) -> Option<f32> {
+        *self.level.lock()
+    }
+
+    /// 更新亮度，使用指数平滑避免效果随读数抖动
+    pub fn update(&self, level: f32) {
+        let level = level.clamp(0.0, 1.0);
+        let mut current = self.level.lock();
+        let level = match *current {
+            Some(current) => current * 0.8 + level * 0.2,
+            None => level,
+        };
+        *current = Some(level);
+        drop(current);
+        event_bus::publish(SensorEvent::Ambient(level));
+    }
+}
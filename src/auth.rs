@@ -0,0 +1,84 @@
+use crate::store::NvsStore;
+use anyhow::{anyhow, bail, Result};
+use esp32_nimble::utilities::mutex::Mutex;
+use std::sync::Arc;
+
+/// 令牌长度
+pub const TOKEN_LEN: usize = 16;
+
+/// 应用层鉴权令牌，用于无法使用BLE绑定的设备
+///
+/// 首次配对时客户端写入令牌并保存到NVS，之后写入控制、场景、定时任务以及
+/// 调色板、按键映射、名称等配置特征时数据前需要带上令牌。恢复出厂设置后清除令牌，需要重新配对。
+#[derive(Clone)]
+pub struct Auth {
+    token: Arc<Mutex<Option<[u8; TOKEN_LEN]>>>,
+    nvs_store: NvsStore,
+}
+
+/// 解析十六进制的令牌，用于HTTP请求头和控制器配置
+pub fn parse_token(hex: &str) -> Result<[u8; TOKEN_LEN]> {
+    if hex.len() != TOKEN_LEN * 2 || !hex.is_ascii() {
+        bail!("Auth token must be {} hex digits", TOKEN_LEN * 2);
+    }
+    let mut bytes = [0u8; TOKEN_LEN];
+    for (index, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16)
+            .map_err(|_| anyhow!("Invalid auth token"))?;
+    }
+    Ok(bytes)
+}
+
+/// 比较时间与内容无关，避免通过响应时间猜测令牌
+fn token_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+impl Auth {
+    pub fn new(nvs_store: NvsStore) -> Result<Self> {
+        let token = nvs_store.read_auth_token()?;
+        Ok(Self {
+            token: Arc::new(Mutex::new(token)),
+            nvs_store,
+        })
+    }
+
+    pub fn paired(&self) -> bool {
+        self.token.lock().is_some()
+    }
+
+    /// 校验并去掉写入数据前的令牌，未配对时不需要令牌
+    pub fn strip<'a>(&self, data: &'a [u8]) -> Option<&'a [u8]> {
+        match &*self.token.lock() {
+            Some(token) => {
+                if data.len() < TOKEN_LEN || !token_eq(&data[..TOKEN_LEN], token) {
+                    return None;
+                }
+                Some(&data[TOKEN_LEN..])
+            }
+            None => Some(data),
+        }
+    }
+
+    /// 校验HTTP请求带的十六进制令牌，未配对时不需要令牌
+    pub fn verify(&self, hex: Option<&str>) -> bool {
+        match &*self.token.lock() {
+            Some(token) => hex
+                .and_then(|hex| parse_token(hex).ok())
+                .is_some_and(|value| token_eq(&value, token)),
+            None => true,
+        }
+    }
+
+    /// 配对，未配对时直接写入新令牌，已配对时需要在新令牌前带上当前令牌
+    pub fn pair(&self, data: &[u8]) -> Result<()> {
+        let new_token = self
+            .strip(data)
+            .ok_or(anyhow!("invalid auth token"))?
+            .try_into()
+            .map_err(|_| anyhow!("auth token must be {TOKEN_LEN} bytes"))?;
+        self.nvs_store.write_auth_token(&new_token)?;
+        *self.token.lock() = Some(new_token);
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+use crate::{
+    ble::BleControl,
+    event_bus::{self, SensorEvent},
+    light::LightEventSender,
+    light::LightState,
+    notify,
+};
+use esp32_nimble::{
+    utilities::{mutex::Mutex, BleUuid},
+    BLECharacteristic, BLEServer, NimbleProperties,
+};
+use std::{sync::Arc, time::Duration};
+
+/// 标准电池服务 (Battery Service)
+const BATTERY_SERVICE: u16 = 0x180F;
+const BATTERY_LEVEL: u16 = 0x2A19;
+
+/// 采样间隔
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// 锂电池电压（毫伏）与电量的对应关系，之间线性插值
+const VOLTAGE_CURVE: [(u32, u8); 10] = [
+    (3300, 0),
+    (3400, 5),
+    (3500, 10),
+    (3600, 20),
+    (3700, 35),
+    (3800, 50),
+    (3900, 65),
+    (4000, 80),
+    (4100, 90),
+    (4200, 100),
+];
+
+/// 根据电池电压估算电量百分比
+pub fn percent_from_millivolts(millivolts: u32) -> u8 {
+    let (first, last) = (VOLTAGE_CURVE[0], VOLTAGE_CURVE[VOLTAGE_CURVE.len() - 1]);
+    if millivolts <= first.0 {
+        return first.1;
+    }
+    if millivolts >= last.0 {
+        return last.1;
+    }
+    let index = VOLTAGE_CURVE
+        .iter()
+        .position(|(voltage, _)| *voltage > millivolts)
+        .unwrap_or(VOLTAGE_CURVE.len() - 1);
+    let (from, to) = (VOLTAGE_CURVE[index - 1], VOLTAGE_CURVE[index]);
+    let t = (millivolts - from.0) as f32 / (to.0 - from.0) as f32;
+    (from.1 as f32 + (to.1 as f32 - from.1 as f32) * t).round() as u8
+}
+
+/// 电池电量，没有电池监测时为None
+#[derive(Clone, Default)]
+pub struct Battery {
+    percent: Arc<Mutex<Option<u8>>>,
+}
+
+impl Battery {
+    pub fn percent(&self) -> Option<u8> {
+        *self.percent.lock()
+    }
+
+    pub fn update(&self, percent: u8) {
+        let percent = percent.min(100);
+        *self.percent.lock() = Some(percent);
+        event_bus::publish(SensorEvent::Battery(percent));
+    }
+}
+
+/// 创建标准电池服务，客户端和系统设置都能直接显示电量
+pub fn create_battery_service(server: &mut BLEServer) -> Arc<Mutex<BLECharacteristic>> {
+    let service = server.create_service(BleUuid::from_uuid16(BATTERY_SERVICE));
+    let characteristic = service.lock().create_characteristic(
+        BleUuid::from_uuid16(BATTERY_LEVEL),
+        NimbleProperties::READ | NimbleProperties::NOTIFY,
+    );
+    characteristic.lock().set_value(&[100]);
+    notify::track(&characteristic, true);
+    characteristic
+}
+
+/// 电量变化后更新电池服务，电量过低时关灯
+#[cfg(feature = "battery")]
+fn on_update(
+    percent: u8,
+    ble_control: &BleControl,
+    light_sender: &mut LightEventSender,
+) -> anyhow::Result<()> {
+    ble_control.set_battery_level(percent);
+    let config = ble_control.nvs_store.battery.lock().clone();
+    if config.is_critical(percent) && !matches!(ble_control.get_state(), LightState::Closed) {
+        log::warn!("battery critical ({percent}%), turning off");
+        light_sender.close()?;
+    }
+    Ok(())
+}
+
+/// 电池经1:1分压后接GPIO3
+#[cfg(feature = "battery")]
+pub fn start_adc(
+    adc: esp_idf_svc::hal::adc::ADC1,
+    pin: esp_idf_svc::hal::gpio::Gpio3,
+    battery: Battery,
+    ble_control: BleControl,
+    mut light_sender: LightEventSender,
+) -> anyhow::Result<()> {
+    use crate::report::{self, ErrorCode, Module};
+    use esp_idf_svc::hal::adc::{
+        attenuation::DB_11,
+        oneshot::{config::AdcChannelConfig, AdcChannelDriver, AdcDriver},
+    };
+
+    /// 每次采样取多次读数的平均值
+    const READS: u32 = 16;
+    /// 分压比
+    const DIVIDER: u32 = 2;
+
+    std::thread::spawn(move || -> anyhow::Result<()> {
+        let adc = AdcDriver::new(adc)?;
+        // 开启校准后读数为毫伏
+        let config = AdcChannelConfig {
+            attenuation: DB_11,
+            calibration: true,
+            ..Default::default()
+        };
+        let mut channel = AdcChannelDriver::new(&adc, pin, &config)?;
+        loop {
+            let mut total = 0u32;
+            for _ in 0..READS {
+                total += adc.read(&mut channel)? as u32;
+            }
+            let percent = percent_from_millivolts(total / READS * DIVIDER);
+            battery.update(percent);
+            if let Err(e) = on_update(percent, &ble_control, &mut light_sender) {
+                report::error(Module::Battery, ErrorCode::Internal, e);
+            }
+            std::thread::sleep(SAMPLE_INTERVAL);
+        }
+    });
+    Ok(())
+}
@@ -1,35 +1,245 @@
 use crate::{
+    advertising::{self, Backoff},
+    auth::Auth,
+    controller,
+    device_info::{create_device_info_service, serial_number},
+    diagnostics,
+    event_bus::{self, ConnectionEvent, DataChange},
+    executor::Executor,
+    indicator::{BleStatus, Indicator},
+    ir::Ir,
     light::{LightEvent, LightEventSender, LightState},
-    store::{time_task::TimeTask, NvsStore, Scene},
+    log_buffer::{self, LogCommand},
+    notify, power,
+    report::{self, ErrorCode, Module},
+    session,
+    store::{
+        ir::IrAction,
+        palette::validate_palettes,
+        share,
+        time_task::{NextFire, TimeTask},
+        timezone, AdaptiveConfig, AdvertisingConfig, BackupCommand, BatteryConfig, BoardConfig,
+        BrightnessCurve, ButtonConfig, CalibrationConfig, ControllerCommand, ControllerConfig,
+        DemoConfig, DeviceSettings, DmxConfig, Favorites, IndicatorConfig, IrConfig,
+        LogLevelConfig, MotionConfig, MqttConfig, NvsStore, Palettes, PowerConfig, Scene,
+        SyncConfig, ThermalConfig, WifiConfig, MAX_NAME_LEN,
+    },
+    sync::{self, Sync},
+    thermal::{self, ThermalStatus},
     timer::{TimerEvent, TimerEventSender},
     transmission::Transmission,
+    usage, watchdog,
 };
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use esp32_nimble::{
-    utilities::mutex::Mutex, uuid128, BLEAdvertisementData, BLEDevice, NimbleProperties,
+    utilities::mutex::Mutex, uuid128, BLEAdvertisementData, BLEAdvertising, BLEDevice,
+    DescriptorProperties, NimbleProperties,
 };
-use futures::executor::ThreadPool;
+use esp_idf_svc::{hal::reset::restart, timer::EspTaskTimerService};
+use futures::task::SpawnExt;
+#[cfg(feature = "dev")]
+use rgb::RGB8;
+use serde::Serialize;
 use std::{sync::Arc, time::Duration};
 
 #[derive(Clone)]
 pub struct BleControl {
     pub nvs_store: NvsStore,
+    /// HTTP等其他写入途径使用同一个令牌
+    pub auth: Auth,
     pub scene_transmission: Transmission,
     pub control_characteristic: Arc<Mutex<esp32_nimble::BLECharacteristic>>,
     pub state_characteristic: Arc<Mutex<esp32_nimble::BLECharacteristic>>,
+    pub state: Arc<Mutex<LightState>>,
+    pub brightness: Arc<Mutex<u8>>,
     pub time_task_transmission: Transmission,
+    /// 各任务的下一次执行时间，与任务列表分开，避免覆盖任务列表
+    pub next_fire_transmission: Transmission,
+    pub indicator_transmission: Transmission,
+    pub palette_transmission: Transmission,
+    pub demo_transmission: Transmission,
+    pub wifi_transmission: Transmission,
+    pub scenes_transmission: Transmission,
+    pub favorites_transmission: Transmission,
+    pub button_transmission: Transmission,
+    pub brightness_curve_transmission: Transmission,
+    pub sync_transmission: Transmission,
+    /// 通过BLE控制其他灯，写入`"scan"`后读取附近的灯
+    pub controller_transmission: Transmission,
+    pub dmx_transmission: Transmission,
+    pub mqtt_transmission: Transmission,
+    pub adaptive_transmission: Transmission,
+    pub motion_transmission: Transmission,
+    pub ir_transmission: Transmission,
+    pub calibration_transmission: Transmission,
+    pub battery_transmission: Transmission,
+    pub power_transmission: Transmission,
+    pub thermal_transmission: Transmission,
+    pub board_transmission: Transmission,
+    pub advertising_transmission: Transmission,
+    pub settings_transmission: Transmission,
+    /// 最近的运行日志
+    pub log_transmission: Transmission,
+    pub log_level_transmission: Transmission,
+    /// 整机配置的导出和导入
+    pub backup_transmission: Transmission,
+    /// 标准电池服务的电量特征，没有电池监测时为None
+    pub battery_level_characteristic: Option<Arc<Mutex<esp32_nimble::BLECharacteristic>>>,
+    pub advertising: &'static Mutex<BLEAdvertising>,
+    /// 空闲时的广播退避
+    pub advertising_backoff: Backoff,
+    /// 撤销删除的定时任务时使用
+    pub timer_sender: TimerEventSender,
+}
+
+/// 状态特征的数据格式版本，通过描述符告知客户端
+/// 版本1为`opened`/`closed`字符串，版本2为JSON
+pub const STATE_PROTOCOL_VERSION: u8 = 2;
+
+/// 状态特征的数据，客户端一次读取即可获得设备状态
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StatePayload<'a> {
+    state: &'a str,
+    scene: &'a str,
+    /// 演示模式下当前展示的效果
+    #[serde(skip_serializing_if = "Option::is_none")]
+    effect: Option<&'a str>,
+    /// 亮度百分比
+    brightness: u8,
+    /// 开机时长，单位：秒
+    uptime: u64,
+    /// 定时任务是否已暂停
+    tasks_paused: bool,
+    /// 芯片温度，单位：摄氏度
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    /// 过热保护状态
+    thermal: ThermalStatus,
+    /// 儿童锁是否开启
+    child_lock: bool,
+}
+
+fn state_payload(state: &LightState, nvs_store: &NvsStore, brightness: u8) -> Result<Vec<u8>> {
+    let scene = nvs_store.scene.lock().name.clone();
+    let uptime = unsafe { esp_idf_svc::sys::esp_timer_get_time() } as u64 / 1_000_000;
+    Ok(serde_json::to_vec(&StatePayload {
+        state: state.name(),
+        scene: &scene,
+        effect: match state {
+            LightState::Demo(name) => Some(name),
+            _ => None,
+        },
+        brightness,
+        uptime,
+        tasks_paused: *nvs_store.tasks_paused.lock(),
+        temperature: thermal::celsius(),
+        thermal: thermal::status(),
+        child_lock: *nvs_store.child_lock.lock(),
+    })?)
+}
+
+/// 发送状态通知，状态变化频繁，传输进行中只保留最新状态
+fn schedule_state(
+    characteristic: &Arc<Mutex<esp32_nimble::BLECharacteristic>>,
+    state: &LightState,
+    nvs_store: &NvsStore,
+    brightness: u8,
+) {
+    match state_payload(state, nvs_store, brightness) {
+        Ok(value) => {
+            event_bus::publish(DataChange {
+                topic: "state",
+                data: value.clone(),
+            });
+            let characteristic = characteristic.clone();
+            notify::schedule("state", move || {
+                notify::send(&characteristic, &value);
+            });
+        }
+        Err(e) => report::error(Module::Ble, ErrorCode::Internal, e),
+    }
+}
+
+/// `child_lock:on`开启儿童锁，`child_lock:off`关闭
+fn child_lock_opcode(data: &[u8]) -> Option<bool> {
+    match data {
+        b"child_lock:on" => Some(true),
+        b"child_lock:off" => Some(false),
+        _ => None,
+    }
+}
+
+/// 等待组内其他灯确认的时间
+const GROUP_ACK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// 设置广播数据，名称放在扫描响应中以免超出广播包长度
+fn set_advertisement(advertising: &Mutex<BLEAdvertising>, name: &str) -> Result<()> {
+    BLEDevice::set_device_name(name)?;
+    let mut advertising = advertising.lock();
+    advertising.set_data(
+        BLEAdvertisementData::new()
+            .add_service_uuid(uuid128!("e572775c-0df9-4b44-926b-b692e31d6971")),
+    )?;
+    advertising.scan_response_data(BLEAdvertisementData::new().name(name))?;
+    Ok(())
+}
+
+/// Wi-Fi配置对外可读的部分，不包含密码
+fn wifi_public_value(wifi: &WifiConfig) -> Result<Vec<u8>> {
+    Ok(serde_json::to_vec(&WifiConfig {
+        ssid: wifi.ssid.clone(),
+        password: String::new(),
+    })?)
+}
+
+/// MQTT配置对外可读的部分，不包含密码
+fn mqtt_public_value(config: &MqttConfig) -> Result<Vec<u8>> {
+    Ok(serde_json::to_vec(&MqttConfig {
+        password: String::new(),
+        ..config.clone()
+    })?)
+}
+
+/// 控制器配置对外可读的部分，不包含其他灯的令牌
+fn controller_public_value(config: &ControllerConfig) -> Result<Vec<u8>> {
+    let mut config = config.clone();
+    for lamp in config.lamps.iter_mut() {
+        lamp.token = None;
+    }
+    Ok(serde_json::to_vec(&config)?)
+}
+
+/// 同步组配置对外可读的部分，附带本机MAC地址
+fn sync_public_value(config: &SyncConfig) -> Result<Vec<u8>> {
+    #[derive(Serialize)]
+    struct SyncValue<'a> {
+        #[serde(flatten)]
+        config: &'a SyncConfig,
+        mac: String,
+    }
+    Ok(serde_json::to_vec(&SyncValue {
+        config,
+        mac: sync::local_mac(),
+    })?)
 }
 
 impl BleControl {
     pub fn new(
         nvs_store: NvsStore,
         light_sender: LightEventSender,
-        mut time_sender: TimerEventSender,
-        pool: ThreadPool,
+        time_sender: TimerEventSender,
+        indicator: Indicator,
+        sync: Sync,
+        ir: Ir,
+        pool: Executor,
     ) -> Result<Self> {
         // 获取BLE设备实例
         let device = BLEDevice::take();
 
+        // 根据MAC地址生成序列号
+        let serial = serial_number(device)?;
+
         // 获取并配置BLE的广告实例
         let advertising = device.get_advertising();
 
@@ -37,39 +247,100 @@ impl BleControl {
         let server = device.get_server();
 
         // 配置BLE连接时的回调函数
-        server.on_connect(|server, desc| {
-            #[cfg(debug_assertions)]
-            log::info!("on_connect: {:#?}", desc);
+        let advertising_backoff = Backoff::new(nvs_store.advertising.clone());
+        let indicator_clone = indicator.clone();
+        let backoff = advertising_backoff.clone();
+        server.on_connect(move |server, desc| {
+            log::debug!("on_connect: {:#?}", desc);
 
+            backoff.reset();
             server
                 .update_conn_params(desc.conn_handle(), 24, 48, 0, 60)
                 .unwrap();
-            if server.connected_count() < (esp_idf_svc::sys::CONFIG_BT_NIMBLE_MAX_CONNECTIONS as _)
-            {
-                advertising.lock().start().unwrap();
+            if let Err(e) = backoff.start_if_allowed(advertising) {
+                report::error(
+                    Module::Advertising,
+                    ErrorCode::Internal,
+                    format!("restart advertising error: {e}"),
+                );
             }
+            indicator_clone.set_status(BleStatus::Connected);
+            event_bus::publish(ConnectionEvent::Connected(desc.conn_handle()));
         });
 
         // 配置BLE断开连接时的回调函数
-        server.on_disconnect(|desc, reason| {
-            #[cfg(debug_assertions)]
-            log::warn!("on_disconnect: {:#?}, reason: {:#?}", desc, reason)
+        let indicator_clone = indicator.clone();
+        let backoff = advertising_backoff.clone();
+        server.on_disconnect(move |desc, reason| {
+            log::debug!("on_disconnect: {:#?}, reason: {:#?}", desc, reason);
+
+            session::disconnect(desc.conn_handle());
+            // 断开后客户端可能马上重连，恢复快速广播
+            if let Err(e) = backoff.wake(advertising) {
+                report::error(
+                    Module::Advertising,
+                    ErrorCode::Internal,
+                    format!("restore advertising error: {e}"),
+                );
+            }
+            indicator_clone.set_status(BleStatus::Advertising);
+            event_bus::publish(ConnectionEvent::Disconnected(desc.conn_handle()));
         });
 
+        // 配对完成后显示连接状态
+        let indicator_clone = indicator.clone();
+        server.on_authentication_complete(move |desc, result| {
+            log::debug!("on_authentication_complete: {:#?}, {:?}", desc, result);
+
+            indicator_clone.set_status(BleStatus::Connected);
+        });
+
+        // 设备信息服务
+        create_device_info_service(server, serial);
+
+        // 电池服务
+        #[cfg(feature = "battery")]
+        let battery_level_characteristic = Some(crate::battery::create_battery_service(server));
+        #[cfg(not(feature = "battery"))]
+        let battery_level_characteristic = None;
+
         // 创建BLE服务
         let service = server.create_service(uuid128!("e572775c-0df9-4b44-926b-b692e31d6971"));
 
+        // 应用层鉴权，场景、控制和定时任务特征的写入需要带上令牌
+        let auth = Auth::new(nvs_store.clone())?;
+        let auth_characteristic = service.lock().create_characteristic(
+            uuid128!("8e3b5d1f-6a2c-4f97-b4e8-1c7a9d2f5e63"),
+            NimbleProperties::READ | NimbleProperties::WRITE,
+        );
+        let auth_clone = auth.clone();
+        let auth_clone2 = auth.clone();
+        auth_characteristic
+            .lock()
+            .on_read(move |attr, _| {
+                // 只返回是否已配对
+                attr.set_value(&[auth_clone.paired() as u8]);
+            })
+            .on_write(move |args| {
+                if let Err(e) = auth_clone2.pair(args.recv_data()) {
+                    report::error(Module::Ble, ErrorCode::InvalidData, e);
+                    args.reject();
+                }
+            });
+
         // 场景服务
         let scene_transmission = Transmission::new(
             service.clone(),
             uuid128!("c7d7ee2f-c84b-4f5c-a2a4-e642c97a880d"),
             pool.clone(),
-        );
+        )
+        .with_auth(auth.clone())
+        .with_topic("scene")
+        .with_child_lock(nvs_store.child_lock.clone());
         let nvs_store_clone = nvs_store.clone();
         scene_transmission.init(Some(move |data: Vec<u8>, transmission: &Transmission| {
             let data = serde_json::from_slice::<Scene>(&data)?;
-            *nvs_store_clone.scene.lock() = data;
-            nvs_store_clone.write_scene()?;
+            nvs_store_clone.set_scene(data)?;
             transmission.notify_update();
             Ok(())
         }));
@@ -79,30 +350,92 @@ impl BleControl {
             NimbleProperties::WRITE,
         );
 
-        let light = light_sender.clone();
-        control_characteristic.lock().on_write(move |args| {
-            let data = args.recv_data();
-            let control = LightEvent::from(data);
-
-            if light.event_tx.send(control).is_err() {
-                args.reject();
-                #[cfg(debug_assertions)]
-                log::error!("control error");
-            }
-        });
-
         let state_characteristic = service.lock().create_characteristic(
             uuid128!("e192efae-9626-4767-8a27-b96cb9753e10"),
             NimbleProperties::NOTIFY | NimbleProperties::READ,
         );
+        let state = Arc::new(Mutex::new(LightState::Closed));
+        let brightness = Arc::new(Mutex::new(100u8));
+        let state_clone = state.clone();
+        let brightness_clone = brightness.clone();
+        let nvs_store_clone = nvs_store.clone();
+        // 订阅后立即发送当前状态
+        notify::track(&state_characteristic, true);
         state_characteristic
             .lock()
-            .on_subscribe(|characteristic, desc, _| {
-                #[cfg(debug_assertions)]
-                log::info!("on_subscribe: {:#?}", desc);
-                characteristic.notify();
+            .on_read(move |attr, _| {
+                // 读取时刷新开机时长
+                let state = state_clone.lock().clone();
+                match state_payload(&state, &nvs_store_clone, *brightness_clone.lock()) {
+                    Ok(value) => {
+                        attr.set_value(&value);
+                    }
+                    Err(e) => report::error(Module::Ble, ErrorCode::Internal, e),
+                }
             })
             .create_2904_descriptor();
+        state_characteristic
+            .lock()
+            .create_descriptor(
+                uuid128!("0b7d3e95-4c2a-4f16-8e9b-7a5d1c3f6e28"),
+                DescriptorProperties::READ,
+            )
+            .lock()
+            .set_value(&[STATE_PROTOCOL_VERSION]);
+
+        // 开关儿童锁后需要通知状态，在状态特征创建之后注册
+        let light = light_sender.clone();
+        let auth_clone = auth.clone();
+        let nvs_store_clone = nvs_store.clone();
+        let state_characteristic_clone = state_characteristic.clone();
+        let state_clone = state.clone();
+        let brightness_clone = brightness.clone();
+        control_characteristic.lock().on_write(move |args| {
+            let Some(data) = auth_clone.strip(args.recv_data()) else {
+                log::warn!("reject unauthenticated control");
+                args.reject();
+                return;
+            };
+            if let Some(locked) = child_lock_opcode(data) {
+                // 未配对时任何人都能解锁，儿童锁没有意义
+                if !auth_clone.paired() {
+                    log::warn!("child lock requires pairing");
+                    args.reject();
+                    return;
+                }
+                if let Err(e) = nvs_store_clone.set_child_lock(locked) {
+                    report::error(Module::Ble, ErrorCode::Storage, e);
+                    args.reject();
+                    return;
+                }
+                log::info!("child lock {}", if locked { "enabled" } else { "disabled" });
+                schedule_state(
+                    &state_characteristic_clone,
+                    &state_clone.lock().clone(),
+                    &nvs_store_clone,
+                    *brightness_clone.lock(),
+                );
+                return;
+            }
+            if *nvs_store_clone.child_lock.lock() {
+                log::warn!("reject control, child lock enabled");
+                args.reject();
+                return;
+            }
+            let control = match LightEvent::try_from(data) {
+                Ok(control) => control,
+                Err(e) => {
+                    report::error(Module::Ble, ErrorCode::InvalidData, e);
+                    args.reject();
+                    return;
+                }
+            };
+
+            if light.send(control).is_err() {
+                args.reject();
+                log::debug!("control error");
+            }
+        });
 
         // 同步时间特征
         let time_characteristic = service.lock().create_characteristic(
@@ -123,12 +456,82 @@ impl BleControl {
                     )
                 }
                 let now = chrono::Utc::now().to_rfc3339();
-                #[cfg(debug_assertions)]
-                log::warn!("set time {now}");
+                log::debug!("set time {now}");
             } else {
                 args.reject();
-                #[cfg(debug_assertions)]
-                log::error!("time error");
+                log::debug!("time error");
+            }
+        });
+
+        // 设备设置特征，名称和时区立即生效，其余在下次开灯或重启后生效
+        let settings_transmission = Transmission::new(
+            service.clone(),
+            uuid128!("9a3f6d2e-1c84-4b57-a0e9-5f7b2d8c4e61"),
+            pool.clone(),
+        )
+        .with_auth(auth.clone());
+        let nvs_store_clone = nvs_store.clone();
+        let mut timer_sender = time_sender.clone();
+        settings_transmission.init(Some(move |data: Vec<u8>, transmission: &Transmission| {
+            let data = serde_json::from_slice::<DeviceSettings>(&data)?;
+            data.validate()?;
+            let old = std::mem::replace(&mut *nvs_store_clone.settings.lock(), data.clone());
+            nvs_store_clone.write_settings()?;
+            if data.name != old.name {
+                set_advertisement(advertising, &data.name)?;
+            }
+            if data.timezone_minutes != old.timezone_minutes {
+                timezone::set_offset_minutes(data.timezone_minutes);
+                timer_sender.reload()?;
+            }
+            transmission.notify_update();
+            Ok(())
+        }));
+
+        // 时区特征，值为相对UTC的偏移分钟数(i32)
+        let timezone_characteristic = service.lock().create_characteristic(
+            uuid128!("2c6e9f41-5b8d-4a7e-9f3c-1d0b8e6a4c75"),
+            NimbleProperties::READ | NimbleProperties::WRITE,
+        );
+        timezone_characteristic
+            .lock()
+            .set_value(&timezone::offset_minutes().to_ne_bytes());
+        let nvs_store_clone = nvs_store.clone();
+        let mut timer_sender = time_sender.clone();
+        let settings_clone = settings_transmission.clone();
+        let auth_clone = auth.clone();
+        timezone_characteristic.lock().on_write(move |args| {
+            let Some(data) = auth_clone.strip(args.recv_data()) else {
+                log::warn!("reject unauthenticated timezone");
+                args.reject();
+                return;
+            };
+            if *nvs_store_clone.child_lock.lock() {
+                log::warn!("reject timezone, child lock enabled");
+                args.reject();
+                return;
+            }
+            let minutes = match <[u8; 4]>::try_from(data) {
+                Ok(bytes) => i32::from_ne_bytes(bytes),
+                Err(_) => {
+                    args.reject();
+                    return;
+                }
+            };
+            if !(timezone::MIN_OFFSET..=timezone::MAX_OFFSET).contains(&minutes) {
+                args.reject();
+                log::debug!("timezone error");
+                return;
+            }
+            if let Err(e) = nvs_store_clone
+                .write_timezone(minutes)
+                .and_then(|_| timer_sender.reload())
+                .and_then(|_| {
+                    settings_clone.set_value(serde_json::to_vec(&*nvs_store_clone.settings.lock())?)
+                })
+            {
+                args.reject();
+                report::error(Module::Store, ErrorCode::Storage, e);
             }
         });
 
@@ -136,40 +539,844 @@ impl BleControl {
         let time_task_transmission = Transmission::new(
             service.clone(),
             uuid128!("f144af69-9642-97e1-d712-9448d1b450a1"),
-            pool,
-        );
+            pool.clone(),
+        )
+        .with_topic("tasks")
+        .with_auth(auth.clone())
+        .with_child_lock(nvs_store.child_lock.clone());
+        let mut timer_sender = time_sender.clone();
+        let nvs_store_clone = nvs_store.clone();
         time_task_transmission.init(Some(move |data: Vec<u8>, _: &Transmission| {
             let event = serde_json::from_slice::<TimerEvent>(&data)?;
             log::warn!("time task event: {:?}", event);
-            time_sender.event_tx.try_send(event)?;
+            // 先校验，错误详情通过传输通知直接返回给客户端
+            match &event {
+                TimerEvent::AddTask(task) => {
+                    task.check(&nvs_store_clone.time_task.lock(), false)?
+                }
+                TimerEvent::UpdateTask(task) => {
+                    task.check(&nvs_store_clone.time_task.lock(), true)?
+                }
+                _ => {}
+            }
+            timer_sender.send(event)?;
             Ok(())
         }));
 
-        // 配置广告数据并启动广告
-        advertising.lock().set_data(
-            BLEAdvertisementData::new()
-                .name("ESP32")
-                .add_service_uuid(uuid128!("e572775c-0df9-4b44-926b-b692e31d6971")),
-        )?;
+        // 下一次执行时间特征，写入任意数据后重新计算，之后读取各任务的下一次执行时间
+        let next_fire_transmission = Transmission::new(
+            service.clone(),
+            uuid128!("8c2e5f17-4a9b-4d3e-b6c0-2f7a9d1e5b84"),
+            pool.clone(),
+        );
+        let mut timer_sender = time_sender.clone();
+        next_fire_transmission.init(Some(move |_: Vec<u8>, _: &Transmission| {
+            timer_sender.send(TimerEvent::NextFire)?;
+            Ok(())
+        }));
+
+        // 状态指示灯配置服务
+        let indicator_transmission = Transmission::new(
+            service.clone(),
+            uuid128!("3d1f6a52-8b7e-4c2a-9e0d-5f4b7a1c9e63"),
+            pool.clone(),
+        );
+        let nvs_store_clone = nvs_store.clone();
+        indicator_transmission.init(Some(move |data: Vec<u8>, transmission: &Transmission| {
+            let data = serde_json::from_slice::<IndicatorConfig>(&data)?;
+            *nvs_store_clone.indicator.lock() = data;
+            nvs_store_clone.write_indicator()?;
+            indicator.refresh()?;
+            transmission.notify_update();
+            Ok(())
+        }));
 
-        advertising.lock().start()?;
+        // 调色板服务，场景可通过名称引用调色板
+        let palette_transmission = Transmission::new(
+            service.clone(),
+            uuid128!("8e4c2b7a-1d6f-4a93-b5e8-0c7f3a9d2e41"),
+            pool.clone(),
+        )
+        .with_auth(auth.clone())
+        .with_child_lock(nvs_store.child_lock.clone());
+        let nvs_store_clone = nvs_store.clone();
+        palette_transmission.init(Some(move |data: Vec<u8>, transmission: &Transmission| {
+            let data = serde_json::from_slice::<Palettes>(&data)?;
+            validate_palettes(&data)?;
+            *nvs_store_clone.palettes.lock() = data;
+            nvs_store_clone.write_palettes()?;
+            transmission.notify_update();
+            Ok(())
+        }));
+
+        // 演示模式配置服务
+        let demo_transmission = Transmission::new(
+            service.clone(),
+            uuid128!("a7f3d2c9-6e1b-4b58-8d4a-3c9e7f0b5a12"),
+            pool.clone(),
+        );
+        let nvs_store_clone = nvs_store.clone();
+        let mut light = light_sender.clone();
+        demo_transmission.init(Some(move |data: Vec<u8>, transmission: &Transmission| {
+            let data = serde_json::from_slice::<DemoConfig>(&data)?;
+            let enabled = data.enabled;
+            *nvs_store_clone.demo.lock() = data;
+            nvs_store_clone.write_demo()?;
+            transmission.notify_update();
+            if enabled {
+                light.demo()?;
+            }
+            Ok(())
+        }));
+
+        // Wi-Fi配置服务
+        let wifi_transmission = Transmission::new(
+            service.clone(),
+            uuid128!("d4b8e2f6-3a7c-4e91-8b5d-6f2a0c9e1d38"),
+            pool.clone(),
+        )
+        .with_auth(auth.clone())
+        .with_child_lock(nvs_store.child_lock.clone());
+        let nvs_store_clone = nvs_store.clone();
+        wifi_transmission.init(Some(move |data: Vec<u8>, transmission: &Transmission| {
+            let data = serde_json::from_slice::<WifiConfig>(&data)?;
+            *nvs_store_clone.wifi.lock() = data;
+            nvs_store_clone.write_wifi()?;
+            // 读取时不返回密码
+            transmission.set_value(wifi_public_value(&nvs_store_clone.wifi.lock())?)?;
+            Ok(())
+        }));
+
+        // 开发者模式：原始帧特征，数据为连续的RGB字节，直接显示而不经过场景
+        #[cfg(feature = "dev")]
+        {
+            let frame_transmission = Transmission::new(
+                service.clone(),
+                uuid128!("6f1a9c3e-2d7b-4e85-a0c4-9b3e5d7f1a26"),
+                pool.clone(),
+            );
+            let mut light = light_sender.clone();
+            frame_transmission.init(Some(move |data: Vec<u8>, _: &Transmission| {
+                let pixels = data
+                    .chunks_exact(3)
+                    .map(|rgb| RGB8::new(rgb[0], rgb[1], rgb[2]))
+                    .collect();
+                light.send(LightEvent::Frame(pixels))?;
+                Ok(())
+            }));
+        }
+
+        // 场景库服务
+        let scenes_transmission = Transmission::new(
+            service.clone(),
+            uuid128!("b93e6d1f-4a28-4c7b-9e05-8d2f6a1c3b74"),
+            pool.clone(),
+        )
+        .with_auth(auth.clone())
+        .with_child_lock(nvs_store.child_lock.clone());
+        let nvs_store_clone = nvs_store.clone();
+        scenes_transmission.init(Some(move |data: Vec<u8>, transmission: &Transmission| {
+            let data = serde_json::from_slice::<Vec<Scene>>(&data)?;
+            *nvs_store_clone.scenes.lock() = data;
+            nvs_store_clone.write_scenes()?;
+            transmission.notify_update();
+            Ok(())
+        }));
+
+        // 收藏场景服务，绑定按钮双击和三击
+        let favorites_transmission = Transmission::new(
+            service.clone(),
+            uuid128!("e1c5a9f3-7b2d-4e68-a4f0-2b9d6c8e5a13"),
+            pool.clone(),
+        )
+        .with_auth(auth.clone());
+        let nvs_store_clone = nvs_store.clone();
+        favorites_transmission.init(Some(move |data: Vec<u8>, transmission: &Transmission| {
+            let data = serde_json::from_slice::<Favorites>(&data)?;
+            *nvs_store_clone.favorites.lock() = data;
+            nvs_store_clone.write_favorites()?;
+            transmission.notify_update();
+            Ok(())
+        }));
+
+        // 按钮操作映射服务，单击、双击、三击和长按可以绑定不同操作
+        let button_transmission = Transmission::new(
+            service.clone(),
+            uuid128!("c4a7e2d9-5b1f-4c83-9e6a-0d8b3f7a2c54"),
+            pool.clone(),
+        )
+        .with_auth(auth.clone());
+        let nvs_store_clone = nvs_store.clone();
+        button_transmission.init(Some(move |data: Vec<u8>, transmission: &Transmission| {
+            let data = serde_json::from_slice::<ButtonConfig>(&data)?;
+            data.validate()?;
+            *nvs_store_clone.button.lock() = data;
+            nvs_store_clone.write_button()?;
+            transmission.notify_update();
+            Ok(())
+        }));
+
+        // 默认亮度曲线服务
+        let brightness_curve_transmission = Transmission::new(
+            service.clone(),
+            uuid128!("6f2a9d47-1c8e-4b3a-b5d2-8e0c7a4f9b61"),
+            pool.clone(),
+        );
+        let nvs_store_clone = nvs_store.clone();
+        brightness_curve_transmission.init(Some(
+            move |data: Vec<u8>, transmission: &Transmission| {
+                let data = serde_json::from_slice::<BrightnessCurve>(&data)?;
+                data.validate()?;
+                *nvs_store_clone.brightness_curve.lock() = data;
+                nvs_store_clone.write_brightness_curve()?;
+                transmission.notify_update();
+                Ok(())
+            },
+        ));
+
+        // 同步组配置服务，读取时附带本机MAC供其他灯添加，写入会改变所属的组，需要鉴权
+        let sync_transmission = Transmission::new(
+            service.clone(),
+            uuid128!("4a8e2c6f-9d1b-4e73-a5c0-7f3b1d9e2a84"),
+            pool.clone(),
+        )
+        .with_auth(auth.clone());
+        let nvs_store_clone = nvs_store.clone();
+        let sync_clone = sync.clone();
+        sync_transmission.init(Some(move |data: Vec<u8>, transmission: &Transmission| {
+            let data = serde_json::from_slice::<SyncConfig>(&data)?;
+            data.peer_addrs()?;
+            *nvs_store_clone.sync.lock() = data;
+            nvs_store_clone.write_sync()?;
+            sync_clone.reload()?;
+            transmission.notify_update();
+            Ok(())
+        }));
+
+        // 控制器配置服务，包含其他灯的令牌，写入需要鉴权
+        let controller_transmission = Transmission::new(
+            service.clone(),
+            uuid128!("4f7c2a9e-8b3d-4e15-a6f0-2d9c5e1b7a84"),
+            pool.clone(),
+        )
+        .with_auth(auth.clone());
+        let nvs_store_clone = nvs_store.clone();
+        controller_transmission.init(Some(move |data: Vec<u8>, transmission: &Transmission| {
+            match serde_json::from_slice::<ControllerCommand>(&data)? {
+                ControllerCommand::Scan => {
+                    let transmission = transmission.clone();
+                    transmission.pool.clone().spawn(async move {
+                        let result = controller::scan()
+                            .await
+                            .and_then(|lamps| Ok(serde_json::to_vec(&lamps)?))
+                            .and_then(|value| transmission.set_value(value));
+                        if let Err(e) = result {
+                            report::error(
+                                Module::Controller,
+                                ErrorCode::Hardware,
+                                format!("scan error: {e}"),
+                            );
+                        }
+                    })?;
+                }
+                ControllerCommand::Config(config) => {
+                    config.validate()?;
+                    *nvs_store_clone.controller.lock() = config;
+                    nvs_store_clone.write_controller()?;
+                    // 写入的数据包含令牌，替换为不含令牌的部分
+                    transmission
+                        .set_value(controller_public_value(&nvs_store_clone.controller.lock())?)?;
+                }
+            }
+            Ok(())
+        }));
+
+        // E1.31和Art-Net输入配置服务，开启和修改universe后重启生效
+        let dmx_transmission = Transmission::new(
+            service.clone(),
+            uuid128!("8e3b5f1a-6c2d-4a97-b0e4-1f7d9c3a5e62"),
+            pool.clone(),
+        );
+        let nvs_store_clone = nvs_store.clone();
+        dmx_transmission.init(Some(move |data: Vec<u8>, transmission: &Transmission| {
+            let data = serde_json::from_slice::<DmxConfig>(&data)?;
+            data.validate()?;
+            *nvs_store_clone.dmx.lock() = data;
+            nvs_store_clone.write_dmx()?;
+            transmission.notify_update();
+            Ok(())
+        }));
+
+        // MQTT遥测配置服务，包含服务器密码，写入需要鉴权，修改后重启生效
+        let mqtt_transmission = Transmission::new(
+            service.clone(),
+            uuid128!("2b7e4c9a-5f1d-4386-a9c2-7e0d3b6f8a15"),
+            pool.clone(),
+        )
+        .with_auth(auth.clone());
+        let nvs_store_clone = nvs_store.clone();
+        mqtt_transmission.init(Some(move |data: Vec<u8>, transmission: &Transmission| {
+            let data = serde_json::from_slice::<MqttConfig>(&data)?;
+            data.validate()?;
+            *nvs_store_clone.mqtt.lock() = data;
+            nvs_store_clone.write_mqtt()?;
+            // 读取时不返回密码
+            transmission.set_value(mqtt_public_value(&nvs_store_clone.mqtt.lock())?)?;
+            Ok(())
+        }));
+
+        // 自适应亮度配置服务
+        let adaptive_transmission = Transmission::new(
+            service.clone(),
+            uuid128!("c3e7a1d5-4f2b-4a96-8b1e-5d9c2f7a3e40"),
+            pool.clone(),
+        );
+        let nvs_store_clone = nvs_store.clone();
+        adaptive_transmission.init(Some(move |data: Vec<u8>, transmission: &Transmission| {
+            let data = serde_json::from_slice::<AdaptiveConfig>(&data)?;
+            data.validate()?;
+            *nvs_store_clone.adaptive.lock() = data;
+            nvs_store_clone.write_adaptive()?;
+            transmission.notify_update();
+            Ok(())
+        }));
+
+        // 人体感应配置服务
+        let motion_transmission = Transmission::new(
+            service.clone(),
+            uuid128!("5e2a9c47-1b3d-4f86-a0c5-7d4e8b2f6a19"),
+            pool.clone(),
+        );
+        let nvs_store_clone = nvs_store.clone();
+        motion_transmission.init(Some(move |data: Vec<u8>, transmission: &Transmission| {
+            let data = serde_json::from_slice::<MotionConfig>(&data)?;
+            data.validate()?;
+            *nvs_store_clone.motion.lock() = data;
+            nvs_store_clone.write_motion()?;
+            transmission.notify_update();
+            Ok(())
+        }));
+
+        // 红外遥控按键绑定服务
+        let ir_transmission = Transmission::new(
+            service.clone(),
+            uuid128!("a4c81f36-9d2e-4b70-8e5a-3f6b1d9c2e84"),
+            pool.clone(),
+        );
+        let nvs_store_clone = nvs_store.clone();
+        ir_transmission.init(Some(move |data: Vec<u8>, transmission: &Transmission| {
+            let data = serde_json::from_slice::<IrConfig>(&data)?;
+            *nvs_store_clone.ir.lock() = data;
+            nvs_store_clone.write_ir()?;
+            transmission.notify_update();
+            Ok(())
+        }));
+
+        // 颜色校准服务，修改后重新开灯使纯色场景也能立即生效
+        let calibration_transmission = Transmission::new(
+            service.clone(),
+            uuid128!("9b4f2d71-3e8a-4c65-b1d7-6a2e9f5c3b80"),
+            pool.clone(),
+        );
+        let nvs_store_clone = nvs_store.clone();
+        let mut light = light_sender.clone();
+        let state_clone = state.clone();
+        calibration_transmission.init(Some(move |data: Vec<u8>, transmission: &Transmission| {
+            let data = serde_json::from_slice::<CalibrationConfig>(&data)?;
+            data.validate()?;
+            *nvs_store_clone.calibration.lock() = data;
+            nvs_store_clone.write_calibration()?;
+            if matches!(*state_clone.lock(), LightState::Opened) {
+                light.open()?;
+            }
+            transmission.notify_update();
+            Ok(())
+        }));
+
+        // 低电量行为配置服务
+        let battery_transmission = Transmission::new(
+            service.clone(),
+            uuid128!("e6a3c8f2-5d1b-4e97-9c04-2b7f8d3a6e15"),
+            pool.clone(),
+        );
+        let nvs_store_clone = nvs_store.clone();
+        battery_transmission.init(Some(move |data: Vec<u8>, transmission: &Transmission| {
+            let data = serde_json::from_slice::<BatteryConfig>(&data)?;
+            data.validate()?;
+            *nvs_store_clone.battery.lock() = data;
+            nvs_store_clone.write_battery()?;
+            transmission.notify_update();
+            Ok(())
+        }));
+
+        // 省电配置服务
+        let power_transmission = Transmission::new(
+            service.clone(),
+            uuid128!("a2d6f8c4-3e1b-4a97-8c5d-0f9b7e3a1d62"),
+            pool.clone(),
+        );
+        let nvs_store_clone = nvs_store.clone();
+        power_transmission.init(Some(move |data: Vec<u8>, transmission: &Transmission| {
+            let data = serde_json::from_slice::<PowerConfig>(&data)?;
+            data.validate()?;
+            *nvs_store_clone.power.lock() = data;
+            nvs_store_clone.write_power()?;
+            transmission.notify_update();
+            Ok(())
+        }));
+
+        // 过热保护配置服务
+        let thermal_transmission = Transmission::new(
+            service.clone(),
+            uuid128!("c4e8a1f6-2b9d-4f53-8e07-6d3a9b5c1f24"),
+            pool.clone(),
+        );
+        let nvs_store_clone = nvs_store.clone();
+        thermal_transmission.init(Some(move |data: Vec<u8>, transmission: &Transmission| {
+            let data = serde_json::from_slice::<ThermalConfig>(&data)?;
+            data.validate()?;
+            *nvs_store_clone.thermal.lock() = data;
+            nvs_store_clone.write_thermal()?;
+            transmission.notify_update();
+            Ok(())
+        }));
+
+        // 开发板引脚配置服务，修改后重启生效
+        let board_transmission = Transmission::new(
+            service.clone(),
+            uuid128!("5d9a3f7e-2c4b-4e81-9a6d-b1f8e0c3a745"),
+            pool.clone(),
+        )
+        .with_auth(auth.clone())
+        .with_child_lock(nvs_store.child_lock.clone());
+        let nvs_store_clone = nvs_store.clone();
+        board_transmission.init(Some(move |data: Vec<u8>, transmission: &Transmission| {
+            let data = serde_json::from_slice::<BoardConfig>(&data)?;
+            data.validate()?;
+            *nvs_store_clone.board.lock() = data;
+            nvs_store_clone.write_board()?;
+            transmission.notify_update();
+            Ok(())
+        }));
+
+        // 广播配置特征
+        let advertising_transmission = Transmission::new(
+            service.clone(),
+            uuid128!("6b1e9c3a-4d7f-4a25-8e60-c2f5a9d3b718"),
+            pool.clone(),
+        );
+        let nvs_store_clone = nvs_store.clone();
+        let backoff = advertising_backoff.clone();
+        advertising_transmission.init(Some(move |data: Vec<u8>, transmission: &Transmission| {
+            let data = serde_json::from_slice::<AdvertisingConfig>(&data)?;
+            data.validate()?;
+            *nvs_store_clone.advertising.lock() = data;
+            nvs_store_clone.write_advertising()?;
+            // 立即按新配置广播
+            backoff.apply(advertising)?;
+            transmission.notify_update();
+            Ok(())
+        }));
+
+        // 日志服务，写入`refresh`后读取最近的日志，写入`clear`清空日志
+        let log_transmission = Transmission::new(
+            service.clone(),
+            uuid128!("1e9d4b7a-3c62-4f8e-a1b5-7d0c2e9f4a36"),
+            pool.clone(),
+        );
+        let nvs_store_clone = nvs_store.clone();
+        log_transmission.init(Some(move |data: Vec<u8>, transmission: &Transmission| {
+            match serde_json::from_slice::<LogCommand>(&data)? {
+                LogCommand::Refresh => {}
+                LogCommand::Clear => log_buffer::clear(&nvs_store_clone)?,
+            }
+            transmission.set_value(log_buffer::snapshot())?;
+            Ok(())
+        }));
+
+        // 日志级别特征，按模块标签调整日志级别，release固件也能输出调试日志
+        let log_level_transmission = Transmission::new(
+            service.clone(),
+            uuid128!("e6c1f8a2-4b3d-4d79-9a05-7f2e1b8c6d43"),
+            pool.clone(),
+        )
+        .with_auth(auth.clone());
+        let nvs_store_clone = nvs_store.clone();
+        log_level_transmission.init(Some(move |data: Vec<u8>, transmission: &Transmission| {
+            let data = serde_json::from_slice::<LogLevelConfig>(&data)?;
+            data.validate()?;
+            let old = std::mem::replace(&mut *nvs_store_clone.log_levels.lock(), data.clone());
+            log_buffer::apply_levels(&old, &data)?;
+            nvs_store_clone.write_log_levels()?;
+            transmission.notify_update();
+            Ok(())
+        }));
+
+        // 备份服务，写入`export`后读取整机配置，写入`{"import": 备份}`导入后重启
+        let backup_transmission = Transmission::new(
+            service.clone(),
+            uuid128!("3d8b5f1a-9e27-4c64-b0a3-6e1f9c2d7b58"),
+            pool.clone(),
+        )
+        .with_auth(auth.clone());
+        let nvs_store_clone = nvs_store.clone();
+        backup_transmission.init(Some(move |data: Vec<u8>, transmission: &Transmission| {
+            match serde_json::from_slice::<BackupCommand>(&data)? {
+                BackupCommand::Export => {
+                    transmission.set_value(serde_json::to_vec(&nvs_store_clone.export())?)?;
+                }
+                BackupCommand::Import(backup) => {
+                    nvs_store_clone.import(*backup)?;
+                    log::warn!("config imported, restart");
+                    // 留出时间回复客户端
+                    std::thread::spawn(|| {
+                        std::thread::sleep(Duration::from_secs(1));
+                        restart();
+                    });
+                }
+            }
+            Ok(())
+        }));
+
+        // 红外学习特征，写入操作后按下遥控按键即可绑定
+        let ir_learn_characteristic = service.lock().create_characteristic(
+            uuid128!("d7e3b9a1-6c4f-4e28-b5d0-1a8f2c7e9b63"),
+            NimbleProperties::WRITE,
+        );
+        ir_learn_characteristic.lock().on_write(move |args| {
+            match serde_json::from_slice::<IrAction>(args.recv_data()) {
+                Ok(action) => ir.learn(action),
+                Err(e) => {
+                    args.reject();
+                    report::error(Module::Ir, ErrorCode::InvalidData, e);
+                }
+            }
+        });
+
+        // 场景分享特征，读取得到当前场景的分享字符串，写入分享字符串导入场景
+        let share_characteristic = service.lock().create_characteristic(
+            uuid128!("8d3b6e1f-2a9c-4f57-b0e4-6c1a9f3d7e25"),
+            NimbleProperties::READ | NimbleProperties::WRITE,
+        );
+        let nvs_store_clone = nvs_store.clone();
+        share_characteristic.lock().on_read(move |attr, _| {
+            let scene = nvs_store_clone.scene.lock().clone();
+            match share::encode(&scene, &nvs_store_clone.palettes.lock()) {
+                Ok(value) => {
+                    attr.set_value(value.as_bytes());
+                }
+                Err(e) => report::error(Module::Ble, ErrorCode::Internal, e),
+            }
+        });
+        let nvs_store_clone = nvs_store.clone();
+        let scene_transmission_clone = scene_transmission.clone();
+        let pool_clone = pool.clone();
+        let auth_clone = auth.clone();
+        share_characteristic.lock().on_write(move |args| {
+            // 导入会覆盖当前场景，与场景特征一样需要令牌并受儿童锁限制
+            let Some(data) = auth_clone.strip(args.recv_data()) else {
+                log::warn!("reject unauthenticated scene import");
+                args.reject();
+                return;
+            };
+            if *nvs_store_clone.child_lock.lock() {
+                log::warn!("reject scene import, child lock enabled");
+                args.reject();
+                return;
+            }
+            let res = std::str::from_utf8(data)
+                .map_err(Into::into)
+                .and_then(share::decode)
+                .and_then(|scene| {
+                    let value = scene.to_u8()?;
+                    nvs_store_clone.set_scene(scene)?;
+                    // 更新场景特征可能需要等待传输结束，不能阻塞NimBLE回调
+                    let transmission = scene_transmission_clone.clone();
+                    pool_clone.spawn(async move {
+                        if let Err(e) = transmission.set_value(value) {
+                            report::error(Module::Transfer, ErrorCode::Internal, e);
+                        }
+                    })?;
+                    Ok(())
+                });
+            if let Err(e) = res {
+                args.reject();
+                report::error(
+                    Module::Ble,
+                    ErrorCode::InvalidData,
+                    format!("import scene error: {e}"),
+                );
+            }
+        });
+
+        // 组内场景特征，写入分享字符串后应用到本机和同步组内所有灯，超时后通知确认结果
+        let group_characteristic = service.lock().create_characteristic(
+            uuid128!("2f8c5a1e-7b3d-4e96-a4c0-9d1e6b3f8a52"),
+            NimbleProperties::WRITE | NimbleProperties::NOTIFY,
+        );
+        let nvs_store_clone = nvs_store.clone();
+        let scene_transmission_clone = scene_transmission.clone();
+        notify::track(&group_characteristic, false);
+        let group_characteristic_clone = group_characteristic.clone();
+        let mut light = light_sender.clone();
+        let state_clone = state.clone();
+        let pool_clone = pool.clone();
+        let auth_clone = auth.clone();
+        group_characteristic.lock().on_write(move |args| {
+            // 会改变本机和组内其他灯的场景，与场景特征一样需要令牌并受儿童锁限制
+            let Some(data) = auth_clone.strip(args.recv_data()) else {
+                log::warn!("reject unauthenticated group scene");
+                args.reject();
+                return;
+            };
+            if *nvs_store_clone.child_lock.lock() {
+                log::warn!("reject group scene, child lock enabled");
+                args.reject();
+                return;
+            }
+            let res = std::str::from_utf8(data)
+                .map_err(Into::into)
+                .and_then(share::decode)
+                .and_then(|scene| {
+                    let id = sync.apply_scene(&scene, &nvs_store_clone.palettes.lock())?;
+                    let value = scene.to_u8()?;
+                    nvs_store_clone.set_scene(scene)?;
+                    if matches!(*state_clone.lock(), LightState::Opened) {
+                        light.open()?;
+                    }
+                    let transmission = scene_transmission_clone.clone();
+                    let sync = sync.clone();
+                    let characteristic = group_characteristic_clone.clone();
+                    pool_clone.spawn(async move {
+                        if let Err(e) = transmission.set_value(value) {
+                            report::error(Module::Transfer, ErrorCode::Internal, e);
+                        }
+                        let res = async {
+                            let mut async_timer = EspTaskTimerService::new()?.timer_async()?;
+                            async_timer.after(GROUP_ACK_TIMEOUT).await?;
+                            let result = sync.take_result(id);
+                            notify::send(&characteristic, &serde_json::to_vec(&result)?);
+                            anyhow::Ok(())
+                        };
+                        if let Err(e) = res.await {
+                            report::error(
+                                Module::Sync,
+                                ErrorCode::Internal,
+                                format!("group scene result error: {e}"),
+                            );
+                        }
+                    })?;
+                    Ok(())
+                });
+            if let Err(e) = res {
+                args.reject();
+                report::error(
+                    Module::Sync,
+                    ErrorCode::InvalidData,
+                    format!("apply group scene error: {e}"),
+                );
+            }
+        });
+
+        // 复位原因特征，看门狗因任务卡死重启时包含卡死的任务
+        let reset_reason_characteristic = service.lock().create_characteristic(
+            uuid128!("3a7f1c9e-5d2b-4e84-b6a0-8c3e1f7d9b52"),
+            NimbleProperties::READ,
+        );
+        reset_reason_characteristic.lock().on_read(|attr, _| {
+            match serde_json::to_vec(&watchdog::last_reset().unwrap_or_default()) {
+                Ok(value) => {
+                    attr.set_value(&value);
+                }
+                Err(e) => report::error(Module::Ble, ErrorCode::Internal, e),
+            }
+        });
+
+        // 诊断特征，启动次数、异常复位次数与最近一次panic
+        let diagnostics_characteristic = service.lock().create_characteristic(
+            uuid128!("4c8e2a6f-1b9d-4f73-a0c5-e7d3b9f1a284"),
+            NimbleProperties::READ,
+        );
+        diagnostics_characteristic.lock().on_read(|attr, _| {
+            match serde_json::to_vec(&diagnostics::diagnostics()) {
+                Ok(value) => {
+                    attr.set_value(&value);
+                }
+                Err(e) => report::error(Module::Ble, ErrorCode::Internal, e),
+            }
+        });
+
+        // 内存特征，空闲堆内存与各任务栈的剩余空间，读取时重新统计
+        let memory_characteristic = service.lock().create_characteristic(
+            uuid128!("e1a7c3d9-5f2b-4e86-9a14-8b6d0f3c7e52"),
+            NimbleProperties::READ,
+        );
+        memory_characteristic.lock().on_read(|attr, _| {
+            match serde_json::to_vec(&diagnostics::memory()) {
+                Ok(value) => {
+                    attr.set_value(&value);
+                }
+                Err(e) => report::error(Module::Ble, ErrorCode::Internal, e),
+            }
+        });
+
+        // 使用统计特征，累计点亮时间、开灯次数和各场景的使用情况
+        let usage_characteristic = service.lock().create_characteristic(
+            uuid128!("b5e2d8f4-7a1c-4396-8d0e-2f6c9a3b1e75"),
+            NimbleProperties::READ,
+        );
+        usage_characteristic
+            .lock()
+            .on_read(|attr, _| match serde_json::to_vec(&usage::stats()) {
+                Ok(value) => {
+                    attr.set_value(&value);
+                }
+                Err(e) => report::error(Module::Ble, ErrorCode::Internal, e),
+            });
+
+        // 错误通知特征，子系统出现可恢复的错误时通知模块、错误类别和信息
+        let error_characteristic = service.lock().create_characteristic(
+            uuid128!("7c4e1a9d-3b6f-4d28-a5e0-8f2b9c6d1e47"),
+            NimbleProperties::NOTIFY,
+        );
+        notify::track(&error_characteristic, false);
+        report::set_characteristic(error_characteristic);
+
+        // 设备名称特征
+        let name_characteristic = service.lock().create_characteristic(
+            uuid128!("5b2e8c1d-7a43-4f6e-b9d0-2c8e4a6f1b37"),
+            NimbleProperties::READ | NimbleProperties::WRITE,
+        );
+        name_characteristic
+            .lock()
+            .set_value(nvs_store.settings.lock().name.as_bytes());
+        let nvs_store_clone = nvs_store.clone();
+        let settings_clone = settings_transmission.clone();
+        let auth_clone = auth.clone();
+        name_characteristic.lock().on_write(move |args| {
+            let Some(data) = auth_clone.strip(args.recv_data()) else {
+                log::warn!("reject unauthenticated name");
+                args.reject();
+                return;
+            };
+            if *nvs_store_clone.child_lock.lock() {
+                log::warn!("reject name, child lock enabled");
+                args.reject();
+                return;
+            }
+            let name = match std::str::from_utf8(data) {
+                Ok(name) if !name.is_empty() && name.len() <= MAX_NAME_LEN => name.to_string(),
+                _ => {
+                    args.reject();
+                    log::debug!("invalid name");
+                    return;
+                }
+            };
+            nvs_store_clone.settings.lock().name = name.clone();
+            if let Err(e) = nvs_store_clone
+                .write_settings()
+                .and_then(|_| set_advertisement(advertising, &name))
+                .and_then(|_| {
+                    settings_clone.set_value(serde_json::to_vec(&*nvs_store_clone.settings.lock())?)
+                })
+            {
+                args.reject();
+                report::error(Module::Ble, ErrorCode::Storage, e);
+            }
+        });
+
+        // 配置广告数据并启动广告
+        set_advertisement(advertising, &nvs_store.settings.lock().name)?;
+        advertising_backoff.apply(advertising)?;
         // 打印蓝牙服务相关日志
         server.ble_gatts_show_local();
 
         Ok(Self {
             nvs_store,
+            auth,
             scene_transmission,
             control_characteristic,
             state_characteristic,
+            state,
+            brightness,
             time_task_transmission,
+            next_fire_transmission,
+            indicator_transmission,
+            palette_transmission,
+            demo_transmission,
+            wifi_transmission,
+            scenes_transmission,
+            favorites_transmission,
+            button_transmission,
+            brightness_curve_transmission,
+            sync_transmission,
+            controller_transmission,
+            dmx_transmission,
+            mqtt_transmission,
+            adaptive_transmission,
+            motion_transmission,
+            ir_transmission,
+            calibration_transmission,
+            battery_transmission,
+            power_transmission,
+            thermal_transmission,
+            board_transmission,
+            advertising_transmission,
+            settings_transmission,
+            log_transmission,
+            log_level_transmission,
+            backup_transmission,
+            battery_level_characteristic,
+            advertising,
+            advertising_backoff,
+            timer_sender: time_sender,
         })
     }
 
     pub fn set_state(&self, state: LightState) {
-        self.state_characteristic
-            .lock()
-            .set_value(state.into())
-            .notify();
+        // 开灯时退出睡眠模式
+        if !matches!(state, LightState::Closed) {
+            power::activity();
+        }
+        #[cfg(feature = "mesh")]
+        crate::mesh::set_onoff(!matches!(state, LightState::Closed));
+        match &state {
+            LightState::Opened => usage::record(Some(&self.nvs_store.scene.lock().name)),
+            LightState::Demo(name) => usage::record(Some(name)),
+            LightState::Closed => usage::record(None),
+        }
+        *self.state.lock() = state.clone();
+        self.notify_state();
+        event_bus::publish(state);
+    }
+
+    pub fn set_brightness(&self, brightness: u8) {
+        *self.brightness.lock() = brightness.min(100);
+        self.notify_state();
+    }
+
+    /// 场景、亮度等变化后通知客户端
+    /// 状态特征的JSON数据，也用于HTTP接口
+    pub fn state_value(&self) -> Result<Vec<u8>> {
+        let state = self.state.lock().clone();
+        state_payload(&state, &self.nvs_store, *self.brightness.lock())
+    }
+
+    pub fn notify_state(&self) {
+        schedule_state(
+            &self.state_characteristic,
+            &self.state.lock().clone(),
+            &self.nvs_store,
+            *self.brightness.lock(),
+        );
+    }
+
+    /// 开关儿童锁并通知客户端
+    pub fn set_child_lock(&self, locked: bool) -> Result<()> {
+        self.nvs_store.set_child_lock(locked)?;
+        log::info!("child lock {}", if locked { "enabled" } else { "disabled" });
+        self.notify_state();
+        Ok(())
     }
 
     pub fn set_scene(&self, scene: &Scene) -> Result<()> {
@@ -183,13 +1390,208 @@ impl BleControl {
         Ok(())
     }
 
+    pub fn set_next_fire(&self, next: &[NextFire]) -> Result<()> {
+        self.next_fire_transmission
+            .set_value(serde_json::to_vec(next)?)?;
+        Ok(())
+    }
+
+    pub fn set_indicator(&self, config: &IndicatorConfig) -> Result<()> {
+        self.indicator_transmission
+            .set_value(serde_json::to_vec(config)?)?;
+        Ok(())
+    }
+
+    pub fn set_palettes(&self, palettes: &Palettes) -> Result<()> {
+        self.palette_transmission
+            .set_value(serde_json::to_vec(palettes)?)?;
+        Ok(())
+    }
+
+    pub fn set_demo(&self, demo: &DemoConfig) -> Result<()> {
+        self.demo_transmission
+            .set_value(serde_json::to_vec(demo)?)?;
+        Ok(())
+    }
+
+    pub fn set_wifi(&self, wifi: &WifiConfig) -> Result<()> {
+        self.wifi_transmission.set_value(wifi_public_value(wifi)?)?;
+        Ok(())
+    }
+
+    pub fn set_scenes(&self, scenes: &[Scene]) -> Result<()> {
+        self.scenes_transmission
+            .set_value(serde_json::to_vec(scenes)?)?;
+        Ok(())
+    }
+
+    pub fn set_favorites(&self, favorites: &Favorites) -> Result<()> {
+        self.favorites_transmission
+            .set_value(serde_json::to_vec(favorites)?)?;
+        Ok(())
+    }
+
+    pub fn set_button(&self, config: &ButtonConfig) -> Result<()> {
+        self.button_transmission
+            .set_value(serde_json::to_vec(config)?)?;
+        Ok(())
+    }
+
+    pub fn set_brightness_curve(&self, curve: &BrightnessCurve) -> Result<()> {
+        self.brightness_curve_transmission
+            .set_value(serde_json::to_vec(curve)?)?;
+        Ok(())
+    }
+
+    pub fn set_sync(&self, config: &SyncConfig) -> Result<()> {
+        self.sync_transmission
+            .set_value(sync_public_value(config)?)?;
+        Ok(())
+    }
+
+    pub fn set_controller(&self, config: &ControllerConfig) -> Result<()> {
+        self.controller_transmission
+            .set_value(controller_public_value(config)?)?;
+        Ok(())
+    }
+
+    pub fn set_dmx(&self, config: &DmxConfig) -> Result<()> {
+        self.dmx_transmission
+            .set_value(serde_json::to_vec(config)?)?;
+        Ok(())
+    }
+
+    pub fn set_mqtt(&self, config: &MqttConfig) -> Result<()> {
+        self.mqtt_transmission
+            .set_value(mqtt_public_value(config)?)?;
+        Ok(())
+    }
+
+    pub fn set_adaptive(&self, config: &AdaptiveConfig) -> Result<()> {
+        self.adaptive_transmission
+            .set_value(serde_json::to_vec(config)?)?;
+        Ok(())
+    }
+
+    pub fn set_motion(&self, config: &MotionConfig) -> Result<()> {
+        self.motion_transmission
+            .set_value(serde_json::to_vec(config)?)?;
+        Ok(())
+    }
+
+    pub fn set_ir(&self, config: &IrConfig) -> Result<()> {
+        self.ir_transmission
+            .set_value(serde_json::to_vec(config)?)?;
+        Ok(())
+    }
+
+    pub fn set_calibration(&self, config: &CalibrationConfig) -> Result<()> {
+        self.calibration_transmission
+            .set_value(serde_json::to_vec(config)?)?;
+        Ok(())
+    }
+
+    pub fn set_battery(&self, config: &BatteryConfig) -> Result<()> {
+        self.battery_transmission
+            .set_value(serde_json::to_vec(config)?)?;
+        Ok(())
+    }
+
+    pub fn set_power(&self, config: &PowerConfig) -> Result<()> {
+        self.power_transmission
+            .set_value(serde_json::to_vec(config)?)?;
+        Ok(())
+    }
+
+    pub fn set_thermal(&self, config: &ThermalConfig) -> Result<()> {
+        self.thermal_transmission
+            .set_value(serde_json::to_vec(config)?)?;
+        Ok(())
+    }
+
+    pub fn set_board(&self, config: &BoardConfig) -> Result<()> {
+        self.board_transmission
+            .set_value(serde_json::to_vec(config)?)?;
+        Ok(())
+    }
+
+    pub fn set_advertising(&self, config: &AdvertisingConfig) -> Result<()> {
+        self.advertising_transmission
+            .set_value(serde_json::to_vec(config)?)?;
+        Ok(())
+    }
+
+    pub fn set_log_levels(&self, config: &LogLevelConfig) -> Result<()> {
+        self.log_level_transmission
+            .set_value(serde_json::to_vec(config)?)?;
+        Ok(())
+    }
+
+    pub fn set_settings(&self, settings: &DeviceSettings) -> Result<()> {
+        self.settings_transmission
+            .set_value(serde_json::to_vec(settings)?)?;
+        Ok(())
+    }
+
+    /// 更新标准电池服务中的电量
+    pub fn set_battery_level(&self, percent: u8) {
+        if let Some(characteristic) = &self.battery_level_characteristic {
+            notify::send(characteristic, &[percent]);
+        }
+    }
+
+    pub fn set_name(&self, name: &str) -> Result<()> {
+        if name.is_empty() || name.len() > MAX_NAME_LEN {
+            return Err(anyhow!("invalid name"));
+        }
+        self.nvs_store.settings.lock().name = name.to_string();
+        self.nvs_store.write_settings()?;
+        set_advertisement(self.advertising, name)?;
+        self.set_settings(&self.nvs_store.settings.lock())?;
+        Ok(())
+    }
+
+    /// 立即恢复快速广播，按下按键时调用
+    pub fn wake_advertising(&self) {
+        if let Err(e) = self.advertising_backoff.wake(self.advertising) {
+            report::error(
+                Module::Advertising,
+                ErrorCode::Internal,
+                format!("wake advertising error: {e}"),
+            );
+        }
+    }
+
     pub fn get_state(&self) -> LightState {
-        self.state_characteristic.lock().value_mut().value().into()
+        self.state.lock().clone()
     }
 
     pub fn init(&self) -> Result<()> {
         self.set_timer(&self.nvs_store.time_task.lock())?;
         self.set_scene(&self.nvs_store.scene.lock())?;
+        self.set_indicator(&self.nvs_store.indicator.lock())?;
+        self.set_palettes(&self.nvs_store.palettes.lock())?;
+        self.set_demo(&self.nvs_store.demo.lock())?;
+        self.set_wifi(&self.nvs_store.wifi.lock())?;
+        self.set_scenes(&self.nvs_store.scenes.lock())?;
+        self.set_favorites(&self.nvs_store.favorites.lock())?;
+        self.set_button(&self.nvs_store.button.lock())?;
+        self.set_brightness_curve(&self.nvs_store.brightness_curve.lock())?;
+        self.set_sync(&self.nvs_store.sync.lock())?;
+        self.set_controller(&self.nvs_store.controller.lock())?;
+        self.set_dmx(&self.nvs_store.dmx.lock())?;
+        self.set_mqtt(&self.nvs_store.mqtt.lock())?;
+        self.set_adaptive(&self.nvs_store.adaptive.lock())?;
+        self.set_motion(&self.nvs_store.motion.lock())?;
+        self.set_ir(&self.nvs_store.ir.lock())?;
+        self.set_calibration(&self.nvs_store.calibration.lock())?;
+        self.set_battery(&self.nvs_store.battery.lock())?;
+        self.set_power(&self.nvs_store.power.lock())?;
+        self.set_thermal(&self.nvs_store.thermal.lock())?;
+        self.set_board(&self.nvs_store.board.lock())?;
+        self.set_advertising(&self.nvs_store.advertising.lock())?;
+        self.set_settings(&self.nvs_store.settings.lock())?;
+        self.log_transmission.set_value(log_buffer::snapshot())?;
         self.set_state(LightState::Closed);
         Ok(())
     }
@@ -203,6 +1605,7 @@ impl BleControl {
     pub fn reset_scene(&self) -> Result<()> {
         self.nvs_store.reset_scene()?;
         self.set_scene(&self.nvs_store.scene.lock())?;
+        self.notify_state();
         Ok(())
     }
 }
@@ -1,66 +1,285 @@
 use crate::{
     ble::BleControl,
-    light::{LightEventSender, LightState},
+    hue_emulation,
+    light::{LightEvent, LightEventSender},
+    power,
+    report::{self, ErrorCode, Module},
+    reset::FACTORY_RESET_HOLD,
+    store::{BoardConfig, ButtonAction},
 };
 use anyhow::Result;
 use esp_idf_svc::hal::{
-    gpio::{Input, InputPin, InterruptType, OutputPin, PinDriver, Pull},
+    delay::{TickType, BLOCK},
+    gpio::{AnyIOPin, Input, InterruptType, PinDriver, Pull},
     task::notification::Notification,
 };
-use std::num::NonZeroU32;
+use std::{
+    num::NonZeroU32,
+    time::{Duration, Instant},
+};
+
+/// 多击判定间隔
+const MULTI_CLICK_WINDOW: Duration = Duration::from_millis(400);
+/// 按住超过该时间视为长按，超过`FACTORY_RESET_HOLD`则恢复出厂设置
+const LONG_PRESS: Duration = Duration::from_secs(1);
+/// 睡眠定时任务的名称，重复设置时重新计时
+const SLEEP_TIMER: &str = "sleep";
+/// 每次调节的亮度百分比
+const BRIGHTNESS_STEP: u8 = 10;
+/// 按键调暗时的最低亮度，避免看起来像关灯
+const MIN_BRIGHTNESS: u8 = 10;
+/// 任意两个按键同时按住超过该时间，开关儿童锁
+const CHILD_LOCK_HOLD: Duration = Duration::from_secs(3);
+
+/// 单个按键的引脚和状态
+struct Key {
+    driver: PinDriver<'static, AnyIOPin, Input>,
+    /// 额外按键绑定的操作，主按键为None，使用按钮操作映射
+    action: Option<ButtonAction>,
+    /// 消抖后的按下时间，松开后为None
+    pressed_at: Option<Instant>,
+}
 
-pub struct Button<T>
-where
-    T: InputPin + OutputPin,
-{
-    button: PinDriver<'static, T, Input>,
+/// 管理开发板配置中的所有按键，共用一个等待中断的线程
+///
+/// 第一个为主按键，支持多击、长按和恢复出厂设置，其余按键每次按下执行绑定的操作；
+/// 任意两个按键同时长按开关儿童锁
+pub struct ButtonManager {
+    keys: Vec<Key>,
     ble_control: BleControl,
     light_event_sender: LightEventSender,
+    /// 主按键已松开的点击次数，等待多击间隔结束
+    clicks: u32,
+    click_deadline: Option<Instant>,
+    /// 组合键已触发，忽略之后的松开，直到所有按键松开
+    chord: bool,
 }
 
-impl<T> Button<T>
-where
-    T: InputPin + OutputPin,
-{
+impl ButtonManager {
     pub fn new(
-        pin: T,
+        board: &BoardConfig,
         ble_control: BleControl,
         light_event_sender: LightEventSender,
     ) -> Result<Self> {
+        let pins = std::iter::once((board.button_pin, None)).chain(
+            board
+                .extra_buttons
+                .iter()
+                .map(|button| (button.pin, Some(button.action.clone()))),
+        );
+        let mut keys = vec![];
+        for (pin, action) in pins {
+            // 引脚已由开发板配置校验
+            let pin = unsafe { AnyIOPin::new(pin as i32) };
+            keys.push(Key {
+                driver: PinDriver::input(pin)?,
+                action,
+                pressed_at: None,
+            });
+        }
         Ok(Self {
-            button: PinDriver::input(pin)?,
+            keys,
             ble_control,
             light_event_sender,
+            clicks: 0,
+            click_deadline: None,
+            chord: false,
         })
     }
 
     pub fn init(mut self) -> Result<()> {
-        self.button.set_pull(Pull::Up)?;
-        self.button.set_interrupt_type(InterruptType::PosEdge)?;
+        for key in self.keys.iter_mut() {
+            key.driver.set_pull(Pull::Up)?;
+            // 按下和松开都触发中断，用于判断长按
+            key.driver.set_interrupt_type(InterruptType::AnyEdge)?;
+            #[cfg(esp_idf_soc_gpio_support_pin_glitch_filter)]
+            enable_glitch_filter(key.driver.pin())?;
+        }
 
         std::thread::spawn(move || -> Result<(), anyhow::Error> {
             let notification = Notification::new();
-            let notifier = notification.notifier();
-            unsafe {
-                self.button.subscribe(move || {
-                    notifier.notify_and_yield(NonZeroU32::new(1).unwrap());
-                })?;
+            // 每个按键对应通知值中的一位
+            for (index, key) in self.keys.iter_mut().enumerate() {
+                let notifier = notification.notifier();
+                let bit = NonZeroU32::new(1 << index).unwrap();
+                unsafe {
+                    key.driver.subscribe(move || {
+                        notifier.notify_and_yield(bit);
+                    })?;
+                }
             }
 
+            for key in self.keys.iter_mut() {
+                key.driver.enable_interrupt()?;
+            }
             loop {
-                self.button.enable_interrupt()?;
-                notification.wait(esp_idf_svc::hal::delay::BLOCK);
-                let state = self.ble_control.get_state();
-                match state {
-                    LightState::Closed => {
-                        self.light_event_sender.open()?;
+                // 松开后在多击间隔内等待下一次按下
+                let timeout = match self.click_deadline {
+                    Some(deadline) => {
+                        TickType::from(deadline.saturating_duration_since(Instant::now())).ticks()
                     }
-                    LightState::Opened => {
-                        self.light_event_sender.close()?;
+                    None => BLOCK,
+                };
+                let Some(bits) = notification.wait(timeout) else {
+                    self.click_deadline = None;
+                    self.on_clicks(std::mem::take(&mut self.clicks))?;
+                    continue;
+                };
+                // 等待抖动结束后再开启中断并读取电平，抖动期间的边沿都被忽略
+                let debounce = self.ble_control.nvs_store.button.lock().debounce_ms;
+                std::thread::sleep(Duration::from_millis(debounce as u64));
+                for key in self.keys.iter_mut() {
+                    key.driver.enable_interrupt()?;
+                }
+                for index in 0..self.keys.len() {
+                    if bits.get() & (1 << index) != 0 {
+                        self.on_edge(index)?;
                     }
                 }
             }
         });
         Ok(())
     }
+
+    fn on_edge(&mut self, index: usize) -> Result<()> {
+        let key = &mut self.keys[index];
+        // 上拉输入，低电平表示按下；电平和之前相同说明只是抖动
+        if key.driver.is_low() {
+            if key.pressed_at.is_some() {
+                return Ok(());
+            }
+            key.pressed_at = Some(Instant::now());
+            if index == 0 {
+                self.click_deadline = None;
+            }
+            power::activity();
+            self.ble_control.wake_advertising();
+            // 同时作为Hue模拟的配对按键
+            hue_emulation::press_link_button();
+            return Ok(());
+        }
+        let Some(pressed_at) = key.pressed_at.take() else {
+            return Ok(());
+        };
+        let action = key.action.clone();
+        if self.chord {
+            self.chord = self.keys.iter().any(|key| key.pressed_at.is_some());
+            return Ok(());
+        }
+        // 从两个按键都按下时开始计时
+        let chord_held = self
+            .keys
+            .iter()
+            .filter_map(|key| key.pressed_at)
+            .min()
+            .map(|other| other.max(pressed_at).elapsed());
+        if chord_held.is_some_and(|held| held >= CHILD_LOCK_HOLD) {
+            self.chord = true;
+            self.clicks = 0;
+            self.click_deadline = None;
+            let locked = *self.ble_control.nvs_store.child_lock.lock();
+            // 安全模式下配置只读，不能因此结束按键线程
+            if let Err(e) = self.ble_control.set_child_lock(!locked) {
+                report::error(Module::Store, ErrorCode::Storage, e);
+            }
+            return Ok(());
+        }
+        if let Some(action) = action {
+            return self.run(action);
+        }
+
+        let held = pressed_at.elapsed();
+        if held >= FACTORY_RESET_HOLD {
+            self.clicks = 0;
+            return self.light_event_sender.send(LightEvent::FactoryReset);
+        }
+        if held >= LONG_PRESS && self.clicks == 0 {
+            let long_press = self.ble_control.nvs_store.button.lock().long_press.clone();
+            if let Some(action) = long_press {
+                return self.run(action);
+            }
+        }
+        self.clicks += 1;
+        // 没有绑定多击操作时单击立即响应
+        if self.clicks >= 3 || !self.has_multi_click() {
+            return self.on_clicks(std::mem::take(&mut self.clicks));
+        }
+        self.click_deadline = Some(Instant::now() + MULTI_CLICK_WINDOW);
+        Ok(())
+    }
+
+    fn has_multi_click(&self) -> bool {
+        let config = self.ble_control.nvs_store.button.lock();
+        config.double_press.is_some()
+            || config.triple_press.is_some()
+            || !self.ble_control.nvs_store.favorites.lock().is_empty()
+    }
+
+    fn on_clicks(&mut self, clicks: u32) -> Result<()> {
+        let config = self.ble_control.nvs_store.button.lock().clone();
+        let favorites = self.ble_control.nvs_store.favorites.lock().clone();
+        // 未设置多击操作时使用收藏场景
+        let action = match clicks {
+            2 => config
+                .double_press
+                .or(favorites.double_click.map(ButtonAction::Scene)),
+            3 => config
+                .triple_press
+                .or(favorites.triple_click.map(ButtonAction::Scene)),
+            _ => None,
+        };
+        self.run(action.unwrap_or(config.short_press))
+    }
+
+    fn run(&mut self, action: ButtonAction) -> Result<()> {
+        let brightness = *self.ble_control.brightness.lock();
+        match action {
+            ButtonAction::Toggle => self.light_event_sender.toggle(),
+            ButtonAction::CycleScenes => {
+                let current = self.ble_control.nvs_store.scene.lock().name.clone();
+                let scenes = self.ble_control.nvs_store.scenes.lock();
+                let Some(first) = scenes.first() else {
+                    log::warn!("scene library is empty");
+                    return Ok(());
+                };
+                // 当前场景不在场景库中时从第一个开始
+                let next = scenes
+                    .iter()
+                    .position(|scene| scene.name == current)
+                    .and_then(|index| scenes.get(index + 1))
+                    .unwrap_or(first);
+                self.light_event_sender
+                    .send(LightEvent::SetScene(next.name.clone()))
+            }
+            ButtonAction::SleepTimer(minutes) => self.ble_control.timer_sender.clone().countdown(
+                SLEEP_TIMER.to_string(),
+                minutes * 60,
+                LightEvent::Close,
+            ),
+            ButtonAction::Scene(name) => self.light_event_sender.send(LightEvent::SetScene(name)),
+            ButtonAction::BrightnessUp => self.light_event_sender.send(LightEvent::OpenAt(
+                brightness.saturating_add(BRIGHTNESS_STEP).min(100),
+            )),
+            ButtonAction::BrightnessDown => self.light_event_sender.send(LightEvent::OpenAt(
+                brightness
+                    .saturating_sub(BRIGHTNESS_STEP)
+                    .max(MIN_BRIGHTNESS),
+            )),
+        }
+    }
+}
+
+/// 开启GPIO硬件毛刺滤波，滤除短于两个时钟周期的脉冲，机械抖动仍需软件消抖
+#[cfg(esp_idf_soc_gpio_support_pin_glitch_filter)]
+fn enable_glitch_filter(pin: i32) -> Result<()> {
+    use esp_idf_svc::sys::*;
+
+    let config = gpio_pin_glitch_filter_config_t {
+        clk_src: soc_periph_glitch_filter_clk_src_t_GLITCH_FILTER_CLK_SRC_DEFAULT,
+        gpio_num: pin,
+    };
+    let mut filter: gpio_glitch_filter_handle_t = std::ptr::null_mut();
+    esp!(unsafe { gpio_new_pin_glitch_filter(&config, &mut filter) })?;
+    esp!(unsafe { gpio_glitch_filter_enable(filter) })?;
+    Ok(())
 }
@@ -0,0 +1,68 @@
+use anyhow::Result;
+pub use smart_brite_core::rtttl::{melody, parse_rtttl, Note};
+use std::sync::mpsc::Sender;
+
+/// 蜂鸣器，旋律在单独的线程中播放，不阻塞调用方
+#[derive(Clone, Default)]
+pub struct Buzzer {
+    tx: Option<Sender<Vec<Note>>>,
+}
+
+impl Buzzer {
+    /// 播放旋律，没有蜂鸣器时忽略
+    pub fn play(&self, name: &str) -> Result<()> {
+        let Some(tx) = &self.tx else {
+            return Ok(());
+        };
+        tx.send(melody(name)?)?;
+        Ok(())
+    }
+
+    /// 无源蜂鸣器接GPIO3，使用LEDC输出PWM
+    #[cfg(feature = "buzzer")]
+    pub fn start(
+        timer: esp_idf_svc::hal::ledc::TIMER0,
+        channel: esp_idf_svc::hal::ledc::CHANNEL0,
+        pin: esp_idf_svc::hal::gpio::Gpio3,
+    ) -> Result<Self> {
+        use esp_idf_svc::{
+            hal::{
+                ledc::{config::TimerConfig, LedcDriver, LedcTimerDriver},
+                units::Hertz,
+            },
+            sys::{esp, ledc_mode_t_LEDC_LOW_SPEED_MODE, ledc_set_freq, ledc_timer_t_LEDC_TIMER_0},
+        };
+
+        let timer = LedcTimerDriver::new(timer, &TimerConfig::default().frequency(Hertz(1000)))?;
+        let mut driver = LedcDriver::new(channel, timer, pin)?;
+        driver.set_duty(0)?;
+        let (tx, rx) = std::sync::mpsc::channel::<Vec<Note>>();
+
+        std::thread::spawn(move || -> Result<()> {
+            let duty = driver.get_max_duty() / 2;
+            while let Ok(notes) = rx.recv() {
+                for note in notes {
+                    if note.frequency > 0 {
+                        esp!(unsafe {
+                            ledc_set_freq(
+                                ledc_mode_t_LEDC_LOW_SPEED_MODE,
+                                ledc_timer_t_LEDC_TIMER_0,
+                                note.frequency,
+                            )
+                        })?;
+                        driver.set_duty(duty)?;
+                    } else {
+                        driver.set_duty(0)?;
+                    }
+                    // 音符之间留出短暂间隔，连续相同的音才能区分
+                    std::thread::sleep(note.duration.mul_f32(0.9));
+                    driver.set_duty(0)?;
+                    std::thread::sleep(note.duration.mul_f32(0.1));
+                }
+            }
+            Ok(())
+        });
+
+        Ok(Self { tx: Some(tx) })
+    }
+}
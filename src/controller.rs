@@ -0,0 +1,149 @@
+use crate::{
+    event_bus,
+    executor::Executor,
+    light::LightEvent,
+    report::{self, ErrorCode, Module},
+    store::{ControlledLamp, ControllerConfig},
+};
+use anyhow::{anyhow, bail, Result};
+use esp32_nimble::{
+    utilities::mutex::Mutex, uuid128, BLEAddress, BLEAddressType, BLEClient, BLEDevice,
+};
+use esp_idf_svc::timer::{EspAsyncTimer, EspTaskTimerService};
+use futures::{
+    future::{select, Either},
+    pin_mut,
+    task::SpawnExt,
+    StreamExt,
+};
+use serde::Serialize;
+use std::{sync::Arc, time::Duration};
+
+/// 扫描附近的灯的时长，单位：毫秒
+const SCAN_DURATION_MS: i32 = 5000;
+/// 连接并写入单盏灯的最长时间，灯不在附近时不会拖慢其他灯
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 扫描到的灯
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveredLamp {
+    pub address: String,
+    pub name: String,
+    pub rssi: i32,
+}
+
+/// 需要转发的灯光事件对应的控制操作码，其余事件只在本机生效
+///
+/// 开关切换由各灯按自身状态执行，状态不一致时需要先统一开灯或关灯
+fn relay_opcode(event: &LightEvent) -> Option<Vec<u8>> {
+    match event {
+        LightEvent::Open => Some(b"open".to_vec()),
+        LightEvent::OpenAt(brightness) => Some(format!("open:{brightness}").into_bytes()),
+        LightEvent::Close => Some(b"close".to_vec()),
+        LightEvent::Toggle => Some(b"toggle".to_vec()),
+        LightEvent::SetScene(name) => Some(format!("scene:{name}").into_bytes()),
+        _ => None,
+    }
+}
+
+/// 连接灯并写入控制特征，对方已配对时在数据前带上令牌
+async fn send(lamp: &ControlledLamp, opcode: &[u8]) -> Result<()> {
+    let address = BLEAddress::from_str(&lamp.address, BLEAddressType::Public)
+        .ok_or(anyhow!("Invalid address {}", lamp.address))?;
+    let mut data = lamp
+        .token_bytes()?
+        .map(|token| token.to_vec())
+        .unwrap_or_default();
+    data.extend_from_slice(opcode);
+
+    let mut client = BLEClient::new();
+    client.connect(&address).await?;
+    let result = async {
+        let service = client
+            .get_service(uuid128!("e572775c-0df9-4b44-926b-b692e31d6971"))
+            .await?;
+        let characteristic = service
+            .get_characteristic(uuid128!("bc00dad8-280c-49f9-9efd-3a8137594ef2"))
+            .await?;
+        characteristic.write_value(&data, true).await?;
+        Ok(())
+    }
+    .await;
+    // 写入失败也要断开，NimBLE的连接数有限
+    client.disconnect()?;
+    result
+}
+
+async fn send_with_timeout(
+    timer: &mut EspAsyncTimer,
+    lamp: &ControlledLamp,
+    opcode: &[u8],
+) -> Result<()> {
+    let send = send(lamp, opcode);
+    let timeout = timer.after(SEND_TIMEOUT);
+    pin_mut!(send, timeout);
+    match select(send, timeout).await {
+        Either::Left((result, _)) => result,
+        Either::Right(_) => bail!("Timed out"),
+    }
+}
+
+/// 转发本机的开关灯和场景切换，按键、定时任务等触发的事件都会转发
+///
+/// 订阅需要在按键等发布者启动前完成
+pub fn start(config: Arc<Mutex<ControllerConfig>>, pool: &Executor) -> Result<()> {
+    let mut events = event_bus::subscribe_async::<LightEvent>();
+    let mut async_timer = EspTaskTimerService::new()?.timer_async()?;
+    pool.spawn(async move {
+        while let Some(event) = events.next().await {
+            let Some(opcode) = relay_opcode(&event) else {
+                continue;
+            };
+            let config = config.lock().clone();
+            if !config.enabled {
+                continue;
+            }
+            // 依次连接，同时作为中心设备的连接数有限
+            for lamp in &config.lamps {
+                if let Err(e) = send_with_timeout(&mut async_timer, lamp, &opcode).await {
+                    report::error(
+                        Module::Controller,
+                        ErrorCode::Network,
+                        format!("relay to {} failed: {e}", lamp.address),
+                    );
+                }
+            }
+        }
+    })?;
+    Ok(())
+}
+
+/// 扫描附近广播灯服务的设备，按信号强度排序
+pub async fn scan() -> Result<Vec<DiscoveredLamp>> {
+    let found = Arc::new(Mutex::new(Vec::<DiscoveredLamp>::new()));
+    let found_clone = found.clone();
+    let scan = BLEDevice::take().get_scan();
+    scan.active_scan(true)
+        .interval(100)
+        .window(99)
+        .on_result(move |_, device| {
+            if !device.is_advertising_service(&uuid128!("e572775c-0df9-4b44-926b-b692e31d6971")) {
+                return;
+            }
+            let address = device.addr().to_string();
+            let mut found = found_clone.lock();
+            if found.iter().any(|lamp| lamp.address == address) {
+                return;
+            }
+            found.push(DiscoveredLamp {
+                address,
+                name: device.name().to_string(),
+                rssi: device.rssi(),
+            });
+        });
+    scan.start(SCAN_DURATION_MS).await?;
+    let mut lamps = std::mem::take(&mut *found.lock());
+    lamps.sort_by_key(|lamp| -lamp.rssi);
+    Ok(lamps)
+}
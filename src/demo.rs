@@ -0,0 +1,102 @@
+use crate::ble::BleControl;
+use crate::led::{ColorSpace, Led};
+use crate::light::{open_led, LightState};
+use crate::modifier::Modifier;
+use crate::store::scene::{Gradient, GradientColorItem, Meteor, Rainbow, Solid};
+use crate::store::Color;
+use anyhow::Result;
+use esp_idf_svc::timer::EspTaskTimerService;
+use futures::future::{select, Either};
+use rgb::RGB8;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+fn gradient(colors: &[(u8, u8, u8)], duration: f32, linear: bool) -> Color {
+    Color::Gradient(Gradient {
+        colors: colors
+            .iter()
+            .map(|&(r, g, b)| GradientColorItem {
+                color: RGB8::new(r, g, b),
+                duration,
+            })
+            .collect(),
+        linear,
+        space: ColorSpace::default(),
+    })
+}
+
+/// 演示模式中循环展示的内置效果
+pub fn demo_effects() -> Vec<(&'static str, Color)> {
+    vec![
+        (
+            "Warm White",
+            Color::Solid(Solid {
+                color: RGB8::new(255, 180, 100),
+            }),
+        ),
+        (
+            "Rainbow",
+            Color::Rainbow(Rainbow {
+                speed: 60.0,
+                saturation: 1.0,
+                brightness: 1.0,
+            }),
+        ),
+        (
+            "Sunset",
+            gradient(&[(255, 94, 0), (255, 0, 80), (120, 0, 160)], 3.0, true),
+        ),
+        (
+            "Ocean",
+            gradient(&[(0, 60, 255), (0, 200, 200), (0, 120, 255)], 2.0, true),
+        ),
+        (
+            "Party",
+            gradient(&[(255, 0, 0), (0, 255, 0), (0, 0, 255)], 0.5, false),
+        ),
+        (
+            "Meteor",
+            Color::Meteor(Meteor {
+                pixels: 30,
+                tail: 8,
+                speed: 20.0,
+                reverse: false,
+                colors: vec![RGB8::new(255, 255, 255), RGB8::new(0, 160, 255)],
+                palette: None,
+            }),
+        ),
+    ]
+}
+
+/// 循环展示内置效果，并通过状态特征通知当前效果名称
+pub async fn run_demo(
+    timer_service: EspTaskTimerService,
+    led: Arc<Mutex<Led<'_>>>,
+    ble_control: BleControl,
+    interval: Duration,
+    fps: u8,
+) -> Result<()> {
+    let effects = demo_effects();
+    for (name, color) in effects.iter().cycle() {
+        ble_control.set_state(LightState::Demo(name.to_string()));
+
+        let effect = Box::pin(open_led(
+            timer_service.timer_async()?,
+            led.clone(),
+            color.clone(),
+            Modifier::default(),
+            fps,
+        ));
+        let mut async_timer = timer_service.timer_async()?;
+        let wait = Box::pin(async_timer.after(interval));
+        match select(effect, wait).await {
+            // 纯色效果会立即完成，需要继续等待展示时间结束
+            Either::Left((res, wait)) => {
+                res?;
+                wait.await?;
+            }
+            Either::Right((res, _)) => res?,
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use anyhow::Result;
+use esp32_nimble::{utilities::BleUuid, BLEDevice, BLEServer, NimbleProperties};
+
+/// 标准设备信息服务 (Device Information Service)
+const DEVICE_INFO_SERVICE: u16 = 0x180A;
+const MODEL_NUMBER: u16 = 0x2A24;
+const SERIAL_NUMBER: u16 = 0x2A25;
+const FIRMWARE_REVISION: u16 = 0x2A26;
+const HARDWARE_REVISION: u16 = 0x2A27;
+const MANUFACTURER_NAME: u16 = 0x2A29;
+
+pub const FIRMWARE_VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const HARDWARE_VERSION: &str = "1.0";
+pub const MODEL: &str = "SmartBrite-C3";
+pub const MANUFACTURER: &str = "SmartBrite";
+
+/// 根据蓝牙MAC地址生成序列号，如 `SB-A1B2C3D4E5F6`
+pub fn serial_number(device: &BLEDevice) -> Result<String> {
+    let addr = device.get_addr()?;
+    Ok(format!(
+        "SB-{}",
+        addr.to_string().replace(':', "").to_uppercase()
+    ))
+}
+
+/// 创建设备信息服务，供客户端根据固件版本判断可用功能
+pub fn create_device_info_service(server: &mut BLEServer, serial: String) {
+    let service = server.create_service(BleUuid::from_uuid16(DEVICE_INFO_SERVICE));
+
+    let values = [
+        (MANUFACTURER_NAME, MANUFACTURER.to_string()),
+        (MODEL_NUMBER, MODEL.to_string()),
+        (SERIAL_NUMBER, serial),
+        (FIRMWARE_REVISION, FIRMWARE_VERSION.to_string()),
+        (HARDWARE_REVISION, HARDWARE_VERSION.to_string()),
+    ];
+    for (uuid, value) in values {
+        service
+            .lock()
+            .create_characteristic(BleUuid::from_uuid16(uuid), NimbleProperties::READ)
+            .lock()
+            .set_value(value.as_bytes());
+    }
+}
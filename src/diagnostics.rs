@@ -0,0 +1,238 @@
+use crate::{self_test::SelfTest, watchdog};
+use anyhow::Result;
+use esp32_nimble::utilities::mutex::Mutex;
+use esp_idf_svc::{
+    nvs::{EspNvs, EspNvsPartition, NvsDefault},
+    sys::{
+        esp_get_free_heap_size, esp_get_minimum_free_heap_size, esp_reset_reason,
+        esp_reset_reason_t_ESP_RST_BROWNOUT, esp_reset_reason_t_ESP_RST_INT_WDT,
+        esp_reset_reason_t_ESP_RST_PANIC, esp_reset_reason_t_ESP_RST_TASK_WDT,
+        esp_reset_reason_t_ESP_RST_WDT, heap_caps_get_largest_free_block,
+        uxTaskGetStackHighWaterMark, xTaskGetCurrentTaskHandle, xTaskGetHandle, TaskHandle_t,
+        MALLOC_CAP_8BIT,
+    },
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    any::Any,
+    panic::Location,
+    sync::{Arc, OnceLock},
+};
+
+/// 诊断信息单独使用一个命名空间，不受配置迁移和安全模式影响
+const NAMESPACE: &str = "diagnostics";
+const BOOT_COUNT: &str = "boot_count";
+const CRASH_COUNT: &str = "crash_count";
+const PANIC: &str = "panic";
+const SELF_TEST: &str = "self_test";
+/// panic信息的最大长度
+const MAX_PANIC_LEN: usize = 256;
+
+/// 最近一次panic的信息，RISC-V上无法在运行时回溯调用栈，只记录panic的位置和线程
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PanicRecord {
+    pub message: String,
+    /// panic发生的源码位置
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thread: Option<String>,
+    /// 发生时的启动次数，用于判断是哪次运行中的panic
+    pub boot: u32,
+    /// 发生时的开机时长，单位：秒
+    pub uptime: u64,
+}
+
+/// 诊断特征的数据
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnostics {
+    pub boot_count: u32,
+    /// 异常复位（panic、看门狗、欠压）的累计次数
+    pub crash_count: u32,
+    pub reset_reason: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stuck_task: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_panic: Option<PanicRecord>,
+    /// 本次启动的自检结果
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub self_test: Option<SelfTest>,
+    /// 上次启动的自检结果，自检失败导致重启时据此排查
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_self_test: Option<SelfTest>,
+}
+
+/// 任务栈的使用情况
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskStack {
+    pub name: String,
+    /// 运行以来栈的最小剩余空间，单位：字节
+    pub free_stack: u32,
+}
+
+/// 内存特征的数据，每次读取时重新统计
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryStats {
+    /// 当前空闲堆内存，单位：字节
+    pub free_heap: u32,
+    /// 启动以来空闲堆内存的最小值
+    pub min_free_heap: u32,
+    /// 最大的连续空闲块，远小于空闲堆内存时说明碎片较多
+    pub largest_free_block: u32,
+    pub tasks: Vec<TaskStack>,
+}
+
+/// 不是通过`track_stack`登记的系统任务
+const SYSTEM_TASKS: [&std::ffi::CStr; 2] = [c"nimble_host", c"esp_timer"];
+
+static DIAGNOSTICS: std::sync::Mutex<Option<Diagnostics>> = std::sync::Mutex::new(None);
+static NVS: OnceLock<Arc<Mutex<EspNvs<NvsDefault>>>> = OnceLock::new();
+/// 登记的任务名称和句柄，句柄保存为整数以便放入静态变量
+static TASKS: std::sync::Mutex<Vec<(String, usize)>> = std::sync::Mutex::new(Vec::new());
+
+fn read_json<T: DeserializeOwned>(nvs: &EspNvs<NvsDefault>, key: &str) -> Result<Option<T>> {
+    let Some(len) = nvs.blob_len(key)? else {
+        return Ok(None);
+    };
+    let mut data = vec![0u8; len];
+    let Some(data) = nvs.get_blob(key, &mut data)? else {
+        return Ok(None);
+    };
+    Ok(serde_json::from_slice(data).ok())
+}
+
+fn record_panic(
+    nvs: &Mutex<EspNvs<NvsDefault>>,
+    boot: u32,
+    payload: &(dyn Any + Send),
+    location: Option<&Location>,
+) -> Result<()> {
+    let mut message = if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".into()
+    };
+    if message.len() > MAX_PANIC_LEN {
+        let mut end = MAX_PANIC_LEN;
+        while !message.is_char_boundary(end) {
+            end -= 1;
+        }
+        message.truncate(end);
+    }
+    let record = PanicRecord {
+        message,
+        location: location.map(|location| format!("{}:{}", location.file(), location.line())),
+        thread: std::thread::current().name().map(str::to_string),
+        boot,
+        uptime: unsafe { esp_idf_svc::sys::esp_timer_get_time() } as u64 / 1_000_000,
+    };
+    nvs.lock().set_blob(PANIC, &serde_json::to_vec(&record)?)?;
+    Ok(())
+}
+
+/// 启动时最先调用：累加启动次数，记录复位原因，并登记panic钩子
+pub fn init(nvs_partition: EspNvsPartition<NvsDefault>) -> Result<()> {
+    let mut nvs = EspNvs::new(nvs_partition, NAMESPACE, true)?;
+    let boot_count = nvs.get_u32(BOOT_COUNT)?.unwrap_or(0).wrapping_add(1);
+    nvs.set_u32(BOOT_COUNT, boot_count)?;
+
+    let reason = unsafe { esp_reset_reason() };
+    #[allow(non_upper_case_globals)]
+    let crashed = matches!(
+        reason,
+        esp_reset_reason_t_ESP_RST_PANIC
+            | esp_reset_reason_t_ESP_RST_INT_WDT
+            | esp_reset_reason_t_ESP_RST_TASK_WDT
+            | esp_reset_reason_t_ESP_RST_WDT
+            | esp_reset_reason_t_ESP_RST_BROWNOUT
+    );
+    let mut crash_count = nvs.get_u32(CRASH_COUNT)?.unwrap_or(0);
+    if crashed {
+        crash_count = crash_count.wrapping_add(1);
+        nvs.set_u32(CRASH_COUNT, crash_count)?;
+    }
+
+    *DIAGNOSTICS.lock().unwrap() = Some(Diagnostics {
+        boot_count,
+        crash_count,
+        reset_reason: watchdog::reason_name(reason).into(),
+        stuck_task: None,
+        last_panic: read_json(&nvs, PANIC)?,
+        self_test: None,
+        last_self_test: read_json(&nvs, SELF_TEST)?,
+    });
+
+    let nvs = Arc::new(Mutex::new(nvs));
+    let _ = NVS.set(nvs.clone());
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if let Err(e) = record_panic(&nvs, boot_count, info.payload(), info.location()) {
+            log::error!("record panic error: {e}");
+        }
+        default_hook(info);
+    }));
+    Ok(())
+}
+
+/// 保存本次启动的自检结果，下次启动后仍能读取
+pub fn record_self_test(self_test: &SelfTest) -> Result<()> {
+    if let Some(diagnostics) = DIAGNOSTICS.lock().unwrap().as_mut() {
+        diagnostics.self_test = Some(self_test.clone());
+    }
+    if let Some(nvs) = NVS.get() {
+        nvs.lock()
+            .set_blob(SELF_TEST, &serde_json::to_vec(self_test)?)?;
+    }
+    Ok(())
+}
+
+/// 登记当前任务，读取内存特征时报告它的栈剩余空间
+///
+/// 任务退出后句柄失效，只能在不会退出的任务中调用
+pub fn track_stack(name: impl Into<String>) {
+    let handle = unsafe { xTaskGetCurrentTaskHandle() } as usize;
+    TASKS.lock().unwrap().push((name.into(), handle));
+}
+
+/// 当前的堆内存和各任务栈的使用情况
+pub fn memory() -> MemoryStats {
+    let mut tasks: Vec<TaskStack> = TASKS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(name, handle)| TaskStack {
+            name: name.clone(),
+            free_stack: unsafe { uxTaskGetStackHighWaterMark(*handle as TaskHandle_t) },
+        })
+        .collect();
+    for name in SYSTEM_TASKS {
+        let handle = unsafe { xTaskGetHandle(name.as_ptr()) };
+        if !handle.is_null() {
+            tasks.push(TaskStack {
+                name: name.to_string_lossy().into(),
+                free_stack: unsafe { uxTaskGetStackHighWaterMark(handle) },
+            });
+        }
+    }
+    unsafe {
+        MemoryStats {
+            free_heap: esp_get_free_heap_size(),
+            min_free_heap: esp_get_minimum_free_heap_size(),
+            largest_free_block: heap_caps_get_largest_free_block(MALLOC_CAP_8BIT) as u32,
+            tasks,
+        }
+    }
+}
+
+/// 诊断信息，看门狗记录的卡死任务在看门狗启动后才能读取到
+pub fn diagnostics() -> Diagnostics {
+    let mut diagnostics = DIAGNOSTICS.lock().unwrap().clone().unwrap_or_default();
+    diagnostics.stuck_task = watchdog::last_reset().and_then(|record| record.stuck_task);
+    diagnostics
+}
@@ -0,0 +1,48 @@
+use crate::led::{Led, RGB8};
+use crate::modifier::Modifier;
+use anyhow::Result;
+use esp_idf_svc::timer::EspAsyncTimer;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+pub use smart_brite_core::effect::{create, Effect};
+
+/// 按固定帧率渲染效果，每帧整条灯带一次写入
+///
+/// 按预定时间点推进，某一帧耗时较长时后面的帧会补上，不会累积误差
+pub async fn render(
+    mut async_timer: EspAsyncTimer,
+    led: Arc<Mutex<Led<'_>>>,
+    mut effect: Box<dyn Effect>,
+    modifier: Modifier,
+    fps: u8,
+) -> Result<()> {
+    let interval = Duration::from_secs_f32(1.0 / fps.max(1) as f32);
+    let mut next = Instant::now();
+    let mut last = next;
+    let mut t = 0f32;
+    loop {
+        let frame: Vec<RGB8> = effect
+            .frame(t)
+            .into_iter()
+            .map(|color| modifier.apply(color))
+            .collect();
+        // 注意防止死锁，计算完成后再获取锁，尽快释放
+        led.lock().unwrap().set_pixels(&frame)?;
+        if effect.is_static() && !modifier.is_active() {
+            return Ok(());
+        }
+
+        next += interval;
+        let now = Instant::now();
+        if next < now {
+            // 落后超过一帧时不再追赶，从现在重新计时
+            next = now;
+        }
+        async_timer.after(next - now).await?;
+        t += last.elapsed().as_secs_f32() * modifier.speed();
+        last = Instant::now();
+    }
+}
@@ -0,0 +1,102 @@
+use crate::{
+    ble::BleControl,
+    light::{LightEvent, LightEventSender, LightState},
+    power,
+};
+use anyhow::Result;
+use esp_idf_svc::hal::{
+    delay::BLOCK,
+    gpio::{AnyIOPin, InterruptType, PinDriver, Pull},
+    task::notification::Notification,
+};
+use std::{
+    num::NonZeroU32,
+    time::{Duration, Instant},
+};
+
+/// 每转过一格调节的亮度百分比
+const BRIGHTNESS_STEP: i32 = 5;
+/// 旋转调暗时的最低亮度，关灯使用按键
+const MIN_BRIGHTNESS: i32 = 5;
+/// 常见编码器每格产生4次相位变化
+const STEPS_PER_DETENT: i32 = 4;
+/// 按键消抖时间
+const DEBOUNCE: Duration = Duration::from_millis(30);
+
+/// 以`上一状态 << 2 | 当前状态`为下标的相位变化方向，状态为`A << 1 | B`，
+/// 同时变化的两相视为抖动
+const TRANSITIONS: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+
+const A_BIT: u32 = 1 << 0;
+const B_BIT: u32 = 1 << 1;
+const BUTTON_BIT: u32 = 1 << 2;
+
+/// 启动开发板配置中的旋转编码器，旋转调节亮度，按下开关灯
+///
+/// ESP32-C3没有PCNT外设，A、B两相的边沿中断在线程中按状态表解码；
+/// 只在开灯时响应旋转，避免误触开灯
+pub fn start(ble_control: BleControl, mut light_sender: LightEventSender) -> Result<()> {
+    let Some(config) = ble_control.nvs_store.board.lock().encoder.clone() else {
+        return Ok(());
+    };
+    // 引脚已由开发板配置校验
+    let mut a = PinDriver::input(unsafe { AnyIOPin::new(config.a_pin as i32) })?;
+    let mut b = PinDriver::input(unsafe { AnyIOPin::new(config.b_pin as i32) })?;
+    let mut button = PinDriver::input(unsafe { AnyIOPin::new(config.button_pin as i32) })?;
+    for pin in [&mut a, &mut b, &mut button] {
+        pin.set_pull(Pull::Up)?;
+        pin.set_interrupt_type(InterruptType::AnyEdge)?;
+    }
+
+    std::thread::spawn(move || -> Result<()> {
+        let notification = Notification::new();
+        for (pin, bit) in [(&mut a, A_BIT), (&mut b, B_BIT), (&mut button, BUTTON_BIT)] {
+            let notifier = notification.notifier();
+            unsafe {
+                pin.subscribe(move || {
+                    notifier.notify_and_yield(NonZeroU32::new(bit).unwrap());
+                })?;
+            }
+        }
+
+        let mut state = (a.is_high() as usize) << 1 | b.is_high() as usize;
+        let mut steps = 0;
+        let mut pressed_at: Option<Instant> = None;
+        loop {
+            for pin in [&mut a, &mut b, &mut button] {
+                pin.enable_interrupt()?;
+            }
+            let Some(bits) = notification.wait(BLOCK) else {
+                continue;
+            };
+            power::activity();
+
+            if bits.get() & (A_BIT | B_BIT) != 0 {
+                let current = (a.is_high() as usize) << 1 | b.is_high() as usize;
+                steps += TRANSITIONS[state << 2 | current] as i32;
+                state = current;
+                let detents = steps / STEPS_PER_DETENT;
+                if detents != 0 {
+                    steps -= detents * STEPS_PER_DETENT;
+                    if matches!(ble_control.get_state(), LightState::Opened) {
+                        let brightness = *ble_control.brightness.lock() as i32;
+                        let brightness =
+                            (brightness + detents * BRIGHTNESS_STEP).clamp(MIN_BRIGHTNESS, 100);
+                        light_sender.send(LightEvent::OpenAt(brightness as u8))?;
+                    }
+                }
+            }
+
+            if bits.get() & BUTTON_BIT != 0 {
+                // 上拉输入，低电平表示按下，松开时开关灯
+                if button.is_low() {
+                    pressed_at = Some(Instant::now());
+                    ble_control.wake_advertising();
+                } else if pressed_at.take().is_some_and(|t| t.elapsed() >= DEBOUNCE) {
+                    light_sender.toggle()?;
+                }
+            }
+        }
+    });
+    Ok(())
+}
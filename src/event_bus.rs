@@ -0,0 +1,144 @@
+use crate::{
+    light::{LightEvent, LightState},
+    report::ErrorEvent,
+    timer::TimerEvent,
+};
+use futures::channel::mpsc::{unbounded, UnboundedReceiver};
+use std::sync::{
+    mpsc::{self, Receiver},
+    Mutex,
+};
+
+/// BLE连接事件，附带连接句柄
+#[derive(Debug, Clone)]
+pub enum ConnectionEvent {
+    Connected(u16),
+    Disconnected(u16),
+}
+
+/// 传感器读数更新
+#[derive(Debug, Clone)]
+pub enum SensorEvent {
+    /// 平滑后的环境光亮度，取值0-1
+    Ambient(f32),
+    /// 人体感应引脚的电平，true为有人
+    Motion(bool),
+    /// 芯片温度，单位：摄氏度
+    Temperature(f32),
+    /// 电池电量百分比
+    Battery(u8),
+}
+
+/// 数据更新，与BLE的数据更新通知对应，附带最新的JSON数据
+#[derive(Debug, Clone)]
+pub struct DataChange {
+    /// 数据类别，如`state`、`scene`、`tasks`
+    pub topic: &'static str,
+    pub data: Vec<u8>,
+}
+
+/// 事件总线上传递的所有事件
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// 交给灯光事件循环处理的操作
+    Light(LightEvent),
+    /// 交给定时任务管理处理的操作
+    Timer(TimerEvent),
+    /// 灯光状态变化后发布
+    State(LightState),
+    Connection(ConnectionEvent),
+    Sensor(SensorEvent),
+    Change(DataChange),
+    /// 通过`report::error`记录的错误
+    Error(ErrorEvent),
+}
+
+/// 可以单独订阅的事件类型
+pub trait Topic: Clone + Send + Into<Event> + 'static {
+    fn from_event(event: &Event) -> Option<&Self>;
+}
+
+macro_rules! topic {
+    ($variant:ident, $ty:ty) => {
+        impl From<$ty> for Event {
+            fn from(event: $ty) -> Self {
+                Event::$variant(event)
+            }
+        }
+
+        impl Topic for $ty {
+            fn from_event(event: &Event) -> Option<&Self> {
+                match event {
+                    Event::$variant(event) => Some(event),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+topic!(Light, LightEvent);
+topic!(Timer, TimerEvent);
+topic!(State, LightState);
+topic!(Connection, ConnectionEvent);
+topic!(Sensor, SensorEvent);
+topic!(Change, DataChange);
+topic!(Error, ErrorEvent);
+
+/// 订阅者处理事件的结果
+enum Delivery {
+    /// 不是订阅的事件类型
+    Skipped,
+    Delivered,
+    /// 接收端已经丢弃，移除该订阅者
+    Closed,
+}
+
+type Subscriber = Box<dyn Fn(&Event) -> Delivery + Send>;
+
+static SUBSCRIBERS: Mutex<Vec<Subscriber>> = Mutex::new(Vec::new());
+
+fn add<T: Topic>(send: impl Fn(T) -> bool + Send + 'static) {
+    SUBSCRIBERS
+        .lock()
+        .unwrap()
+        .push(Box::new(move |event| match T::from_event(event) {
+            Some(event) if send(event.clone()) => Delivery::Delivered,
+            Some(_) => Delivery::Closed,
+            None => Delivery::Skipped,
+        }));
+}
+
+/// 发布事件，返回收到事件的订阅者数量
+///
+/// 只有订阅之后发布的事件才会收到，订阅需要在发布者启动前完成
+pub fn publish(event: impl Into<Event>) -> usize {
+    let event = event.into();
+    let mut delivered = 0;
+    SUBSCRIBERS
+        .lock()
+        .unwrap()
+        .retain(|subscriber| match subscriber(&event) {
+            Delivery::Skipped => true,
+            Delivery::Delivered => {
+                delivered += 1;
+                true
+            }
+            Delivery::Closed => false,
+        });
+    delivered
+}
+
+/// 订阅一类事件，在线程中阻塞接收
+pub fn subscribe<T: Topic>() -> Receiver<T> {
+    let (tx, rx) = mpsc::channel();
+    add(move |event: T| tx.send(event).is_ok());
+    rx
+}
+
+/// 订阅一类事件，在异步任务中接收
+pub fn subscribe_async<T: Topic>() -> UnboundedReceiver<T> {
+    let (tx, rx) = unbounded();
+    add(move |event: T| tx.unbounded_send(event).is_ok());
+    rx
+}
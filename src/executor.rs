@@ -0,0 +1,38 @@
+use crate::diagnostics;
+use anyhow::Result;
+use futures::{
+    future::{pending, FutureObj},
+    task::{Spawn, SpawnError},
+};
+
+/// 执行线程的栈大小，所有异步任务共用
+const STACK_SIZE: usize = 12 * 1024;
+
+static EXECUTOR: edge_executor::Executor<'static> = edge_executor::Executor::new();
+
+/// 单线程执行器，所有异步任务在同一个线程中轮询
+///
+/// ESP32-C3只有一个核心，多个线程并不能并行执行，只用一个线程可以省下其余线程的栈；
+/// 任务中不能长时间阻塞，否则其他任务都会被推迟
+#[derive(Clone, Copy)]
+pub struct Executor(&'static edge_executor::Executor<'static>);
+
+impl Executor {
+    /// 启动执行线程，其他线程中也可以提交任务
+    pub fn start() -> Result<Self> {
+        std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(|| {
+                diagnostics::track_stack("executor");
+                futures::executor::block_on(EXECUTOR.run(pending::<()>()))
+            })?;
+        Ok(Self(&EXECUTOR))
+    }
+}
+
+impl Spawn for Executor {
+    fn spawn_obj(&self, future: FutureObj<'static, ()>) -> Result<(), SpawnError> {
+        self.0.spawn(future).detach();
+        Ok(())
+    }
+}
@@ -0,0 +1,283 @@
+use crate::{
+    ble::BleControl,
+    device_info,
+    diagnostics::{self, MemoryStats},
+    event_bus::{self, DataChange},
+    light::{LightEvent, LightEventSender},
+    ota,
+    store::Scene,
+    sync,
+    timer::{TimerEvent, TimerEventSender},
+};
+use anyhow::{anyhow, bail, Result};
+use esp_idf_svc::{
+    http::{
+        server::{
+            ws::{EspHttpWsConnection, EspHttpWsDetachedSender},
+            Configuration, EspHttpConnection, EspHttpServer, Request,
+        },
+        Method,
+    },
+    io::{Read, Write},
+    ws::FrameType,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::sync::{Arc, Mutex};
+
+/// 请求体的最大长度
+const MAX_BODY_SIZE: usize = 8 * 1024;
+/// 同时推送的WebSocket客户端数量，每个客户端都占用一个套接字
+const MAX_WS_CLIENTS: usize = 3;
+/// 客户端发来的帧只读取后丢弃
+const MAX_WS_FRAME: usize = 128;
+/// 网页控制台，编译时嵌入固件，不需要单独的文件系统分区
+const INDEX_HTML: &str = include_str!("../web/index.html");
+
+/// `GET /info`返回的固件信息
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FirmwareInfo {
+    firmware: &'static str,
+    name: String,
+    mac: String,
+    /// 开机时长，单位：秒
+    uptime: u64,
+    memory: MemoryStats,
+}
+
+pub(crate) type HttpRequest<'a, 'b> = Request<&'a mut EspHttpConnection<'b>>;
+
+pub(crate) fn read_json<T: DeserializeOwned>(req: &mut HttpRequest) -> Result<T> {
+    let len = req.content_len().unwrap_or(0) as usize;
+    if len > MAX_BODY_SIZE {
+        bail!("Body too large");
+    }
+    let mut body = vec![0u8; len];
+    req.read_exact(&mut body)
+        .map_err(|e| anyhow!("Read body failed: {e:?}"))?;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+/// 成功返回JSON，失败返回400和错误信息
+pub(crate) fn respond(req: HttpRequest, res: Result<Vec<u8>>) -> Result<()> {
+    match res {
+        Ok(body) => {
+            req.into_response(200, None, &[("Content-Type", "application/json")])?
+                .write_all(&body)?;
+        }
+        Err(e) => {
+            req.into_response(400, None, &[("Content-Type", "text/plain")])?
+                .write_all(e.to_string().as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// 与蓝牙特征相同的写入检查：已配对时需要带上`Authorization: Bearer <十六进制令牌>`，
+/// 开启儿童锁时只能读取，失败时返回状态码和原因
+fn check_write(ble: &BleControl, req: &HttpRequest) -> Result<(), (u16, &'static str)> {
+    let token = req
+        .header("Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if !ble.auth.verify(token) {
+        return Err((401, "Unauthorized"));
+    }
+    if *ble.nvs_store.child_lock.lock() {
+        return Err((403, "Child lock enabled"));
+    }
+    Ok(())
+}
+
+fn reject(req: HttpRequest, status: u16, message: &str) -> Result<()> {
+    req.into_response(status, None, &[("Content-Type", "text/plain")])?
+        .write_all(message.as_bytes())?;
+    Ok(())
+}
+
+/// 推送消息，`data`为与对应BLE特征相同的JSON数据
+fn ws_message(topic: &str, data: &[u8]) -> String {
+    format!(
+        r#"{{"type":"{topic}","data":{}}}"#,
+        String::from_utf8_lossy(data)
+    )
+}
+
+/// 连接后先推送当前状态，之后转发事件总线上的数据更新
+fn start_push(server: &mut EspHttpServer<'static>, ble_control: BleControl) -> Result<()> {
+    let clients = Arc::new(Mutex::new(Vec::<EspHttpWsDetachedSender>::new()));
+
+    let clients_clone = clients.clone();
+    server.ws_handler("/events", move |ws: &mut EspHttpWsConnection| {
+        if ws.is_new() {
+            let mut clients = clients_clone.lock().unwrap();
+            if clients.len() >= MAX_WS_CLIENTS {
+                log::warn!("too many websocket clients");
+                return Ok(());
+            }
+            clients.push(ws.create_detached_sender()?);
+            if let Ok(state) = ble_control.state_value() {
+                ws.send(
+                    FrameType::Text(false),
+                    ws_message("state", &state).as_bytes(),
+                )?;
+            }
+            return Ok(());
+        }
+        if ws.is_closed() {
+            return Ok(());
+        }
+        // 不处理客户端的消息，但需要读出，否则连接无法继续使用
+        let mut buf = [0u8; MAX_WS_FRAME];
+        if let Err(e) = ws.recv(&mut buf) {
+            log::debug!("websocket recv error: {e}");
+        }
+        Ok::<(), anyhow::Error>(())
+    })?;
+
+    let changes = event_bus::subscribe::<DataChange>();
+    std::thread::Builder::new()
+        .stack_size(4 * 1024)
+        .spawn(move || {
+            diagnostics::track_stack("ws");
+            for change in changes {
+                let message = ws_message(change.topic, &change.data);
+                // 发送失败说明连接已经断开
+                clients.lock().unwrap().retain_mut(|client| {
+                    !client.is_closed()
+                        && client
+                            .send(FrameType::Text(false), message.as_bytes())
+                            .is_ok()
+                });
+            }
+        })?;
+    Ok(())
+}
+
+/// 创建不带接口的HTTP服务，Hue模拟的接口路径中带有用户名，需要通配符匹配
+pub fn server() -> Result<EspHttpServer<'static>> {
+    Ok(EspHttpServer::new(&Configuration {
+        uri_match_wildcard: true,
+        ..Default::default()
+    })?)
+}
+
+/// 启动HTTP服务，与蓝牙共用同一套事件通道
+///
+/// - `GET /`：网页控制台，可调色、编辑定时任务和查看固件信息
+/// - `GET /info`：固件版本、设备名称、MAC地址和内存统计
+/// - `GET /state`：灯光状态，格式与状态特征相同
+/// - `PUT /state`：灯光控制，如`"open"`、`"close"`、`{"set_scene":"name"}`，不能恢复出厂设置和重启
+/// - `GET /scene`、`PUT /scene`：当前场景
+/// - `GET /tasks`：定时任务列表
+/// - `PUT /tasks`：定时任务事件，格式与蓝牙定时任务特征相同
+/// - `POST /ota`：固件升级，请求体为签名后的固件镜像，安装成功后重启
+/// - `/events`：WebSocket，推送状态、场景和定时任务列表的变化，消息格式如`{"type":"state","data":{...}}`
+///
+/// `PUT`请求与蓝牙写入一样需要鉴权令牌，开启儿童锁时返回403。
+/// 返回的服务需要一直持有，释放后服务停止
+pub fn start(
+    ble_control: BleControl,
+    light_sender: LightEventSender,
+    timer_sender: TimerEventSender,
+) -> Result<EspHttpServer<'static>> {
+    let mut server = server()?;
+
+    server.fn_handler("/", Method::Get, |req| {
+        req.into_response(200, None, &[("Content-Type", "text/html; charset=utf-8")])?
+            .write_all(INDEX_HTML.as_bytes())
+    })?;
+
+    let ble = ble_control.clone();
+    server.fn_handler("/info", Method::Get, move |req| {
+        let info = FirmwareInfo {
+            firmware: device_info::FIRMWARE_VERSION,
+            name: ble.nvs_store.settings.lock().name.clone(),
+            mac: sync::local_mac(),
+            uptime: unsafe { esp_idf_svc::sys::esp_timer_get_time() } as u64 / 1_000_000,
+            memory: diagnostics::memory(),
+        };
+        respond(req, serde_json::to_vec(&info).map_err(Into::into))
+    })?;
+
+    let ble = ble_control.clone();
+    server.fn_handler("/state", Method::Get, move |req| {
+        respond(req, ble.state_value())
+    })?;
+
+    // 写入完成后先返回结果再重启
+    let ble = ble_control.clone();
+    let mut light = light_sender.clone();
+    server.fn_handler("/ota", Method::Post, move |mut req| {
+        if let Err((status, message)) = check_write(&ble, &req) {
+            return reject(req, status, message);
+        }
+        let len = req.content_len().unwrap_or(0) as usize;
+        let res = ota::install(&mut req, len);
+        let installed = res.is_ok();
+        respond(req, res.and_then(|_| Ok(serde_json::to_vec("installed")?)))?;
+        if installed {
+            light.send(LightEvent::Reboot)?;
+        }
+        Ok(())
+    })?;
+
+    // 事件异步处理，返回接受的事件，最新状态通过`GET /state`获取
+    let ble = ble_control.clone();
+    server.fn_handler("/state", Method::Put, move |mut req| {
+        if let Err((status, message)) = check_write(&ble, &req) {
+            return reject(req, status, message);
+        }
+        let res = read_json::<LightEvent>(&mut req).and_then(|event| {
+            // 恢复出厂设置和重启只能通过蓝牙或按键操作
+            if matches!(event, LightEvent::FactoryReset | LightEvent::Reboot) {
+                bail!("Not allowed over HTTP");
+            }
+            let body = serde_json::to_vec(&event)?;
+            light_sender.clone().send(event)?;
+            Ok(body)
+        });
+        respond(req, res)
+    })?;
+
+    let ble = ble_control.clone();
+    server.fn_handler("/scene", Method::Get, move |req| {
+        let res = ble.nvs_store.scene.lock().to_u8();
+        respond(req, res)
+    })?;
+
+    let ble = ble_control.clone();
+    server.fn_handler("/scene", Method::Put, move |mut req| {
+        if let Err((status, message)) = check_write(&ble, &req) {
+            return reject(req, status, message);
+        }
+        let res = read_json::<Scene>(&mut req).and_then(|scene| {
+            ble.nvs_store.set_scene(scene.clone())?;
+            ble.set_scene(&scene)?;
+            scene.to_u8()
+        });
+        respond(req, res)
+    })?;
+
+    start_push(&mut server, ble_control.clone())?;
+
+    let ble = ble_control.clone();
+    server.fn_handler("/tasks", Method::Get, move |req| {
+        let res = serde_json::to_vec(&*ble.nvs_store.time_task.lock()).map_err(Into::into);
+        respond(req, res)
+    })?;
+
+    let ble = ble_control;
+    server.fn_handler("/tasks", Method::Put, move |mut req| {
+        if let Err((status, message)) = check_write(&ble, &req) {
+            return reject(req, status, message);
+        }
+        let res = read_json::<TimerEvent>(&mut req).and_then(|event| {
+            let body = serde_json::to_vec(&event)?;
+            timer_sender.clone().send(event)?;
+            Ok(body)
+        });
+        respond(req, res)
+    })?;
+
+    Ok(server)
+}
@@ -0,0 +1,404 @@
+//! 模拟Philips Hue网桥，语音助手在局域网中发现后即可控制，不需要云端账号或Matter
+//!
+//! - SSDP：响应`M-SEARCH`，告知`description.xml`的地址
+//! - `GET /description.xml`：UPnP设备描述
+//! - `POST /api`：创建用户，需要在此之前30秒内按下设备上的按键，相当于网桥的配对按键
+//! - `GET /api/{user}/lights`、`GET /api/{user}/lights/1`：灯光状态，只接受已创建的用户名
+//! - `PUT /api/{user}/lights/1/state`：开关、亮度、色相饱和度、色温和色度坐标，童锁开启时拒绝
+
+use crate::{
+    ble::BleControl,
+    diagnostics,
+    http::{read_json, respond, HttpRequest},
+    light::{LightEvent, LightEventSender, LightState},
+    sync::local_mac,
+    wifi::Wifi,
+};
+use anyhow::{bail, Result};
+use esp_idf_svc::{
+    http::{server::EspHttpServer, Method},
+    io::Write,
+};
+use rgb::RGB8;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use smart_brite_core::{
+    brightness::{level_to_percent, percent_to_level},
+    color::{hsv_to_rgb, mireds_to_rgb, xy_to_rgb, MAX_XY},
+};
+use std::{
+    net::{Ipv4Addr, UdpSocket},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+const SSDP_ADDR: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+const SSDP_PORT: u16 = 1900;
+/// 未连接Wi-Fi或套接字出错后重试的间隔
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+/// 只模拟一盏灯
+const LIGHT_ID: &str = "1";
+/// 按下按键后允许创建用户的时间
+const LINK_WINDOW: Duration = Duration::from_secs(30);
+/// 最多保存的用户数，超过后移除最早创建的
+const MAX_USERS: usize = 8;
+
+/// 最近一次按下按键的时间
+static LINK_PRESSED: Mutex<Option<Instant>> = Mutex::new(None);
+/// 已创建的用户名，启动时从NVS读取
+static USERS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// 客户端最后设置的颜色，灯光事件中只有RGB，读取状态时原样返回
+#[derive(Clone, Copy)]
+struct HueColor {
+    hue: u16,
+    sat: u8,
+    ct: u16,
+    xy: [f32; 2],
+    colormode: &'static str,
+}
+
+static COLOR: Mutex<HueColor> = Mutex::new(HueColor {
+    hue: 0,
+    sat: 0,
+    ct: 370,
+    xy: [0.4573, 0.41],
+    colormode: "ct",
+});
+
+/// `PUT /api/{user}/lights/1/state`的请求体，未包含的字段保持不变
+#[derive(Debug, Deserialize)]
+struct StateUpdate {
+    on: Option<bool>,
+    /// 亮度，1-254
+    bri: Option<u8>,
+    /// 色相，0-65535
+    hue: Option<u16>,
+    /// 饱和度，0-254
+    sat: Option<u8>,
+    /// 色温，单位：mired
+    ct: Option<u16>,
+    xy: Option<[f32; 2]>,
+}
+
+/// 与Hue网桥相同格式的网桥ID，由MAC地址中间插入`FFFE`得到
+fn bridge_id(mac: &str) -> String {
+    format!("{}FFFE{}", &mac[..6], &mac[6..]).to_uppercase()
+}
+
+fn description(ip: Ipv4Addr, name: &str, mac: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+<root xmlns="urn:schemas-upnp-org:device-1-0">
+<specVersion><major>1</major><minor>0</minor></specVersion>
+<URLBase>http://{ip}:80/</URLBase>
+<device>
+<deviceType>urn:schemas-upnp-org:device:Basic:1</deviceType>
+<friendlyName>{name} ({ip})</friendlyName>
+<manufacturer>Royal Philips Electronics</manufacturer>
+<manufacturerURL>http://www.philips.com</manufacturerURL>
+<modelDescription>Philips hue Personal Wireless Lighting</modelDescription>
+<modelName>Philips hue bridge 2012</modelName>
+<modelNumber>929000226503</modelNumber>
+<modelURL>http://www.meethue.com</modelURL>
+<serialNumber>{mac}</serialNumber>
+<UDN>uuid:2f402f80-da50-11e1-9b23-{mac}</UDN>
+<presentationURL>index.html</presentationURL>
+</device>
+</root>
+"#
+    )
+}
+
+fn ssdp_response(ip: Ipv4Addr, mac: &str) -> String {
+    format!(
+        "HTTP/1.1 200 OK\r\n\
+         CACHE-CONTROL: max-age=100\r\n\
+         EXT:\r\n\
+         LOCATION: http://{ip}:80/description.xml\r\n\
+         SERVER: FreeRTOS/6.0.5, UPnP/1.0, IpBridge/1.17.0\r\n\
+         hue-bridgeid: {}\r\n\
+         ST: urn:schemas-upnp-org:device:basic:1\r\n\
+         USN: uuid:2f402f80-da50-11e1-9b23-{mac}::upnp:rootdevice\r\n\r\n",
+        bridge_id(mac)
+    )
+}
+
+/// 只响应搜索网桥或所有设备的请求
+fn is_bridge_search(request: &str) -> bool {
+    request.starts_with("M-SEARCH")
+        && ["ssdp:all", "upnp:rootdevice", "device:basic:1"]
+            .iter()
+            .any(|target| request.contains(target))
+}
+
+fn serve_ssdp(wifi: &Wifi, mac: &str) -> Result<()> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, SSDP_PORT))?;
+    socket.join_multicast_v4(&SSDP_ADDR, &Ipv4Addr::UNSPECIFIED)?;
+    let mut buf = [0u8; 1024];
+    loop {
+        let (len, from) = socket.recv_from(&mut buf)?;
+        if !is_bridge_search(&String::from_utf8_lossy(&buf[..len])) {
+            continue;
+        }
+        // 断线重连后地址可能变化，需要重新加入组播
+        let Some(ip) = wifi.ip() else {
+            bail!("Wi-Fi disconnected");
+        };
+        socket.send_to(ssdp_response(ip, mac).as_bytes(), from)?;
+    }
+}
+
+/// 灯光的Hue格式状态
+fn light_value(ble_control: &BleControl) -> Value {
+    let on = !matches!(ble_control.get_state(), LightState::Closed);
+    let bri = percent_to_level(*ble_control.brightness.lock()).max(1);
+    let color = *COLOR.lock().unwrap();
+    let name = ble_control.nvs_store.settings.lock().name.clone();
+    json!({
+        "state": {
+            "on": on,
+            "bri": bri,
+            "hue": color.hue,
+            "sat": color.sat,
+            "ct": color.ct,
+            "xy": color.xy,
+            "alert": "none",
+            "effect": "none",
+            "colormode": color.colormode,
+            "mode": "homeautomation",
+            "reachable": true,
+        },
+        "type": "Extended color light",
+        "name": name,
+        "modelid": "LCT015",
+        "manufacturername": "Signify Netherlands B.V.",
+        "productname": "Hue color lamp",
+        "uniqueid": format!("{}-0b", local_mac()),
+        "swversion": "1.46.13_r26312",
+    })
+}
+
+fn error_value(address: &str, kind: u8, description: &str) -> Value {
+    json!([{
+        "error": {
+            "type": kind,
+            "address": address,
+            "description": description,
+        }
+    }])
+}
+
+/// 成功修改的字段，格式与Hue网桥相同
+fn success_value(success: &[(&str, Value)]) -> Value {
+    success
+        .iter()
+        .map(|(key, value)| {
+            let mut path = Map::new();
+            path.insert(format!("/lights/{LIGHT_ID}/state/{key}"), value.clone());
+            json!({ "success": path })
+        })
+        .collect()
+}
+
+/// 更新保存的颜色，请求中没有颜色时返回None；同时带有多种颜色时按色度坐标、色温、色相饱和度的顺序取一种
+fn update_color(update: &StateUpdate, success: &mut Vec<(&str, Value)>) -> Option<RGB8> {
+    let mut color = COLOR.lock().unwrap();
+    if let Some(xy) = update.xy {
+        color.xy = xy;
+        color.colormode = "xy";
+        success.push(("xy", json!(xy)));
+    } else if let Some(ct) = update.ct {
+        color.ct = ct;
+        color.colormode = "ct";
+        success.push(("ct", ct.into()));
+    } else if update.hue.is_some() || update.sat.is_some() {
+        if let Some(hue) = update.hue {
+            color.hue = hue;
+            success.push(("hue", hue.into()));
+        }
+        if let Some(sat) = update.sat {
+            color.sat = sat;
+            success.push(("sat", sat.into()));
+        }
+        color.colormode = "hs";
+    } else {
+        return None;
+    }
+    Some(match color.colormode {
+        "xy" => xy_to_rgb(
+            (color.xy[0].clamp(0.0, 1.0) * MAX_XY as f32) as u16,
+            (color.xy[1].clamp(0.0, 1.0) * MAX_XY as f32) as u16,
+        ),
+        "ct" => mireds_to_rgb(color.ct),
+        _ => hsv_to_rgb(
+            color.hue as f32 * 360.0 / 65536.0,
+            color.sat.min(254) as f32 / 254.0,
+            1.0,
+        ),
+    })
+}
+
+/// 转换为灯光事件，关灯时忽略其他字段；切换颜色会开灯并保持原亮度，再按需调整亮度
+fn apply(update: &StateUpdate, light_sender: &mut LightEventSender) -> Result<Value> {
+    let mut success = vec![];
+    if update.on == Some(false) {
+        light_sender.send(LightEvent::Close)?;
+        success.push(("on", false.into()));
+        return Ok(success_value(&success));
+    }
+    let color = update_color(update, &mut success);
+    if let Some(color) = color {
+        light_sender.send(LightEvent::SetColor(color))?;
+    }
+    match update.bri {
+        Some(bri) => {
+            light_sender.send(LightEvent::OpenAt(level_to_percent(bri.max(1))))?;
+            success.push(("bri", bri.into()));
+        }
+        None if update.on == Some(true) && color.is_none() => light_sender.open()?,
+        None => {}
+    }
+    if update.on == Some(true) {
+        success.push(("on", true.into()));
+    }
+    Ok(success_value(&success))
+}
+
+fn config_value(ble_control: &BleControl, wifi: &Wifi, mac: &str) -> Value {
+    json!({
+        "name": ble_control.nvs_store.settings.lock().name.clone(),
+        "bridgeid": bridge_id(mac),
+        "mac": local_mac(),
+        "ipaddress": wifi.ip().unwrap_or(Ipv4Addr::UNSPECIFIED).to_string(),
+        "modelid": "BSB002",
+        "apiversion": "1.17.0",
+        "swversion": "1941132080",
+    })
+}
+
+/// `/api/{user}/...`中的用户名和之后的部分
+fn resource(req: &HttpRequest) -> (String, String, Vec<String>) {
+    let path = req.uri().split('?').next().unwrap_or_default().to_string();
+    let mut segments = path
+        .trim_matches('/')
+        .split('/')
+        .skip(1)
+        .map(str::to_string);
+    let user = segments.next().unwrap_or_default();
+    (path, user, segments.collect())
+}
+
+fn authorized(user: &str) -> bool {
+    USERS.lock().unwrap().iter().any(|known| known == user)
+}
+
+/// 按下设备上的按键，之后一段时间内允许创建用户
+pub fn press_link_button() {
+    *LINK_PRESSED.lock().unwrap() = Some(Instant::now());
+}
+
+/// 创建随机用户名并保存，未按下按键时返回与Hue网桥相同的错误
+fn create_user(ble_control: &BleControl) -> Result<Value> {
+    let pressed = LINK_PRESSED
+        .lock()
+        .unwrap()
+        .is_some_and(|pressed| pressed.elapsed() < LINK_WINDOW);
+    if !pressed {
+        return Ok(error_value("", 101, "link button not pressed"));
+    }
+    let mut bytes = [0u8; 16];
+    unsafe { esp_idf_svc::sys::esp_fill_random(bytes.as_mut_ptr().cast(), bytes.len()) };
+    let username = bytes
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    let mut users = USERS.lock().unwrap();
+    let mut updated = users.clone();
+    if updated.len() >= MAX_USERS {
+        updated.remove(0);
+    }
+    updated.push(username.clone());
+    ble_control.nvs_store.write_hue_users(&updated)?;
+    *users = updated;
+    Ok(json!([{ "success": { "username": username } }]))
+}
+
+/// 在HTTP服务上注册Hue接口，并启动SSDP响应线程
+pub fn start(
+    server: &mut EspHttpServer<'static>,
+    wifi: Wifi,
+    ble_control: BleControl,
+    light_sender: LightEventSender,
+) -> Result<()> {
+    let mac = local_mac().replace(':', "");
+    *USERS.lock().unwrap() = ble_control.nvs_store.read_hue_users()?;
+
+    let ble = ble_control.clone();
+    let wifi_clone = wifi.clone();
+    let mac_clone = mac.clone();
+    server.fn_handler("/description.xml", Method::Get, move |req| {
+        let ip = wifi_clone.ip().unwrap_or(Ipv4Addr::UNSPECIFIED);
+        let name = ble.nvs_store.settings.lock().name.clone();
+        req.into_response(200, None, &[("Content-Type", "text/xml")])?
+            .write_all(description(ip, &name, &mac_clone).as_bytes())?;
+        Ok::<(), anyhow::Error>(())
+    })?;
+
+    let ble = ble_control.clone();
+    server.fn_handler("/api", Method::Post, move |req| {
+        respond(
+            req,
+            create_user(&ble).and_then(|value| Ok(serde_json::to_vec(&value)?)),
+        )
+    })?;
+
+    let ble = ble_control.clone();
+    let wifi_clone = wifi.clone();
+    let mac_clone = mac.clone();
+    server.fn_handler("/api/*", Method::Get, move |req| {
+        let (path, user, segments) = resource(&req);
+        let segments = segments.iter().map(String::as_str).collect::<Vec<_>>();
+        let value = match segments.as_slice() {
+            _ if !authorized(&user) => error_value(&path, 1, "unauthorized user"),
+            [] => json!({ "lights": { LIGHT_ID: light_value(&ble) } }),
+            ["lights"] => json!({ LIGHT_ID: light_value(&ble) }),
+            ["lights", LIGHT_ID] => light_value(&ble),
+            ["config"] => config_value(&ble, &wifi_clone, &mac_clone),
+            _ => error_value(&path, 3, "resource not available"),
+        };
+        respond(req, serde_json::to_vec(&value).map_err(Into::into))
+    })?;
+
+    let ble = ble_control;
+    server.fn_handler("/api/*", Method::Put, move |mut req| {
+        let (path, user, segments) = resource(&req);
+        let segments = segments.iter().map(String::as_str).collect::<Vec<_>>();
+        let res = match segments.as_slice() {
+            _ if !authorized(&user) => Ok(error_value(&path, 1, "unauthorized user")),
+            // 童锁开启时与BLE控制一样拒绝修改
+            ["lights", LIGHT_ID, "state"] if *ble.nvs_store.child_lock.lock() => {
+                Ok(error_value(&path, 201, "child lock enabled"))
+            }
+            ["lights", LIGHT_ID, "state"] => read_json::<StateUpdate>(&mut req)
+                .and_then(|update| apply(&update, &mut light_sender.clone())),
+            _ => Ok(error_value(&path, 3, "resource not available")),
+        };
+        respond(req, res.and_then(|value| Ok(serde_json::to_vec(&value)?)))
+    })?;
+
+    std::thread::Builder::new()
+        .stack_size(4 * 1024)
+        .spawn(move || {
+            diagnostics::track_stack("ssdp");
+            loop {
+                if wifi.ip().is_some() {
+                    if let Err(e) = serve_ssdp(&wifi, &mac) {
+                        log::warn!("ssdp error: {e}");
+                    }
+                }
+                std::thread::sleep(RETRY_INTERVAL);
+            }
+        })?;
+    Ok(())
+}
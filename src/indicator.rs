@@ -0,0 +1,134 @@
+use crate::executor::Executor;
+use crate::led::{adjust_brightness, cycle_value_sin, Led};
+use crate::report::{self, ErrorCode, Module};
+use crate::store::IndicatorConfig;
+use anyhow::Result;
+use esp32_nimble::utilities::mutex::Mutex;
+use esp_idf_svc::timer::{EspAsyncTimer, EspTaskTimerService};
+use futures::future::abortable;
+use futures::stream::AbortHandle;
+use futures::task::SpawnExt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BleStatus {
+    Advertising,
+    Connected,
+    Pairing,
+}
+
+/// 蓝牙状态指示，作为低优先级的叠加效果，只在灯关闭时显示
+#[derive(Clone)]
+pub struct Indicator {
+    pub config: Arc<Mutex<IndicatorConfig>>,
+    led: Arc<std::sync::Mutex<Led<'static>>>,
+    status: Arc<Mutex<BleStatus>>,
+    light_on: Arc<Mutex<bool>>,
+    task: Arc<Mutex<Option<AbortHandle>>>,
+    pool: Executor,
+}
+
+impl Indicator {
+    pub fn new(
+        config: Arc<Mutex<IndicatorConfig>>,
+        led: Arc<std::sync::Mutex<Led<'static>>>,
+        pool: Executor,
+    ) -> Self {
+        Self {
+            config,
+            led,
+            status: Arc::new(Mutex::new(BleStatus::Advertising)),
+            light_on: Arc::new(Mutex::new(false)),
+            task: Arc::new(Mutex::new(None)),
+            pool,
+        }
+    }
+
+    /// 蓝牙状态变化时调用
+    pub fn set_status(&self, status: BleStatus) {
+        *self.status.lock() = status;
+        if let Err(e) = self.start(true) {
+            report::error(Module::Indicator, ErrorCode::Hardware, e);
+        }
+    }
+
+    /// 灯光开关时调用，开灯时暂停指示效果
+    pub fn set_light(&self, on: bool) {
+        *self.light_on.lock() = on;
+        if let Err(e) = self.start(false) {
+            report::error(Module::Indicator, ErrorCode::Hardware, e);
+        }
+    }
+
+    /// 配置更新后重新显示当前状态
+    pub fn refresh(&self) -> Result<()> {
+        self.start(false)
+    }
+
+    fn stop(&self) {
+        if let Some(handle) = self.task.lock().take() {
+            handle.abort();
+        }
+    }
+
+    fn start(&self, transition: bool) -> Result<()> {
+        self.stop();
+        if *self.light_on.lock() {
+            return Ok(());
+        }
+        let config = self.config.lock().clone();
+        let status = *self.status.lock();
+        self.led.lock().unwrap().close()?;
+        // 连接成功的闪烁只在状态切换时显示一次
+        if !config.enabled || (status == BleStatus::Connected && !transition) {
+            return Ok(());
+        }
+
+        let async_timer = EspTaskTimerService::new()?.timer_async()?;
+        let (future, abort_handle) =
+            abortable(show_status(async_timer, self.led.clone(), config, status));
+        self.pool.spawn(async move {
+            if let Ok(Err(e)) = future.await {
+                log::debug!("indicator error: {e}");
+            }
+        })?;
+        *self.task.lock() = Some(abort_handle);
+        Ok(())
+    }
+}
+
+async fn show_status(
+    mut async_timer: EspAsyncTimer,
+    led: Arc<std::sync::Mutex<Led<'_>>>,
+    config: IndicatorConfig,
+    status: BleStatus,
+) -> Result<()> {
+    match status {
+        BleStatus::Advertising => loop {
+            led.lock().unwrap().set_pixel(config.advertising())?;
+            async_timer.after(Duration::from_millis(200)).await?;
+            led.lock().unwrap().close()?;
+            async_timer.after(Duration::from_millis(2800)).await?;
+        },
+        BleStatus::Connected => {
+            for _ in 0..2 {
+                led.lock().unwrap().set_pixel(config.connected())?;
+                async_timer.after(Duration::from_millis(150)).await?;
+                led.lock().unwrap().close()?;
+                async_timer.after(Duration::from_millis(150)).await?;
+            }
+            Ok(())
+        }
+        BleStatus::Pairing => {
+            let instant = Instant::now();
+            loop {
+                let brightness = cycle_value_sin(instant.elapsed().as_secs_f32());
+                led.lock()
+                    .unwrap()
+                    .set_pixel(adjust_brightness(config.pairing(), brightness))?;
+                async_timer.after(Duration::from_millis(60)).await?;
+            }
+        }
+    }
+}
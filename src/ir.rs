@@ -0,0 +1,133 @@
+use crate::{
+    ble::BleControl,
+    light::{LightEvent, LightEventSender},
+    store::ir::IrAction,
+};
+use anyhow::Result;
+use esp32_nimble::utilities::mutex::Mutex;
+use smart_brite_core::nec::{decode_nec, NecCode};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// 进入学习模式后等待按键的时间
+const LEARN_TIMEOUT: Duration = Duration::from_secs(30);
+/// 每次调节的亮度百分比
+const BRIGHTNESS_STEP: u8 = 10;
+/// 遥控调暗时的最低亮度，避免看起来像关灯
+const MIN_BRIGHTNESS: u8 = 10;
+
+/// 红外遥控接收，按键通过学习模式绑定到操作
+#[derive(Clone, Default)]
+pub struct Ir {
+    learning: Arc<Mutex<Option<(IrAction, Instant)>>>,
+}
+
+impl Ir {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 进入学习模式，下一个收到的按键绑定到该操作
+    pub fn learn(&self, action: IrAction) {
+        *self.learning.lock() = Some((action, Instant::now()));
+    }
+
+    /// 处理收到的按键
+    pub fn handle(
+        &self,
+        code: NecCode,
+        last_code: &mut Option<u32>,
+        ble_control: &BleControl,
+        light_sender: &mut LightEventSender,
+    ) -> Result<()> {
+        let nvs_store = &ble_control.nvs_store;
+        let code = match code {
+            NecCode::Code(code) => {
+                *last_code = Some(code);
+                // 学习模式下只记录完整的按键码，不处理重复码
+                let learning = self.learning.lock().take();
+                if let Some((action, started)) = learning {
+                    if started.elapsed() < LEARN_TIMEOUT {
+                        log::info!("learned ir code {code:#010x} for {action:?}");
+                        nvs_store.ir.lock().bind(code, action);
+                        nvs_store.write_ir()?;
+                        ble_control.set_ir(&nvs_store.ir.lock())?;
+                        return Ok(());
+                    }
+                }
+                code
+            }
+            NecCode::Repeat => match *last_code {
+                Some(code) => code,
+                None => return Ok(()),
+            },
+        };
+
+        let config = nvs_store.ir.lock().clone();
+        if !config.enabled {
+            return Ok(());
+        }
+        let Some(action) = config.action(code) else {
+            log::debug!("unknown ir code {code:#010x}");
+            return Ok(());
+        };
+        let brightness = *ble_control.brightness.lock();
+        match action {
+            IrAction::Power => light_sender.toggle(),
+            IrAction::BrightnessUp => light_sender.send(LightEvent::OpenAt(
+                brightness.saturating_add(BRIGHTNESS_STEP).min(100),
+            )),
+            IrAction::BrightnessDown => light_sender.send(LightEvent::OpenAt(
+                brightness
+                    .saturating_sub(BRIGHTNESS_STEP)
+                    .max(MIN_BRIGHTNESS),
+            )),
+            IrAction::Scene(name) => light_sender.send(LightEvent::SetScene(name.clone())),
+        }
+    }
+
+    /// 红外接收头接GPIO6，使用RMT接收通道
+    #[cfg(feature = "ir")]
+    pub fn start(
+        &self,
+        channel: esp_idf_svc::hal::rmt::CHANNEL2,
+        pin: esp_idf_svc::hal::gpio::Gpio6,
+        ble_control: BleControl,
+        mut light_sender: LightEventSender,
+    ) -> Result<()> {
+        use crate::report::{self, ErrorCode, Module};
+        use esp_idf_svc::hal::{
+            delay::BLOCK,
+            rmt::{config::ReceiveConfig, Pulse, Receive, RxRmtDriver},
+        };
+
+        // 80分频后每个tick为1微秒，帧间隔超过12毫秒即认为一帧结束
+        let config = ReceiveConfig::new().clock_divider(80).idle_threshold(12000);
+        let mut rx = RxRmtDriver::new(channel, pin, &config, 1000)?;
+        rx.start()?;
+
+        let ir = self.clone();
+        std::thread::spawn(move || -> Result<()> {
+            let mut buffer = [(Pulse::zero(), Pulse::zero()); 64];
+            let mut last_code = None;
+            loop {
+                let Receive::Read(len) = rx.receive(&mut buffer, BLOCK)? else {
+                    continue;
+                };
+                let pulses = buffer[..len]
+                    .iter()
+                    .map(|(mark, space)| (mark.ticks.ticks() as u32, space.ticks.ticks() as u32))
+                    .collect::<Vec<_>>();
+                let Some(code) = decode_nec(&pulses) else {
+                    continue;
+                };
+                if let Err(e) = ir.handle(code, &mut last_code, &ble_control, &mut light_sender) {
+                    report::error(Module::Ir, ErrorCode::Internal, e);
+                }
+            }
+        });
+        Ok(())
+    }
+}
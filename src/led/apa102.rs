@@ -0,0 +1,56 @@
+use super::{LedStrip, RGB8};
+use anyhow::Result;
+use esp_idf_svc::hal::{
+    gpio::{AnyIOPin, AnyOutputPin, OutputPin},
+    peripheral::Peripheral,
+    spi::{
+        config::{Config, DriverConfig},
+        Dma, SpiAnyPins, SpiDeviceDriver, SpiDriver,
+    },
+    units::FromValueType,
+};
+
+/// 一次DMA传输的最大字节数，更长的帧分多次发送
+const DMA_BUFFER: usize = 4096;
+
+/// 使用SPI驱动的APA102双线灯带，有独立的时钟线，对时序不敏感
+pub struct Apa102<'a> {
+    spi: SpiDeviceDriver<'a, SpiDriver<'a>>,
+}
+
+impl<'a> Apa102<'a> {
+    pub fn new<SPI: SpiAnyPins>(
+        spi: impl Peripheral<P = SPI> + 'a,
+        data: impl Peripheral<P = impl OutputPin> + 'a,
+        clock: impl Peripheral<P = impl OutputPin> + 'a,
+    ) -> Result<Self> {
+        let driver = SpiDriver::new(
+            spi,
+            clock,
+            data,
+            Option::<AnyIOPin>::None,
+            &DriverConfig::new().dma(Dma::Auto(DMA_BUFFER)),
+        )?;
+        let spi = SpiDeviceDriver::new(
+            driver,
+            Option::<AnyOutputPin>::None,
+            &Config::new().baudrate(4.MHz().into()),
+        )?;
+        Ok(Self { spi })
+    }
+}
+
+impl LedStrip for Apa102<'_> {
+    fn write(&mut self, pixels: &[RGB8]) -> Result<()> {
+        // 起始帧32位0，结束帧至少需要灯珠数一半的时钟沿，把数据推到最后一颗灯珠
+        let mut frame = vec![0u8; 4];
+        frame.reserve(pixels.len() * 4 + pixels.len() / 16 + 1);
+        for rgb in pixels {
+            // 每颗灯珠以0b111开头，低5位为全局亮度，使用最大值，由颜色控制亮度
+            frame.extend([0xff, rgb.b, rgb.g, rgb.r]);
+        }
+        frame.extend(std::iter::repeat(0).take(pixels.len() / 16 + 1));
+        self.spi.write(&frame)?;
+        Ok(())
+    }
+}
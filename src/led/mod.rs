@@ -0,0 +1,129 @@
+use std::sync::Arc;
+
+use crate::store::{CalibrationConfig, DeviceSettings};
+use anyhow::Result;
+use esp32_nimble::utilities::mutex::Mutex;
+
+mod apa102;
+mod pwm;
+mod rmt;
+
+pub use apa102::Apa102;
+pub use pwm::Pwm;
+pub use rgb::RGB8;
+pub use rmt::RmtStrip;
+pub use smart_brite_core::color::*;
+pub use smart_brite_core::hal::LedStrip;
+
+/// 单个通道满亮度时的电流，单位：毫安
+const CHANNEL_MA: u32 = 20;
+/// 每颗灯珠熄灭时的静态电流，单位：毫安
+const IDLE_MA: u32 = 1;
+
+/// 三个通道共有的部分由白光灯珠发出，更亮也更省电，返回剩余的颜色和白光亮度
+fn split_white(rgb: RGB8) -> (RGB8, u8) {
+    let white = rgb.r.min(rgb.g).min(rgb.b);
+    (
+        RGB8::new(rgb.r - white, rgb.g - white, rgb.b - white),
+        white,
+    )
+}
+
+/// 灯带，输出前校准颜色，并跳过与上一帧相同的帧
+pub struct Led<'a> {
+    strip: Box<dyn LedStrip + 'a>,
+    calibration: Option<Arc<Mutex<CalibrationConfig>>>,
+    /// 根据校准配置生成的查找表，配置变化时重新生成
+    table: Option<(CalibrationConfig, [[u8; 256]; 3])>,
+    /// 读取功率限制
+    settings: Option<Arc<Mutex<DeviceSettings>>>,
+    /// 上一次发送的帧（已校准）
+    last: Vec<RGB8>,
+}
+
+impl<'a> Led<'a> {
+    pub fn new(strip: impl LedStrip + 'a) -> Self {
+        Self {
+            strip: Box::new(strip),
+            calibration: None,
+            table: None,
+            settings: None,
+            last: vec![],
+        }
+    }
+
+    /// 设置颜色校准，输出前对每个像素进行伽马校正和白点调整
+    pub fn set_calibration(&mut self, calibration: Arc<Mutex<CalibrationConfig>>) {
+        self.calibration = Some(calibration);
+    }
+
+    /// 设置功率限制，按估算的电流整体降低亮度
+    pub fn set_power_limit(&mut self, settings: Arc<Mutex<DeviceSettings>>) {
+        self.settings = Some(settings);
+    }
+
+    /// 估算整帧的电流，超过限制时按比例降低所有像素的亮度，不改变颜色
+    fn limit_power(&self, pixels: &mut [RGB8]) {
+        let Some(settings) = &self.settings else {
+            return;
+        };
+        let Some(max_current) = settings.lock().max_current_ma else {
+            return;
+        };
+        let available = (max_current as u32).saturating_sub(pixels.len() as u32 * IDLE_MA);
+        let level: u32 = pixels
+            .iter()
+            .map(|rgb| rgb.r as u32 + rgb.g as u32 + rgb.b as u32)
+            .sum();
+        let current = level * CHANNEL_MA / 255;
+        if current <= available {
+            return;
+        }
+        let scale = available as f32 / current as f32;
+        for pixel in pixels.iter_mut() {
+            *pixel = adjust_brightness(*pixel, scale);
+        }
+    }
+
+    fn calibrate(&mut self, rgb: RGB8) -> RGB8 {
+        let Some(calibration) = &self.calibration else {
+            return rgb;
+        };
+        let config = calibration.lock().clone();
+        if self.table.as_ref().map(|(cached, _)| cached) != Some(&config) {
+            let table = config.table();
+            self.table = Some((config, table));
+        }
+        let Some((_, table)) = &self.table else {
+            return rgb;
+        };
+        RGB8::new(
+            table[0][rgb.r as usize],
+            table[1][rgb.g as usize],
+            table[2][rgb.b as usize],
+        )
+    }
+
+    pub fn set_pixel(&mut self, rgb: RGB8) -> Result<()> {
+        self.set_pixels(&[rgb])
+    }
+
+    /// 一次写入多个像素，用于灯带，与上一帧相同时不再发送
+    pub fn set_pixels(&mut self, pixels: &[RGB8]) -> Result<()> {
+        let mut pixels: Vec<RGB8> = pixels.iter().map(|&rgb| self.calibrate(rgb)).collect();
+        // 校准后的值才是实际输出
+        self.limit_power(&mut pixels);
+        if pixels == self.last {
+            return Ok(());
+        }
+        self.strip.write(&pixels)?;
+        self.last = pixels;
+        Ok(())
+    }
+
+    /// 熄灭上一帧点亮的所有灯珠
+    pub fn close(&mut self) -> Result<()> {
+        let len = self.last.len().max(1);
+        self.set_pixels(&vec![RGB8::new(0, 0, 0); len])
+    }
+}
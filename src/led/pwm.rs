@@ -0,0 +1,86 @@
+use super::{split_white, LedStrip, RGB8};
+use crate::store::PwmPins;
+use anyhow::Result;
+use esp_idf_svc::hal::{
+    gpio::AnyOutputPin,
+    ledc::{
+        config::TimerConfig, LedcDriver, LedcTimerDriver, Resolution, CHANNEL1, CHANNEL2, CHANNEL3,
+        CHANNEL4, CHANNEL5, TIMER1,
+    },
+    units::Hertz,
+};
+
+/// 模拟灯带使用LEDC定时器1和通道1-5，定时器0和通道0留给蜂鸣器
+pub struct Pwm {
+    red: LedcDriver<'static>,
+    green: LedcDriver<'static>,
+    blue: LedcDriver<'static>,
+    white: Option<LedcDriver<'static>>,
+    warm: Option<LedcDriver<'static>>,
+}
+
+impl Pwm {
+    pub fn new(
+        timer: TIMER1,
+        channels: (CHANNEL1, CHANNEL2, CHANNEL3, CHANNEL4, CHANNEL5),
+        pins: PwmPins,
+    ) -> Result<Self> {
+        // 频率足够高，摄像头下也不会闪烁
+        let config = TimerConfig::default()
+            .frequency(Hertz(4000))
+            .resolution(Resolution::Bits12);
+        // 所有通道共用一个定时器，程序运行期间一直使用
+        let timer: &'static LedcTimerDriver<_> =
+            Box::leak(Box::new(LedcTimerDriver::new(timer, &config)?));
+        let pin = |pin: u8| unsafe { AnyOutputPin::new(pin as i32) };
+        let (red, green, blue, white, warm) = channels;
+        Ok(Self {
+            red: LedcDriver::new(red, timer, pin(pins.red))?,
+            green: LedcDriver::new(green, timer, pin(pins.green))?,
+            blue: LedcDriver::new(blue, timer, pin(pins.blue))?,
+            white: pins
+                .white
+                .map(|white_pin| LedcDriver::new(white, timer, pin(white_pin)))
+                .transpose()?,
+            warm: pins
+                .warm
+                .map(|warm_pin| LedcDriver::new(warm, timer, pin(warm_pin)))
+                .transpose()?,
+        })
+    }
+}
+
+fn set_level(driver: &mut LedcDriver<'static>, level: u8) -> Result<()> {
+    let duty = driver.get_max_duty() * level as u32 / 255;
+    driver.set_duty(duty)?;
+    Ok(())
+}
+
+impl LedStrip for Pwm {
+    /// 整条灯带只能显示一种颜色，使用第一个像素
+    fn write(&mut self, pixels: &[RGB8]) -> Result<()> {
+        let color = pixels.first().copied().unwrap_or_default();
+        let (rgb, white) = match self.white {
+            Some(_) => split_white(color),
+            None => (color, 0),
+        };
+        set_level(&mut self.red, rgb.r)?;
+        set_level(&mut self.green, rgb.g)?;
+        set_level(&mut self.blue, rgb.b)?;
+        match (&mut self.white, &mut self.warm) {
+            (Some(cold), Some(warm)) => {
+                // 按颜色偏红还是偏蓝分配冷暖白光
+                let total = color.r as u32 + color.b as u32;
+                let warm_level = match total {
+                    0 => white / 2,
+                    _ => (white as u32 * color.r as u32 / total) as u8,
+                };
+                set_level(warm, warm_level)?;
+                set_level(cold, white - warm_level)?;
+            }
+            (Some(cold), None) => set_level(cold, white)?,
+            _ => {}
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+use super::{split_white, LedStrip, RGB8};
+use anyhow::Result;
+use esp_idf_svc::{
+    hal::{
+        delay,
+        gpio::OutputPin,
+        peripheral::Peripheral,
+        rmt::{
+            config::TransmitConfig, PinState, Pulse, RmtChannel, Signal, TxRmtDriver,
+            VariableLengthSignal,
+        },
+    },
+    sys::{esp, rmt_wait_tx_done, rmt_write_items},
+};
+use std::time::Duration;
+
+/// 单线灯带的0、1码高低电平时长，单位：纳秒
+struct Timing {
+    t0h: u64,
+    t0l: u64,
+    t1h: u64,
+    t1l: u64,
+}
+
+const WS2812: Timing = Timing {
+    t0h: 400,
+    t0l: 850,
+    t1h: 800,
+    t1l: 450,
+};
+
+const SK6812: Timing = Timing {
+    t0h: 300,
+    t0l: 900,
+    t1h: 600,
+    t1l: 600,
+};
+
+/// 使用RMT驱动的单线灯带，支持WS2812和SK6812 RGBW
+pub struct RmtStrip<'a> {
+    tx_rmt_derive: TxRmtDriver<'a>,
+    /// 0码和1码的脉冲对
+    pulses: [(Pulse, Pulse); 2],
+    /// 是否为带白光通道的RGBW灯珠
+    rgbw: bool,
+    /// 正在发送的信号，长灯带每帧数十KB，与下一帧的信号同时存在
+    sending: Option<VariableLengthSignal>,
+}
+
+impl<'a> RmtStrip<'a> {
+    pub fn ws2812(
+        led: impl Peripheral<P = impl OutputPin> + 'a,
+        channel: impl Peripheral<P = impl RmtChannel> + 'a,
+    ) -> Result<Self> {
+        Self::new(led, channel, &WS2812, false)
+    }
+
+    pub fn sk6812(
+        led: impl Peripheral<P = impl OutputPin> + 'a,
+        channel: impl Peripheral<P = impl RmtChannel> + 'a,
+    ) -> Result<Self> {
+        Self::new(led, channel, &SK6812, true)
+    }
+
+    fn new(
+        led: impl Peripheral<P = impl OutputPin> + 'a,
+        channel: impl Peripheral<P = impl RmtChannel> + 'a,
+        timing: &Timing,
+        rgbw: bool,
+    ) -> Result<Self> {
+        // 配置RMT的传输参数
+        let config = TransmitConfig::new().clock_divider(2);
+        // 初始化RMT驱动
+        let tx = TxRmtDriver::new(channel, led, &config)?;
+        let ticks_hz = tx.counter_clock()?;
+        let pulse =
+            |state, ns| Pulse::new_with_duration(ticks_hz, state, &Duration::from_nanos(ns));
+        let pulses = [
+            (
+                pulse(PinState::High, timing.t0h)?,
+                pulse(PinState::Low, timing.t0l)?,
+            ),
+            (
+                pulse(PinState::High, timing.t1h)?,
+                pulse(PinState::Low, timing.t1l)?,
+            ),
+        ];
+        Ok(Self {
+            tx_rmt_derive: tx,
+            pulses,
+            rgbw,
+            sending: None,
+        })
+    }
+
+    /// 把像素转换为RMT信号
+    fn encode(&self, pixels: &[RGB8]) -> Result<VariableLengthSignal> {
+        let bits = if self.rgbw { 32 } else { 24 };
+        let mut signal = VariableLengthSignal::with_capacity(pixels.len() * bits);
+        for &rgb in pixels {
+            // WS2812按GRB顺序接收数据，SK6812在后面多一个白光通道
+            let data: u32 = if self.rgbw {
+                let (rgb, white) = split_white(rgb);
+                ((rgb.g as u32) << 24)
+                    | ((rgb.r as u32) << 16)
+                    | ((rgb.b as u32) << 8)
+                    | white as u32
+            } else {
+                ((rgb.g as u32) << 16) | ((rgb.r as u32) << 8) | (rgb.b as u32)
+            };
+            for i in (0..bits).rev() {
+                let (high, low) = &self.pulses[((data >> i) & 1) as usize];
+                signal.push([high, low])?;
+            }
+        }
+        Ok(signal)
+    }
+
+    /// 等待正在发送的帧发送完成
+    fn wait_done(&mut self) -> Result<()> {
+        if self.sending.is_some() {
+            esp!(unsafe { rmt_wait_tx_done(self.tx_rmt_derive.channel(), delay::BLOCK) })?;
+            self.sending = None;
+        }
+        Ok(())
+    }
+}
+
+impl LedStrip for RmtStrip<'_> {
+    /// 发送不等待完成，下一帧的信号在上一帧发送期间生成
+    fn write(&mut self, pixels: &[RGB8]) -> Result<()> {
+        let signal = self.encode(pixels)?;
+        self.wait_done()?;
+        let items = signal.as_slice();
+        // 信号保存在sending中，发送完成前不会被释放
+        esp!(unsafe {
+            rmt_write_items(
+                self.tx_rmt_derive.channel(),
+                items.as_ptr(),
+                items.len() as i32,
+                false,
+            )
+        })?;
+        self.sending = Some(signal);
+        Ok(())
+    }
+}
+
+impl Drop for RmtStrip<'_> {
+    fn drop(&mut self) {
+        // 释放信号前必须等待发送完成
+        let _ = self.wait_done();
+    }
+}
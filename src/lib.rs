@@ -3,20 +3,68 @@ use esp_idf_svc::eventloop::EspSystemEventLoop;
 use esp_idf_svc::hal::peripherals::Peripherals;
 use esp_idf_svc::nvs::EspDefaultNvsPartition;
 
+pub mod advertising;
+pub mod ambient;
+pub mod auth;
+pub mod battery;
 pub mod ble;
 pub mod button;
+pub mod buzzer;
+pub mod controller;
+pub mod demo;
+pub mod device_info;
+pub mod diagnostics;
+pub mod effect;
+pub mod encoder;
+pub mod event_bus;
+pub mod executor;
+pub mod http;
+pub mod hue_emulation;
+pub mod indicator;
+pub mod ir;
 pub mod led;
 pub mod light;
+pub mod log_buffer;
+#[cfg(feature = "mesh")]
+pub mod mesh;
+pub mod mic;
+pub mod modifier;
+pub mod motion;
+pub mod mqtt;
+pub mod notify;
+pub mod ota;
+pub mod portal;
+pub mod power;
+pub mod realtime;
+pub mod report;
+pub mod reset;
+pub mod self_test;
+#[cfg(any(feature = "als", feature = "als-i2c"))]
+pub mod sensor;
+pub mod session;
+pub mod sntp;
 pub mod store;
+pub mod sync;
+pub mod thermal;
 pub mod timer;
 pub mod transmission;
+pub mod usage;
+pub mod watchdog;
+pub mod wifi;
+
+#[cfg(all(feature = "battery", feature = "als"))]
+compile_error!("`battery` and `als` both use ADC1");
+#[cfg(all(feature = "battery", feature = "buzzer"))]
+compile_error!("`battery` and `buzzer` both use GPIO3");
+#[cfg(all(feature = "board-c3-mini", feature = "mic"))]
+compile_error!("`board-c3-mini` uses GPIO7 for the LED, which `mic` needs for BCLK");
 
 pub fn init() -> Result<(EspSystemEventLoop, Peripherals, EspDefaultNvsPartition)> {
     // 链接SDK中的补丁，以修正某些功能的兼容性问题。
     esp_idf_svc::sys::link_patches();
 
-    // 初始化日志系统，为后续的调试和错误追踪提供支持。
-    esp_idf_svc::log::EspLogger::initialize_default();
+    // 初始化日志系统，为后续的调试和错误追踪提供支持，同时记录到内存中供App读取。
+    log_buffer::init();
 
     // 获取系统事件循环实例，用于处理系统级别的事件。
     let sys_loop = esp_idf_svc::eventloop::EspSystemEventLoop::take()?;
@@ -27,6 +75,9 @@ pub fn init() -> Result<(EspSystemEventLoop, Peripherals, EspDefaultNvsPartition
     // 获取默认的NVS分区，用于存储配置数据和运行时信息。
     let nvs_partition = EspDefaultNvsPartition::take()?;
 
+    // 记录启动次数和复位原因，尽早登记panic钩子
+    diagnostics::init(nvs_partition.clone())?;
+
     // 返回初始化完成的系统事件循环、外设句柄和默认NVS分区。
     Ok((sys_loop, peripherals, nvs_partition))
 }
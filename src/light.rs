@@ -1,17 +1,33 @@
+use crate::ambient::Ambient;
+use crate::battery::Battery;
 use crate::ble::BleControl;
-use crate::led::{blend_colors, WS2812RMT};
-use crate::store::{Color, NvsStore};
-use anyhow::Result;
+use crate::demo::run_demo;
+use crate::effect;
+use crate::event_bus;
+use crate::executor::Executor;
+use crate::indicator::Indicator;
+use crate::led::{Led, RGB8};
+use crate::mic::Audio;
+use crate::modifier::{Modifier, Tweak};
+use crate::report::{self, ErrorCode, Module};
+use crate::reset::{confirm_blink, factory_reset, reboot};
+use crate::store::{history::Change, scene::Solid, share, Color, NvsStore, Scene};
+use crate::sync::{Sync, SyncMessage};
+use crate::thermal::{self, ThermalStatus};
+use crate::watchdog;
+use anyhow::{bail, Result};
 use esp_idf_svc::timer::{EspAsyncTimer, EspTaskTimerService};
-use futures::executor::ThreadPool;
+use futures::channel::oneshot;
 use futures::future::abortable;
+use futures::future::RemoteHandle;
 use futures::stream::AbortHandle;
 use futures::task::SpawnExt;
 use serde::{Deserialize, Serialize};
-use std::sync::mpsc::{self, Receiver, Sender};
+use std::future::Future;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
 use std::{
     sync::{Arc, Mutex},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,43 +35,147 @@ use std::{
 pub enum LightEvent {
     Close,
     Open,
+    /// 以指定亮度百分比开灯，不使用默认亮度曲线
+    OpenAt(u8),
     Reset,
+    Demo,
+    /// 开发者模式或实时串流直接显示的原始帧
+    Frame(Vec<RGB8>),
+    /// 恢复出厂设置
+    FactoryReset,
+    /// 保存尚未写入的数据后重启
+    Reboot,
+    /// 切换到场景库中的场景并开灯
+    SetScene(String),
+    /// 撤销最近一次场景覆盖、重置或定时任务删除
+    Undo,
+    /// 调整正在播放效果的速度倍率，不重新开始
+    SetSpeed(f32),
+    /// 调整正在播放效果的亮度倍率（0-1），不重新开始
+    SetIntensity(f32),
+    /// 临时显示场景，不写入NVS，超时或取消后恢复原场景
+    Preview(Scene),
+    /// 保存正在预览的场景
+    PreviewConfirm,
+    /// 取消预览，恢复原场景
+    PreviewCancel,
+    /// 切换为纯色场景并开灯，亮度保持不变
+    SetColor(RGB8),
+    /// 从同步组收到的消息，处理后不再转发
+    #[serde(skip)]
+    Sync(SyncMessage),
+    /// 开灯时关灯，关灯或演示时开灯，按事件循环中的实际状态判断
+    Toggle,
+    /// 查询事件循环中的实际状态，不会滞后于前面发送的事件
+    #[serde(skip)]
+    GetState(StateReply),
 }
 
-impl From<&[u8]> for LightEvent {
-    fn from(data: &[u8]) -> Self {
-        match data {
+/// 状态查询的回复通道，事件需要能够克隆，只有第一次回复有效
+#[derive(Debug, Clone)]
+pub struct StateReply(Arc<Mutex<Option<oneshot::Sender<LightState>>>>);
+
+impl StateReply {
+    fn new(tx: oneshot::Sender<LightState>) -> Self {
+        Self(Arc::new(Mutex::new(Some(tx))))
+    }
+
+    fn send(&self, state: LightState) {
+        if let Some(tx) = self.0.lock().unwrap().take() {
+            let _ = tx.send(state);
+        }
+    }
+}
+
+impl TryFrom<&[u8]> for LightEvent {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> Result<Self> {
+        /// 取出`prefix:`之后的参数
+        fn arg<'a>(data: &'a [u8], prefix: &[u8]) -> Result<&'a str> {
+            Ok(std::str::from_utf8(&data[prefix.len()..])?)
+        }
+
+        Ok(match data {
             b"close" => LightEvent::Close,
             b"open" => LightEvent::Open,
             b"reset" => LightEvent::Reset,
-            _ => panic!("invalid control"),
-        }
+            b"demo" => LightEvent::Demo,
+            b"factory_reset" => LightEvent::FactoryReset,
+            b"reboot" => LightEvent::Reboot,
+            b"toggle" => LightEvent::Toggle,
+            b"undo" => LightEvent::Undo,
+            b"preview_confirm" => LightEvent::PreviewConfirm,
+            b"preview_cancel" => LightEvent::PreviewCancel,
+            // `open:50`表示以50%亮度开灯
+            data if data.starts_with(b"open:") => match arg(data, b"open:")?.parse() {
+                Ok(brightness) => LightEvent::OpenAt(brightness),
+                Err(_) => bail!("Invalid brightness"),
+            },
+            // `speed:1.5`表示以1.5倍速度播放
+            data if data.starts_with(b"speed:") => match arg(data, b"speed:")?.parse() {
+                Ok(speed) => LightEvent::SetSpeed(speed),
+                Err(_) => bail!("Invalid speed"),
+            },
+            // `intensity:0.5`表示亮度减半
+            data if data.starts_with(b"intensity:") => match arg(data, b"intensity:")?.parse() {
+                Ok(intensity) => LightEvent::SetIntensity(intensity),
+                Err(_) => bail!("Invalid intensity"),
+            },
+            // `preview:sb1.xxx`预览分享字符串中的场景
+            data if data.starts_with(b"preview:") => {
+                LightEvent::Preview(share::decode(arg(data, b"preview:")?)?)
+            }
+            // `scene:阅读`切换到场景库中的场景
+            data if data.starts_with(b"scene:") => {
+                LightEvent::SetScene(arg(data, b"scene:")?.to_string())
+            }
+            _ => bail!("Invalid control"),
+        })
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct LightEventSender {
-    pub event_tx: Sender<LightEvent>,
-}
+/// 通过事件总线发送灯光事件
+#[derive(Debug, Clone, Default)]
+pub struct LightEventSender;
 
 impl LightEventSender {
-    pub fn new(event_tx: Sender<LightEvent>) -> Self {
-        LightEventSender { event_tx }
-    }
     pub fn close(&mut self) -> Result<()> {
-        Ok(self.event_tx.send(LightEvent::Close)?)
+        self.send(LightEvent::Close)
     }
     pub fn open(&mut self) -> Result<()> {
-        Ok(self.event_tx.send(LightEvent::Open)?)
+        self.send(LightEvent::Open)
     }
 
     pub fn reset(&mut self) -> Result<()> {
-        Ok(self.event_tx.send(LightEvent::Reset)?)
+        self.send(LightEvent::Reset)
+    }
+
+    pub fn demo(&mut self) -> Result<()> {
+        self.send(LightEvent::Demo)
+    }
+
+    pub fn toggle(&mut self) -> Result<()> {
+        self.send(LightEvent::Toggle)
+    }
+
+    /// 等待事件循环处理完之前的事件后返回实际状态，不能在事件循环中调用
+    pub fn query_state(&mut self) -> Result<LightState> {
+        let (tx, rx) = oneshot::channel();
+        self.send(LightEvent::GetState(StateReply::new(tx)))?;
+        Ok(futures::executor::block_on(rx)?)
     }
 
+    pub fn send(&mut self, event: LightEvent) -> Result<()> {
+        if event_bus::publish(event) == 0 {
+            bail!("Light event loop is not running");
+        }
+        Ok(())
+    }
+
+    /// 订阅灯光事件，需要在发送事件前调用
     pub fn new_pari() -> (LightEventSender, Receiver<LightEvent>) {
-        let (tx, rx) = mpsc::channel();
-        (LightEventSender::new(tx), rx)
+        (LightEventSender, event_bus::subscribe())
     }
 }
 
@@ -63,74 +183,260 @@ impl LightEventSender {
 pub enum LightState {
     Opened,
     Closed,
+    /// 演示模式，附带当前效果名称
+    Demo(String),
 }
 
-impl Into<&'static [u8]> for LightState {
-    fn into(self) -> &'static [u8] {
+impl LightState {
+    pub fn name(&self) -> &'static str {
         match self {
-            LightState::Opened => b"opened",
-            LightState::Closed => b"closed",
+            LightState::Opened => "opened",
+            LightState::Closed => "closed",
+            LightState::Demo(_) => "demo",
         }
     }
 }
 
-impl From<&[u8]> for LightState {
-    fn from(value: &[u8]) -> Self {
-        match value {
-            b"opened" => LightState::Opened,
-            b"closed" => LightState::Closed,
-            _ => panic!("invalid state"),
+/// 灯光状态变化后延迟写入NVS，避免频繁开关时反复擦写Flash
+const STATE_WRITE_DELAY: Duration = Duration::from_secs(3);
+/// 处理单个事件的最长时间，超过即认为事件循环卡死
+const LIGHT_TIMEOUT: Duration = Duration::from_secs(10);
+/// 预览场景的持续时间，超时未确认则恢复原场景
+const PREVIEW_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 正在预览的场景
+#[derive(Clone)]
+struct Preview {
+    scene: Scene,
+    deadline: Instant,
+    /// 预览前灯是否打开，恢复时据此开灯或关灯
+    was_open: bool,
+}
+
+/// 正在运行的渲染任务
+struct EffectTask {
+    abort_handle: AbortHandle,
+    done: RemoteHandle<()>,
+}
+
+impl EffectTask {
+    fn spawn(
+        pool: &Executor,
+        render: impl Future<Output = Result<()>> + Send + 'static,
+    ) -> Result<Self> {
+        let (future, abort_handle) = abortable(render);
+        let done = pool.spawn_with_handle(async move {
+            match future.await {
+                Ok(Ok(_)) => log::debug!("render finished"),
+                Ok(Err(e)) => report::error(Module::Light, ErrorCode::Hardware, e),
+                Err(_) => log::debug!("render aborted"),
+            }
+        })?;
+        Ok(Self { abort_handle, done })
+    }
+
+    /// 中断渲染并等待任务退出，返回后渲染任务不会再写入灯带
+    fn stop(self) {
+        self.abort_handle.abort();
+        futures::executor::block_on(self.done);
+    }
+}
+
+/// 灯光事件循环的状态
+enum Mode {
+    Off,
+    /// 显示场景，`scene`为解析调色板后的场景
+    On {
+        scene: Scene,
+        effect: EffectTask,
+    },
+    /// 预览未保存的场景，超时未确认则恢复
+    Preview {
+        preview: Preview,
+        effect: EffectTask,
+    },
+    Demo(EffectTask),
+    /// 显示客户端发送的原始帧，没有渲染任务
+    Frame,
+    /// 旧的渲染任务已停止，新的状态还未建立
+    Transitioning,
+}
+
+impl Mode {
+    fn is_on(&self) -> bool {
+        matches!(self, Mode::On { .. })
+    }
+
+    /// 对外的灯光状态，演示效果的名称由演示任务更新
+    fn light_state(&self, ble_control: &BleControl) -> LightState {
+        match self {
+            Mode::Off | Mode::Transitioning => LightState::Closed,
+            Mode::Demo(_) => match ble_control.get_state() {
+                state @ LightState::Demo(_) => state,
+                _ => LightState::Demo(String::new()),
+            },
+            Mode::On { .. } | Mode::Preview { .. } | Mode::Frame => LightState::Opened,
+        }
+    }
+
+    /// 停止渲染任务并进入切换状态，返回正在进行的预览
+    fn stop(&mut self) -> Option<Preview> {
+        match std::mem::replace(self, Mode::Transitioning) {
+            Mode::On { effect, .. } | Mode::Demo(effect) => {
+                effect.stop();
+                None
+            }
+            Mode::Preview { preview, effect } => {
+                effect.stop();
+                Some(preview)
+            }
+            Mode::Off | Mode::Frame | Mode::Transitioning => None,
+        }
+    }
+
+    /// 结束预览但继续显示预览的场景，不再自动恢复
+    fn end_preview(&mut self) -> Option<Preview> {
+        match std::mem::replace(self, Mode::Transitioning) {
+            Mode::Preview { preview, effect } => {
+                *self = Mode::On {
+                    scene: preview.scene.clone(),
+                    effect,
+                };
+                Some(preview)
+            }
+            mode => {
+                *self = mode;
+                None
+            }
         }
     }
 }
 
+fn persist_light_state(
+    timer_server: &EspTaskTimerService,
+    nvs_store: &NvsStore,
+    write_task: &mut Option<AbortHandle>,
+    on: bool,
+    pool: &Executor,
+) -> Result<()> {
+    if let Some(handle) = write_task.take() {
+        handle.abort();
+    }
+    let mut async_timer = timer_server.timer_async()?;
+    let nvs_store = nvs_store.clone();
+    let (future, abort_handle) = abortable(async move {
+        async_timer.after(STATE_WRITE_DELAY).await?;
+        nvs_store.write_light_on(on)
+    });
+    pool.spawn(async move {
+        if let Ok(Err(e)) = future.await {
+            report::error(Module::Store, ErrorCode::Storage, e);
+        }
+    })?;
+    *write_task = Some(abort_handle);
+    Ok(())
+}
+
+/// 按场景颜色创建效果并以设置的帧率渲染
 pub async fn open_led(
-    mut async_timer: EspAsyncTimer,
-    led: Arc<Mutex<WS2812RMT<'_>>>,
+    async_timer: EspAsyncTimer,
+    led: Arc<Mutex<Led<'_>>>,
     color: Color,
+    modifier: Modifier,
+    fps: u8,
 ) -> Result<(), anyhow::Error> {
-    // 注意防止死锁，这里使用这种方式获取颜色是为了更快的释放锁
-    match color {
-        Color::Solid(solid) => {
-            led.lock().unwrap().set_pixel(solid.color)?;
-            Ok(())
-        }
-        Color::Gradient(gradient) => {
-            if gradient.linear {
-                let durations = gradient.get_color_durations();
-                let mut current = 0usize;
-                loop {
-                    let index = current % durations.len();
-                    let color_duration = &durations[index];
-                    let instance = std::time::Instant::now();
-
-                    while instance.elapsed() < color_duration.duration {
-                        let color = blend_colors(
-                            color_duration.start_color,
-                            color_duration.end_color,
-                            (instance.elapsed().as_millis() as f32)
-                                / color_duration.duration.as_millis() as f32,
-                        );
-                        led.lock().unwrap().set_pixel(color)?;
-                        async_timer.after(Duration::from_millis(60)).await?;
-                    }
-                    current += 1;
-                }
-            } else {
-                let durations = gradient.colors.clone();
-                let mut current = 0usize;
-                loop {
-                    let index = current % durations.len();
-                    let color_duration = &durations[index];
+    let effect = effect::create(color, &modifier)?;
+    effect::render(async_timer, led, effect, modifier, fps).await
+}
 
-                    led.lock().unwrap().set_pixel(color_duration.color)?;
-                    async_timer
-                        .after(Duration::from_secs_f32(color_duration.duration))
-                        .await?;
-                    current += 1;
-                }
+/// 创建渲染任务需要的资源
+struct Renderer {
+    timer_server: EspTaskTimerService,
+    led: Arc<Mutex<Led<'static>>>,
+    nvs_store: NvsStore,
+    ambient: Ambient,
+    audio: Audio,
+    battery: Battery,
+    /// 运行时的速度和亮度调整，关灯后恢复
+    tweak: Tweak,
+    pool: Executor,
+}
+
+impl Renderer {
+    /// 检查能否开灯并解析场景引用的调色板，不能开灯时返回None
+    fn prepare(&self, scene: &Scene) -> Option<Color> {
+        // 电量过低时不允许开灯
+        if let Some(percent) = self.battery.percent() {
+            if self.nvs_store.battery.lock().is_critical(percent) {
+                log::warn!("battery critical ({percent}%), ignore open");
+                return None;
             }
         }
+        // 过热关灯后降温前不允许开灯
+        if thermal::status() == ThermalStatus::Shutdown {
+            log::warn!("chip overheated, ignore open");
+            return None;
+        }
+        let mut color = match scene.color.resolve(&self.nvs_store.palettes.lock()) {
+            Ok(color) => color,
+            Err(e) => {
+                report::error(Module::Light, ErrorCode::NotFound, e);
+                return None;
+            }
+        };
+        let max_leds = self.nvs_store.settings.lock().max_leds;
+        match &mut color {
+            Color::Meteor(meteor) => meteor.pixels = meteor.pixels.min(max_leds),
+            Color::Fire(fire) => fire.pixels = fire.pixels.min(max_leds),
+            _ => {}
+        }
+        Some(color)
+    }
+
+    /// 未指定亮度时使用设置的默认亮度，没有设置则按当前时间选择
+    fn default_brightness(&self) -> u8 {
+        let default_brightness = self.nvs_store.settings.lock().default_brightness;
+        default_brightness.unwrap_or_else(|| self.nvs_store.brightness_curve.lock().current())
+    }
+
+    fn spawn_scene(&self, color: Color, ambient_aware: bool, brightness: u8) -> Result<EffectTask> {
+        // 场景开启环境光自适应时，根据环境光调整效果
+        let modifier = if ambient_aware {
+            Modifier::ambient(self.ambient.clone())
+        } else {
+            Modifier::default()
+        }
+        .with_brightness(brightness)
+        .with_adaptive(self.ambient.clone(), self.nvs_store.adaptive.clone())
+        .with_audio(self.audio.clone())
+        .with_battery(self.battery.clone(), self.nvs_store.battery.clone())
+        .with_thermal(self.nvs_store.thermal.clone())
+        .with_tweak(self.tweak.clone());
+        let fps = self.nvs_store.settings.lock().fps;
+        EffectTask::spawn(
+            &self.pool,
+            open_led(
+                self.timer_server.timer_async()?,
+                self.led.clone(),
+                color,
+                modifier,
+                fps,
+            ),
+        )
+    }
+
+    fn spawn_demo(&self, ble_control: &BleControl, interval: Duration) -> Result<EffectTask> {
+        let fps = self.nvs_store.settings.lock().fps;
+        EffectTask::spawn(
+            &self.pool,
+            run_demo(
+                self.timer_server.clone(),
+                self.led.clone(),
+                ble_control.clone(),
+                interval,
+                fps,
+            ),
+        )
     }
 }
 
@@ -138,61 +444,334 @@ pub fn handle_light_event(
     event_rx: Receiver<LightEvent>,
     ble_control: BleControl,
     nvs_store: NvsStore,
-    led: Arc<Mutex<WS2812RMT<'static>>>,
-    pool: ThreadPool,
+    led: Arc<Mutex<Led<'static>>>,
+    indicator: Indicator,
+    ambient: Ambient,
+    audio: Audio,
+    battery: Battery,
+    sync: Sync,
+    pool: Executor,
 ) -> Result<()> {
     let timer_server = EspTaskTimerService::new()?;
-    let open_task: Arc<Mutex<Option<AbortHandle>>> = Arc::new(Mutex::new(None));
+    let renderer = Renderer {
+        timer_server: timer_server.clone(),
+        led: led.clone(),
+        nvs_store: nvs_store.clone(),
+        ambient,
+        audio,
+        battery,
+        tweak: Tweak::default(),
+        pool,
+    };
+    let tweak = renderer.tweak.clone();
     let scene = nvs_store.scene.clone();
-    while let Ok(event) = event_rx.recv() {
-        match event {
-            LightEvent::Close => {
-                #[cfg(debug_assertions)]
-                log::warn!("close");
-
-                if open_task.lock().unwrap().is_some() {
-                    open_task.lock().unwrap().take().unwrap().abort();
+    let mut state_write_task: Option<AbortHandle> = None;
+    let mut mode = Mode::Off;
+    let heartbeat = watchdog::register("light", LIGHT_TIMEOUT);
+    loop {
+        // 等待事件时不监控
+        heartbeat.idle();
+        let demo = nvs_store.demo.lock().clone();
+        let deadline = match &mode {
+            Mode::Preview { preview, .. } => Some(preview.deadline),
+            _ => None,
+        };
+        // 开启演示模式后，无操作一段时间自动回到演示
+        let event = if let Some(deadline) = deadline {
+            match event_rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => LightEvent::PreviewCancel,
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        } else if demo.enabled && !matches!(mode, Mode::Demo(_)) {
+            match event_rx.recv_timeout(Duration::from_secs(demo.idle_timeout as u64)) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => {
+                    if demo.reset_on_idle {
+                        ble_control.reset_scene()?;
+                    }
+                    LightEvent::Demo
                 }
-                led.lock().unwrap().close()?;
-                ble_control.set_state(LightState::Closed);
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        } else {
+            match event_rx.recv() {
+                Ok(event) => event,
+                Err(_) => break,
             }
-            LightEvent::Open => {
-                #[cfg(debug_assertions)]
-                log::warn!("open");
-
-                if open_task.lock().unwrap().is_some() {
-                    open_task.lock().unwrap().take().unwrap().abort();
-                }
-
-                let (future, abort_handle) = abortable(open_led(
-                    timer_server.timer_async()?,
-                    led.clone(),
-                    scene.lock().color.clone(),
-                ));
-                pool.spawn(async move {
-                    match future.await {
-                        Ok(res) => match res {
-                            Ok(_) => {
-                                #[cfg(debug_assertions)]
-                                log::info!("open led success");
+        };
+        heartbeat.beat();
+        // 预览期间的其他操作以新操作为准，不再恢复原场景
+        if !matches!(
+            event,
+            LightEvent::Preview(_)
+                | LightEvent::PreviewConfirm
+                | LightEvent::PreviewCancel
+                | LightEvent::SetSpeed(_)
+                | LightEvent::SetIntensity(_)
+                | LightEvent::GetState(_)
+        ) {
+            mode.end_preview();
+        }
+        // SetScene切换场景后需要继续执行开灯
+        let mut pending = Some(event);
+        let mut from_peer = false;
+        // 开始预览时交给开灯处理
+        let mut staged_preview: Option<Preview> = None;
+        while let Some(event) = pending.take() {
+            match event {
+                LightEvent::Sync(message) => {
+                    from_peer = true;
+                    pending = match message {
+                        SyncMessage::Close => Some(LightEvent::Close),
+                        SyncMessage::Open {
+                            scene: peer_scene,
+                            brightness,
+                        } => {
+                            if let Some(peer_scene) = peer_scene {
+                                *scene.lock() = peer_scene;
+                                if let Err(e) = nvs_store.write_scene() {
+                                    report::error(Module::Store, ErrorCode::Storage, e);
+                                }
+                                ble_control.set_scene(&scene.lock())?;
                             }
-                            Err(e) => {
-                                #[cfg(debug_assertions)]
-                                log::error!("open led error:{e}");
+                            Some(LightEvent::OpenAt(brightness))
+                        }
+                        SyncMessage::ApplyScene { id, scene: value } => {
+                            match share::decode(&value)
+                                .and_then(|peer_scene| nvs_store.set_scene(peer_scene))
+                            {
+                                Ok(_) => {
+                                    ble_control.set_scene(&scene.lock())?;
+                                    sync.ack(id);
+                                }
+                                Err(e) => report::error(Module::Sync, ErrorCode::InvalidData, e),
                             }
+                            // 灯打开时立即显示新场景
+                            mode.is_on().then_some(LightEvent::Open)
+                        }
+                        SyncMessage::Ack { .. } => None,
+                    };
+                }
+                LightEvent::SetColor(color) => {
+                    let solid = Scene {
+                        color: Color::Solid(Solid { color }),
+                        ..scene.lock().clone()
+                    };
+                    if let Err(e) = nvs_store.set_scene(solid) {
+                        report::error(Module::Store, ErrorCode::Storage, e);
+                    }
+                    ble_control.set_scene(&scene.lock())?;
+                    pending = Some(if mode.is_on() {
+                        LightEvent::OpenAt(*ble_control.brightness.lock())
+                    } else {
+                        LightEvent::Open
+                    });
+                }
+                LightEvent::Close => {
+                    log::debug!("close");
+
+                    mode.stop();
+                    led.lock().unwrap().close()?;
+                    mode = Mode::Off;
+                    tweak.reset();
+                    ble_control.set_state(LightState::Closed);
+                    indicator.set_light(false);
+                    persist_light_state(
+                        &timer_server,
+                        &nvs_store,
+                        &mut state_write_task,
+                        false,
+                        &renderer.pool,
+                    )?;
+                    if !from_peer {
+                        sync.broadcast(&SyncMessage::Close);
+                    }
+                }
+                LightEvent::Open | LightEvent::OpenAt(_) => {
+                    log::debug!("open");
+
+                    let brightness = match event {
+                        LightEvent::OpenAt(brightness) => brightness.min(100),
+                        _ => renderer.default_brightness(),
+                    };
+                    // 预览时显示预览的场景
+                    let preview = match (staged_preview.take(), &mode) {
+                        (Some(preview), _) => Some(preview),
+                        (None, Mode::Preview { preview, .. }) => Some(preview.clone()),
+                        _ => None,
+                    };
+                    let current = match &preview {
+                        Some(preview) => preview.scene.clone(),
+                        None => scene.lock().clone(),
+                    };
+                    let Some(color) = renderer.prepare(&current) else {
+                        continue;
+                    };
+
+                    mode.stop();
+                    indicator.set_light(true);
+                    let effect =
+                        renderer.spawn_scene(color.clone(), current.ambient_aware, brightness)?;
+                    mode = match preview {
+                        Some(preview) => Mode::Preview { preview, effect },
+                        None => Mode::On {
+                            scene: Scene { color, ..current },
+                            effect,
                         },
-                        Err(_) => {
-                            #[cfg(debug_assertions)]
-                            log::warn!("open led abort");
+                    };
+                    ble_control.set_brightness(brightness);
+                    ble_control.set_state(LightState::Opened);
+                    persist_light_state(
+                        &timer_server,
+                        &nvs_store,
+                        &mut state_write_task,
+                        true,
+                        &renderer.pool,
+                    )?;
+                    // 预览的场景未保存，不同步给其他设备
+                    if let (false, Mode::On { scene, .. }) = (from_peer, &mode) {
+                        sync.broadcast(&SyncMessage::Open {
+                            scene: Some(scene.clone()),
+                            brightness,
+                        });
+                    }
+                }
+                LightEvent::Toggle => {
+                    pending = Some(match mode {
+                        Mode::Off | Mode::Demo(_) | Mode::Transitioning => LightEvent::Open,
+                        _ => LightEvent::Close,
+                    });
+                }
+                LightEvent::GetState(reply) => {
+                    reply.send(mode.light_state(&ble_control));
+                }
+                LightEvent::Reset => {
+                    ble_control.reset_scene()?;
+                }
+                LightEvent::Demo => {
+                    log::debug!("demo");
+
+                    mode.stop();
+                    indicator.set_light(true);
+                    let interval = Duration::from_secs(demo.interval as u64);
+                    mode = Mode::Demo(renderer.spawn_demo(&ble_control, interval)?);
+                }
+                LightEvent::Frame(mut pixels) => {
+                    pixels.truncate(nvs_store.settings.lock().max_leds as usize);
+                    // 实时串流每秒几十帧，只在第一帧时更新状态
+                    let streaming = matches!(mode, Mode::Frame);
+                    mode.stop();
+                    if !streaming {
+                        indicator.set_light(true);
+                    }
+                    led.lock().unwrap().set_pixels(&pixels)?;
+                    mode = Mode::Frame;
+                    if !streaming {
+                        ble_control.set_state(LightState::Opened);
+                    }
+                }
+                LightEvent::FactoryReset => {
+                    mode.stop();
+                    indicator.set_light(true);
+                    confirm_blink(&led, nvs_store.indicator.lock().error())?;
+                    factory_reset()?;
+                }
+                LightEvent::Reboot => {
+                    let light_on = !matches!(mode, Mode::Off);
+                    if let Some(handle) = state_write_task.take() {
+                        handle.abort();
+                    }
+                    mode.stop();
+                    led.lock().unwrap().close()?;
+                    reboot(&nvs_store, light_on)?;
+                }
+                LightEvent::SetScene(name) => {
+                    let found = nvs_store
+                        .scenes
+                        .lock()
+                        .iter()
+                        .find(|item| item.name == name)
+                        .cloned();
+                    let Some(found) = found else {
+                        report::error(
+                            Module::Light,
+                            ErrorCode::NotFound,
+                            format!("scene {name} not found"),
+                        );
+                        continue;
+                    };
+                    if let Err(e) = nvs_store.set_scene(found) {
+                        report::error(Module::Store, ErrorCode::Storage, e);
+                    }
+                    ble_control.set_scene(&scene.lock())?;
+                    pending = Some(LightEvent::Open);
+                }
+                LightEvent::Undo => match nvs_store.history.pop() {
+                    Some(Change::Scene(old)) => {
+                        // 撤销场景修改不再记录历史
+                        *scene.lock() = old;
+                        if let Err(e) = nvs_store.write_scene() {
+                            report::error(Module::Store, ErrorCode::Storage, e);
+                        }
+                        ble_control.set_scene(&scene.lock())?;
+                        if mode.is_on() {
+                            pending = Some(LightEvent::Open);
                         }
                     }
-                })
-                .unwrap();
-                *open_task.lock().unwrap() = Some(abort_handle);
-                ble_control.set_state(LightState::Opened);
-            }
-            LightEvent::Reset => {
-                ble_control.reset_scene()?;
+                    Some(Change::Task(task)) => {
+                        ble_control.timer_sender.clone().add_task(task)?;
+                    }
+                    None => log::warn!("nothing to undo"),
+                },
+                LightEvent::Preview(value) => {
+                    let was_open = match &mode {
+                        Mode::Preview { preview, .. } => preview.was_open,
+                        mode => mode.is_on(),
+                    };
+                    staged_preview = Some(Preview {
+                        scene: value,
+                        deadline: Instant::now() + PREVIEW_TIMEOUT,
+                        was_open,
+                    });
+                    pending = Some(LightEvent::Open);
+                }
+                LightEvent::PreviewConfirm => {
+                    let Some(confirmed) = mode.end_preview() else {
+                        log::warn!("nothing to confirm");
+                        continue;
+                    };
+                    if let Err(e) = nvs_store.set_scene(confirmed.scene) {
+                        report::error(Module::Store, ErrorCode::Storage, e);
+                    }
+                    ble_control.set_scene(&scene.lock())?;
+                    // 重新开灯以同步给其他设备
+                    pending = Some(LightEvent::Open);
+                }
+                LightEvent::PreviewCancel => {
+                    if let Some(cancelled) = mode.end_preview() {
+                        pending = Some(if cancelled.was_open {
+                            LightEvent::Open
+                        } else {
+                            LightEvent::Close
+                        });
+                    }
+                }
+                // 关灯时会重置调整，灯关闭时的调整没有意义
+                LightEvent::SetSpeed(_) | LightEvent::SetIntensity(_)
+                    if matches!(mode, Mode::Off) =>
+                {
+                    log::warn!("light is off, ignore {event:?}");
+                }
+                // 渲染任务每帧读取倍率，不需要重新开始效果
+                LightEvent::SetSpeed(speed) => {
+                    tweak.set_speed(speed);
+                    log::info!("speed: {}", tweak.speed());
+                }
+                LightEvent::SetIntensity(intensity) => {
+                    tweak.set_intensity(intensity);
+                    log::info!("intensity: {}", tweak.intensity());
+                }
             }
         }
     }
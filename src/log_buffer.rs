@@ -0,0 +1,213 @@
+use crate::{
+    executor::Executor,
+    report::{self, ErrorCode, Module},
+    store::{LogLevel, LogLevelConfig, NvsStore, DEFAULT_TAG},
+};
+use anyhow::Result;
+use esp_idf_svc::{log::EspLogger, timer::EspTaskTimerService};
+use futures::task::SpawnExt;
+use log::{Level, Log, Metadata, Record};
+use serde::Deserialize;
+use std::{
+    collections::VecDeque,
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+/// 内存中最多保留的日志字节数，超过后丢弃最早的
+const CAPACITY: usize = 4 * 1024;
+/// 单行日志的最大长度
+const MAX_LINE_LEN: usize = 200;
+/// 有新日志时保存到NVS的间隔，避免频繁擦写Flash
+const PERSIST_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Buffer {
+    lines: VecDeque<String>,
+    size: usize,
+    /// 上次保存后是否有新日志
+    dirty: bool,
+    /// 上次启动时保存的日志
+    previous: String,
+}
+
+impl Buffer {
+    const fn new() -> Self {
+        Self {
+            lines: VecDeque::new(),
+            size: 0,
+            dirty: false,
+            previous: String::new(),
+        }
+    }
+
+    fn push(&mut self, line: String) {
+        self.size += line.len();
+        self.lines.push_back(line);
+        while self.size > CAPACITY {
+            match self.lines.pop_front() {
+                Some(line) => self.size -= line.len(),
+                None => break,
+            }
+        }
+        self.dirty = true;
+    }
+
+    fn text(&self) -> String {
+        self.lines.iter().map(String::as_str).collect()
+    }
+}
+
+static BUFFER: Mutex<Buffer> = Mutex::new(Buffer::new());
+/// 用于运行时调整日志级别
+static LOGGER: OnceLock<&'static BufferLogger> = OnceLock::new();
+
+/// 在`EspLogger`输出到串口的同时，把日志记录到环形缓冲区
+struct BufferLogger {
+    inner: EspLogger,
+}
+
+impl Log for BufferLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        self.inner.log(record);
+        // 调试日志过多，只记录Info及以上级别
+        if !self.enabled(record.metadata()) || record.level() > Level::Info {
+            return;
+        }
+        let uptime = unsafe { esp_idf_svc::sys::esp_timer_get_time() } as u64 / 1000;
+        let mut line = format!(
+            "{}.{:03} {} {}: {}",
+            uptime / 1000,
+            uptime % 1000,
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        if line.len() > MAX_LINE_LEN {
+            let mut end = MAX_LINE_LEN;
+            while !line.is_char_boundary(end) {
+                end -= 1;
+            }
+            line.truncate(end);
+        }
+        line.push('\n');
+        // 日志可能在任意线程中输出，锁异常时直接丢弃，不影响调用方
+        if let Ok(mut buffer) = BUFFER.lock() {
+            buffer.push(line);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// 替代`EspLogger::initialize_default`，启动时最先调用
+pub fn init() {
+    let logger = Box::leak(Box::new(BufferLogger {
+        inner: EspLogger::new(),
+    }));
+    if log::set_logger(logger).is_ok() {
+        logger.inner.initialize();
+        let _ = LOGGER.set(logger);
+    }
+}
+
+/// 应用日志级别，同时作用于Rust日志和esp-idf组件的日志
+///
+/// 先设置默认级别，之前单独设置而现在移除的标签恢复为默认级别
+pub fn apply_levels(old: &LogLevelConfig, new: &LogLevelConfig) -> Result<()> {
+    let Some(logger) = LOGGER.get() else {
+        return Ok(());
+    };
+    let default = new
+        .levels
+        .get(DEFAULT_TAG)
+        .copied()
+        .unwrap_or(LogLevel::Info);
+    logger.inner.set_target_level(DEFAULT_TAG, default.into())?;
+    for tag in old.levels.keys() {
+        if !new.levels.contains_key(tag) {
+            logger.inner.set_target_level(tag, default.into())?;
+        }
+    }
+    for (tag, level) in &new.levels {
+        logger.inner.set_target_level(tag, (*level).into())?;
+    }
+    Ok(())
+}
+
+/// 日志特征支持的命令
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LogCommand {
+    /// 把当前日志更新到特征中，之后客户端再读取
+    Refresh,
+    /// 清空日志
+    Clear,
+}
+
+/// 上次启动和本次启动的日志
+pub fn snapshot() -> Vec<u8> {
+    let buffer = BUFFER.lock().unwrap();
+    let mut text = String::new();
+    if !buffer.previous.is_empty() {
+        text.push_str("--- previous boot ---\n");
+        text.push_str(&buffer.previous);
+        text.push_str("--- current boot ---\n");
+    }
+    text.push_str(&buffer.text());
+    text.into_bytes()
+}
+
+pub fn clear(nvs_store: &NvsStore) -> Result<()> {
+    {
+        let mut buffer = BUFFER.lock().unwrap();
+        *buffer = Buffer::new();
+    }
+    nvs_store.write_log(&[])
+}
+
+/// 立即保存本次启动的日志，重启前调用
+pub fn save(nvs_store: &NvsStore) -> Result<()> {
+    let text = {
+        let mut buffer = BUFFER.lock().unwrap();
+        buffer.dirty = false;
+        buffer.text()
+    };
+    nvs_store.write_log(text.as_bytes())
+}
+
+/// 读取上次启动保存的日志，并定期保存本次启动的日志
+pub fn start(nvs_store: NvsStore, pool: &Executor) -> Result<()> {
+    let previous = nvs_store.read_log()?;
+    BUFFER.lock().unwrap().previous = String::from_utf8_lossy(&previous).into_owned();
+    let levels = nvs_store.log_levels.lock().clone();
+    if let Err(e) = apply_levels(&LogLevelConfig::default(), &levels) {
+        report::error(Module::Store, ErrorCode::InvalidData, e);
+    }
+    if nvs_store.safe_mode {
+        return Ok(());
+    }
+
+    let mut async_timer = EspTaskTimerService::new()?.timer_async()?;
+    pool.spawn(async move {
+        while async_timer.after(PERSIST_INTERVAL).await.is_ok() {
+            let text = {
+                let mut buffer = BUFFER.lock().unwrap();
+                if !buffer.dirty {
+                    continue;
+                }
+                buffer.dirty = false;
+                buffer.text()
+            };
+            if let Err(e) = nvs_store.write_log(text.as_bytes()) {
+                report::error(Module::Store, ErrorCode::Storage, e);
+            }
+        }
+    })?;
+    Ok(())
+}
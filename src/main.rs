@@ -1,51 +1,305 @@
-use futures::executor::ThreadPool;
+use esp_idf_svc::hal::gpio::AnyOutputPin;
 use smart_brite::{
+    ambient::Ambient,
+    battery::Battery,
     ble::BleControl,
-    button::Button,
-    led::WS2812RMT,
+    button::ButtonManager,
+    buzzer::Buzzer,
+    executor::Executor,
+    indicator::{BleStatus, Indicator},
+    ir::Ir,
+    led::{Apa102, Led, Pwm, RmtStrip},
     light::{handle_light_event, LightEventSender},
-    store::NvsStore,
+    mic::Audio,
+    report::{self, ErrorCode, Module},
+    self_test::{Failure, SelfTest},
+    store::{BoardConfig, NvsStore, StartupBehavior, StripType},
+    sync::Sync,
     timer::{TimeTaskManager, TimerEventSender},
+    wifi::Wifi,
 };
 use std::sync::{Arc, Mutex};
 
 fn main() -> anyhow::Result<()> {
-    let (_sys_loop, peripherals, nvs_partition) = smart_brite::init()?;
+    let (sys_loop, peripherals, nvs_partition) = smart_brite::init()?;
+    // 主线程最后处理灯光事件，不会退出
+    smart_brite::diagnostics::track_stack("main");
 
-    let led = Arc::new(Mutex::new(WS2812RMT::new(
-        peripherals.pins.gpio8,
-        peripherals.rmt.channel0,
-    )?));
+    // 开机时按住按键进入安全模式，损坏的配置不会导致反复重启
+    let safe_boot =
+        smart_brite::reset::safe_boot_requested(NvsStore::stored_button_pin(&nvs_partition));
+    let nvs_store = NvsStore::new(nvs_partition.clone(), safe_boot)?;
+    // 启动自检，失败时记录结果并以闪烁码提示
+    let mut self_test = SelfTest::default();
+    self_test.check(Failure::Nvs, &nvs_store.self_test());
 
-    let pool = ThreadPool::builder().pool_size(3).create()?;
+    // 引脚由开发板配置决定，配置无效时使用默认引脚，保证能够启动
+    let board = nvs_store.board.lock().clone();
+    let settings = nvs_store.settings.lock().clone();
+    let board = match board.validate() {
+        Ok(_) => board,
+        Err(e) => {
+            report::error(Module::Store, ErrorCode::InvalidData, e);
+            BoardConfig::default()
+        }
+    };
+    let led_pin = unsafe { AnyOutputPin::new(board.led_pin as i32) };
+    let led = match (board.strip, board.led_channel) {
+        (StripType::Apa102 { clock_pin }, _) => {
+            let clock_pin = unsafe { AnyOutputPin::new(clock_pin as i32) };
+            Led::new(Apa102::new(peripherals.spi2, led_pin, clock_pin)?)
+        }
+        (StripType::Pwm(pins), _) => {
+            let channels = (
+                peripherals.ledc.channel1,
+                peripherals.ledc.channel2,
+                peripherals.ledc.channel3,
+                peripherals.ledc.channel4,
+                peripherals.ledc.channel5,
+            );
+            Led::new(Pwm::new(peripherals.ledc.timer1, channels, pins)?)
+        }
+        (StripType::Ws2812, 0) => Led::new(RmtStrip::ws2812(led_pin, peripherals.rmt.channel0)?),
+        (StripType::Ws2812, _) => Led::new(RmtStrip::ws2812(led_pin, peripherals.rmt.channel1)?),
+        (StripType::Sk6812, 0) => Led::new(RmtStrip::sk6812(led_pin, peripherals.rmt.channel0)?),
+        (StripType::Sk6812, _) => Led::new(RmtStrip::sk6812(led_pin, peripherals.rmt.channel1)?),
+    };
+    let led = Arc::new(Mutex::new(led));
+    self_test.check(Failure::Led, &led.lock().unwrap().close());
 
-    let nvs_store = NvsStore::new(nvs_partition)?;
+    let ambient = Ambient::default();
+    #[cfg(feature = "als")]
+    smart_brite::sensor::start_adc(peripherals.adc1, peripherals.pins.gpio0, ambient.clone())?;
+    #[cfg(feature = "als-i2c")]
+    smart_brite::sensor::start_bh1750(
+        peripherals.i2c0,
+        peripherals.pins.gpio4,
+        peripherals.pins.gpio5,
+        ambient.clone(),
+    )?;
+
+    #[cfg(feature = "buzzer")]
+    let buzzer = Buzzer::start(
+        peripherals.ledc.timer0,
+        peripherals.ledc.channel0,
+        peripherals.pins.gpio3,
+    )?;
+    #[cfg(not(feature = "buzzer"))]
+    let buzzer = Buzzer::default();
+
+    let audio = Audio::default();
+    #[cfg(feature = "mic")]
+    smart_brite::mic::start_i2s(
+        peripherals.i2s0,
+        peripherals.pins.gpio7,
+        peripherals.pins.gpio1,
+        peripherals.pins.gpio2,
+        audio.clone(),
+    )?;
+
+    let pool = Executor::start()?;
 
-    let (light_event_sender, event_rx) = LightEventSender::new_pari();
+    {
+        let mut led = led.lock().unwrap();
+        led.set_calibration(nvs_store.calibration.clone());
+        led.set_power_limit(nvs_store.settings.clone());
+    }
+    // 读取上次启动的日志，并定期保存本次的日志
+    smart_brite::log_buffer::start(nvs_store.clone(), &pool)?;
+    smart_brite::usage::start(nvs_store.clone(), &pool)?;
+
+    let indicator = Indicator::new(nvs_store.indicator.clone(), led.clone(), pool.clone());
+
+    let (mut light_event_sender, event_rx) = LightEventSender::new_pari();
     let (timer_event_sender, time_event_rx) = TimerEventSender::new_pair();
 
     let time_task_manager = TimeTaskManager::new(
         nvs_store.time_task.clone(),
+        nvs_store.tasks_paused.clone(),
         light_event_sender.clone(),
+        timer_event_sender.clone(),
+        buzzer,
         pool.clone(),
     );
 
+    let sync = Sync::new(nvs_store.sync.clone());
+    let ir = Ir::new();
+    smart_brite::notify::start()?;
     let ble_control = BleControl::new(
         nvs_store.clone(),
         light_event_sender.clone(),
-        timer_event_sender,
+        timer_event_sender.clone(),
+        indicator.clone(),
+        sync.clone(),
+        ir.clone(),
         pool.clone(),
-    )?;
-    let button = Button::new(
-        peripherals.pins.gpio9,
-        ble_control.clone(),
-        light_event_sender,
-    )?;
+    );
+    self_test.check(Failure::Ble, &ble_control);
+    self_test.finish(&led)?;
+    // OTA升级后的新固件自检失败时回滚，通过后稳定运行一段时间再确认
+    smart_brite::ota::start(&self_test, &pool)?;
+    let ble_control = ble_control?;
+    // Mesh与自定义GATT服务共用NimBLE，需要在其初始化之后启动
+    #[cfg(feature = "mesh")]
+    if !settings.features.mesh {
+        log::info!("mesh disabled by settings");
+    } else if let Err(e) = smart_brite::mesh::start() {
+        report::error(
+            Module::Mesh,
+            ErrorCode::Internal,
+            format!("start mesh error: {e}"),
+        );
+    }
+    // 需要在按键等发布者启动前订阅灯光事件
+    if let Err(e) = smart_brite::controller::start(nvs_store.controller.clone(), &pool) {
+        report::error(
+            Module::Controller,
+            ErrorCode::Internal,
+            format!("start controller error: {e}"),
+        );
+    }
+    let button = ButtonManager::new(&board, ble_control.clone(), light_event_sender.clone())?;
+    let wifi = Wifi::new(nvs_store.wifi.clone()).with_portal(settings.features.portal);
+    wifi.start(peripherals.modem, sys_loop, nvs_partition)?;
+    // ESP-NOW依赖Wi-Fi驱动，未配置路由器时也能同步
+    if !settings.features.sync {
+        log::info!("sync disabled by settings");
+    } else if let Err(e) = sync.start(light_event_sender.clone()) {
+        report::error(
+            Module::Sync,
+            ErrorCode::Network,
+            format!("start sync error: {e}"),
+        );
+    }
+    let portal = settings.features.portal && nvs_store.wifi.lock().ssid.is_empty();
+    // Wi-Fi连接后即可通过HTTP控制，服务需要一直持有
+    let mut http_server = if settings.features.http {
+        Some(smart_brite::http::start(
+            ble_control.clone(),
+            light_event_sender.clone(),
+            timer_event_sender.clone(),
+        )?)
+    } else if portal {
+        // 关闭了HTTP控制时只提供配网页面
+        Some(smart_brite::http::server()?)
+    } else {
+        None
+    };
+    if let Some(server) = http_server.as_mut().filter(|_| portal) {
+        if let Err(e) = smart_brite::portal::start(server, ble_control.clone()) {
+            report::error(
+                Module::System,
+                ErrorCode::Network,
+                format!("start wifi portal error: {e}"),
+            );
+        }
+    }
+    if let Some(server) = http_server.as_mut().filter(|_| settings.features.hue) {
+        if let Err(e) = smart_brite::hue_emulation::start(
+            server,
+            wifi.clone(),
+            ble_control.clone(),
+            light_event_sender.clone(),
+        ) {
+            report::error(
+                Module::System,
+                ErrorCode::Network,
+                format!("start hue emulation error: {e}"),
+            );
+        }
+    }
+    if settings.features.mqtt {
+        if let Err(e) = smart_brite::mqtt::start(ble_control.clone(), wifi.clone()) {
+            report::error(
+                Module::System,
+                ErrorCode::Network,
+                format!("start mqtt error: {e}"),
+            );
+        }
+    }
+    if settings.features.realtime || nvs_store.dmx.lock().enabled {
+        if let Err(e) =
+            smart_brite::realtime::start(ble_control.clone(), light_event_sender.clone())
+        {
+            report::error(
+                Module::System,
+                ErrorCode::Network,
+                format!("start realtime stream error: {e}"),
+            );
+        }
+    }
+    smart_brite::sntp::start(wifi, timer_event_sender)?;
+
     time_task_manager.handle_event(time_event_rx, ble_control.clone())?;
     ble_control.init()?;
+    smart_brite::advertising::start_backoff(ble_control.clone(), &pool)?;
+    smart_brite::power::start(ble_control.clone(), &pool)?;
+    let battery = Battery::default();
+    #[cfg(feature = "battery")]
+    smart_brite::battery::start_adc(
+        peripherals.adc1,
+        peripherals.pins.gpio3,
+        battery.clone(),
+        ble_control.clone(),
+        light_event_sender.clone(),
+    )?;
+    // 温度传感器出错时不影响其他功能
+    if let Err(e) = smart_brite::thermal::start(ble_control.clone(), light_event_sender.clone()) {
+        report::error(
+            Module::Thermal,
+            ErrorCode::Hardware,
+            format!("start temperature sensor error: {e}"),
+        );
+    }
+    indicator.set_status(BleStatus::Advertising);
     button.init()?;
+    // 人体感应引脚可配置，配置错误时不影响其他功能
+    if let Err(e) = smart_brite::motion::start(ble_control.clone(), light_event_sender.clone()) {
+        report::error(
+            Module::Motion,
+            ErrorCode::Hardware,
+            format!("start motion sensor error: {e}"),
+        );
+    }
+    if let Err(e) = smart_brite::encoder::start(ble_control.clone(), light_event_sender.clone()) {
+        report::error(
+            Module::Encoder,
+            ErrorCode::Hardware,
+            format!("start encoder error: {e}"),
+        );
+    }
+    #[cfg(feature = "ir")]
+    ir.start(
+        peripherals.rmt.channel2,
+        peripherals.pins.gpio6,
+        ble_control.clone(),
+        light_event_sender.clone(),
+    )?;
     time_task_manager.run()?;
-    handle_light_event(event_rx, ble_control, nvs_store, led, pool)?;
+
+    // 默认在场景设置了自动开灯，或断电前灯是打开的时候，上电后恢复开灯
+    let startup_on = match settings.startup {
+        StartupBehavior::Restore => nvs_store.scene.lock().auto_on || nvs_store.light_on()?,
+        StartupBehavior::On => true,
+        StartupBehavior::Off => false,
+    };
+    if startup_on {
+        light_event_sender.open()?;
+    }
+    // 监控事件循环、执行器与渲染任务，卡死时记录原因并重启
+    smart_brite::watchdog::start(nvs_store.clone(), led.clone(), &pool)?;
+    handle_light_event(
+        event_rx,
+        ble_control,
+        nvs_store,
+        led,
+        indicator,
+        ambient,
+        audio,
+        battery,
+        sync,
+        pool,
+    )?;
 
     Ok(())
 }
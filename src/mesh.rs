@@ -0,0 +1,244 @@
+use crate::{
+    led::hsl_to_rgb,
+    light::{LightEvent, LightEventSender},
+    report::{self, ErrorCode, Module},
+};
+use anyhow::Result;
+use esp_idf_svc::sys::*;
+use std::{
+    ffi::c_void,
+    sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+};
+
+/// 乐鑫在蓝牙SIG注册的公司ID
+const COMPANY_ID: u16 = 0x02E5;
+
+/// 规范中定义的SIG模型ID
+const MODEL_ID_CONFIG_SRV: u16 = 0x0000;
+const MODEL_ID_GEN_ONOFF_SRV: u16 = 0x1000;
+const MODEL_ID_LIGHT_HSL_SRV: u16 = 0x1307;
+const MODEL_ID_LIGHT_HSL_SETUP_SRV: u16 = 0x1308;
+const MODEL_ID_LIGHT_HSL_HUE_SRV: u16 = 0x130A;
+const MODEL_ID_LIGHT_HSL_SAT_SRV: u16 = 0x130B;
+
+/// 协议栈的回调只能注册一次
+static STARTED: AtomicBool = AtomicBool::new(false);
+/// 开关状态，本地开关灯后同步，使控制器读取到的状态正确
+static ONOFF: AtomicPtr<esp_ble_mesh_gen_onoff_srv_t> = AtomicPtr::new(std::ptr::null_mut());
+/// HSL服务器共用的状态
+static HSL: AtomicPtr<esp_ble_mesh_light_hsl_state_t> = AtomicPtr::new(std::ptr::null_mut());
+
+/// 协议栈在初始化时保存指针，模型等数据需要一直存在
+fn leak<T>(value: T) -> *mut T {
+    Box::leak(Box::new(value))
+}
+
+fn auto_rsp() -> esp_ble_mesh_server_rsp_ctrl_t {
+    esp_ble_mesh_server_rsp_ctrl_t {
+        get_auto_rsp: ESP_BLE_MESH_SERVER_AUTO_RSP as u8,
+        set_auto_rsp: ESP_BLE_MESH_SERVER_AUTO_RSP as u8,
+        status_auto_rsp: ESP_BLE_MESH_SERVER_AUTO_RSP as u8,
+    }
+}
+
+/// 对应`ESP_BLE_MESH_SIG_MODEL`宏，操作码由协议栈根据模型ID填充
+fn sig_model(id: u16, user_data: *mut c_void) -> esp_ble_mesh_model_t {
+    let mut model = esp_ble_mesh_model_t::default();
+    model.__bindgen_anon_1.model_id = id;
+    model.keys.fill(ESP_BLE_MESH_KEY_UNUSED as u16);
+    model.groups.fill(ESP_BLE_MESH_ADDR_UNASSIGNED as u16);
+    model.user_data = user_data;
+    model
+}
+
+fn element(models: Vec<esp_ble_mesh_model_t>) -> esp_ble_mesh_elem_t {
+    let count = models.len() as u8;
+    esp_ble_mesh_elem_t {
+        location: 0,
+        sig_model_count: count,
+        sig_models: Box::leak(models.into_boxed_slice()).as_mut_ptr(),
+        ..Default::default()
+    }
+}
+
+/// 设备UUID，前两个字节固定，后面为蓝牙MAC地址
+fn device_uuid() -> [u8; 16] {
+    let mut uuid = [0u8; 16];
+    uuid[0] = 0x53;
+    uuid[1] = 0x42;
+    unsafe {
+        esp_read_mac(uuid[2..].as_mut_ptr(), esp_mac_type_t_ESP_MAC_BT);
+    }
+    uuid
+}
+
+/// 收到Mesh消息后通过事件总线发送灯光事件
+fn send(event: LightEvent) {
+    if LightEventSender.send(event).is_err() {
+        report::error(Module::Mesh, ErrorCode::Internal, "mesh event error");
+    }
+}
+
+/// 启动Mesh节点并开始等待配网，需要在`BleControl::new`初始化NimBLE之后调用。
+///
+/// 主元素包含配置服务器、Generic OnOff、Light HSL和Light HSL Setup服务器，
+/// 规范要求Hue和Saturation服务器位于后续的两个元素中。
+/// 服务器模型由协议栈自动回复并更新状态，这里只把状态变化转换为灯光事件。
+pub fn start() -> Result<()> {
+    if STARTED.swap(true, Ordering::SeqCst) {
+        anyhow::bail!("mesh already started");
+    }
+
+    let cfg_srv = leak(esp_ble_mesh_cfg_srv_t {
+        // 2次重传，间隔20ms
+        net_transmit: 2 | (1 << 3),
+        relay: ESP_BLE_MESH_RELAY_DISABLED as u8,
+        relay_retransmit: 2 | (1 << 3),
+        beacon: ESP_BLE_MESH_BEACON_ENABLED as u8,
+        gatt_proxy: ESP_BLE_MESH_GATT_PROXY_ENABLED as u8,
+        friend_state: ESP_BLE_MESH_FRIEND_NOT_SUPPORTED as u8,
+        default_ttl: 7,
+        ..Default::default()
+    });
+    let onoff_srv = leak(esp_ble_mesh_gen_onoff_srv_t {
+        rsp_ctrl: auto_rsp(),
+        ..Default::default()
+    });
+    let hsl_state = leak(esp_ble_mesh_light_hsl_state_t {
+        lightness: u16::MAX,
+        saturation_range_max: u16::MAX,
+        hue_range_max: u16::MAX,
+        ..Default::default()
+    });
+    let hsl_srv = leak(esp_ble_mesh_light_hsl_srv_t {
+        rsp_ctrl: auto_rsp(),
+        state: hsl_state,
+        ..Default::default()
+    });
+    let hsl_setup_srv = leak(esp_ble_mesh_light_hsl_setup_srv_t {
+        rsp_ctrl: auto_rsp(),
+        state: hsl_state,
+        ..Default::default()
+    });
+    let hue_srv = leak(esp_ble_mesh_light_hsl_hue_srv_t {
+        rsp_ctrl: auto_rsp(),
+        state: hsl_state,
+        ..Default::default()
+    });
+    let sat_srv = leak(esp_ble_mesh_light_hsl_sat_srv_t {
+        rsp_ctrl: auto_rsp(),
+        state: hsl_state,
+        ..Default::default()
+    });
+    ONOFF.store(onoff_srv, Ordering::Release);
+    HSL.store(hsl_state, Ordering::Release);
+
+    let elements = vec![
+        element(vec![
+            sig_model(MODEL_ID_CONFIG_SRV, cfg_srv.cast()),
+            sig_model(MODEL_ID_GEN_ONOFF_SRV, onoff_srv.cast()),
+            sig_model(MODEL_ID_LIGHT_HSL_SRV, hsl_srv.cast()),
+            sig_model(MODEL_ID_LIGHT_HSL_SETUP_SRV, hsl_setup_srv.cast()),
+        ]),
+        element(vec![sig_model(MODEL_ID_LIGHT_HSL_HUE_SRV, hue_srv.cast())]),
+        element(vec![sig_model(MODEL_ID_LIGHT_HSL_SAT_SRV, sat_srv.cast())]),
+    ];
+    let element_count = elements.len();
+    let comp = leak(esp_ble_mesh_comp_t {
+        cid: COMPANY_ID,
+        element_count,
+        elements: Box::leak(elements.into_boxed_slice()).as_mut_ptr(),
+        ..Default::default()
+    });
+    let prov = leak(esp_ble_mesh_prov_t {
+        uuid: Box::leak(Box::new(device_uuid())).as_ptr(),
+        ..Default::default()
+    });
+
+    esp!(unsafe { esp_ble_mesh_register_prov_callback(Some(prov_cb)) })?;
+    esp!(unsafe { esp_ble_mesh_register_generic_server_callback(Some(generic_server_cb)) })?;
+    esp!(unsafe { esp_ble_mesh_register_lighting_server_callback(Some(lighting_server_cb)) })?;
+    esp!(unsafe { esp_ble_mesh_init(prov, comp) })?;
+    // 同时支持广播承载和GATT承载配网，已配网时协议栈会忽略
+    esp!(unsafe {
+        esp_ble_mesh_node_prov_enable(
+            (esp_ble_mesh_prov_bearer_t_ESP_BLE_MESH_PROV_ADV
+                | esp_ble_mesh_prov_bearer_t_ESP_BLE_MESH_PROV_GATT) as _,
+        )
+    })?;
+    log::info!("mesh node started");
+    Ok(())
+}
+
+/// 本地开关灯后更新Mesh中的开关状态
+pub fn set_onoff(on: bool) {
+    let srv = ONOFF.load(Ordering::Acquire);
+    if !srv.is_null() {
+        unsafe { (*srv).state.onoff = on as u8 };
+    }
+}
+
+extern "C" fn prov_cb(
+    event: esp_ble_mesh_prov_cb_event_t,
+    param: *mut esp_ble_mesh_prov_cb_param_t,
+) {
+    match event {
+        esp_ble_mesh_prov_cb_event_t_ESP_BLE_MESH_NODE_PROV_COMPLETE_EVT => {
+            let complete = unsafe { (*param).node_prov_complete };
+            log::info!(
+                "mesh provisioned, net_idx {:#06x}, addr {:#06x}",
+                complete.net_idx,
+                complete.addr
+            );
+        }
+        esp_ble_mesh_prov_cb_event_t_ESP_BLE_MESH_NODE_PROV_RESET_EVT => {
+            log::warn!("mesh node reset");
+        }
+        _ => {}
+    }
+}
+
+extern "C" fn generic_server_cb(
+    event: esp_ble_mesh_generic_server_cb_event_t,
+    _param: *mut esp_ble_mesh_generic_server_cb_param_t,
+) {
+    if event != esp_ble_mesh_generic_server_cb_event_t_ESP_BLE_MESH_GENERIC_SERVER_STATE_CHANGE_EVT
+    {
+        return;
+    }
+    let srv = ONOFF.load(Ordering::Acquire);
+    if srv.is_null() {
+        return;
+    }
+    match unsafe { (*srv).state.onoff } {
+        0 => send(LightEvent::Close),
+        _ => send(LightEvent::Open),
+    }
+}
+
+extern "C" fn lighting_server_cb(
+    event: esp_ble_mesh_lighting_server_cb_event_t,
+    _param: *mut esp_ble_mesh_lighting_server_cb_param_t,
+) {
+    if event
+        != esp_ble_mesh_lighting_server_cb_event_t_ESP_BLE_MESH_LIGHTING_SERVER_STATE_CHANGE_EVT
+    {
+        return;
+    }
+    let state = HSL.load(Ordering::Acquire);
+    if state.is_null() {
+        return;
+    }
+    // 色相、饱和度和亮度的设置都已经写入共用的状态
+    let state = unsafe { *state };
+    if state.lightness == 0 {
+        send(LightEvent::Close);
+        return;
+    }
+    let color = hsl_to_rgb(
+        state.hue as f32 * 360.0 / 65536.0,
+        state.saturation as f32 / u16::MAX as f32,
+        state.lightness as f32 / u16::MAX as f32,
+    );
+    send(LightEvent::SetColor(color));
+}
@@ -0,0 +1,213 @@
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    Arc,
+};
+
+pub use smart_brite_core::effect::AudioFrame;
+
+/// 采样率，频谱最高可分析到8kHz
+pub const SAMPLE_RATE: u32 = 16000;
+/// 每帧的采样点数，16kHz下约16ms一帧，与渲染帧率相当
+pub const FRAME_SIZE: usize = 256;
+/// 低音、中音的上限频率，其余为高音
+const BASS_MAX_HZ: f32 = 250.0;
+const MID_MAX_HZ: f32 = 2000.0;
+/// 自动增益的峰值衰减系数，安静一段时间后灵敏度逐渐恢复
+const PEAK_DECAY: f32 = 0.995;
+/// 峰值下限，避免底噪被放大
+const MIN_PEAK: f32 = 0.01;
+
+/// 采样任务与渲染之间共享的最新一帧
+///
+/// 每项单独用原子变量保存，渲染时不需要加锁，不会阻塞高优先级的采样任务
+#[derive(Clone, Default)]
+pub struct Audio {
+    active: Arc<AtomicBool>,
+    values: Arc<[AtomicU32; 4]>,
+}
+
+impl Audio {
+    /// 最新一帧，没有麦克风时为None
+    pub fn frame(&self) -> Option<AudioFrame> {
+        if !self.active.load(Ordering::Acquire) {
+            return None;
+        }
+        let [level, bass, mid, treble] =
+            [0, 1, 2, 3].map(|index| f32::from_bits(self.values[index].load(Ordering::Relaxed)));
+        Some(AudioFrame {
+            level,
+            bass,
+            mid,
+            treble,
+        })
+    }
+
+    pub fn publish(&self, frame: AudioFrame) {
+        for (index, value) in [frame.level, frame.bass, frame.mid, frame.treble]
+            .into_iter()
+            .enumerate()
+        {
+            self.values[index].store(value.to_bits(), Ordering::Relaxed);
+        }
+        self.active.store(true, Ordering::Release);
+    }
+}
+
+/// 原地基2快速傅里叶变换，长度必须是2的幂
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * std::f32::consts::PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// 分析一帧采样，峰值用于自动增益，不同环境音量下效果一致
+pub struct Analyzer {
+    peaks: [f32; 4],
+}
+
+impl Default for Analyzer {
+    fn default() -> Self {
+        Self {
+            peaks: [MIN_PEAK; 4],
+        }
+    }
+}
+
+impl Analyzer {
+    /// 输入为归一化到-1~1的采样
+    pub fn analyze(&mut self, samples: &[f32]) -> AudioFrame {
+        let n = samples.len().next_power_of_two().min(FRAME_SIZE);
+        let mut re = vec![0f32; n];
+        let mut im = vec![0f32; n];
+        let mean = samples.iter().sum::<f32>() / samples.len().max(1) as f32;
+        let mut rms = 0f32;
+        for (index, sample) in samples.iter().take(n).enumerate() {
+            let sample = sample - mean;
+            rms += sample * sample;
+            // 汉宁窗，减少频谱泄漏
+            let window = 0.5 - 0.5 * (2.0 * std::f32::consts::PI * index as f32 / n as f32).cos();
+            re[index] = sample * window;
+        }
+        let rms = (rms / samples.len().clamp(1, n) as f32).sqrt();
+        fft(&mut re, &mut im);
+
+        let mut bands = [0f32; 3];
+        let bin_hz = SAMPLE_RATE as f32 / n as f32;
+        // 跳过直流分量
+        for bin in 1..n / 2 {
+            let magnitude = (re[bin] * re[bin] + im[bin] * im[bin]).sqrt();
+            let hz = bin as f32 * bin_hz;
+            let band = if hz < BASS_MAX_HZ {
+                0
+            } else if hz < MID_MAX_HZ {
+                1
+            } else {
+                2
+            };
+            bands[band] += magnitude;
+        }
+
+        let raw = [rms, bands[0], bands[1], bands[2]];
+        let mut normalized = [0f32; 4];
+        for (index, value) in raw.into_iter().enumerate() {
+            self.peaks[index] = (self.peaks[index] * PEAK_DECAY).max(value).max(MIN_PEAK);
+            normalized[index] = (value / self.peaks[index]).clamp(0.0, 1.0);
+        }
+        let [level, bass, mid, treble] = normalized;
+        AudioFrame {
+            level,
+            bass,
+            mid,
+            treble,
+        }
+    }
+}
+
+/// I2S数字麦克风（如INMP441），BCLK接GPIO7，WS接GPIO1，DATA接GPIO2
+///
+/// 采样在高优先级线程中进行，保证渲染效果跟得上节奏
+#[cfg(feature = "mic")]
+pub fn start_i2s(
+    i2s: esp_idf_svc::hal::i2s::I2S0,
+    bclk: esp_idf_svc::hal::gpio::Gpio7,
+    ws: esp_idf_svc::hal::gpio::Gpio1,
+    din: esp_idf_svc::hal::gpio::Gpio2,
+    audio: Audio,
+) -> anyhow::Result<()> {
+    use esp_idf_svc::hal::{
+        delay::BLOCK,
+        gpio::AnyIOPin,
+        i2s::{
+            config::{DataBitWidth, StdConfig},
+            I2sDriver, I2sRx,
+        },
+        task::thread::ThreadSpawnConfiguration,
+    };
+
+    let config = StdConfig::philips(SAMPLE_RATE, DataBitWidth::Bits32);
+    let mut driver =
+        I2sDriver::<I2sRx>::new_std_rx(i2s, &config, bclk, din, Option::<AnyIOPin>::None, ws)?;
+    driver.rx_enable()?;
+
+    ThreadSpawnConfiguration {
+        name: Some(b"mic\0"),
+        priority: 10,
+        ..Default::default()
+    }
+    .set()?;
+    let res =
+        std::thread::Builder::new()
+            .stack_size(8 * 1024)
+            .spawn(move || -> anyhow::Result<()> {
+                crate::diagnostics::track_stack("mic");
+                let mut analyzer = Analyzer::default();
+                let mut buffer = [0u8; FRAME_SIZE * 4];
+                loop {
+                    let len = driver.read(&mut buffer, BLOCK)?;
+                    // 32位采样，有效数据为高24位
+                    let samples = buffer[..len]
+                        .chunks_exact(4)
+                        .map(|bytes| {
+                            let sample =
+                                i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                            (sample >> 8) as f32 / (1 << 23) as f32
+                        })
+                        .collect::<Vec<_>>();
+                    if !samples.is_empty() {
+                        audio.publish(analyzer.analyze(&samples));
+                    }
+                }
+            });
+    // 恢复默认配置，不影响之后创建的线程
+    ThreadSpawnConfiguration::default().set()?;
+    res?;
+    Ok(())
+}
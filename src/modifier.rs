@@ -0,0 +1,220 @@
+use crate::ambient::Ambient;
+use crate::battery::Battery;
+use crate::led::adjust_brightness;
+use crate::mic::{Audio, AudioFrame};
+use crate::store::{AdaptiveConfig, BatteryConfig, ThermalConfig};
+use crate::thermal;
+use esp32_nimble::utilities::mutex::Mutex;
+use rgb::RGB8;
+use smart_brite_core::effect::EffectInput;
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+};
+
+/// 运行时速度倍率的范围
+const MIN_SPEED: f32 = 0.1;
+const MAX_SPEED: f32 = 5.0;
+
+/// 运行时调整的速度和亮度倍率，修改后正在播放的效果立即生效，不需要重新开始
+#[derive(Clone)]
+pub struct Tweak {
+    speed: Arc<AtomicU32>,
+    intensity: Arc<AtomicU32>,
+}
+
+impl Default for Tweak {
+    fn default() -> Self {
+        Self {
+            speed: Arc::new(AtomicU32::new(1f32.to_bits())),
+            intensity: Arc::new(AtomicU32::new(1f32.to_bits())),
+        }
+    }
+}
+
+impl Tweak {
+    pub fn speed(&self) -> f32 {
+        f32::from_bits(self.speed.load(Ordering::Relaxed))
+    }
+
+    pub fn intensity(&self) -> f32 {
+        f32::from_bits(self.intensity.load(Ordering::Relaxed))
+    }
+
+    pub fn set_speed(&self, speed: f32) {
+        let speed = if speed.is_finite() { speed } else { 1.0 };
+        self.speed.store(
+            speed.clamp(MIN_SPEED, MAX_SPEED).to_bits(),
+            Ordering::Relaxed,
+        );
+    }
+
+    pub fn set_intensity(&self, intensity: f32) {
+        let intensity = if intensity.is_finite() {
+            intensity
+        } else {
+            1.0
+        };
+        self.intensity
+            .store(intensity.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn reset(&self) {
+        self.set_speed(1.0);
+        self.set_intensity(1.0);
+    }
+}
+
+/// 渲染修饰器，在不修改场景的情况下调整效果的速度和亮度
+#[derive(Clone)]
+pub struct Modifier {
+    ambient: Option<Ambient>,
+    /// 整体亮度，0.0~1.0
+    brightness: f32,
+    /// 自适应亮度，渲染时读取最新配置
+    adaptive: Option<(Ambient, Arc<Mutex<AdaptiveConfig>>)>,
+    /// 麦克风音频，音乐律动效果使用
+    audio: Option<Audio>,
+    /// 低电量时降低亮度
+    battery: Option<(Battery, Arc<Mutex<BatteryConfig>>)>,
+    /// 芯片过热时降低亮度
+    thermal: Option<Arc<Mutex<ThermalConfig>>>,
+    /// 运行时调整
+    tweak: Option<Tweak>,
+}
+
+impl Default for Modifier {
+    fn default() -> Self {
+        Self {
+            ambient: None,
+            brightness: 1.0,
+            adaptive: None,
+            audio: None,
+            battery: None,
+            thermal: None,
+            tweak: None,
+        }
+    }
+}
+
+impl Modifier {
+    /// 根据环境光调整效果：环境越暗，效果越慢越柔和
+    pub fn ambient(ambient: Ambient) -> Self {
+        Self {
+            ambient: Some(ambient),
+            ..Default::default()
+        }
+    }
+
+    /// 设置亮度百分比
+    pub fn with_brightness(mut self, percent: u8) -> Self {
+        self.brightness = percent.min(100) as f32 / 100.0;
+        self
+    }
+
+    pub fn with_adaptive(mut self, ambient: Ambient, config: Arc<Mutex<AdaptiveConfig>>) -> Self {
+        self.adaptive = Some((ambient, config));
+        self
+    }
+
+    pub fn with_audio(mut self, audio: Audio) -> Self {
+        self.audio = Some(audio);
+        self
+    }
+
+    pub fn with_battery(mut self, battery: Battery, config: Arc<Mutex<BatteryConfig>>) -> Self {
+        self.battery = Some((battery, config));
+        self
+    }
+
+    pub fn with_thermal(mut self, config: Arc<Mutex<ThermalConfig>>) -> Self {
+        self.thermal = Some(config);
+        self
+    }
+
+    pub fn with_tweak(mut self, tweak: Tweak) -> Self {
+        self.tweak = Some(tweak);
+        self
+    }
+
+    /// 电量亮度倍率，没有电池监测时为None
+    fn battery_scale(&self) -> Option<f32> {
+        let (battery, config) = self.battery.as_ref()?;
+        Some(config.lock().scale(battery.percent()?))
+    }
+
+    /// 过热降温的亮度倍率，没有温度读数时为None
+    fn thermal_scale(&self) -> Option<f32> {
+        let config = self.thermal.as_ref()?;
+        thermal::celsius()?;
+        Some(thermal::scale(&config.lock()))
+    }
+
+    /// 最新的音频分析结果，没有麦克风时为None
+    pub fn audio_frame(&self) -> Option<AudioFrame> {
+        self.audio.as_ref()?.frame()
+    }
+
+    fn ambient_level(&self) -> Option<f32> {
+        self.ambient.as_ref()?.level()
+    }
+
+    /// 自适应亮度倍率，未开启或没有传感器时为None
+    fn adaptive_scale(&self) -> Option<f32> {
+        let (ambient, config) = self.adaptive.as_ref()?;
+        let level = ambient.level()?;
+        let config = config.lock();
+        config.enabled.then(|| config.scale(level))
+    }
+
+    /// 效果是否随环境光变化，需要持续刷新
+    pub fn is_active(&self) -> bool {
+        self.ambient_level().is_some()
+            || self
+                .adaptive
+                .as_ref()
+                .is_some_and(|(ambient, _)| ambient.level().is_some())
+            || self.battery_scale().is_some()
+            || self.thermal_scale().is_some()
+            // 运行时调整随时可能变化
+            || self.tweak.is_some()
+    }
+
+    /// 速度倍率
+    pub fn speed(&self) -> f32 {
+        self.ambient_level()
+            .map(|level| 0.3 + 0.7 * level)
+            .unwrap_or(1.0)
+            * self.tweak.as_ref().map_or(1.0, Tweak::speed)
+    }
+
+    /// 亮度倍率
+    pub fn intensity(&self) -> f32 {
+        self.ambient_level()
+            .map(|level| 0.3 + 0.7 * level)
+            .unwrap_or(1.0)
+            * self.adaptive_scale().unwrap_or(1.0)
+            * self.battery_scale().unwrap_or(1.0)
+            * self.thermal_scale().unwrap_or(1.0)
+            * self.tweak.as_ref().map_or(1.0, Tweak::intensity)
+            * self.brightness
+    }
+
+    pub fn apply(&self, color: RGB8) -> RGB8 {
+        if self.is_active() || self.brightness < 1.0 {
+            adjust_brightness(color, self.intensity())
+        } else {
+            color
+        }
+    }
+}
+
+impl EffectInput for Modifier {
+    fn speed(&self) -> f32 {
+        Modifier::speed(self)
+    }
+
+    fn audio_frame(&self) -> Option<AudioFrame> {
+        Modifier::audio_frame(self)
+    }
+}
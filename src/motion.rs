@@ -0,0 +1,84 @@
+use crate::{
+    ble::BleControl,
+    event_bus::{self, SensorEvent},
+    light::{LightEventSender, LightState},
+};
+use anyhow::Result;
+use esp_idf_svc::hal::{
+    delay::{TickType, BLOCK},
+    gpio::{AnyInputPin, InterruptType, PinDriver, Pull},
+    task::notification::Notification,
+};
+use std::{
+    num::NonZeroU32,
+    time::{Duration, Instant},
+};
+
+/// 启动人体感应，PIR传感器有人时输出高电平
+///
+/// 有人时如果灯是关着的则开灯，无人超过设定时间后关灯；
+/// 只自动关闭由感应打开的灯，手动打开的灯不受影响
+pub fn start(ble_control: BleControl, mut light_sender: LightEventSender) -> Result<()> {
+    let config = ble_control.nvs_store.motion.lock().clone();
+    config.validate()?;
+    let board = ble_control.nvs_store.board.lock().clone();
+    if board.used_pins().contains(&config.pin) {
+        anyhow::bail!("Motion pin {} is used by the board", config.pin);
+    }
+    // 引脚由配置决定，已排除其他外设占用的引脚
+    let pin = unsafe { AnyInputPin::new(config.pin as i32) };
+    let mut sensor = PinDriver::input(pin)?;
+    sensor.set_pull(Pull::Down)?;
+    sensor.set_interrupt_type(InterruptType::AnyEdge)?;
+
+    std::thread::spawn(move || -> Result<()> {
+        let notification = Notification::new();
+        let notifier = notification.notifier();
+        unsafe {
+            sensor.subscribe(move || {
+                notifier.notify_and_yield(NonZeroU32::new(1).unwrap());
+            })?;
+        }
+
+        // 由感应打开的灯，无人开始的时间
+        let mut motion_opened = false;
+        let mut idle_since: Option<Instant> = None;
+        loop {
+            sensor.enable_interrupt()?;
+            let config = ble_control.nvs_store.motion.lock().clone();
+            let timeout = match idle_since {
+                Some(since) if motion_opened => {
+                    let timeout = Duration::from_secs(config.timeout_secs as u64);
+                    TickType::from(timeout.saturating_sub(since.elapsed())).ticks()
+                }
+                _ => BLOCK,
+            };
+            if notification.wait(timeout).is_none() {
+                // 无人超时，灯仍然打开则关灯
+                if matches!(light_sender.query_state()?, LightState::Opened) {
+                    light_sender.close()?;
+                }
+                motion_opened = false;
+                idle_since = None;
+                continue;
+            }
+            if !config.enabled {
+                motion_opened = false;
+                idle_since = None;
+                continue;
+            }
+            event_bus::publish(SensorEvent::Motion(sensor.is_high()));
+            if sensor.is_high() {
+                idle_since = None;
+                // 按事件循环中的实际状态判断，避免刚发出的关灯还未处理时误判
+                if matches!(light_sender.query_state()?, LightState::Closed) {
+                    light_sender.open()?;
+                    motion_opened = true;
+                }
+            } else {
+                idle_since = Some(Instant::now());
+            }
+        }
+    });
+    Ok(())
+}
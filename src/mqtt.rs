@@ -0,0 +1,179 @@
+//! 通过MQTT发布遥测和错误事件，便于集中监控多盏灯
+//!
+//! 本模块的错误不通过`report::error`上报，以免错误事件循环发布
+
+use crate::{
+    ble::BleControl, diagnostics, event_bus, report::ErrorEvent, sync::local_mac, thermal,
+    wifi::Wifi,
+};
+use anyhow::{bail, Result};
+use esp_idf_svc::{
+    mqtt::client::{EspMqttClient, EventPayload, MqttClientConfiguration, QoS},
+    sys::esp_crt_bundle_attach,
+};
+use serde::Serialize;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::RecvTimeoutError,
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// 错误发布频率的统计周期
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// 遥测数据
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Telemetry<'a> {
+    /// 开机时长，单位：秒
+    uptime: u64,
+    free_heap: u32,
+    min_free_heap: u32,
+    /// 路由器信号强度，单位：dBm
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rssi: Option<i8>,
+    /// 芯片温度，单位：摄氏度
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    state: &'a str,
+    brightness: u8,
+    scene: String,
+    /// 上次遥测以来因超出频率限制或未连接而丢弃的错误数
+    dropped_errors: u32,
+}
+
+/// 每个统计周期内最多发布的错误数
+struct RateLimit {
+    max: u8,
+    window_start: Instant,
+    count: u8,
+    dropped: u32,
+}
+
+impl RateLimit {
+    fn new(max: u8) -> Self {
+        Self {
+            max,
+            window_start: Instant::now(),
+            count: 0,
+            dropped: 0,
+        }
+    }
+
+    fn allow(&mut self) -> bool {
+        if self.window_start.elapsed() >= RATE_WINDOW {
+            self.window_start = Instant::now();
+            self.count = 0;
+        }
+        if self.count < self.max {
+            self.count += 1;
+            true
+        } else {
+            self.dropped += 1;
+            false
+        }
+    }
+}
+
+fn telemetry(ble_control: &BleControl, wifi: &Wifi, dropped_errors: u32) -> Result<Vec<u8>> {
+    let memory = diagnostics::memory();
+    let state = ble_control.get_state();
+    Ok(serde_json::to_vec(&Telemetry {
+        uptime: unsafe { esp_idf_svc::sys::esp_timer_get_time() } as u64 / 1_000_000,
+        free_heap: memory.free_heap,
+        min_free_heap: memory.min_free_heap,
+        rssi: wifi.rssi(),
+        temperature: thermal::celsius(),
+        state: state.name(),
+        brightness: *ble_control.brightness.lock(),
+        scene: ble_control.nvs_store.scene.lock().name.clone(),
+        dropped_errors,
+    })?)
+}
+
+/// 发布失败只写入日志，返回是否成功
+fn publish(client: &mut EspMqttClient<'static>, topic: &str, payload: Result<Vec<u8>>) -> bool {
+    let result = payload.and_then(|payload| {
+        client.publish(topic, QoS::AtMostOnce, false, &payload)?;
+        Ok(())
+    });
+    if let Err(e) = &result {
+        log::warn!("mqtt publish {topic} error: {e}");
+    }
+    result.is_ok()
+}
+
+/// 连接服务器并启动发布线程，断线后由客户端自动重连
+pub fn start(ble_control: BleControl, wifi: Wifi) -> Result<()> {
+    let config = ble_control.nvs_store.mqtt.lock().clone();
+    if config.url.is_empty() {
+        bail!("Mqtt url not configured");
+    }
+    let id = local_mac().replace(':', "");
+    let (telemetry_topic, error_topic) = config.topics(&id);
+    let client_id = format!("smartbrite-{id}");
+    let connected = Arc::new(AtomicBool::new(false));
+    let connected_clone = connected.clone();
+    let mut client = EspMqttClient::new_cb(
+        &config.url,
+        &MqttClientConfiguration {
+            client_id: Some(&client_id),
+            username: (!config.username.is_empty()).then_some(config.username.as_str()),
+            password: (!config.password.is_empty()).then_some(config.password.as_str()),
+            crt_bundle_attach: config
+                .url
+                .starts_with("mqtts://")
+                .then_some(esp_crt_bundle_attach),
+            ..Default::default()
+        },
+        move |event| match event.payload() {
+            EventPayload::Connected(_) => {
+                log::info!("mqtt connected");
+                connected_clone.store(true, Ordering::Relaxed);
+            }
+            EventPayload::Disconnected => {
+                log::warn!("mqtt disconnected");
+                connected_clone.store(false, Ordering::Relaxed);
+            }
+            EventPayload::Error(e) => log::warn!("mqtt error: {e:?}"),
+            _ => {}
+        },
+    )?;
+
+    let errors = event_bus::subscribe::<ErrorEvent>();
+    let interval = Duration::from_secs(config.interval as u64);
+    std::thread::Builder::new()
+        .stack_size(6 * 1024)
+        .spawn(move || {
+            diagnostics::track_stack("mqtt");
+            let mut limit = RateLimit::new(config.max_errors_per_minute);
+            let mut next = Instant::now() + interval;
+            loop {
+                match errors.recv_timeout(next.saturating_duration_since(Instant::now())) {
+                    Ok(event) => {
+                        if !connected.load(Ordering::Relaxed) {
+                            limit.dropped += 1;
+                        } else if limit.allow() {
+                            let payload = serde_json::to_vec(&event).map_err(Into::into);
+                            publish(&mut client, &error_topic, payload);
+                        }
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+                next = Instant::now() + interval;
+                if !connected.load(Ordering::Relaxed) {
+                    continue;
+                }
+                let payload = telemetry(&ble_control, &wifi, limit.dropped);
+                if publish(&mut client, &telemetry_topic, payload) {
+                    limit.dropped = 0;
+                }
+            }
+        })?;
+    Ok(())
+}
@@ -0,0 +1,288 @@
+use crate::{
+    diagnostics,
+    report::{self, ErrorCode, Module},
+    session,
+    transmission::State,
+};
+use anyhow::Result;
+use esp32_nimble::{BLECharacteristic, NimbleSub};
+use esp_idf_svc::sys::{BLE_HS_EAGAIN, BLE_HS_ENOMEM};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Condvar, Mutex, PoisonError},
+    time::Duration,
+};
+
+type Pending = Box<dyn FnOnce() + Send>;
+type TransferState = Arc<Mutex<Option<State>>>;
+type Characteristic = Arc<esp32_nimble::utilities::mutex::Mutex<BLECharacteristic>>;
+
+/// 每个连接最多排队的通知数，超过后丢弃最早的
+const MAX_OUTGOING: usize = 16;
+/// NimBLE缓冲区耗尽时单条通知的最大重试次数
+const MAX_RETRIES: u8 = 10;
+/// 链路拥塞时等待后重试
+const RETRY_DELAY: Duration = Duration::from_millis(20);
+
+/// 待发送的通知
+struct Outgoing {
+    id: u64,
+    characteristic: Characteristic,
+    /// 为空时发送特征的当前值
+    value: Option<Vec<u8>>,
+    retries: u8,
+}
+
+#[derive(Default)]
+struct Outbox {
+    next_id: u64,
+    /// 按连接句柄排队，连接之间互不阻塞
+    queues: HashMap<u16, VecDeque<Outgoing>>,
+    /// 各连接订阅的特征
+    subscribers: HashMap<u16, Vec<Characteristic>>,
+}
+
+static OUTBOX: Mutex<Option<Outbox>> = Mutex::new(None);
+static WAKE: Condvar = Condvar::new();
+
+/// 所有分块传输的状态，任一传输进行中即认为链路繁忙
+static TRANSFERS: Mutex<Vec<TransferState>> = Mutex::new(Vec::new());
+/// 链路繁忙时推迟的通知，相同`key`只保留最新的一条
+static PENDING: Mutex<Option<HashMap<String, Pending>>> = Mutex::new(None);
+
+/// 登记分块传输的状态，在`Transmission::new`中调用
+pub fn register(state: TransferState) {
+    TRANSFERS.lock().unwrap().push(state);
+}
+
+/// 是否有分块传输正在进行
+pub fn busy() -> bool {
+    TRANSFERS
+        .lock()
+        .unwrap()
+        .iter()
+        // 状态锁中毒时数据仍然有效，不能让通知跟着panic
+        .any(|state| {
+            state
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .is_some()
+        })
+}
+
+/// 发送非必要的通知（状态变化、数据更新等）
+///
+/// 传输进行中时先缓存，传输结束后再发送，优先保证传输的吞吐，避免NimBLE队列耗尽
+pub fn schedule<F>(key: impl Into<String>, notify: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    if !busy() {
+        notify();
+        return;
+    }
+    log::debug!("notify deferred while transfer in progress");
+    PENDING
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(key.into(), Box::new(notify));
+}
+
+/// 链路空闲时发送缓存的通知，传输结束时调用
+pub fn flush() {
+    if busy() {
+        return;
+    }
+    // 先取出再发送，避免发送过程中再次登记时死锁
+    let pending = PENDING.lock().unwrap().take();
+    for (_, notify) in pending.into_iter().flatten() {
+        notify();
+    }
+}
+
+fn enqueue(
+    outbox: &mut Outbox,
+    conn_handle: u16,
+    characteristic: Characteristic,
+    value: Option<Vec<u8>>,
+) {
+    outbox.next_id += 1;
+    let queue = outbox.queues.entry(conn_handle).or_default();
+    if queue.len() == MAX_OUTGOING {
+        queue.pop_front();
+        log::warn!("notify queue of connection {conn_handle} full, drop oldest");
+    }
+    queue.push_back(Outgoing {
+        id: outbox.next_id,
+        characteristic,
+        value,
+        retries: 0,
+    });
+}
+
+/// 记录客户端对特征的订阅，创建带通知属性的特征后调用
+///
+/// `resend`为真时客户端订阅后立即收到特征的当前值
+pub fn track(characteristic: &Characteristic, resend: bool) {
+    let characteristic_clone = characteristic.clone();
+    characteristic.lock().on_subscribe(move |_, desc, sub| {
+        let conn_handle = desc.conn_handle();
+        let mut outbox = OUTBOX.lock().unwrap();
+        let outbox = outbox.get_or_insert_with(Outbox::default);
+        let subscribed = outbox.subscribers.entry(conn_handle).or_default();
+        subscribed.retain(|c| !Arc::ptr_eq(c, &characteristic_clone));
+        if !sub.contains(NimbleSub::NOTIFY) {
+            return;
+        }
+        subscribed.push(characteristic_clone.clone());
+        session::on_disconnect(conn_handle, "notify", move || {
+            if let Some(outbox) = OUTBOX.lock().unwrap().as_mut() {
+                outbox.queues.remove(&conn_handle);
+                outbox.subscribers.remove(&conn_handle);
+            }
+        });
+        if resend {
+            enqueue(outbox, conn_handle, characteristic_clone.clone(), None);
+            WAKE.notify_one();
+        }
+    });
+}
+
+/// 更新特征的值，并加入所有订阅连接的发送队列
+///
+/// 链路拥塞时排队重试，不会像直接调用`notify`那样静默丢失
+pub fn send(characteristic: &Characteristic, value: &[u8]) {
+    characteristic.lock().set_value(value);
+    let mut outbox = OUTBOX.lock().unwrap();
+    let outbox = outbox.get_or_insert_with(Outbox::default);
+    let conns = outbox
+        .subscribers
+        .iter()
+        .filter(|(_, subscribed)| subscribed.iter().any(|c| Arc::ptr_eq(c, characteristic)))
+        .map(|(conn_handle, _)| *conn_handle)
+        .collect::<Vec<_>>();
+    for conn_handle in conns {
+        enqueue(
+            outbox,
+            conn_handle,
+            characteristic.clone(),
+            Some(value.to_vec()),
+        );
+    }
+    WAKE.notify_one();
+}
+
+/// 只通知指定连接，用于分块传输等一问一答的消息，不更新特征的值
+pub fn send_to(characteristic: &Characteristic, conn_handle: u16, value: &[u8]) {
+    let mut outbox = OUTBOX.lock().unwrap();
+    let outbox = outbox.get_or_insert_with(Outbox::default);
+    enqueue(
+        outbox,
+        conn_handle,
+        characteristic.clone(),
+        Some(value.to_vec()),
+    );
+    WAKE.notify_one();
+}
+
+/// 取出各连接队首的通知，连接之间轮流发送
+fn next_batch() -> Vec<(u16, u64, Characteristic, Option<Vec<u8>>)> {
+    let mut outbox = OUTBOX.lock().unwrap();
+    loop {
+        let batch = outbox
+            .as_ref()
+            .map(|outbox| {
+                outbox
+                    .queues
+                    .iter()
+                    .filter_map(|(conn_handle, queue)| {
+                        queue.front().map(|outgoing| {
+                            (
+                                *conn_handle,
+                                outgoing.id,
+                                outgoing.characteristic.clone(),
+                                outgoing.value.clone(),
+                            )
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        if !batch.is_empty() {
+            return batch;
+        }
+        outbox = WAKE.wait(outbox).unwrap();
+    }
+}
+
+/// 发送完成或放弃后移出队列，发送期间队首可能已因队列满被丢弃
+fn finish(conn_handle: u16, id: u64) {
+    if let Some(queue) = OUTBOX
+        .lock()
+        .unwrap()
+        .as_mut()
+        .and_then(|outbox| outbox.queues.get_mut(&conn_handle))
+    {
+        if queue.front().is_some_and(|outgoing| outgoing.id == id) {
+            queue.pop_front();
+        }
+    }
+}
+
+/// 记录一次失败，返回是否还能继续重试
+fn retry(conn_handle: u16, id: u64) -> bool {
+    let mut outbox = OUTBOX.lock().unwrap();
+    let Some(outgoing) = outbox
+        .as_mut()
+        .and_then(|outbox| outbox.queues.get_mut(&conn_handle))
+        .and_then(|queue| queue.front_mut())
+        .filter(|outgoing| outgoing.id == id)
+    else {
+        return false;
+    };
+    outgoing.retries += 1;
+    outgoing.retries <= MAX_RETRIES
+}
+
+/// 启动发送线程，按连接依次发送排队的通知
+pub fn start() -> Result<()> {
+    std::thread::Builder::new().stack_size(4 * 1024).spawn(|| {
+        diagnostics::track_stack("notify");
+        loop {
+            let mut congested = false;
+            for (conn_handle, id, characteristic, value) in next_batch() {
+                let res = {
+                    let mut characteristic = characteristic.lock();
+                    let value =
+                        value.unwrap_or_else(|| characteristic.value_mut().value().to_vec());
+                    characteristic.notify_with(&value, conn_handle)
+                };
+                match res {
+                    Ok(_) => finish(conn_handle, id),
+                    // 缓冲区耗尽说明链路拥塞，保留在队首稍后重试
+                    Err(e) if e.code() == BLE_HS_ENOMEM || e.code() == BLE_HS_EAGAIN => {
+                        congested = true;
+                        if !retry(conn_handle, id) {
+                            finish(conn_handle, id);
+                            report::error(
+                                Module::Ble,
+                                ErrorCode::Internal,
+                                format!("notify to connection {conn_handle} dropped after retries"),
+                            );
+                        }
+                    }
+                    // 连接已断开等无法恢复的错误，直接丢弃
+                    Err(e) => {
+                        finish(conn_handle, id);
+                        log::warn!("notify to connection {conn_handle} failed: {e:?}");
+                    }
+                }
+            }
+            if congested {
+                std::thread::sleep(RETRY_DELAY);
+            }
+        }
+    })?;
+    Ok(())
+}
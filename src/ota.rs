@@ -0,0 +1,138 @@
+//! OTA升级：写入新固件和升级后的启动确认
+//!
+//! - 写入前检查镜像中的版本号，不允许降级
+//! - `esp_ota_end`用当前固件签名块中的公钥校验新固件的签名，需要开启
+//!   `CONFIG_SECURE_SIGNED_ON_UPDATE_NO_SECURE_BOOT`，当前固件和新固件都要用同一个私钥签名：
+//!   `espsecure.py sign_data --version 2 --keyfile <私钥> <镜像>`，未签名的固件无法再升级
+//! - 新固件首次启动时处于待确认状态，通过启动自检并稳定运行一段时间后才确认，
+//!   确认前发生复位（panic、看门狗）时由引导程序回滚，需要开启`CONFIG_BOOTLOADER_APP_ROLLBACK_ENABLE`
+
+use crate::{
+    executor::Executor,
+    report::{self, ErrorCode, Module},
+    self_test::{Failure, SelfTest},
+};
+use anyhow::{anyhow, bail, Result};
+use esp_idf_svc::{
+    io::Read,
+    sys::{
+        esp, esp_app_get_description, esp_ota_abort, esp_ota_begin, esp_ota_end,
+        esp_ota_get_next_update_partition, esp_ota_get_running_partition,
+        esp_ota_get_state_partition, esp_ota_handle_t,
+        esp_ota_img_states_t_ESP_OTA_IMG_PENDING_VERIFY,
+        esp_ota_mark_app_invalid_rollback_and_reboot, esp_ota_mark_app_valid_cancel_rollback,
+        esp_ota_set_boot_partition, esp_ota_write, OTA_WITH_SEQUENTIAL_WRITES,
+    },
+    timer::EspTaskTimerService,
+};
+use futures::task::SpawnExt;
+use smart_brite_core::ota::{check_upgrade, image_version, HEADER_LEN};
+use std::{ffi::CStr, time::Duration};
+
+/// 新固件需要稳定运行的时间，期间的任务卡死或panic都会导致回滚
+const HEALTH_CHECK_PERIOD: Duration = Duration::from_secs(60);
+/// 每次写入闪存的数据长度
+const CHUNK_SIZE: usize = 4096;
+
+/// 当前固件的版本号，与新镜像中的版本号来源相同
+fn running_version() -> Result<&'static str> {
+    let desc = unsafe { &*esp_app_get_description() };
+    Ok(unsafe { CStr::from_ptr(desc.version.as_ptr()) }.to_str()?)
+}
+
+fn write_image(
+    handle: esp_ota_handle_t,
+    reader: &mut impl Read,
+    header: &[u8],
+    len: usize,
+) -> Result<()> {
+    esp!(unsafe { esp_ota_write(handle, header.as_ptr().cast(), header.len()) })?;
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut remaining = len - header.len();
+    while remaining > 0 {
+        let size = reader
+            .read(&mut buf[..remaining.min(CHUNK_SIZE)])
+            .map_err(|e| anyhow!("Read image failed: {e:?}"))?;
+        if size == 0 {
+            bail!("Image truncated");
+        }
+        esp!(unsafe { esp_ota_write(handle, buf.as_ptr().cast(), size) })?;
+        remaining -= size;
+    }
+    Ok(())
+}
+
+/// 把长度为`len`的新固件写入另一个OTA分区，版本和签名都校验通过后才设为启动分区，重启后生效
+pub fn install(reader: &mut impl Read, len: usize) -> Result<()> {
+    if len < HEADER_LEN {
+        bail!("Image too small");
+    }
+    let mut header = [0u8; HEADER_LEN];
+    reader
+        .read_exact(&mut header)
+        .map_err(|e| anyhow!("Read image failed: {e:?}"))?;
+    let version = image_version(&header)?;
+    check_upgrade(running_version()?, version)?;
+
+    let partition = unsafe { esp_ota_get_next_update_partition(std::ptr::null()) };
+    if partition.is_null() {
+        bail!("No OTA partition");
+    }
+    let mut handle = 0;
+    // 边写入边擦除，避免开始时长时间擦除整个分区
+    esp!(unsafe { esp_ota_begin(partition, OTA_WITH_SEQUENTIAL_WRITES as usize, &mut handle) })?;
+    if let Err(e) = write_image(handle, reader, &header, len) {
+        unsafe { esp_ota_abort(handle) };
+        return Err(e);
+    }
+    // 校验镜像完整性和签名，失败时不修改启动分区
+    esp!(unsafe { esp_ota_end(handle) })?;
+    esp!(unsafe { esp_ota_set_boot_partition(partition) })?;
+    log::warn!("firmware {version} installed");
+    Ok(())
+}
+
+/// 当前固件是否是尚未确认的新固件
+fn pending_verify() -> bool {
+    let mut state = 0;
+    let partition = unsafe { esp_ota_get_running_partition() };
+    esp!(unsafe { esp_ota_get_state_partition(partition, &mut state) }).is_ok()
+        && state == esp_ota_img_states_t_ESP_OTA_IMG_PENDING_VERIFY
+}
+
+/// 启动自检完成后调用，不是新固件时直接返回
+///
+/// 灯带无法输出多半是硬件问题，回滚也无法解决，不作为回滚的条件
+pub fn start(self_test: &SelfTest, pool: &Executor) -> Result<()> {
+    if !pending_verify() {
+        return Ok(());
+    }
+    if self_test
+        .failures
+        .iter()
+        .any(|failure| *failure != Failure::Led)
+    {
+        report::error(
+            Module::System,
+            ErrorCode::Internal,
+            "self test failed on new firmware, rolling back",
+        );
+        esp!(unsafe { esp_ota_mark_app_invalid_rollback_and_reboot() })?;
+    }
+    log::warn!("new firmware pending verification");
+    let mut async_timer = EspTaskTimerService::new()?.timer_async()?;
+    pool.spawn(async move {
+        if async_timer.after(HEALTH_CHECK_PERIOD).await.is_err() {
+            return;
+        }
+        match esp!(unsafe { esp_ota_mark_app_valid_cancel_rollback() }) {
+            Ok(_) => log::info!("new firmware verified"),
+            Err(e) => report::error(
+                Module::System,
+                ErrorCode::Internal,
+                format!("mark firmware valid error: {e}"),
+            ),
+        }
+    })?;
+    Ok(())
+}
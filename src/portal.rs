@@ -0,0 +1,157 @@
+//! 配网门户，未配置Wi-Fi时手机连接灯的热点，在弹出的页面中填写路由器信息，
+//! 不需要安装App
+//!
+//! - DNS：所有域名都解析到本机，系统据此判断需要登录并弹出页面
+//! - `GET /setup`：配网页面
+//! - `POST /setup`：保存路由器信息和设备名称，如`{"ssid":"..","password":"..","name":".."}`
+
+use crate::{
+    ble::BleControl,
+    diagnostics,
+    http::{read_json, respond},
+    store::WifiConfig,
+};
+use anyhow::{bail, Result};
+use esp32_nimble::utilities::mutex::Mutex;
+use esp_idf_svc::{
+    http::{server::EspHttpServer, Method},
+    io::Write,
+};
+use serde::Deserialize;
+use std::{
+    net::{Ipv4Addr, UdpSocket},
+    time::Duration,
+};
+
+/// esp-idf-svc创建热点网卡时的默认地址
+const PORTAL_IP: Ipv4Addr = Ipv4Addr::new(192, 168, 71, 1);
+const PORTAL_HTML: &str = include_str!("../web/portal.html");
+/// 各系统检测是否需要登录的地址，重定向到配网页面后会自动弹出
+const CAPTIVE_CHECKS: [&str; 4] = [
+    "/generate_204",
+    "/hotspot-detect.html",
+    "/connecttest.txt",
+    "/ncsi.txt",
+];
+const DNS_PORT: u16 = 53;
+/// 检查是否已配网的间隔，配网后DNS线程退出
+const DNS_TIMEOUT: Duration = Duration::from_secs(1);
+/// 解析结果的缓存时间，单位：秒
+const DNS_TTL: u32 = 60;
+const SSID_MAX_LEN: usize = 32;
+const PASSWORD_MAX_LEN: usize = 64;
+
+/// `POST /setup`的请求体
+#[derive(Debug, Deserialize)]
+struct Setup {
+    ssid: String,
+    #[serde(default)]
+    password: String,
+    /// 为空时保留原名称
+    #[serde(default)]
+    name: String,
+}
+
+/// 保存配网信息，Wi-Fi线程发现配置变化后关闭热点并连接路由器
+fn apply(ble: &BleControl, setup: Setup) -> Result<Vec<u8>> {
+    // 配网后页面仍能在局域网中访问，不能用来修改已有的配置
+    if !ble.nvs_store.wifi.lock().ssid.is_empty() {
+        bail!("Wi-Fi already configured");
+    }
+    if setup.ssid.is_empty() || setup.ssid.len() > SSID_MAX_LEN {
+        bail!("Invalid ssid");
+    }
+    if setup.password.len() > PASSWORD_MAX_LEN {
+        bail!("Invalid password");
+    }
+    if !setup.name.is_empty() {
+        ble.set_name(&setup.name)?;
+    }
+    let wifi = WifiConfig {
+        ssid: setup.ssid,
+        password: setup.password,
+    };
+    *ble.nvs_store.wifi.lock() = wifi.clone();
+    ble.nvs_store.write_wifi()?;
+    ble.set_wifi(&wifi)?;
+    Ok(serde_json::to_vec(&wifi.ssid)?)
+}
+
+/// 对任意域名的A记录查询都返回热点地址，其他类型返回空结果
+fn dns_reply(query: &[u8]) -> Option<Vec<u8>> {
+    // 头部12字节，只处理包含一个问题的标准查询
+    if query.len() < 12 || query[2] & 0x80 != 0 || query[4..6] != [0, 1] {
+        return None;
+    }
+    let mut end = 12;
+    while *query.get(end)? != 0 {
+        end += 1 + query[end] as usize;
+    }
+    // 名称结束的0字节之后是类型和类别
+    let question_end = end + 5;
+    let question = query.get(12..question_end)?;
+    let is_a = question[question.len() - 4..question.len() - 2] == [0, 1];
+
+    let mut reply = Vec::with_capacity(question_end + 16);
+    reply.extend_from_slice(&query[..2]);
+    // 响应、递归可用、无错误
+    reply.extend_from_slice(&[0x81, 0x80, 0, 1, 0, is_a as u8, 0, 0, 0, 0]);
+    reply.extend_from_slice(question);
+    if is_a {
+        // 名称指向问题中的名称
+        reply.extend_from_slice(&[0xc0, 0x0c, 0, 1, 0, 1]);
+        reply.extend_from_slice(&DNS_TTL.to_be_bytes());
+        reply.extend_from_slice(&[0, 4]);
+        reply.extend_from_slice(&PORTAL_IP.octets());
+    }
+    Some(reply)
+}
+
+fn serve_dns(wifi: &Mutex<WifiConfig>) -> Result<()> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, DNS_PORT))?;
+    socket.set_read_timeout(Some(DNS_TIMEOUT))?;
+    let mut buf = [0u8; 512];
+    while wifi.lock().ssid.is_empty() {
+        let Ok((len, addr)) = socket.recv_from(&mut buf) else {
+            continue;
+        };
+        if let Some(reply) = dns_reply(&buf[..len]) {
+            socket.send_to(&reply, addr)?;
+        }
+    }
+    Ok(())
+}
+
+/// 在HTTP服务上注册配网页面并启动DNS，只在未配置Wi-Fi时调用
+pub fn start(server: &mut EspHttpServer<'static>, ble_control: BleControl) -> Result<()> {
+    server.fn_handler("/setup", Method::Get, |req| {
+        req.into_response(200, None, &[("Content-Type", "text/html; charset=utf-8")])?
+            .write_all(PORTAL_HTML.as_bytes())
+    })?;
+
+    let ble = ble_control.clone();
+    server.fn_handler("/setup", Method::Post, move |mut req| {
+        let res = read_json::<Setup>(&mut req).and_then(|setup| apply(&ble, setup));
+        respond(req, res)
+    })?;
+
+    let location = format!("http://{PORTAL_IP}/setup");
+    for uri in CAPTIVE_CHECKS {
+        let location = location.clone();
+        server.fn_handler(uri, Method::Get, move |req| {
+            req.into_response(302, None, &[("Location", location.as_str())])
+                .map(|_| ())
+        })?;
+    }
+
+    let wifi = ble_control.nvs_store.wifi.clone();
+    std::thread::Builder::new()
+        .stack_size(4 * 1024)
+        .spawn(move || {
+            diagnostics::track_stack("dns");
+            if let Err(e) = serve_dns(&wifi) {
+                log::warn!("dns error: {e}");
+            }
+        })?;
+    Ok(())
+}
@@ -0,0 +1,120 @@
+use crate::{
+    ble::BleControl,
+    executor::Executor,
+    light::LightState,
+    report::{self, ErrorCode, Module},
+};
+use anyhow::Result;
+use esp32_nimble::BLEDevice;
+use esp_idf_svc::{
+    sys::{
+        esp, esp_pm_config_t, esp_pm_configure, esp_sleep_enable_gpio_wakeup,
+        gpio_int_type_t_GPIO_INTR_ANYEDGE, gpio_int_type_t_GPIO_INTR_LOW_LEVEL, gpio_set_intr_type,
+        gpio_wakeup_disable, gpio_wakeup_enable,
+    },
+    timer::EspTaskTimerService,
+};
+use futures::task::SpawnExt;
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicI32, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// 检查是否空闲的间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// CPU频率范围，睡眠模式下空闲时降频
+const MAX_FREQ_MHZ: i32 = 160;
+const MIN_FREQ_MHZ: i32 = 40;
+
+/// 是否已允许自动浅睡眠
+static SLEEPING: AtomicBool = AtomicBool::new(false);
+/// 按键引脚，按下为低电平，用于唤醒
+static WAKE_PIN: AtomicI32 = AtomicI32::new(9);
+static IDLE_SINCE: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// 配置电源管理，允许浅睡眠时所有任务空闲即自动进入浅睡眠，
+/// 由定时器、BLE事件或按键唤醒
+fn configure(light_sleep: bool) -> Result<()> {
+    let config = esp_pm_config_t {
+        max_freq_mhz: MAX_FREQ_MHZ,
+        min_freq_mhz: if light_sleep {
+            MIN_FREQ_MHZ
+        } else {
+            MAX_FREQ_MHZ
+        },
+        light_sleep_enable: light_sleep,
+    };
+    esp!(unsafe { esp_pm_configure(&config as *const _ as *const _) })?;
+    Ok(())
+}
+
+fn enter_sleep() -> Result<()> {
+    // 浅睡眠只支持电平唤醒，唤醒后恢复按键的边沿中断
+    esp!(unsafe {
+        gpio_wakeup_enable(
+            WAKE_PIN.load(Ordering::Relaxed),
+            gpio_int_type_t_GPIO_INTR_LOW_LEVEL,
+        )
+    })?;
+    esp!(unsafe { esp_sleep_enable_gpio_wakeup() })?;
+    configure(true)
+}
+
+fn exit_sleep() -> Result<()> {
+    esp!(unsafe { gpio_wakeup_disable(WAKE_PIN.load(Ordering::Relaxed)) })?;
+    esp!(unsafe {
+        gpio_set_intr_type(
+            WAKE_PIN.load(Ordering::Relaxed),
+            gpio_int_type_t_GPIO_INTR_ANYEDGE,
+        )
+    })?;
+    configure(false)
+}
+
+/// 有操作时调用，重新开始计时并退出睡眠模式
+pub fn activity() {
+    *IDLE_SINCE.lock().unwrap() = Some(Instant::now());
+    if SLEEPING.swap(false, Ordering::AcqRel) {
+        log::info!("exit light sleep");
+        if let Err(e) = exit_sleep() {
+            report::error(Module::Power, ErrorCode::Hardware, e);
+        }
+    }
+}
+
+/// 启动省电任务，灯关闭且无客户端连接超过设定时间后允许自动浅睡眠
+pub fn start(ble_control: BleControl, pool: &Executor) -> Result<()> {
+    let button_pin = ble_control.nvs_store.board.lock().button_pin;
+    WAKE_PIN.store(button_pin as i32, Ordering::Relaxed);
+    configure(false)?;
+    activity();
+    let mut async_timer = EspTaskTimerService::new()?.timer_async()?;
+    pool.spawn(async move {
+        while async_timer.after(CHECK_INTERVAL).await.is_ok() {
+            let config = ble_control.nvs_store.power.lock().clone();
+            let busy = !config.enabled
+                || !matches!(ble_control.get_state(), LightState::Closed)
+                || BLEDevice::take().get_server().connected_count() > 0;
+            if busy {
+                activity();
+                continue;
+            }
+            let idle = IDLE_SINCE
+                .lock()
+                .unwrap()
+                .map_or(Duration::ZERO, |since| since.elapsed());
+            if idle >= Duration::from_secs(config.idle_timeout_secs as u64)
+                && !SLEEPING.swap(true, Ordering::AcqRel)
+            {
+                log::info!("enter light sleep");
+                if let Err(e) = enter_sleep() {
+                    report::error(Module::Power, ErrorCode::Hardware, e);
+                }
+            }
+        }
+    })?;
+    Ok(())
+}
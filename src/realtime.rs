@@ -0,0 +1,239 @@
+//! 实时串流，监听WLED UDP实时协议、DDP、E1.31和Art-Net，收到的像素直接显示而不经过场景，
+//! 停止接收超时后恢复串流前的状态
+
+use crate::{
+    ble::BleControl,
+    diagnostics,
+    light::{LightEvent, LightEventSender, LightState},
+    store::{DmxConfig, DmxMode},
+};
+use anyhow::Result;
+use rgb::RGB8;
+use smart_brite_core::{
+    color::{mireds_to_rgb, MAX_MIREDS, MIN_MIREDS},
+    realtime::{
+        e131_multicast_addr, parse_artnet, parse_ddp, parse_e131, parse_wled, DmxPacket,
+        PixelUpdate, ARTNET_PORT, DDP_PORT, DMX_CHANNELS, E131_PORT, WLED_PORT,
+    },
+};
+use std::{
+    net::{Ipv4Addr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+/// 没有数据时检查的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+/// 单个数据包的最大长度，DRGB每包最多490个灯珠
+const MAX_PACKET: usize = 1500;
+/// 端口被占用等错误后重试的间隔
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+/// DMX停止接收后恢复原状态的时间，Art-Net在数据不变时最长4秒发送一次
+const DMX_TIMEOUT: Duration = Duration::from_secs(5);
+/// lwIP能加入的组播组有限，超出的universe需要单播发送
+const MAX_MULTICAST: u16 = 4;
+
+#[derive(Debug, Clone, Copy)]
+enum Protocol {
+    Wled,
+    Ddp,
+    E131,
+    ArtNet,
+}
+
+/// 正在进行的串流
+struct Stream {
+    pixels: Vec<RGB8>,
+    /// 串流前的状态，结束后恢复
+    previous: LightEvent,
+    timeout: Option<Duration>,
+    last_packet: Instant,
+    last_frame: Instant,
+    /// 收到了需要显示但还未发送的帧
+    dirty: bool,
+}
+
+impl Stream {
+    fn new(light_sender: &mut LightEventSender, brightness: u8) -> Result<Self> {
+        let previous = match light_sender.query_state()? {
+            LightState::Opened => LightEvent::OpenAt(brightness),
+            LightState::Closed => LightEvent::Close,
+            LightState::Demo(_) => LightEvent::Demo,
+        };
+        let now = Instant::now();
+        Ok(Self {
+            pixels: Vec::new(),
+            previous,
+            timeout: None,
+            last_packet: now,
+            last_frame: now,
+            dirty: false,
+        })
+    }
+
+    /// 写入像素，超出最大灯珠数的部分丢弃
+    fn apply(&mut self, update: PixelUpdate, max_leds: usize) {
+        let end = (update.start + update.pixels.len()).min(max_leds);
+        if update.start < end {
+            if self.pixels.len() < end {
+                self.pixels.resize(end, RGB8::default());
+            }
+            self.pixels[update.start..end].copy_from_slice(&update.pixels[..end - update.start]);
+        }
+        self.timeout = update.timeout;
+        self.last_packet = Instant::now();
+        self.dirty |= update.push;
+    }
+
+    fn expired(&self) -> bool {
+        self.timeout
+            .is_some_and(|timeout| self.last_packet.elapsed() >= timeout)
+    }
+}
+
+fn bind(port: u16) -> Result<UdpSocket> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))?;
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+/// 把DMX数据换算为像素，不属于本机的universe返回`None`
+fn dmx_update(config: &DmxConfig, packet: DmxPacket, max_leds: usize) -> Option<PixelUpdate> {
+    let offset = packet.universe.checked_sub(config.universe)? as usize;
+    let address = config.address as usize - 1;
+    let (start, pixels) = match config.mode {
+        DmxMode::Pixels => {
+            // 第一个universe从起始通道开始，之后的universe从第1个通道开始
+            let (start, data) = match offset {
+                0 => (0, packet.data.get(address..)?),
+                _ => {
+                    let first = (DMX_CHANNELS - address) / 3;
+                    (first + (offset - 1) * (DMX_CHANNELS / 3), packet.data)
+                }
+            };
+            let pixels = data
+                .chunks_exact(3)
+                .map(|rgb| RGB8::new(rgb[0], rgb[1], rgb[2]))
+                .collect();
+            (start, pixels)
+        }
+        DmxMode::DimmerCct => {
+            if offset != 0 {
+                return None;
+            }
+            let level = *packet.data.get(address)? as u16;
+            let cct = *packet.data.get(address + 1)? as u16;
+            let mireds = MAX_MIREDS - (MAX_MIREDS - MIN_MIREDS) * cct / 255;
+            let color = mireds_to_rgb(mireds);
+            let scale = |value: u8| (value as u16 * level / 255) as u8;
+            let color = RGB8::new(scale(color.r), scale(color.g), scale(color.b));
+            (0, vec![color; max_leds])
+        }
+    };
+    Some(PixelUpdate {
+        start,
+        pixels,
+        push: true,
+        timeout: Some(DMX_TIMEOUT),
+    })
+}
+
+fn parse(
+    protocol: Protocol,
+    packet: &[u8],
+    dmx: &DmxConfig,
+    max_leds: usize,
+) -> Option<PixelUpdate> {
+    match protocol {
+        Protocol::Wled => parse_wled(packet),
+        Protocol::Ddp => parse_ddp(packet),
+        Protocol::E131 => dmx_update(dmx, parse_e131(packet)?, max_leds),
+        Protocol::ArtNet => dmx_update(dmx, parse_artnet(packet)?, max_leds),
+    }
+}
+
+/// 按配置监听各协议的端口，开启的功能和E1.31的组播地址在启动时确定
+fn listen(ble_control: &BleControl) -> Result<Vec<(UdpSocket, Protocol)>> {
+    let (realtime, max_leds) = {
+        let settings = ble_control.nvs_store.settings.lock();
+        (settings.features.realtime, settings.max_leds as usize)
+    };
+    let dmx = ble_control.nvs_store.dmx.lock().clone();
+    let mut sockets = Vec::new();
+    if realtime {
+        sockets.push((bind(WLED_PORT)?, Protocol::Wled));
+        sockets.push((bind(DDP_PORT)?, Protocol::Ddp));
+    }
+    if dmx.enabled {
+        let e131 = bind(E131_PORT)?;
+        let count = dmx.universe_count(max_leds).min(MAX_MULTICAST);
+        for universe in dmx.universe..dmx.universe.saturating_add(count) {
+            e131.join_multicast_v4(&e131_multicast_addr(universe), &Ipv4Addr::UNSPECIFIED)?;
+        }
+        sockets.push((e131, Protocol::E131));
+        sockets.push((bind(ARTNET_PORT)?, Protocol::ArtNet));
+    }
+    Ok(sockets)
+}
+
+fn serve(ble_control: &BleControl, light_sender: &mut LightEventSender) -> Result<()> {
+    let sockets = listen(ble_control)?;
+    let mut buf = vec![0u8; MAX_PACKET];
+    let mut stream: Option<Stream> = None;
+    loop {
+        let (max_leds, fps) = {
+            let settings = ble_control.nvs_store.settings.lock();
+            (settings.max_leds as usize, settings.fps.max(1) as u32)
+        };
+        let dmx = ble_control.nvs_store.dmx.lock().clone();
+        let mut received = false;
+        for (socket, protocol) in &sockets {
+            while let Ok(len) = socket.recv(&mut buf) {
+                received = true;
+                let Some(update) = parse(*protocol, &buf[..len], &dmx, max_leds) else {
+                    continue;
+                };
+                let stream = match &mut stream {
+                    Some(stream) => stream,
+                    None => {
+                        stream.insert(Stream::new(light_sender, *ble_control.brightness.lock())?)
+                    }
+                };
+                stream.apply(update, max_leds);
+            }
+        }
+
+        if let Some(current) = &mut stream {
+            // 发送方帧率高于渲染帧率时只显示最新的一帧
+            let frame_interval = Duration::from_secs(1) / fps;
+            if current.dirty && current.last_frame.elapsed() >= frame_interval {
+                light_sender.send(LightEvent::Frame(current.pixels.clone()))?;
+                current.dirty = false;
+                current.last_frame = Instant::now();
+            }
+            if current.expired() {
+                log::info!("realtime stream ended");
+                light_sender.send(current.previous.clone())?;
+                stream = None;
+            }
+        }
+        if !received {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+/// 启动实时串流监听，需要开启`realtime`功能或DMX输入，未连接Wi-Fi时也可以通过配网热点串流
+pub fn start(ble_control: BleControl, mut light_sender: LightEventSender) -> Result<()> {
+    std::thread::Builder::new()
+        .stack_size(4 * 1024)
+        .spawn(move || {
+            diagnostics::track_stack("realtime");
+            loop {
+                if let Err(e) = serve(&ble_control, &mut light_sender) {
+                    log::warn!("realtime error: {e}");
+                }
+                std::thread::sleep(RETRY_INTERVAL);
+            }
+        })?;
+    Ok(())
+}
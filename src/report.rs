@@ -0,0 +1,124 @@
+use crate::{event_bus, notify};
+use esp32_nimble::BLECharacteristic;
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    sync::{Arc, Mutex},
+};
+
+/// 链路繁忙时最多缓存的错误数，超过后丢弃最早的
+const MAX_QUEUED: usize = 8;
+/// 错误信息的最大长度，保证一次通知能发送完
+const MAX_MESSAGE_LEN: usize = 160;
+
+type Characteristic = Arc<esp32_nimble::utilities::mutex::Mutex<BLECharacteristic>>;
+
+/// 出错的子系统
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Module {
+    Ble,
+    Transfer,
+    Light,
+    Timer,
+    Store,
+    Sync,
+    Indicator,
+    Advertising,
+    Sntp,
+    Motion,
+    Encoder,
+    Ir,
+    Battery,
+    Power,
+    Thermal,
+    Mesh,
+    Controller,
+    System,
+}
+
+/// 错误类别，App据此决定如何提示用户
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorCode {
+    /// 客户端写入的数据无效
+    InvalidData,
+    /// 读写NVS失败
+    Storage,
+    /// 外设驱动出错
+    Hardware,
+    /// 网络或时间同步出错
+    Network,
+    /// 引用的场景等不存在
+    NotFound,
+    /// 定时任务在断电期间错过了执行时间
+    Expired,
+    /// 内部通道、任务等出错
+    Internal,
+}
+
+/// 错误事件，以JSON格式通知客户端
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorEvent {
+    pub module: Module,
+    pub code: ErrorCode,
+    pub message: String,
+    /// 开机时长，单位：秒
+    pub uptime: u64,
+}
+
+static CHARACTERISTIC: Mutex<Option<Characteristic>> = Mutex::new(None);
+static QUEUE: Mutex<VecDeque<ErrorEvent>> = Mutex::new(VecDeque::new());
+
+/// 设置错误通知特征，在`BleControl::new`中调用
+pub fn set_characteristic(characteristic: Characteristic) {
+    *CHARACTERISTIC.lock().unwrap() = Some(characteristic);
+}
+
+/// 记录可恢复的错误，写入日志并通知客户端
+pub fn error(module: Module, code: ErrorCode, message: impl Display) {
+    let mut message = message.to_string();
+    log::error!("[{module:?}] {message}");
+    if message.len() > MAX_MESSAGE_LEN {
+        let mut end = MAX_MESSAGE_LEN;
+        while !message.is_char_boundary(end) {
+            end -= 1;
+        }
+        message.truncate(end);
+    }
+    let uptime = unsafe { esp_idf_svc::sys::esp_timer_get_time() } as u64 / 1_000_000;
+    let event = ErrorEvent {
+        module,
+        code,
+        message,
+        uptime,
+    };
+    // 远程日志等订阅者在各自的线程中处理
+    event_bus::publish(event.clone());
+    {
+        let mut queue = QUEUE.lock().unwrap();
+        if queue.len() == MAX_QUEUED {
+            queue.pop_front();
+        }
+        queue.push_back(event);
+    }
+    // 传输进行中时推迟发送，缓存的错误在传输结束后一起发出
+    notify::schedule("error", flush);
+}
+
+fn flush() {
+    let Some(characteristic) = CHARACTERISTIC.lock().unwrap().clone() else {
+        return;
+    };
+    let events = std::mem::take(&mut *QUEUE.lock().unwrap());
+    for event in events {
+        match serde_json::to_vec(&event) {
+            Ok(value) => {
+                notify::send(&characteristic, &value);
+            }
+            Err(e) => log::error!("error event payload error: {e}"),
+        }
+    }
+}
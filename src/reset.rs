@@ -0,0 +1,86 @@
+use crate::{
+    led::{Led, RGB8},
+    log_buffer,
+    store::NvsStore,
+    usage,
+};
+use anyhow::Result;
+use esp_idf_svc::{
+    hal::{
+        gpio::{AnyIOPin, PinDriver, Pull},
+        reset::restart,
+    },
+    sys::esp,
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// 长按按钮超过该时间恢复出厂设置
+pub const FACTORY_RESET_HOLD: Duration = Duration::from_secs(10);
+/// 开机后在该时间内按下按键才能进入安全模式
+const SAFE_BOOT_WINDOW: Duration = Duration::from_secs(1);
+/// 进入安全模式需要按住的时间
+const SAFE_BOOT_HOLD: Duration = Duration::from_secs(1);
+/// 检测按键的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// 以错误提示色闪烁三次，提示即将恢复出厂设置
+pub fn confirm_blink(led: &Arc<Mutex<Led<'static>>>, color: RGB8) -> Result<()> {
+    for _ in 0..3 {
+        led.lock().unwrap().set_pixel(color)?;
+        std::thread::sleep(Duration::from_millis(300));
+        led.lock().unwrap().close()?;
+        std::thread::sleep(Duration::from_millis(300));
+    }
+    Ok(())
+}
+
+/// 开机后按住按键进入安全模式，不读取保存的配置
+///
+/// 默认的BOOT按键是启动模式引脚，上电时按住会进入下载模式，需要上电后立即按下
+pub fn safe_boot_requested(pin: u8) -> bool {
+    let check = || -> Result<bool> {
+        let mut driver = PinDriver::input(unsafe { AnyIOPin::new(pin as i32) })?;
+        driver.set_pull(Pull::Up)?;
+        let start = Instant::now();
+        while start.elapsed() < SAFE_BOOT_WINDOW {
+            let pressed = Instant::now();
+            while driver.is_low() {
+                if pressed.elapsed() >= SAFE_BOOT_HOLD {
+                    return Ok(true);
+                }
+                std::thread::sleep(POLL_INTERVAL);
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        Ok(false)
+    };
+    check().unwrap_or_else(|e| {
+        log::warn!("check safe boot button error: {e}");
+        false
+    })
+}
+
+/// 保存尚未写入的配置、统计和日志后重启，light_on为重启后是否恢复开灯
+pub fn reboot(nvs_store: &NvsStore, light_on: bool) -> Result<()> {
+    log::warn!("reboot");
+    if !nvs_store.safe_mode {
+        nvs_store.write_light_on(light_on)?;
+        nvs_store.flush()?;
+        usage::save(nvs_store)?;
+        log_buffer::save(nvs_store)?;
+    }
+    restart();
+}
+
+/// 恢复出厂设置：清除蓝牙配对信息和整个NVS分区（场景、定时任务、Wi-Fi、设备名称等），然后重启
+pub fn factory_reset() -> Result<()> {
+    log::warn!("factory reset");
+    unsafe {
+        esp_idf_svc::sys::ble_store_clear();
+        esp!(esp_idf_svc::sys::nvs_flash_erase())?;
+    }
+    restart();
+}
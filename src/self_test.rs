@@ -0,0 +1,95 @@
+use crate::{
+    diagnostics,
+    led::{Led, RGB8},
+    report::{self, ErrorCode, Module},
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Display,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// 闪烁码的颜色
+const BLINK_COLOR: RGB8 = RGB8::new(255, 0, 0);
+/// 每个闪烁码重复的次数
+const REPEAT: u32 = 2;
+
+/// 自检失败的类别
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Failure {
+    /// NVS无法读写，配置不会被保存
+    Nvs,
+    /// 蓝牙协议栈初始化失败，随后会重启
+    Ble,
+    /// 灯带无法输出，无法显示闪烁码
+    Led,
+}
+
+impl Failure {
+    /// 闪烁次数，不同类别的失败以次数区分
+    fn blinks(self) -> Option<u32> {
+        match self {
+            Failure::Nvs => Some(2),
+            Failure::Ble => Some(3),
+            Failure::Led => None,
+        }
+    }
+
+    fn code(self) -> ErrorCode {
+        match self {
+            Failure::Nvs => ErrorCode::Storage,
+            Failure::Ble | Failure::Led => ErrorCode::Hardware,
+        }
+    }
+}
+
+/// 启动自检的结果，通过诊断特征读取
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelfTest {
+    /// 失败的检查项，全部通过时为空
+    pub failures: Vec<Failure>,
+}
+
+impl SelfTest {
+    /// 记录一项检查的结果
+    pub fn check<T, E: Display>(&mut self, failure: Failure, res: &Result<T, E>) {
+        if let Err(e) = res {
+            report::error(
+                Module::System,
+                failure.code(),
+                format!("self test {failure:?} failed: {e}"),
+            );
+            self.failures.push(failure);
+        }
+    }
+
+    /// 保存结果，并依次显示各项失败的闪烁码
+    pub fn finish(&self, led: &Arc<Mutex<Led<'static>>>) -> Result<()> {
+        diagnostics::record_self_test(self)?;
+        // 灯带本身出错时无法显示
+        if self.failures.contains(&Failure::Led) {
+            return Ok(());
+        }
+        for blinks in self.failures.iter().filter_map(|failure| failure.blinks()) {
+            for _ in 0..REPEAT {
+                blink(led, blinks)?;
+                std::thread::sleep(Duration::from_secs(1));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn blink(led: &Arc<Mutex<Led<'static>>>, times: u32) -> Result<()> {
+    for _ in 0..times {
+        led.lock().unwrap().set_pixel(BLINK_COLOR)?;
+        std::thread::sleep(Duration::from_millis(200));
+        led.lock().unwrap().close()?;
+        std::thread::sleep(Duration::from_millis(300));
+    }
+    Ok(())
+}
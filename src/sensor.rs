@@ -0,0 +1,72 @@
+use crate::ambient::Ambient;
+use std::time::Duration;
+
+/// 传感器采样间隔
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 光敏电阻接ADC，读数越大环境越亮
+#[cfg(feature = "als")]
+pub fn start_adc(
+    adc: esp_idf_svc::hal::adc::ADC1,
+    pin: esp_idf_svc::hal::gpio::Gpio0,
+    ambient: Ambient,
+) -> anyhow::Result<()> {
+    use esp_idf_svc::hal::adc::{
+        attenuation::DB_11,
+        oneshot::{config::AdcChannelConfig, AdcChannelDriver, AdcDriver},
+    };
+
+    std::thread::spawn(move || -> anyhow::Result<()> {
+        let adc = AdcDriver::new(adc)?;
+        let config = AdcChannelConfig {
+            attenuation: DB_11,
+            ..Default::default()
+        };
+        let mut channel = AdcChannelDriver::new(&adc, pin, &config)?;
+        loop {
+            // 12位ADC原始读数
+            let raw = adc.read(&mut channel)?;
+            ambient.update(raw as f32 / 4095.0);
+            std::thread::sleep(SAMPLE_INTERVAL);
+        }
+    });
+    Ok(())
+}
+
+/// BH1750 I2C照度传感器，SDA接GPIO4，SCL接GPIO5
+#[cfg(feature = "als-i2c")]
+pub fn start_bh1750(
+    i2c: esp_idf_svc::hal::i2c::I2C0,
+    sda: esp_idf_svc::hal::gpio::Gpio4,
+    scl: esp_idf_svc::hal::gpio::Gpio5,
+    ambient: Ambient,
+) -> anyhow::Result<()> {
+    use esp_idf_svc::hal::{
+        delay::BLOCK,
+        i2c::{I2cConfig, I2cDriver},
+        units::Hertz,
+    };
+
+    const ADDRESS: u8 = 0x23;
+    /// 连续高分辨率测量模式
+    const CONTINUOUS_HIGH_RES: u8 = 0x10;
+    /// 达到该照度视为最亮，单位：lux
+    const MAX_LUX: f32 = 1000.0;
+
+    let mut driver = I2cDriver::new(i2c, sda, scl, &I2cConfig::new().baudrate(Hertz(100_000)))?;
+    driver.write(ADDRESS, &[CONTINUOUS_HIGH_RES], BLOCK)?;
+    std::thread::spawn(move || -> anyhow::Result<()> {
+        loop {
+            std::thread::sleep(SAMPLE_INTERVAL);
+            let mut buf = [0u8; 2];
+            if let Err(e) = driver.read(ADDRESS, &mut buf, BLOCK) {
+                log::warn!("read bh1750 error: {e}");
+                continue;
+            }
+            let lux = u16::from_be_bytes(buf) as f32 / 1.2;
+            // 人眼对亮度的感知接近对数关系
+            ambient.update((lux + 1.0).log10() / (MAX_LUX + 1.0).log10());
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,41 @@
+use std::{collections::HashMap, sync::Mutex};
+
+type Hook = Box<dyn FnOnce() + Send>;
+
+/// 按连接句柄登记的清理回调，连接断开时统一执行
+static HOOKS: Mutex<Option<HashMap<u16, HashMap<String, Hook>>>> = Mutex::new(None);
+
+/// 为连接登记清理回调，同一连接下相同`key`的回调会被替换
+pub fn on_disconnect<F>(conn_handle: u16, key: impl Into<String>, hook: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    let mut hooks = HOOKS.lock().unwrap();
+    hooks
+        .get_or_insert_with(HashMap::new)
+        .entry(conn_handle)
+        .or_default()
+        .insert(key.into(), Box::new(hook));
+}
+
+/// 资源已正常释放时移除对应的清理回调
+pub fn remove(conn_handle: u16, key: &str) {
+    let mut hooks = HOOKS.lock().unwrap();
+    if let Some(conn_hooks) = hooks.as_mut().and_then(|h| h.get_mut(&conn_handle)) {
+        conn_hooks.remove(key);
+    }
+}
+
+/// 执行并清空连接的所有清理回调，在`server.on_disconnect`中调用
+pub fn disconnect(conn_handle: u16) {
+    // 先取出回调再执行，避免回调中再次登记时死锁
+    let conn_hooks = HOOKS
+        .lock()
+        .unwrap()
+        .as_mut()
+        .and_then(|h| h.remove(&conn_handle));
+    for (key, hook) in conn_hooks.into_iter().flatten() {
+        log::debug!("cleanup {} for connection {}", key, conn_handle);
+        hook();
+    }
+}
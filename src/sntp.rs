@@ -0,0 +1,65 @@
+use crate::report::{self, ErrorCode, Module};
+use crate::timer::TimerEventSender;
+use crate::wifi::Wifi;
+use anyhow::Result;
+use chrono::{TimeDelta, Utc};
+use esp_idf_svc::sntp::{EspSntp, SntpConf, SyncMode};
+use std::time::{Duration, Instant};
+
+/// SNTP同步间隔
+const SYNC_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// 检查系统时间跳变的间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+/// 系统时间跳变超过该值（秒）时重新计算定时任务
+const JUMP_THRESHOLD_SECS: i64 = 60;
+
+/// Wi-Fi连接后通过SNTP同步系统时间，并在时间发生较大跳变时重新计算定时任务
+pub fn start(wifi: Wifi, mut timer_sender: TimerEventSender) -> Result<()> {
+    std::thread::spawn(move || {
+        let mut sntp: Option<EspSntp<'static>> = None;
+        let mut last_wall = Utc::now();
+        let mut last_mono = Instant::now();
+        loop {
+            std::thread::sleep(CHECK_INTERVAL);
+
+            if sntp.is_none() && wifi.is_connected() {
+                // 平滑模式下小幅误差通过adjtime逐渐校正，避免时间来回跳动
+                let conf = SntpConf {
+                    sync_mode: SyncMode::Smooth,
+                    ..Default::default()
+                };
+                match EspSntp::new(&conf) {
+                    Ok(client) => {
+                        unsafe {
+                            esp_idf_svc::sys::sntp_set_sync_interval(
+                                SYNC_INTERVAL.as_millis() as u32
+                            );
+                        }
+                        sntp = Some(client);
+                    }
+                    Err(e) => report::error(Module::Sntp, ErrorCode::Network, e),
+                }
+            }
+
+            // 比较系统时间和单调时钟的变化量，判断时间是否被调整
+            let now_wall = Utc::now();
+            let now_mono = Instant::now();
+            let wall = now_wall.signed_duration_since(last_wall);
+            let mono = TimeDelta::from_std(now_mono - last_mono).unwrap_or_default();
+            if (wall - mono).abs() > TimeDelta::seconds(JUMP_THRESHOLD_SECS) {
+                log::debug!("system time jumped to {}", now_wall.to_rfc3339());
+
+                if let Err(e) = timer_sender.reload() {
+                    report::error(
+                        Module::Timer,
+                        ErrorCode::Internal,
+                        format!("reload tasks failed: {e}"),
+                    );
+                }
+            }
+            last_wall = now_wall;
+            last_mono = now_mono;
+        }
+    });
+    Ok(())
+}
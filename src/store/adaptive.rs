@@ -0,0 +1,35 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// 自适应亮度：环境越暗，输出亮度越低，需要光线传感器
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AdaptiveConfig {
+    pub enabled: bool,
+    /// 完全黑暗时的亮度百分比
+    pub min_brightness: u8,
+}
+
+impl Default for AdaptiveConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_brightness: 20,
+        }
+    }
+}
+
+impl AdaptiveConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.min_brightness > 100 {
+            bail!("Invalid min brightness {}", self.min_brightness);
+        }
+        Ok(())
+    }
+
+    /// 根据环境光亮度（0-1）计算亮度倍率
+    pub fn scale(&self, level: f32) -> f32 {
+        let min = self.min_brightness as f32 / 100.0;
+        min + (1.0 - min) * level.clamp(0.0, 1.0)
+    }
+}
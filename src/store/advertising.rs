@@ -0,0 +1,43 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// 广播间隔范围，单位：毫秒
+const MIN_INTERVAL_MS: u32 = 20;
+const MAX_INTERVAL_MS: u32 = 10_000;
+/// 支持的发射功率范围，单位：dBm，步进3dBm
+const MIN_TX_POWER: i8 = -24;
+const MAX_TX_POWER: i8 = 18;
+
+/// 广播配置，在发现速度和空闲功耗之间取舍
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AdvertisingConfig {
+    /// 快速广播间隔，单位：毫秒，空闲退避时的间隔不会小于该值
+    pub interval_ms: u32,
+    /// 发射功率，单位：dBm
+    pub tx_power: i8,
+    /// 有客户端连接时是否继续广播，允许其他客户端同时连接
+    pub advertise_when_connected: bool,
+}
+
+impl Default for AdvertisingConfig {
+    fn default() -> Self {
+        Self {
+            interval_ms: 100,
+            tx_power: 9,
+            advertise_when_connected: true,
+        }
+    }
+}
+
+impl AdvertisingConfig {
+    pub fn validate(&self) -> Result<()> {
+        if !(MIN_INTERVAL_MS..=MAX_INTERVAL_MS).contains(&self.interval_ms) {
+            bail!("Invalid advertising interval {}", self.interval_ms);
+        }
+        if !(MIN_TX_POWER..=MAX_TX_POWER).contains(&self.tx_power) || self.tx_power % 3 != 0 {
+            bail!("Invalid tx power {}", self.tx_power);
+        }
+        Ok(())
+    }
+}
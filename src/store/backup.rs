@@ -0,0 +1,172 @@
+use super::{
+    migration::SCHEMA_VERSION, palette::validate_palettes, read_blob, time_task, AdaptiveConfig,
+    AdvertisingConfig, BatteryConfig, BrightnessCurve, ButtonConfig, DemoConfig, DeviceSettings,
+    Favorites, IndicatorConfig, IrConfig, MotionConfig, NvsStore, Palettes, PowerConfig, Scene,
+    ThermalConfig, RESTORE,
+};
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// 整机配置备份，用于更换设备或批量配置多台灯
+///
+/// 不包含Wi-Fi、同步组、鉴权令牌等凭据，也不包含开发板引脚、颜色校准等与硬件相关的配置
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Backup {
+    /// 导出时的配置格式版本，不能导入更新版本的备份
+    pub version: u32,
+    pub settings: DeviceSettings,
+    pub scene: Scene,
+    pub scenes: Vec<Scene>,
+    pub time_tasks: Vec<time_task::TimeTask>,
+    pub tasks_paused: bool,
+    pub palettes: Palettes,
+    pub favorites: Favorites,
+    pub button: ButtonConfig,
+    pub brightness_curve: BrightnessCurve,
+    pub indicator: IndicatorConfig,
+    pub demo: DemoConfig,
+    pub adaptive: AdaptiveConfig,
+    pub motion: MotionConfig,
+    pub ir: IrConfig,
+    pub battery: BatteryConfig,
+    pub power: PowerConfig,
+    pub advertising: AdvertisingConfig,
+    /// 旧版本导出的备份没有该项
+    #[serde(default)]
+    pub thermal: ThermalConfig,
+}
+
+/// 备份特征支持的命令
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BackupCommand {
+    /// 把当前配置更新到特征中，之后客户端再读取
+    Export,
+    /// 校验并导入配置，完成后重启
+    Import(Box<Backup>),
+}
+
+impl Backup {
+    pub fn validate(&self) -> Result<()> {
+        if self.version > SCHEMA_VERSION {
+            bail!(
+                "Backup schema {} is newer than {}",
+                self.version,
+                SCHEMA_VERSION
+            );
+        }
+        self.settings.validate()?;
+        if self.time_tasks.len() > time_task::MAX_TASKS {
+            bail!("At most {} time tasks", time_task::MAX_TASKS);
+        }
+        for (index, task) in self.time_tasks.iter().enumerate() {
+            task.check(&self.time_tasks[..index], false)?;
+        }
+        validate_palettes(&self.palettes)?;
+        self.button.validate()?;
+        self.brightness_curve.validate()?;
+        self.adaptive.validate()?;
+        self.motion.validate()?;
+        self.battery.validate()?;
+        self.power.validate()?;
+        self.advertising.validate()?;
+        self.thermal.validate()?;
+        Ok(())
+    }
+}
+
+impl NvsStore {
+    pub fn export(&self) -> Backup {
+        Backup {
+            version: SCHEMA_VERSION,
+            settings: self.settings.lock().clone(),
+            scene: self.scene.lock().clone(),
+            scenes: self.scenes.lock().clone(),
+            time_tasks: self.time_task.lock().clone(),
+            tasks_paused: *self.tasks_paused.lock(),
+            palettes: self.palettes.lock().clone(),
+            favorites: self.favorites.lock().clone(),
+            button: self.button.lock().clone(),
+            brightness_curve: self.brightness_curve.lock().clone(),
+            indicator: self.indicator.lock().clone(),
+            demo: self.demo.lock().clone(),
+            adaptive: self.adaptive.lock().clone(),
+            motion: self.motion.lock().clone(),
+            ir: self.ir.lock().clone(),
+            battery: self.battery.lock().clone(),
+            power: self.power.lock().clone(),
+            advertising: self.advertising.lock().clone(),
+            thermal: self.thermal.lock().clone(),
+        }
+    }
+
+    /// 校验后导入备份，需要重启才能让各模块使用新的配置
+    ///
+    /// 先把整个备份作为一项写入，再逐项写入各配置，中途断电时下次启动继续导入
+    pub fn import(&self, backup: Backup) -> Result<()> {
+        self.check_writable()?;
+        backup.validate()?;
+        self.nvs
+            .lock()
+            .set_blob(RESTORE, &serde_json::to_vec(&backup)?)?;
+        self.apply(backup)?;
+        self.nvs.lock().remove(RESTORE)?;
+        Ok(())
+    }
+
+    /// 继续上次未完成的导入
+    pub(super) fn resume_import(&self) -> Result<()> {
+        let backup = read_blob::<Backup>(&self.nvs.lock(), RESTORE)?;
+        let Some(backup) = backup else {
+            return Ok(());
+        };
+        log::warn!("resume interrupted config import");
+        self.apply(backup)?;
+        self.nvs.lock().remove(RESTORE)?;
+        Ok(())
+    }
+
+    fn apply(&self, backup: Backup) -> Result<()> {
+        super::timezone::set_offset_minutes(backup.settings.timezone_minutes);
+        *self.settings.lock() = backup.settings;
+        *self.scene.lock() = backup.scene;
+        *self.scenes.lock() = backup.scenes;
+        *self.time_task.lock() = backup.time_tasks;
+        *self.tasks_paused.lock() = backup.tasks_paused;
+        *self.palettes.lock() = backup.palettes;
+        *self.favorites.lock() = backup.favorites;
+        *self.button.lock() = backup.button;
+        *self.brightness_curve.lock() = backup.brightness_curve;
+        *self.indicator.lock() = backup.indicator;
+        *self.demo.lock() = backup.demo;
+        *self.adaptive.lock() = backup.adaptive;
+        *self.motion.lock() = backup.motion;
+        *self.ir.lock() = backup.ir;
+        *self.battery.lock() = backup.battery;
+        *self.power.lock() = backup.power;
+        *self.advertising.lock() = backup.advertising;
+        *self.thermal.lock() = backup.thermal;
+
+        // 直接写入，不经过后台线程，保证移除备份前已全部保存
+        self.write_settings()?;
+        self.save_scene()?;
+        self.write_scenes()?;
+        self.save_time_task()?;
+        self.write_tasks_paused()?;
+        self.write_palettes()?;
+        self.write_favorites()?;
+        self.write_button()?;
+        self.write_brightness_curve()?;
+        self.write_indicator()?;
+        self.write_demo()?;
+        self.write_adaptive()?;
+        self.write_motion()?;
+        self.write_ir()?;
+        self.write_battery()?;
+        self.write_power()?;
+        self.write_advertising()?;
+        self.write_thermal()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,50 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// 低电量时的行为，电池供电时使用
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BatteryConfig {
+    /// 电量低于该百分比时降低亮度
+    pub dim_percent: u8,
+    /// 低电量时的亮度百分比
+    pub dim_brightness: u8,
+    /// 电量低于该百分比时关灯
+    pub off_percent: u8,
+}
+
+impl Default for BatteryConfig {
+    fn default() -> Self {
+        Self {
+            dim_percent: 20,
+            dim_brightness: 30,
+            off_percent: 5,
+        }
+    }
+}
+
+impl BatteryConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.dim_percent > 100
+            || self.dim_brightness > 100
+            || self.off_percent > self.dim_percent
+        {
+            bail!("Invalid battery config {:?}", self);
+        }
+        Ok(())
+    }
+
+    /// 电量过低，需要关灯
+    pub fn is_critical(&self, percent: u8) -> bool {
+        percent < self.off_percent
+    }
+
+    /// 根据电量计算亮度倍率
+    pub fn scale(&self, percent: u8) -> f32 {
+        if percent < self.dim_percent {
+            self.dim_brightness as f32 / 100.0
+        } else {
+            1.0
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use super::ExtraButton;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// ESP32-C3可用的最大GPIO编号
+const MAX_PIN: u8 = 21;
+/// 内置SPI Flash占用的引脚
+const FLASH_PINS: std::ops::RangeInclusive<u8> = 12..=17;
+/// 可用于灯带的RMT发送通道，通道2、3只能接收
+const MAX_LED_CHANNEL: u8 = 1;
+/// 额外按键的最大数量
+const MAX_EXTRA_BUTTONS: usize = 4;
+
+/// 已开启的功能固定占用的引脚，接线见`Cargo.toml`中各功能的说明
+fn feature_pins() -> Vec<u8> {
+    let mut pins = vec![];
+    if cfg!(feature = "als") {
+        pins.push(0);
+    }
+    if cfg!(feature = "als-i2c") {
+        pins.extend([4, 5]);
+    }
+    if cfg!(feature = "buzzer") || cfg!(feature = "battery") {
+        pins.push(3);
+    }
+    if cfg!(feature = "ir") {
+        pins.push(6);
+    }
+    if cfg!(feature = "mic") {
+        pins.extend([7, 1, 2]);
+    }
+    pins
+}
+
+/// 不同开发板的默认引脚
+#[cfg(not(feature = "board-c3-mini"))]
+mod defaults {
+    /// ESP32-C3-DevKitM-1：板载WS2812接GPIO8，BOOT按键接GPIO9
+    pub const LED_PIN: u8 = 8;
+    pub const BUTTON_PIN: u8 = 9;
+}
+#[cfg(feature = "board-c3-mini")]
+mod defaults {
+    /// LOLIN C3 Mini：板载WS2812接GPIO7，BOOT按键接GPIO9
+    pub const LED_PIN: u8 = 7;
+    pub const BUTTON_PIN: u8 = 9;
+}
+
+/// 灯带型号
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum StripType {
+    #[default]
+    Ws2812,
+    /// 带白光通道的RGBW灯带，三色共有的部分由白光发出
+    Sk6812,
+    /// SPI驱动的双线灯带，数据接`led_pin`，时钟接`clock_pin`
+    #[serde(rename_all = "camelCase")]
+    Apa102 { clock_pin: u8 },
+    /// 不可寻址的模拟灯带，每个通道由一路PWM经MOSFET驱动，整条灯带同一颜色
+    Pwm(PwmPins),
+}
+
+/// 模拟灯带各通道的PWM引脚
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PwmPins {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+    /// RGBW灯带的白光通道，双色温灯带的冷白通道
+    #[serde(default)]
+    pub white: Option<u8>,
+    /// 双色温灯带的暖白通道，需要同时设置`white`
+    #[serde(default)]
+    pub warm: Option<u8>,
+}
+
+impl PwmPins {
+    fn pins(&self) -> Vec<u8> {
+        let mut pins = vec![self.red, self.green, self.blue];
+        pins.extend(self.white);
+        pins.extend(self.warm);
+        pins
+    }
+}
+
+/// 开发板引脚配置，同一固件可用于不同开发板和灯带接线，修改后重启生效
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BoardConfig {
+    /// 灯带数据引脚，模拟灯带不使用
+    pub led_pin: u8,
+    /// 灯带使用的RMT通道，APA102使用SPI，不需要该通道
+    pub led_channel: u8,
+    #[serde(default)]
+    pub strip: StripType,
+    /// 按键引脚，按下为低电平
+    pub button_pin: u8,
+    /// 额外的按键，如亮度加减
+    #[serde(default)]
+    pub extra_buttons: Vec<ExtraButton>,
+    /// 旋转编码器，未接时为None
+    #[serde(default)]
+    pub encoder: Option<EncoderConfig>,
+}
+
+/// 带按键的旋转编码器接线，A、B两相和按键均上拉，接通时为低电平
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EncoderConfig {
+    pub a_pin: u8,
+    pub b_pin: u8,
+    pub button_pin: u8,
+}
+
+impl Default for BoardConfig {
+    fn default() -> Self {
+        Self {
+            led_pin: defaults::LED_PIN,
+            led_channel: 0,
+            strip: StripType::Ws2812,
+            button_pin: defaults::BUTTON_PIN,
+            extra_buttons: vec![],
+            encoder: None,
+        }
+    }
+}
+
+impl BoardConfig {
+    /// 灯带、所有按键和编码器占用的引脚
+    pub fn used_pins(&self) -> Vec<u8> {
+        let mut pins = match self.strip {
+            StripType::Apa102 { clock_pin } => vec![self.led_pin, clock_pin],
+            StripType::Pwm(pwm) => pwm.pins(),
+            _ => vec![self.led_pin],
+        };
+        pins.push(self.button_pin);
+        pins.extend(self.extra_buttons.iter().map(|button| button.pin));
+        if let Some(encoder) = &self.encoder {
+            pins.extend([encoder.a_pin, encoder.b_pin, encoder.button_pin]);
+        }
+        pins
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.extra_buttons.len() > MAX_EXTRA_BUTTONS {
+            bail!("At most {MAX_EXTRA_BUTTONS} extra buttons");
+        }
+        let pins = self.used_pins();
+        let reserved = feature_pins();
+        for (index, pin) in pins.iter().enumerate() {
+            if *pin > MAX_PIN || FLASH_PINS.contains(pin) {
+                bail!("Invalid pin {pin}");
+            }
+            if reserved.contains(pin) {
+                bail!("Pin {pin} is used by an enabled feature");
+            }
+            if pins[..index].contains(pin) {
+                bail!("Pin {pin} is used more than once");
+            }
+        }
+        for button in &self.extra_buttons {
+            button.action.validate()?;
+        }
+        if let StripType::Pwm(pwm) = self.strip {
+            if pwm.warm.is_some() && pwm.white.is_none() {
+                bail!("Warm white channel requires a white channel");
+            }
+        }
+        if self.led_channel > MAX_LED_CHANNEL {
+            bail!("Invalid RMT channel {}", self.led_channel);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,92 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// 睡眠定时的最长时间，单位：分钟
+const MAX_SLEEP_MINUTES: u32 = 12 * 60;
+/// 按键消抖时间的上限，过长会丢失快速的多击，单位：毫秒
+const MAX_DEBOUNCE_MS: u32 = 200;
+
+/// 按钮可以绑定的操作
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ButtonAction {
+    /// 开关灯
+    Toggle,
+    /// 依次切换场景库中的场景
+    CycleScenes,
+    /// 开始睡眠定时，指定分钟后关灯
+    SleepTimer(u32),
+    /// 切换到场景库中的场景
+    Scene(String),
+    BrightnessUp,
+    BrightnessDown,
+}
+
+impl ButtonAction {
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            ButtonAction::SleepTimer(minutes) if !(1..=MAX_SLEEP_MINUTES).contains(minutes) => {
+                bail!("Invalid sleep timer {minutes}")
+            }
+            ButtonAction::Scene(name) if name.is_empty() => bail!("Empty scene name"),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// 额外的按键，每次按下执行绑定的操作，不区分多击和长按
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtraButton {
+    /// 按键引脚，按下为低电平
+    pub pin: u8,
+    pub action: ButtonAction,
+}
+
+/// 按钮操作映射，未设置的多击操作使用收藏场景
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ButtonConfig {
+    pub short_press: ButtonAction,
+    pub double_press: Option<ButtonAction>,
+    pub triple_press: Option<ButtonAction>,
+    /// 未设置时长按视为单击
+    pub long_press: Option<ButtonAction>,
+    /// 按键消抖时间，边沿之后等待电平稳定再读取，单位：毫秒
+    #[serde(default = "default_debounce_ms")]
+    pub debounce_ms: u32,
+}
+
+fn default_debounce_ms() -> u32 {
+    30
+}
+
+impl Default for ButtonConfig {
+    fn default() -> Self {
+        Self {
+            short_press: ButtonAction::Toggle,
+            double_press: None,
+            triple_press: None,
+            long_press: None,
+            debounce_ms: default_debounce_ms(),
+        }
+    }
+}
+
+impl ButtonConfig {
+    pub fn validate(&self) -> Result<()> {
+        let actions = [
+            Some(&self.short_press),
+            self.double_press.as_ref(),
+            self.triple_press.as_ref(),
+            self.long_press.as_ref(),
+        ];
+        for action in actions.into_iter().flatten() {
+            action.validate()?;
+        }
+        if self.debounce_ms > MAX_DEBOUNCE_MS {
+            bail!("Invalid debounce {}ms", self.debounce_ms);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,47 @@
+use anyhow::{bail, Result};
+use rgb::RGB8;
+use serde::{Deserialize, Serialize};
+
+/// 颜色校准：各通道的伽马校正和白点
+///
+/// 灯珠亮度与输入值不是线性关系，低亮度时调节不均匀；不少灯带的白色偏蓝，
+/// 通过白点降低对应通道的最大输出
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CalibrationConfig {
+    /// 红、绿、蓝通道的伽马值，1.0表示不校正
+    pub gamma: [f32; 3],
+    /// 输入纯白时各通道的实际输出
+    pub white_point: RGB8,
+}
+
+impl Default for CalibrationConfig {
+    fn default() -> Self {
+        Self {
+            gamma: [1.0; 3],
+            white_point: RGB8::new(255, 255, 255),
+        }
+    }
+}
+
+impl CalibrationConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.gamma.iter().any(|gamma| !(0.5..=4.0).contains(gamma)) {
+            bail!("Invalid gamma {:?}", self.gamma);
+        }
+        Ok(())
+    }
+
+    /// 生成各通道的查找表，避免每次输出都计算幂函数
+    pub fn table(&self) -> [[u8; 256]; 3] {
+        let white = [self.white_point.r, self.white_point.g, self.white_point.b];
+        let mut table = [[0u8; 256]; 3];
+        for (channel, values) in table.iter_mut().enumerate() {
+            for (input, value) in values.iter_mut().enumerate() {
+                let linear = (input as f32 / 255.0).powf(self.gamma[channel]);
+                *value = (linear * white[channel] as f32).round() as u8;
+            }
+        }
+        table
+    }
+}
@@ -0,0 +1,57 @@
+use super::sync::parse_mac;
+use crate::auth::{parse_token, TOKEN_LEN};
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// 最多控制的灯，每次转发都要依次连接，太多时响应明显变慢
+const MAX_LAMPS: usize = 8;
+
+/// 被控制的灯
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ControlledLamp {
+    /// 蓝牙地址，格式如`aa:bb:cc:dd:ee:ff`
+    pub address: String,
+    /// 对方已配对时的鉴权令牌，十六进制
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+impl ControlledLamp {
+    /// 解析十六进制的鉴权令牌
+    pub fn token_bytes(&self) -> Result<Option<[u8; TOKEN_LEN]>> {
+        self.token.as_deref().map(parse_token).transpose()
+    }
+}
+
+/// 控制器配置，开启后本机的开关灯和切换场景会通过BLE转发给其他灯，
+/// 可以把同一固件做成控制多盏灯的墙壁开关
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ControllerConfig {
+    pub enabled: bool,
+    pub lamps: Vec<ControlledLamp>,
+}
+
+impl ControllerConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.lamps.len() > MAX_LAMPS {
+            bail!("At most {MAX_LAMPS} lamps");
+        }
+        for lamp in &self.lamps {
+            parse_mac(&lamp.address)?;
+            lamp.token_bytes()?;
+        }
+        Ok(())
+    }
+}
+
+/// 控制器特征支持的命令
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ControllerCommand {
+    /// 扫描附近的灯，完成后把结果更新到特征中，之后客户端再读取
+    Scan,
+    /// 保存配置
+    Config(ControllerConfig),
+}
@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+/// 演示模式配置
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DemoConfig {
+    pub enabled: bool,
+    /// 每个效果的展示时间，单位：秒
+    pub interval: u32,
+    /// 无操作多久后回到演示模式，单位：秒
+    pub idle_timeout: u32,
+    /// 回到演示模式时是否重置访客修改过的场景
+    pub reset_on_idle: bool,
+}
+
+impl Default for DemoConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: 10,
+            idle_timeout: 60,
+            reset_on_idle: true,
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use smart_brite_core::realtime::DMX_CHANNELS;
+
+/// sACN的universe范围，Art-Net使用相同的编号
+const MAX_UNIVERSE: u16 = 63999;
+
+/// DMX通道的用法
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum DmxMode {
+    /// 每个灯珠占3个通道（RGB），超出一个universe时从下一个universe的第1个通道继续
+    #[default]
+    Pixels,
+    /// 占2个通道：亮度和色温，色温通道0为最暖、255为最冷，整条灯带显示相同颜色
+    DimmerCct,
+}
+
+/// E1.31（sACN）和Art-Net输入配置，开启和修改universe后重启生效
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DmxConfig {
+    pub enabled: bool,
+    /// 起始universe
+    pub universe: u16,
+    /// 起始通道，1-512
+    pub address: u16,
+    pub mode: DmxMode,
+}
+
+impl Default for DmxConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            universe: 1,
+            address: 1,
+            mode: DmxMode::Pixels,
+        }
+    }
+}
+
+impl DmxConfig {
+    pub fn validate(&self) -> Result<()> {
+        let channels = match self.mode {
+            DmxMode::Pixels => 3,
+            DmxMode::DimmerCct => 2,
+        };
+        if self.universe > MAX_UNIVERSE
+            || self.address == 0
+            || self.address as usize + channels - 1 > DMX_CHANNELS
+        {
+            bail!("Invalid dmx config {:?}", self);
+        }
+        Ok(())
+    }
+
+    /// 灯带占用的universe数，像素模式下由最大灯珠数决定
+    pub fn universe_count(&self, max_leds: usize) -> u16 {
+        match self.mode {
+            DmxMode::Pixels => {
+                let first = (DMX_CHANNELS + 1 - self.address as usize) / 3;
+                let rest = max_leds.saturating_sub(first);
+                1 + rest.div_ceil(DMX_CHANNELS / 3) as u16
+            }
+            DmxMode::DimmerCct => 1,
+        }
+    }
+}
@@ -0,0 +1,15 @@
+use serde::{Deserialize, Serialize};
+
+/// 按钮多击绑定的收藏场景，值为场景库中的场景名称
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Favorites {
+    pub double_click: Option<String>,
+    pub triple_click: Option<String>,
+}
+
+impl Favorites {
+    pub fn is_empty(&self) -> bool {
+        self.double_click.is_none() && self.triple_click.is_none()
+    }
+}
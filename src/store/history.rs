@@ -0,0 +1,44 @@
+use super::{time_task::TimeTask, Scene};
+use esp32_nimble::utilities::mutex::Mutex;
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// 最多保留的修改记录数
+const MAX_ENTRIES: usize = 8;
+/// 超过该时间的修改不能再撤销
+const UNDO_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+/// 可撤销的修改，保存修改前的数据
+#[derive(Debug, Clone)]
+pub enum Change {
+    /// 当前场景被覆盖或重置
+    Scene(Scene),
+    /// 定时任务被删除
+    Task(TimeTask),
+}
+
+/// 最近的配置修改记录，只保存在内存中
+#[derive(Clone, Default)]
+pub struct History {
+    entries: Arc<Mutex<VecDeque<(Instant, Change)>>>,
+}
+
+impl History {
+    pub fn record(&self, change: Change) {
+        let mut entries = self.entries.lock();
+        if entries.len() == MAX_ENTRIES {
+            entries.pop_front();
+        }
+        entries.push_back((Instant::now(), change));
+    }
+
+    /// 取出最近一次可撤销的修改
+    pub fn pop(&self) -> Option<Change> {
+        let mut entries = self.entries.lock();
+        entries.retain(|(time, _)| time.elapsed() <= UNDO_WINDOW);
+        entries.pop_back().map(|(_, change)| change)
+    }
+}
@@ -0,0 +1,72 @@
+use rgb::RGB8;
+use serde::{Deserialize, Serialize};
+
+/// 蓝牙状态指示灯配置
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct IndicatorConfig {
+    pub enabled: bool,
+    /// 广播中：慢速蓝色脉冲
+    pub advertising: RGB8,
+    /// 已连接：短暂绿色闪烁
+    pub connected: RGB8,
+    /// 配对中：蓝色呼吸
+    pub pairing: RGB8,
+    /// 色盲友好模式，状态颜色改用Okabe-Ito配色，忽略上面的自定义颜色
+    #[serde(default)]
+    pub colorblind: bool,
+}
+
+impl Default for IndicatorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            advertising: RGB8::new(0, 0, 255),
+            connected: RGB8::new(0, 255, 0),
+            pairing: RGB8::new(0, 0, 255),
+            colorblind: false,
+        }
+    }
+}
+
+/// Okabe-Ito配色中各种色觉都能区分的颜色
+const SAFE_ADVERTISING: RGB8 = RGB8::new(0, 114, 178);
+const SAFE_CONNECTED: RGB8 = RGB8::new(230, 159, 0);
+const SAFE_PAIRING: RGB8 = RGB8::new(204, 121, 167);
+const SAFE_ERROR: RGB8 = RGB8::new(213, 94, 0);
+const ERROR: RGB8 = RGB8::new(255, 0, 0);
+
+impl IndicatorConfig {
+    pub fn advertising(&self) -> RGB8 {
+        if self.colorblind {
+            SAFE_ADVERTISING
+        } else {
+            self.advertising
+        }
+    }
+
+    pub fn connected(&self) -> RGB8 {
+        if self.colorblind {
+            SAFE_CONNECTED
+        } else {
+            self.connected
+        }
+    }
+
+    pub fn pairing(&self) -> RGB8 {
+        if self.colorblind {
+            SAFE_PAIRING
+        } else {
+            self.pairing
+        }
+    }
+
+    /// 错误及危险操作（如恢复出厂设置）的提示颜色
+    pub fn error(&self) -> RGB8 {
+        if self.colorblind {
+            SAFE_ERROR
+        } else {
+            ERROR
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+
+/// 遥控按键对应的操作
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum IrAction {
+    /// 开关灯
+    Power,
+    BrightnessUp,
+    BrightnessDown,
+    /// 切换到场景库中的场景，用作预设颜色
+    Scene(String),
+}
+
+/// 学习到的遥控按键
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct IrBinding {
+    /// NEC编码：地址、地址反码、命令、命令反码，低字节在前
+    pub code: u32,
+    pub action: IrAction,
+}
+
+/// 红外遥控配置
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct IrConfig {
+    pub enabled: bool,
+    pub bindings: Vec<IrBinding>,
+}
+
+impl Default for IrConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            bindings: vec![],
+        }
+    }
+}
+
+impl IrConfig {
+    pub fn action(&self, code: u32) -> Option<&IrAction> {
+        self.bindings
+            .iter()
+            .find(|binding| binding.code == code)
+            .map(|binding| &binding.action)
+    }
+
+    /// 绑定按键，同一个按键只能对应一个操作
+    pub fn bind(&mut self, code: u32, action: IrAction) {
+        self.bindings.retain(|binding| binding.code != code);
+        self.bindings.push(IrBinding { code, action });
+    }
+}
@@ -0,0 +1,57 @@
+use anyhow::{bail, Result};
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// 最多单独设置级别的标签数
+const MAX_TAGS: usize = 16;
+/// esp-idf日志标签的最大长度
+const MAX_TAG_LEN: usize = 32;
+/// 设置所有标签默认级别的通配标签
+pub const DEFAULT_TAG: &str = "*";
+
+/// 与esp-idf一致的日志级别，Debug以上受编译时的最大级别限制
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Verbose,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Off => LevelFilter::Off,
+            LogLevel::Error => LevelFilter::Error,
+            LogLevel::Warn => LevelFilter::Warn,
+            LogLevel::Info => LevelFilter::Info,
+            LogLevel::Debug => LevelFilter::Debug,
+            LogLevel::Verbose => LevelFilter::Trace,
+        }
+    }
+}
+
+/// 各模块的日志级别，标签为Rust模块路径（如`smart_brite::ble`）或esp-idf组件标签（如`NimBLE`）
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LogLevelConfig {
+    pub levels: BTreeMap<String, LogLevel>,
+}
+
+impl LogLevelConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.levels.len() > MAX_TAGS {
+            bail!("At most {MAX_TAGS} log tags");
+        }
+        for tag in self.levels.keys() {
+            if tag.is_empty() || tag.len() > MAX_TAG_LEN || tag.contains('\0') {
+                bail!("Invalid log tag {tag}");
+            }
+        }
+        Ok(())
+    }
+}
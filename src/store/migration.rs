@@ -0,0 +1,48 @@
+use super::{DeviceSettings, SETTINGS};
+use anyhow::Result;
+use esp_idf_svc::nvs::{EspNvs, NvsDefault};
+
+/// 当前固件使用的配置格式版本
+pub const SCHEMA_VERSION: u32 = 2;
+const SCHEMA: &str = "schema";
+/// 版本1单独保存的设备名称和时区
+const LEGACY_NAME: &str = "name";
+const LEGACY_TIMEZONE: &str = "tz_offset";
+
+type Migration = fn(&mut EspNvs<NvsDefault>) -> Result<()>;
+
+/// 索引为i的迁移函数将配置从版本i升级到i+1
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [migrate_v0, migrate_v1];
+
+/// 最初的固件没有记录版本号，配置格式与版本1一致
+fn migrate_v0(_nvs: &mut EspNvs<NvsDefault>) -> Result<()> {
+    Ok(())
+}
+
+/// 设备名称和时区合并到设备设置中
+fn migrate_v1(nvs: &mut EspNvs<NvsDefault>) -> Result<()> {
+    let mut settings = DeviceSettings::default();
+    let mut name_buf = [0u8; 32];
+    if let Some(name) = nvs.get_str(LEGACY_NAME, &mut name_buf)? {
+        settings.name = name.to_string();
+    }
+    if let Some(minutes) = nvs.get_i32(LEGACY_TIMEZONE)? {
+        settings.timezone_minutes = minutes;
+    }
+    nvs.set_blob(SETTINGS, &serde_json::to_vec(&settings)?)?;
+    nvs.remove(LEGACY_NAME)?;
+    nvs.remove(LEGACY_TIMEZONE)?;
+    Ok(())
+}
+
+/// 按顺序执行未完成的迁移，返回迁移前存储的配置版本
+pub fn migrate(nvs: &mut EspNvs<NvsDefault>) -> Result<u32> {
+    let stored = nvs.get_u32(SCHEMA)?.unwrap_or(0);
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(stored as usize) {
+        log::info!("migrate config schema {} -> {}", version, version + 1);
+        migration(nvs)?;
+        // 每完成一步就记录版本，迁移中途断电下次可以继续
+        nvs.set_u32(SCHEMA, version as u32 + 1)?;
+    }
+    Ok(stored)
+}
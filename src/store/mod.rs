@@ -1,64 +1,689 @@
-use anyhow::Result;
+use crate::{
+    auth::TOKEN_LEN,
+    report::{self, ErrorCode, Module},
+};
+use anyhow::{bail, Result};
 use esp32_nimble::utilities::mutex::Mutex;
 use esp_idf_svc::nvs::{EspNvs, EspNvsPartition, NvsDefault};
-use std::sync::Arc;
+use migration::SCHEMA_VERSION;
+use persist::Pending;
+use serde::de::DeserializeOwned;
+use std::sync::{mpsc, Arc};
 
-mod scene;
+mod adaptive;
+mod advertising;
+mod backup;
+mod battery;
+mod board;
+mod button;
+mod calibration;
+mod controller;
+mod demo;
+mod dmx;
+mod favorites;
+pub mod history;
+mod indicator;
+pub mod ir;
+mod log_level;
+pub mod migration;
+mod motion;
+mod mqtt;
+mod persist;
+mod power;
+mod settings;
+pub mod sync;
+pub mod thermal;
+// 与硬件无关的部分在`smart-brite-core`中，保持原有的模块路径
+pub use adaptive::AdaptiveConfig;
+pub use advertising::AdvertisingConfig;
+pub use backup::{Backup, BackupCommand};
+pub use battery::BatteryConfig;
+pub use board::{BoardConfig, EncoderConfig, PwmPins, StripType};
+pub use brightness::BrightnessCurve;
+pub use button::{ButtonAction, ButtonConfig, ExtraButton};
+pub use calibration::CalibrationConfig;
+pub use controller::{ControlledLamp, ControllerCommand, ControllerConfig};
+pub use demo::DemoConfig;
+pub use dmx::{DmxConfig, DmxMode};
+pub use favorites::Favorites;
+use history::{Change, History};
+pub use indicator::IndicatorConfig;
+pub use ir::IrConfig;
+pub use log_level::{LogLevel, LogLevelConfig, DEFAULT_TAG};
+pub use motion::MotionConfig;
+pub use mqtt::MqttConfig;
+pub use palette::Palettes;
+pub use power::PowerConfig;
 pub use scene::{Color, Scene};
+pub use settings::{DeviceSettings, Features, StartupBehavior, MAX_NAME_LEN};
+use smart_brite_core::brightness;
+use smart_brite_core::hal::Storage;
+pub use smart_brite_core::{cron, palette, scene, share, timezone};
+pub use sync::SyncConfig;
+pub use thermal::ThermalConfig;
+pub use usage::{SceneUsage, UsageStats};
+pub use wifi::WifiConfig;
 pub mod time_task;
+mod usage;
+mod wifi;
 
 const SCENE: &str = "scene";
 const TIME_TASK: &str = "time_task";
+const INDICATOR: &str = "indicator";
+const LIGHT_ON: &str = "light_on";
+const PALETTES: &str = "palettes";
+const DEMO: &str = "demo";
+const WIFI: &str = "wifi";
+const SCENES: &str = "scenes";
+const FAVORITES: &str = "favorites";
+const BUTTON: &str = "button";
+const TASKS_PAUSED: &str = "tasks_paused";
+const BRIGHTNESS_CURVE: &str = "brightness";
+const SYNC: &str = "sync";
+const ADAPTIVE: &str = "adaptive";
+const MOTION: &str = "motion";
+const IR: &str = "ir";
+const CALIBRATION: &str = "calibration";
+const BATTERY: &str = "battery";
+const POWER: &str = "power";
+const THERMAL: &str = "thermal";
+const BOARD: &str = "board";
+const ADVERTISING: &str = "advertising";
+const LOG: &str = "log";
+const LOG_LEVELS: &str = "log_levels";
+const STUCK_TASK: &str = "stuck_task";
+const AUTH_TOKEN: &str = "auth_token";
+const CHILD_LOCK: &str = "child_lock";
+const HUE_USERS: &str = "hue_users";
+const CONTROLLER: &str = "controller";
+const DMX: &str = "dmx";
+const MQTT: &str = "mqtt";
+const SETTINGS: &str = "settings";
+/// 正在导入的备份
+const RESTORE: &str = "restore";
+const USAGE: &str = "usage";
+/// 启动自检时临时写入
+const SELF_TEST: &str = "self_test";
+pub const DEFAULT_NAME: &str = "ESP32";
 const NAMESPACE: &str = "config";
 
 #[derive(Clone)]
 pub struct NvsStore {
     pub scene: Arc<Mutex<Scene>>,
     pub time_task: Arc<Mutex<Vec<time_task::TimeTask>>>,
+    pub indicator: Arc<Mutex<IndicatorConfig>>,
+    pub palettes: Arc<Mutex<Palettes>>,
+    pub demo: Arc<Mutex<DemoConfig>>,
+    pub wifi: Arc<Mutex<WifiConfig>>,
+    /// 场景库
+    pub scenes: Arc<Mutex<Vec<Scene>>>,
+    pub favorites: Arc<Mutex<Favorites>>,
+    /// 按钮操作映射
+    pub button: Arc<Mutex<ButtonConfig>>,
+    /// 未指定亮度开灯时的默认亮度曲线
+    pub brightness_curve: Arc<Mutex<BrightnessCurve>>,
+    /// 根据环境光自动调整亮度
+    pub adaptive: Arc<Mutex<AdaptiveConfig>>,
+    /// 人体感应开关灯
+    pub motion: Arc<Mutex<MotionConfig>>,
+    /// 红外遥控按键绑定
+    pub ir: Arc<Mutex<IrConfig>>,
+    /// 灯珠颜色校准
+    pub calibration: Arc<Mutex<CalibrationConfig>>,
+    /// 低电量行为
+    pub battery: Arc<Mutex<BatteryConfig>>,
+    /// 灯关闭且无连接时自动浅睡眠
+    pub power: Arc<Mutex<PowerConfig>>,
+    /// 芯片过热保护
+    pub thermal: Arc<Mutex<ThermalConfig>>,
+    /// 运行时调整的日志级别
+    pub log_levels: Arc<Mutex<LogLevelConfig>>,
+    /// 设备名称、时区等全局设置
+    pub settings: Arc<Mutex<DeviceSettings>>,
+    /// 开发板引脚
+    pub board: Arc<Mutex<BoardConfig>>,
+    /// BLE广播间隔、发射功率
+    pub advertising: Arc<Mutex<AdvertisingConfig>>,
+    /// ESP-NOW同步组
+    pub sync: Arc<Mutex<SyncConfig>>,
+    /// 定时任务总开关，暂停时保留任务定义
+    pub tasks_paused: Arc<Mutex<bool>>,
+    /// 儿童锁，开启后拒绝通过BLE修改灯光、场景和定时任务
+    pub child_lock: Arc<Mutex<bool>>,
+    /// 通过BLE控制其他灯
+    pub controller: Arc<Mutex<ControllerConfig>>,
+    pub dmx: Arc<Mutex<DmxConfig>>,
+    pub mqtt: Arc<Mutex<MqttConfig>>,
+    /// 可撤销的修改记录
+    pub history: History,
     pub nvs: Arc<Mutex<EspNvs<NvsDefault>>>,
+    pub safe_mode: bool,
+    /// 交给后台线程写入的配置
+    persist_tx: mpsc::Sender<Pending>,
 }
 
-impl NvsStore {
-    pub fn new(nvs_partition: EspNvsPartition<NvsDefault>) -> Result<Self> {
-        let nvs = EspNvs::new(nvs_partition, NAMESPACE, true)?;
-        let scene = if nvs.contains(SCENE)? {
-            let len = nvs.blob_len(SCENE)?.unwrap_or(512);
-            let mut data = vec![0u8; len];
-            nvs.get_blob(SCENE, &mut data)?;
-            Scene::from_u8(&data)?
-        } else {
-            Scene::default()
+/// 以通用存储接口访问NVS，供与硬件无关的模块使用
+#[derive(Clone)]
+pub struct NvsStorage(pub Arc<Mutex<EspNvs<NvsDefault>>>);
+
+impl Storage for NvsStorage {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let nvs = self.0.lock();
+        let Some(len) = nvs.blob_len(key)? else {
+            return Ok(None);
         };
-        let time_task = if nvs.contains(TIME_TASK)? {
-            let len = nvs.blob_len(TIME_TASK)?.unwrap_or(512);
-            let mut data = vec![0u8; len];
-            nvs.get_blob(TIME_TASK, &mut data)?;
-            serde_json::from_slice(&data)?
+        let mut data = vec![0u8; len];
+        Ok(nvs.get_blob(key, &mut data)?.map(<[u8]>::to_vec))
+    }
+
+    fn set(&self, key: &str, data: &[u8]) -> Result<()> {
+        self.0.lock().set_blob(key, data)?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<bool> {
+        Ok(self.0.lock().remove(key)?)
+    }
+}
+
+fn read_blob<T: DeserializeOwned>(nvs: &EspNvs<NvsDefault>, key: &str) -> Result<Option<T>> {
+    if !nvs.contains(key)? {
+        return Ok(None);
+    }
+    let len = nvs.blob_len(key)?.unwrap_or(512);
+    let mut data = vec![0u8; len];
+    nvs.get_blob(key, &mut data)?;
+    Ok(Some(serde_json::from_slice(&data)?))
+}
+
+fn read_blob_or_default<T: DeserializeOwned + Default>(
+    nvs: &EspNvs<NvsDefault>,
+    key: &str,
+    safe_mode: bool,
+) -> Result<T> {
+    if safe_mode {
+        return Ok(T::default());
+    }
+    Ok(read_blob(nvs, key)?.unwrap_or_default())
+}
+
+impl NvsStore {
+    /// `safe_boot`为true时不迁移也不读取保存的配置，避免损坏的配置导致反复重启
+    pub fn new(nvs_partition: EspNvsPartition<NvsDefault>, safe_boot: bool) -> Result<Self> {
+        let mut nvs = EspNvs::new(nvs_partition, NAMESPACE, true)?;
+        if safe_boot {
+            report::error(
+                Module::Store,
+                ErrorCode::Storage,
+                "safe boot requested, stored config ignored",
+            );
+        }
+        let stored_version = if safe_boot {
+            0
         } else {
-            vec![]
+            migration::migrate(&mut nvs)?
         };
+        // 固件降级且无法解析新版本的配置时进入安全模式，使用默认配置且不写入NVS
+        let safe_mode =
+            safe_boot || (stored_version > SCHEMA_VERSION && Self::check(&nvs).is_err());
+        if safe_mode && !safe_boot {
+            report::error(
+                Module::Store,
+                ErrorCode::Storage,
+                format!(
+                    "config schema {} is newer than {}, enter safe mode",
+                    stored_version, SCHEMA_VERSION
+                ),
+            );
+        }
 
-        Ok(Self {
+        let scene: Scene = read_blob_or_default(&nvs, SCENE, safe_mode)?;
+        let time_task: Vec<time_task::TimeTask> = read_blob_or_default(&nvs, TIME_TASK, safe_mode)?;
+        let indicator: IndicatorConfig = read_blob_or_default(&nvs, INDICATOR, safe_mode)?;
+        let palettes: Palettes = read_blob_or_default(&nvs, PALETTES, safe_mode)?;
+        let demo: DemoConfig = read_blob_or_default(&nvs, DEMO, safe_mode)?;
+        let wifi: WifiConfig = read_blob_or_default(&nvs, WIFI, safe_mode)?;
+        let scenes: Vec<Scene> = read_blob_or_default(&nvs, SCENES, safe_mode)?;
+        let favorites: Favorites = read_blob_or_default(&nvs, FAVORITES, safe_mode)?;
+        let button: ButtonConfig = read_blob_or_default(&nvs, BUTTON, safe_mode)?;
+        let brightness_curve: BrightnessCurve =
+            read_blob_or_default(&nvs, BRIGHTNESS_CURVE, safe_mode)?;
+        let sync: SyncConfig = read_blob_or_default(&nvs, SYNC, safe_mode)?;
+        let controller: ControllerConfig = read_blob_or_default(&nvs, CONTROLLER, safe_mode)?;
+        let dmx: DmxConfig = read_blob_or_default(&nvs, DMX, safe_mode)?;
+        let mqtt: MqttConfig = read_blob_or_default(&nvs, MQTT, safe_mode)?;
+        let adaptive: AdaptiveConfig = read_blob_or_default(&nvs, ADAPTIVE, safe_mode)?;
+        let motion: MotionConfig = read_blob_or_default(&nvs, MOTION, safe_mode)?;
+        let ir: IrConfig = read_blob_or_default(&nvs, IR, safe_mode)?;
+        let calibration: CalibrationConfig = read_blob_or_default(&nvs, CALIBRATION, safe_mode)?;
+        let battery: BatteryConfig = read_blob_or_default(&nvs, BATTERY, safe_mode)?;
+        let power: PowerConfig = read_blob_or_default(&nvs, POWER, safe_mode)?;
+        let thermal: ThermalConfig = read_blob_or_default(&nvs, THERMAL, safe_mode)?;
+        let board: BoardConfig = read_blob_or_default(&nvs, BOARD, safe_mode)?;
+        let advertising: AdvertisingConfig = read_blob_or_default(&nvs, ADVERTISING, safe_mode)?;
+        let settings: DeviceSettings = read_blob_or_default(&nvs, SETTINGS, safe_mode)?;
+        let log_levels: LogLevelConfig = read_blob_or_default(&nvs, LOG_LEVELS, safe_mode)?;
+        timezone::set_offset_minutes(settings.timezone_minutes);
+        let tasks_paused = nvs.get_u8(TASKS_PAUSED)?.unwrap_or(0) != 0;
+        // 与鉴权令牌一样，安全模式下同样生效
+        let child_lock = nvs.get_u8(CHILD_LOCK)?.unwrap_or(0) != 0;
+        let (persist_tx, persist_rx) = mpsc::channel();
+
+        let store = Self {
             scene: Arc::new(Mutex::new(scene)),
             time_task: Arc::new(Mutex::new(time_task)),
+            indicator: Arc::new(Mutex::new(indicator)),
+            palettes: Arc::new(Mutex::new(palettes)),
+            demo: Arc::new(Mutex::new(demo)),
+            wifi: Arc::new(Mutex::new(wifi)),
+            scenes: Arc::new(Mutex::new(scenes)),
+            favorites: Arc::new(Mutex::new(favorites)),
+            button: Arc::new(Mutex::new(button)),
+            brightness_curve: Arc::new(Mutex::new(brightness_curve)),
+            adaptive: Arc::new(Mutex::new(adaptive)),
+            motion: Arc::new(Mutex::new(motion)),
+            ir: Arc::new(Mutex::new(ir)),
+            calibration: Arc::new(Mutex::new(calibration)),
+            battery: Arc::new(Mutex::new(battery)),
+            power: Arc::new(Mutex::new(power)),
+            thermal: Arc::new(Mutex::new(thermal)),
+            log_levels: Arc::new(Mutex::new(log_levels)),
+            settings: Arc::new(Mutex::new(settings)),
+            board: Arc::new(Mutex::new(board)),
+            advertising: Arc::new(Mutex::new(advertising)),
+            sync: Arc::new(Mutex::new(sync)),
+            tasks_paused: Arc::new(Mutex::new(tasks_paused)),
+            child_lock: Arc::new(Mutex::new(child_lock)),
+            controller: Arc::new(Mutex::new(controller)),
+            dmx: Arc::new(Mutex::new(dmx)),
+            mqtt: Arc::new(Mutex::new(mqtt)),
+            history: History::default(),
             nvs: Arc::new(Mutex::new(nvs)),
-        })
+            safe_mode,
+            persist_tx,
+        };
+        persist::start(store.clone(), persist_rx)?;
+        if !safe_mode {
+            store.resume_import()?;
+        }
+        Ok(store)
+    }
+
+    /// 检查存储的配置能否被当前固件解析
+    fn check(nvs: &EspNvs<NvsDefault>) -> Result<()> {
+        read_blob::<Scene>(nvs, SCENE)?;
+        read_blob::<Vec<time_task::TimeTask>>(nvs, TIME_TASK)?;
+        read_blob::<IndicatorConfig>(nvs, INDICATOR)?;
+        read_blob::<Palettes>(nvs, PALETTES)?;
+        read_blob::<DemoConfig>(nvs, DEMO)?;
+        read_blob::<WifiConfig>(nvs, WIFI)?;
+        read_blob::<Vec<Scene>>(nvs, SCENES)?;
+        read_blob::<Favorites>(nvs, FAVORITES)?;
+        read_blob::<ButtonConfig>(nvs, BUTTON)?;
+        read_blob::<BrightnessCurve>(nvs, BRIGHTNESS_CURVE)?;
+        read_blob::<SyncConfig>(nvs, SYNC)?;
+        read_blob::<ControllerConfig>(nvs, CONTROLLER)?;
+        read_blob::<DmxConfig>(nvs, DMX)?;
+        read_blob::<MqttConfig>(nvs, MQTT)?;
+        read_blob::<AdaptiveConfig>(nvs, ADAPTIVE)?;
+        read_blob::<MotionConfig>(nvs, MOTION)?;
+        read_blob::<IrConfig>(nvs, IR)?;
+        read_blob::<CalibrationConfig>(nvs, CALIBRATION)?;
+        read_blob::<BatteryConfig>(nvs, BATTERY)?;
+        read_blob::<PowerConfig>(nvs, POWER)?;
+        read_blob::<ThermalConfig>(nvs, THERMAL)?;
+        read_blob::<BoardConfig>(nvs, BOARD)?;
+        read_blob::<AdvertisingConfig>(nvs, ADVERTISING)?;
+        read_blob::<DeviceSettings>(nvs, SETTINGS)?;
+        read_blob::<LogLevelConfig>(nvs, LOG_LEVELS)?;
+        Ok(())
+    }
+
+    /// 启动自检：写入、读回并删除一个测试项，安全模式下不影响已保存的配置
+    pub fn self_test(&self) -> Result<()> {
+        const PATTERN: [u8; 2] = [0x5a, 0xa5];
+        let storage = self.storage();
+        storage.set(SELF_TEST, &PATTERN)?;
+        let value = storage.get(SELF_TEST)?;
+        storage.remove(SELF_TEST)?;
+        if value.as_deref() != Some(&PATTERN[..]) {
+            bail!("NVS read back {value:?}, expected {PATTERN:?}");
+        }
+        Ok(())
+    }
+
+    pub fn storage(&self) -> NvsStorage {
+        NvsStorage(self.nvs.clone())
     }
 
+    /// 读取保存的按键引脚，用于开机时判断是否进入安全模式，读取失败时使用默认引脚
+    pub fn stored_button_pin(nvs_partition: &EspNvsPartition<NvsDefault>) -> u8 {
+        EspNvs::new(nvs_partition.clone(), NAMESPACE, false)
+            .ok()
+            .and_then(|nvs| read_blob::<BoardConfig>(&nvs, BOARD).ok().flatten())
+            .filter(|board| board.validate().is_ok())
+            .unwrap_or_default()
+            .button_pin
+    }
+
+    /// 立即写入等待后台写入的配置，重启前调用
+    pub fn flush(&self) -> Result<()> {
+        self.check_writable()?;
+        self.save_scene()?;
+        self.save_time_task()?;
+        Ok(())
+    }
+
+    fn check_writable(&self) -> Result<()> {
+        if self.safe_mode {
+            bail!("Config is read-only in safe mode");
+        }
+        Ok(())
+    }
+
+    /// 由后台线程写入，快速连续的修改合并为一次
     pub fn write_scene(&self) -> Result<()> {
+        self.check_writable()?;
+        self.persist_tx.send(Pending::Scene)?;
+        Ok(())
+    }
+
+    fn save_scene(&self) -> Result<()> {
         let data = self.scene.lock().to_u8()?;
         self.nvs.lock().set_blob(SCENE, &data)?;
         Ok(())
     }
 
+    /// 覆盖当前场景并保存，原场景可撤销
+    pub fn set_scene(&self, scene: Scene) -> Result<()> {
+        let old = std::mem::replace(&mut *self.scene.lock(), scene);
+        self.history.record(Change::Scene(old));
+        self.write_scene()
+    }
+
     pub fn reset_scene(&self) -> Result<bool> {
-        *self.scene.lock() = Scene::default();
+        self.check_writable()?;
+        let old = std::mem::take(&mut *self.scene.lock());
+        self.history.record(Change::Scene(old));
         Ok(self.nvs.lock().remove(SCENE)?)
     }
 
+    /// 由后台线程写入，快速连续的修改合并为一次
     pub fn write_time_task(&self) -> Result<()> {
+        self.check_writable()?;
+        self.persist_tx.send(Pending::TimeTask)?;
+        Ok(())
+    }
+
+    fn save_time_task(&self) -> Result<()> {
         let data = serde_json::to_vec(&*self.time_task.lock())?;
         self.nvs.lock().set_blob(TIME_TASK, &data)?;
         Ok(())
     }
+
+    pub fn write_indicator(&self) -> Result<()> {
+        self.check_writable()?;
+        let data = serde_json::to_vec(&*self.indicator.lock())?;
+        self.nvs.lock().set_blob(INDICATOR, &data)?;
+        Ok(())
+    }
+
+    pub fn write_palettes(&self) -> Result<()> {
+        self.check_writable()?;
+        let data = serde_json::to_vec(&*self.palettes.lock())?;
+        self.nvs.lock().set_blob(PALETTES, &data)?;
+        Ok(())
+    }
+
+    pub fn write_demo(&self) -> Result<()> {
+        self.check_writable()?;
+        let data = serde_json::to_vec(&*self.demo.lock())?;
+        self.nvs.lock().set_blob(DEMO, &data)?;
+        Ok(())
+    }
+
+    pub fn write_wifi(&self) -> Result<()> {
+        self.check_writable()?;
+        let data = serde_json::to_vec(&*self.wifi.lock())?;
+        self.nvs.lock().set_blob(WIFI, &data)?;
+        Ok(())
+    }
+
+    pub fn write_scenes(&self) -> Result<()> {
+        self.check_writable()?;
+        let data = serde_json::to_vec(&*self.scenes.lock())?;
+        self.nvs.lock().set_blob(SCENES, &data)?;
+        Ok(())
+    }
+
+    pub fn write_favorites(&self) -> Result<()> {
+        self.check_writable()?;
+        let data = serde_json::to_vec(&*self.favorites.lock())?;
+        self.nvs.lock().set_blob(FAVORITES, &data)?;
+        Ok(())
+    }
+
+    pub fn write_button(&self) -> Result<()> {
+        self.check_writable()?;
+        let data = serde_json::to_vec(&*self.button.lock())?;
+        self.nvs.lock().set_blob(BUTTON, &data)?;
+        Ok(())
+    }
+
+    pub fn write_brightness_curve(&self) -> Result<()> {
+        self.check_writable()?;
+        let data = serde_json::to_vec(&*self.brightness_curve.lock())?;
+        self.nvs.lock().set_blob(BRIGHTNESS_CURVE, &data)?;
+        Ok(())
+    }
+
+    pub fn write_sync(&self) -> Result<()> {
+        self.check_writable()?;
+        let data = serde_json::to_vec(&*self.sync.lock())?;
+        self.nvs.lock().set_blob(SYNC, &data)?;
+        Ok(())
+    }
+
+    pub fn write_controller(&self) -> Result<()> {
+        self.check_writable()?;
+        let data = serde_json::to_vec(&*self.controller.lock())?;
+        self.nvs.lock().set_blob(CONTROLLER, &data)?;
+        Ok(())
+    }
+
+    pub fn write_dmx(&self) -> Result<()> {
+        self.check_writable()?;
+        let data = serde_json::to_vec(&*self.dmx.lock())?;
+        self.nvs.lock().set_blob(DMX, &data)?;
+        Ok(())
+    }
+
+    pub fn write_mqtt(&self) -> Result<()> {
+        self.check_writable()?;
+        let data = serde_json::to_vec(&*self.mqtt.lock())?;
+        self.nvs.lock().set_blob(MQTT, &data)?;
+        Ok(())
+    }
+
+    pub fn write_adaptive(&self) -> Result<()> {
+        self.check_writable()?;
+        let data = serde_json::to_vec(&*self.adaptive.lock())?;
+        self.nvs.lock().set_blob(ADAPTIVE, &data)?;
+        Ok(())
+    }
+
+    pub fn write_motion(&self) -> Result<()> {
+        self.check_writable()?;
+        let data = serde_json::to_vec(&*self.motion.lock())?;
+        self.nvs.lock().set_blob(MOTION, &data)?;
+        Ok(())
+    }
+
+    pub fn write_ir(&self) -> Result<()> {
+        self.check_writable()?;
+        let data = serde_json::to_vec(&*self.ir.lock())?;
+        self.nvs.lock().set_blob(IR, &data)?;
+        Ok(())
+    }
+
+    pub fn write_calibration(&self) -> Result<()> {
+        self.check_writable()?;
+        let data = serde_json::to_vec(&*self.calibration.lock())?;
+        self.nvs.lock().set_blob(CALIBRATION, &data)?;
+        Ok(())
+    }
+
+    pub fn write_battery(&self) -> Result<()> {
+        self.check_writable()?;
+        let data = serde_json::to_vec(&*self.battery.lock())?;
+        self.nvs.lock().set_blob(BATTERY, &data)?;
+        Ok(())
+    }
+
+    pub fn write_power(&self) -> Result<()> {
+        self.check_writable()?;
+        let data = serde_json::to_vec(&*self.power.lock())?;
+        self.nvs.lock().set_blob(POWER, &data)?;
+        Ok(())
+    }
+
+    pub fn write_thermal(&self) -> Result<()> {
+        self.check_writable()?;
+        let data = serde_json::to_vec(&*self.thermal.lock())?;
+        self.nvs.lock().set_blob(THERMAL, &data)?;
+        Ok(())
+    }
+
+    pub fn write_board(&self) -> Result<()> {
+        self.check_writable()?;
+        let data = serde_json::to_vec(&*self.board.lock())?;
+        self.nvs.lock().set_blob(BOARD, &data)?;
+        Ok(())
+    }
+
+    pub fn write_advertising(&self) -> Result<()> {
+        self.check_writable()?;
+        let data = serde_json::to_vec(&*self.advertising.lock())?;
+        self.nvs.lock().set_blob(ADVERTISING, &data)?;
+        Ok(())
+    }
+
+    pub fn write_log_levels(&self) -> Result<()> {
+        self.check_writable()?;
+        let data = serde_json::to_vec(&*self.log_levels.lock())?;
+        self.nvs.lock().set_blob(LOG_LEVELS, &data)?;
+        Ok(())
+    }
+
+    pub fn write_settings(&self) -> Result<()> {
+        self.check_writable()?;
+        let data = serde_json::to_vec(&*self.settings.lock())?;
+        self.nvs.lock().set_blob(SETTINGS, &data)?;
+        Ok(())
+    }
+
+    pub fn write_timezone(&self, minutes: i32) -> Result<()> {
+        self.check_writable()?;
+        timezone::set_offset_minutes(minutes);
+        self.settings.lock().timezone_minutes = minutes;
+        self.write_settings()
+    }
+
+    /// 断电前灯是否处于打开状态
+    pub fn light_on(&self) -> Result<bool> {
+        Ok(self.nvs.lock().get_u8(LIGHT_ON)?.unwrap_or(0) != 0)
+    }
+
+    pub fn write_light_on(&self, on: bool) -> Result<()> {
+        self.check_writable()?;
+        self.nvs.lock().set_u8(LIGHT_ON, on as u8)?;
+        Ok(())
+    }
+
+    pub fn write_tasks_paused(&self) -> Result<()> {
+        self.check_writable()?;
+        let paused = *self.tasks_paused.lock();
+        self.nvs.lock().set_u8(TASKS_PAUSED, paused as u8)?;
+        Ok(())
+    }
+
+    pub fn set_child_lock(&self, locked: bool) -> Result<()> {
+        self.check_writable()?;
+        self.nvs.lock().set_u8(CHILD_LOCK, locked as u8)?;
+        *self.child_lock.lock() = locked;
+        Ok(())
+    }
+
+    /// 上次启动保存的日志
+    pub fn read_log(&self) -> Result<Vec<u8>> {
+        let nvs = self.nvs.lock();
+        let len = nvs.blob_len(LOG)?.unwrap_or(0);
+        let mut data = vec![0u8; len];
+        let len = nvs.get_blob(LOG, &mut data)?.map_or(0, |data| data.len());
+        data.truncate(len);
+        Ok(data)
+    }
+
+    pub fn write_log(&self, data: &[u8]) -> Result<()> {
+        self.check_writable()?;
+        self.nvs.lock().set_blob(LOG, data)?;
+        Ok(())
+    }
+
+    /// 累计使用统计，不属于配置，不在启动时读取
+    pub fn read_usage(&self) -> Result<UsageStats> {
+        read_blob_or_default(&self.nvs.lock(), USAGE, self.safe_mode)
+    }
+
+    pub fn write_usage(&self, usage: &UsageStats) -> Result<()> {
+        self.check_writable()?;
+        let data = serde_json::to_vec(usage)?;
+        self.nvs.lock().set_blob(USAGE, &data)?;
+        Ok(())
+    }
+
+    /// 读取并清除看门狗重启前记录的卡死任务
+    pub fn take_stuck_task(&self) -> Result<Option<String>> {
+        let mut nvs = self.nvs.lock();
+        let mut buf = [0u8; 32];
+        let task = nvs.get_str(STUCK_TASK, &mut buf)?.map(str::to_string);
+        if task.is_some() {
+            nvs.remove(STUCK_TASK)?;
+        }
+        Ok(task)
+    }
+
+    /// 安全模式下也需要记录，便于排查问题
+    pub fn write_stuck_task(&self, name: &str) -> Result<()> {
+        self.nvs.lock().set_str(STUCK_TASK, name)?;
+        Ok(())
+    }
+
+    /// 鉴权令牌，安全模式下同样生效
+    pub fn read_auth_token(&self) -> Result<Option<[u8; TOKEN_LEN]>> {
+        let nvs = self.nvs.lock();
+        let mut token = [0u8; TOKEN_LEN];
+        Ok(nvs
+            .get_blob(AUTH_TOKEN, &mut token)?
+            .filter(|data| data.len() == TOKEN_LEN)
+            .map(|_| token))
+    }
+
+    pub fn write_auth_token(&self, token: &[u8; TOKEN_LEN]) -> Result<()> {
+        self.check_writable()?;
+        self.nvs.lock().set_blob(AUTH_TOKEN, token)?;
+        Ok(())
+    }
+
+    /// Hue模拟中已创建的用户名，与鉴权令牌一样不受安全模式影响
+    pub fn read_hue_users(&self) -> Result<Vec<String>> {
+        Ok(read_blob(&self.nvs.lock(), HUE_USERS)?.unwrap_or_default())
+    }
+
+    pub fn write_hue_users(&self, users: &[String]) -> Result<()> {
+        self.check_writable()?;
+        let data = serde_json::to_vec(users)?;
+        self.nvs.lock().set_blob(HUE_USERS, &data)?;
+        Ok(())
+    }
 }
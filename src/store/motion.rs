@@ -0,0 +1,40 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// 已被其他外设占用的引脚：光敏电阻、麦克风、蜂鸣器、I2C、红外接收、灯带和按键
+const RESERVED_PINS: [u8; 10] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+/// ESP32-C3可用的最大GPIO编号
+const MAX_PIN: u8 = 21;
+
+/// 人体感应配置，有人时自动开灯，无人超时后自动关灯
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MotionConfig {
+    pub enabled: bool,
+    /// PIR传感器输出接的GPIO，修改后重启生效
+    pub pin: u8,
+    /// 无人后自动关灯的时间，单位：秒
+    pub timeout_secs: u32,
+}
+
+impl Default for MotionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            pin: 10,
+            timeout_secs: 300,
+        }
+    }
+}
+
+impl MotionConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.pin > MAX_PIN || RESERVED_PINS.contains(&self.pin) {
+            bail!("Invalid motion pin {}", self.pin);
+        }
+        if self.timeout_secs == 0 {
+            bail!("Invalid motion timeout {}", self.timeout_secs);
+        }
+        Ok(())
+    }
+}
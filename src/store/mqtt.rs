@@ -0,0 +1,69 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// 遥测间隔的下限，单位：秒
+const MIN_INTERVAL: u32 = 10;
+/// 主题中替换为设备MAC地址的占位符
+const DEVICE_ID: &str = "{id}";
+
+/// MQTT遥测配置，需要同时开启`mqtt`功能，修改后重启生效
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MqttConfig {
+    /// 服务器地址，如`mqtt://192.168.1.2:1883`
+    pub url: String,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+    /// 定期发布内存、信号强度、温度和灯光状态的主题，`{id}`替换为设备MAC地址
+    pub telemetry_topic: String,
+    /// 发布错误事件的主题
+    pub error_topic: String,
+    /// 遥测间隔，单位：秒
+    pub interval: u32,
+    /// 每分钟最多发布的错误数，超出的丢弃，丢弃数量在下一次遥测中上报
+    pub max_errors_per_minute: u8,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            username: String::new(),
+            password: String::new(),
+            telemetry_topic: "smartbrite/{id}/telemetry".to_string(),
+            error_topic: "smartbrite/{id}/error".to_string(),
+            interval: 60,
+            max_errors_per_minute: 10,
+        }
+    }
+}
+
+impl MqttConfig {
+    pub fn validate(&self) -> Result<()> {
+        if !self.url.is_empty()
+            && !self.url.starts_with("mqtt://")
+            && !self.url.starts_with("mqtts://")
+        {
+            bail!("Invalid mqtt url {}", self.url);
+        }
+        for topic in [&self.telemetry_topic, &self.error_topic] {
+            if topic.is_empty() || topic.contains(['+', '#']) {
+                bail!("Invalid mqtt topic {topic}");
+            }
+        }
+        if self.interval < MIN_INTERVAL {
+            bail!("Telemetry interval must be at least {MIN_INTERVAL}s");
+        }
+        Ok(())
+    }
+
+    /// 替换占位符后的遥测主题和错误主题
+    pub fn topics(&self, id: &str) -> (String, String) {
+        (
+            self.telemetry_topic.replace(DEVICE_ID, id),
+            self.error_topic.replace(DEVICE_ID, id),
+        )
+    }
+}
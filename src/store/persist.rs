@@ -0,0 +1,78 @@
+use super::NvsStore;
+use crate::{
+    diagnostics,
+    report::{self, ErrorCode, Module},
+};
+use anyhow::Result;
+use std::{
+    sync::mpsc::{Receiver, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+/// 最后一次修改后等待该时间再写入，连续修改只写一次
+const COALESCE: Duration = Duration::from_millis(500);
+/// 持续修改时最长延迟写入的时间，避免一直不保存
+const MAX_DELAY: Duration = Duration::from_secs(3);
+
+/// 等待后台写入的配置
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Pending {
+    Scene,
+    TimeTask,
+}
+
+/// 启动写入线程，写Flash期间会暂停缓存访问，放在NimBLE回调中容易导致连接超时
+pub(super) fn start(store: NvsStore, rx: Receiver<Pending>) -> Result<()> {
+    std::thread::Builder::new()
+        .stack_size(6 * 1024)
+        .spawn(move || {
+            diagnostics::track_stack("persist");
+            let mut pending: Vec<Pending> = vec![];
+            let mut since = Instant::now();
+            loop {
+                if pending.is_empty() {
+                    let Ok(key) = rx.recv() else {
+                        return;
+                    };
+                    pending.push(key);
+                    since = Instant::now();
+                    continue;
+                }
+                // 写入的是最新的内存数据，重复的请求直接合并
+                let timeout = COALESCE.min(MAX_DELAY.saturating_sub(since.elapsed()));
+                match rx.recv_timeout(timeout) {
+                    Ok(key) => {
+                        if !pending.contains(&key) {
+                            pending.push(key);
+                        }
+                        if since.elapsed() < MAX_DELAY {
+                            continue;
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => {
+                        flush(&store, &mut pending);
+                        return;
+                    }
+                }
+                flush(&store, &mut pending);
+            }
+        })?;
+    Ok(())
+}
+
+fn flush(store: &NvsStore, pending: &mut Vec<Pending>) {
+    for key in pending.drain(..) {
+        let res = match key {
+            Pending::Scene => store.save_scene(),
+            Pending::TimeTask => store.save_time_task(),
+        };
+        if let Err(e) = res {
+            report::error(
+                Module::Store,
+                ErrorCode::Storage,
+                format!("write {key:?} error: {e}"),
+            );
+        }
+    }
+}
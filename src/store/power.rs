@@ -0,0 +1,32 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// 空闲超时的最小值，避免刚断开连接就进入睡眠
+const MIN_IDLE_SECS: u32 = 10;
+
+/// 省电配置，灯关闭且没有客户端连接一段时间后进入自动浅睡眠
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PowerConfig {
+    pub enabled: bool,
+    /// 进入睡眠前的空闲时间，单位：秒
+    pub idle_timeout_secs: u32,
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            idle_timeout_secs: 60,
+        }
+    }
+}
+
+impl PowerConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.idle_timeout_secs < MIN_IDLE_SECS {
+            bail!("Invalid idle timeout {}", self.idle_timeout_secs);
+        }
+        Ok(())
+    }
+}
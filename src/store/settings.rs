@@ -0,0 +1,134 @@
+use super::{timezone, DEFAULT_NAME};
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// 广播名称的最大长度，受扫描响应包长度限制
+pub const MAX_NAME_LEN: usize = 29;
+/// 单个RMT通道能够驱动的最大灯珠数
+const MAX_LEDS: u16 = 1024;
+/// 电流限制的下限，低于该值时灯带几乎无法点亮
+const MIN_CURRENT_MA: u16 = 100;
+/// 渲染帧率上限，更高时长灯带来不及发送一帧
+const MAX_FPS: u8 = 60;
+
+/// 上电后的灯光状态
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum StartupBehavior {
+    /// 场景设置了自动开灯，或断电前灯是打开的，则开灯
+    #[default]
+    Restore,
+    On,
+    Off,
+}
+
+/// 可在运行时关闭的功能，修改后重启生效
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Features {
+    /// Wi-Fi连接后的HTTP控制
+    pub http: bool,
+    /// ESP-NOW同步组
+    pub sync: bool,
+    /// 蓝牙Mesh，需要编译时开启`mesh`特性
+    pub mesh: bool,
+    /// 在局域网中模拟Hue网桥，供语音助手本地控制，需要同时开启`http`
+    #[serde(default)]
+    pub hue: bool,
+    /// 未配置Wi-Fi时开启热点和配网页面
+    #[serde(default = "default_portal")]
+    pub portal: bool,
+    /// 接收WLED UDP实时协议和DDP串流
+    #[serde(default)]
+    pub realtime: bool,
+    /// 通过MQTT发布遥测和错误事件
+    #[serde(default)]
+    pub mqtt: bool,
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Self {
+            http: true,
+            sync: true,
+            mesh: true,
+            hue: false,
+            portal: true,
+            realtime: false,
+            mqtt: false,
+        }
+    }
+}
+
+/// 设备的全局设置
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceSettings {
+    /// 广播名称
+    pub name: String,
+    /// 相对UTC的偏移分钟数
+    pub timezone_minutes: i32,
+    /// 未指定亮度开灯时使用的亮度百分比，未设置时使用亮度曲线
+    pub default_brightness: Option<u8>,
+    pub startup: StartupBehavior,
+    /// 灯带效果最多点亮的灯珠数
+    pub max_leds: u16,
+    /// 效果渲染的帧率
+    #[serde(default = "default_fps")]
+    pub fps: u8,
+    /// 5V供电时灯带允许的最大电流，单位：毫安，超过时降低整帧亮度，未设置时不限制
+    #[serde(default)]
+    pub max_current_ma: Option<u16>,
+    pub features: Features,
+}
+
+impl Default for DeviceSettings {
+    fn default() -> Self {
+        Self {
+            name: DEFAULT_NAME.to_string(),
+            timezone_minutes: 0,
+            default_brightness: None,
+            startup: StartupBehavior::Restore,
+            max_leds: 300,
+            fps: default_fps(),
+            max_current_ma: None,
+            features: Features::default(),
+        }
+    }
+}
+
+impl DeviceSettings {
+    pub fn validate(&self) -> Result<()> {
+        if self.name.is_empty() || self.name.len() > MAX_NAME_LEN {
+            bail!("Invalid name {}", self.name);
+        }
+        if !(timezone::MIN_OFFSET..=timezone::MAX_OFFSET).contains(&self.timezone_minutes) {
+            bail!("Invalid timezone offset {}", self.timezone_minutes);
+        }
+        if let Some(brightness) = self.default_brightness {
+            if !(1..=100).contains(&brightness) {
+                bail!("Invalid default brightness {brightness}");
+            }
+        }
+        if !(1..=MAX_LEDS).contains(&self.max_leds) {
+            bail!("Invalid LED count {}", self.max_leds);
+        }
+        if let Some(max_current) = self.max_current_ma {
+            if max_current < MIN_CURRENT_MA {
+                bail!("Invalid current limit {max_current}mA");
+            }
+        }
+        if !(1..=MAX_FPS).contains(&self.fps) {
+            bail!("Invalid frame rate {}", self.fps);
+        }
+        Ok(())
+    }
+}
+
+fn default_fps() -> u8 {
+    30
+}
+
+fn default_portal() -> bool {
+    true
+}
@@ -0,0 +1,37 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// 同步组配置，组内的灯通过ESP-NOW同步开关和场景
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncConfig {
+    pub enabled: bool,
+    /// 组内其他灯的Wi-Fi MAC地址，格式如`aa:bb:cc:dd:ee:ff`
+    pub peers: Vec<String>,
+}
+
+pub fn parse_mac(value: &str) -> Result<[u8; 6]> {
+    let mut mac = [0u8; 6];
+    let mut parts = value.split(':');
+    for byte in mac.iter_mut() {
+        let part = parts.next().ok_or(anyhow!("Invalid mac `{value}`"))?;
+        *byte = u8::from_str_radix(part, 16).map_err(|_| anyhow!("Invalid mac `{value}`"))?;
+    }
+    if parts.next().is_some() {
+        return Err(anyhow!("Invalid mac `{value}`"));
+    }
+    Ok(mac)
+}
+
+pub fn format_mac(mac: &[u8]) -> String {
+    mac.iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+impl SyncConfig {
+    pub fn peer_addrs(&self) -> Result<Vec<[u8; 6]>> {
+        self.peers.iter().map(|peer| parse_mac(peer)).collect()
+    }
+}
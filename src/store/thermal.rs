@@ -0,0 +1,41 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// 内置温度传感器在该范围内误差较小
+pub const MAX_CELSIUS: u8 = 100;
+
+/// 芯片过热时的行为
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ThermalConfig {
+    pub enabled: bool,
+    /// 芯片温度达到该值时降低亮度
+    pub throttle_celsius: u8,
+    /// 降温期间的亮度百分比
+    pub throttle_brightness: u8,
+    /// 芯片温度达到该值时关灯
+    pub shutdown_celsius: u8,
+}
+
+impl Default for ThermalConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            throttle_celsius: 70,
+            throttle_brightness: 40,
+            shutdown_celsius: 85,
+        }
+    }
+}
+
+impl ThermalConfig {
+    pub fn validate(&self) -> Result<()> {
+        if !(1..=100).contains(&self.throttle_brightness)
+            || self.throttle_celsius >= self.shutdown_celsius
+            || self.shutdown_celsius > MAX_CELSIUS
+        {
+            bail!("Invalid thermal config {:?}", self);
+        }
+        Ok(())
+    }
+}
@@ -1,157 +1,297 @@
-use std::time::Duration;
-
 use crate::light::LightEvent;
-use anyhow::{anyhow, Ok, Result};
-use chrono::{DateTime, Datelike, TimeDelta, Utc};
-use esp_idf_svc::timer::{EspTimerService, Task};
+use anyhow::{bail, Ok, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+use esp_idf_svc::timer::{EspAsyncTimer, EspTimerService, Task};
+use rand::random;
 use serde::{Deserialize, Serialize};
+use std::fmt::Display;
 
-/// 获取延迟执行时间
-pub trait GetDelta {
-    fn get_delta(&self) -> anyhow::Result<TimeDelta>;
-    fn timeout(&self) -> anyhow::Result<bool> {
-        let delay = self.get_delta()?;
-        if delay > TimeDelta::zero() && delay <= TimeDelta::seconds(60) {
-            Ok(true)
-        } else {
-            Ok(false)
+use smart_brite_core::{hal::Clock, time_task::MISSED_TOLERANCE_SECS};
+
+pub use smart_brite_core::time_task::{
+    CountdownTask, CronTask, DatesTask, DayTask, GetDelta, OnceTask, TimeFrequency, WeekTask,
+};
+
+/// 单次等待的最长时间（秒），系统时间被调整后能及时重新计算
+const MAX_WAIT_SECS: i64 = 30;
+/// 最多的任务数，任务列表整体保存为一个NVS blob，需要控制大小
+pub const MAX_TASKS: usize = 16;
+const MAX_NAME_LEN: usize = 32;
+/// 单个任务最多的日期数，包括例外日期
+const MAX_DATES: usize = 32;
+const MAX_JITTER_MINUTES: u32 = 120;
+
+/// 任务校验失败的原因，以JSON格式返回给客户端
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum TaskError {
+    TooManyTasks {
+        max: usize,
+    },
+    DuplicateName {
+        name: String,
+    },
+    NotFound {
+        name: String,
+    },
+    InvalidField {
+        field: &'static str,
+        message: String,
+    },
+}
+
+impl Display for TaskError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match serde_json::to_string(self) {
+            Result::Ok(value) => f.write_str(&value),
+            Err(_) => write!(f, "{self:?}"),
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(tag = "kind", rename_all = "camelCase")]
-pub enum TimeFrequency {
-    Once(OnceTask),
-    Day(DayTask),
-    Week(WeekTask),
+impl std::error::Error for TaskError {}
+
+fn invalid(field: &'static str, message: impl Into<String>) -> TaskError {
+    TaskError::InvalidField {
+        field,
+        message: message.into(),
+    }
 }
+
+/// 等待到下一次执行时间，jitter_minutes不为0时在此基础上随机推迟
+async fn wait_next<T: GetDelta>(
+    task: &T,
+    clock: &impl Clock,
+    async_timer: &mut EspAsyncTimer,
+    jitter_minutes: u32,
+) -> Result<()> {
+    // 只向后推迟，提前执行会导致同一周期内重复执行
+    let jitter = TimeDelta::seconds(random::<u32>() as i64 % (jitter_minutes as i64 * 60 + 1));
+    let mut target = clock.now() + task.get_delta(clock)? + jitter;
+    loop {
+        let remaining = target.signed_duration_since(clock.now());
+        if remaining <= TimeDelta::zero() {
+            if -remaining <= TimeDelta::seconds(MISSED_TOLERANCE_SECS) {
+                return Ok(());
+            }
+            // 系统时间发生跳变，重新计算执行时间
+            target = clock.now() + task.get_delta(clock)?;
+            if target < clock.now() - TimeDelta::seconds(MISSED_TOLERANCE_SECS) {
+                bail!("Task time has passed");
+            }
+            continue;
+        }
+        let wait = remaining.min(TimeDelta::seconds(MAX_WAIT_SECS));
+        async_timer.after(wait.to_std()?).await?;
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct TimeTask {
     pub name: String,
     pub operation: LightEvent,
     #[serde(flatten)]
     pub frequency: TimeFrequency,
+    /// 执行时播放的旋律，内置旋律名称或RTTTL字符串
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub melody: Option<String>,
+    /// 每次执行随机推迟的最长分钟数，模拟有人在家（度假模式）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jitter_minutes: Option<u32>,
+    /// 最近一次执行的时间，与任务一起保存，重启后据此判断是否已经执行过
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_fired: Option<DateTime<Utc>>,
+    /// 一次性任务在断电期间错过且未设置补执行，保留在列表中供App提示，不再运行
+    #[serde(default)]
+    pub expired: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct OnceTask {
-    pub end_time: DateTime<Utc>,
+/// 任务结束的原因
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TaskEnd {
+    /// 执行完成，从任务列表中移除
+    Finished,
+    /// 断电期间错过了执行时间
+    Expired,
 }
 
-impl GetDelta for OnceTask {
-    fn get_delta(&self) -> Result<TimeDelta> {
-        let now = Utc::now();
-        Ok(self.end_time.signed_duration_since(now))
-    }
-}
-
-impl OnceTask {
-    async fn run<F>(&self, timer_service: EspTimerService<Task>, mut cb: F) -> Result<()>
-    where
-        F: FnMut() -> Result<()>,
-    {
-        let mut async_timer = timer_service.timer_async()?;
-        loop {
-            async_timer.after(Duration::from_secs(60)).await?;
-            if self.timeout()? {
-                cb()?;
-                break;
-            }
+/// 断电期间错过的一次性任务，按设置补执行或标记为过期
+async fn run_once<F>(
+    task: &OnceTask,
+    timer_service: EspTimerService<Task>,
+    clock: &impl Clock,
+    jitter_minutes: u32,
+    mut cb: F,
+) -> Result<TaskEnd>
+where
+    F: FnMut() -> Result<()>,
+{
+    if task.missed(clock)? {
+        if !task.catch_up {
+            return Ok(TaskEnd::Expired);
         }
-        Ok(())
+        cb()?;
+        return Ok(TaskEnd::Finished);
     }
+    let mut async_timer = timer_service.timer_async()?;
+    wait_next(task, clock, &mut async_timer, jitter_minutes).await?;
+    cb()?;
+    Ok(TaskEnd::Finished)
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct DayTask {
-    pub delay: DateTime<Utc>,
+/// 每天、每周和cron任务一直重复执行
+async fn run_repeat<T, F>(
+    task: &T,
+    timer_service: EspTimerService<Task>,
+    clock: &impl Clock,
+    jitter_minutes: u32,
+    mut cb: F,
+) -> Result<()>
+where
+    T: GetDelta,
+    F: FnMut() -> Result<()>,
+{
+    let mut async_timer = timer_service.timer_async()?;
+    loop {
+        wait_next(task, clock, &mut async_timer, jitter_minutes).await?;
+        cb()?;
+    }
 }
 
-impl GetDelta for DayTask {
-    fn get_delta(&self) -> Result<TimeDelta> {
-        let now = Utc::now();
-        let time = now
-            .with_time(self.delay.time())
-            .single()
-            .ok_or(anyhow!("Invalid time"))?;
-
-        if time > now {
-            Ok(time.signed_duration_since(now))
-        } else {
-            Ok(time.signed_duration_since(now) + TimeDelta::days(1))
-        }
+async fn run_dates<F>(
+    task: &DatesTask,
+    timer_service: EspTimerService<Task>,
+    clock: &impl Clock,
+    jitter_minutes: u32,
+    mut cb: F,
+) -> Result<()>
+where
+    F: FnMut() -> Result<()>,
+{
+    let mut async_timer = timer_service.timer_async()?;
+    while task.next(clock)?.is_some() {
+        wait_next(task, clock, &mut async_timer, jitter_minutes).await?;
+        cb()?;
     }
+    Ok(())
 }
 
-impl DayTask {
-    async fn run<F>(&self, timer_service: EspTimerService<Task>, mut cb: F) -> Result<()>
-    where
-        F: FnMut() -> Result<()>,
-    {
-        let mut async_timer = timer_service.timer_async()?;
-        loop {
-            async_timer.after(Duration::from_secs(60)).await?;
-            if self.timeout()? {
-                cb()?;
-            }
-        }
+async fn run_countdown<F>(
+    task: &CountdownTask,
+    timer_service: EspTimerService<Task>,
+    clock: &impl Clock,
+    cb: F,
+) -> Result<()>
+where
+    F: FnOnce() -> Result<()>,
+{
+    let delay = task.get_delta(clock)?;
+    // 断电重启时倒计时已过期太久，则不再执行
+    if delay < -TimeDelta::seconds(60) {
+        return Ok(());
     }
+    let mut async_timer = timer_service.timer_async()?;
+    async_timer
+        .after(delay.to_std().unwrap_or_default())
+        .await?;
+    cb()
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// 任务的下一次执行时间，用于App显示倒计时
+#[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
-pub struct WeekTask {
-    pub day_of_week: u32,
-    pub delay: DateTime<Utc>,
+pub struct NextFire {
+    pub name: String,
+    /// 暂停或不会再执行时为None
+    pub next_fire: Option<DateTime<Utc>>,
 }
 
-impl GetDelta for WeekTask {
-    fn get_delta(&self) -> Result<TimeDelta> {
-        let now = Utc::now();
-        let weekday = now.weekday().number_from_monday();
-        let days_until_target = (self.day_of_week + 7 - weekday) % 7;
-        let time = now
-            .with_time(self.delay.time())
-            .single()
-            .ok_or(anyhow!("Invalid time"))?
-            + TimeDelta::days(days_until_target as i64);
-
-        if time > now {
-            Ok(time.signed_duration_since(now))
-        } else {
-            Ok(time.signed_duration_since(now) + TimeDelta::days(7))
+impl TimeTask {
+    /// 检查各字段是否在有效范围内
+    pub fn validate(&self) -> Result<(), TaskError> {
+        if self.name.is_empty() || self.name.len() > MAX_NAME_LEN {
+            return Err(invalid("name", format!("length must be 1-{MAX_NAME_LEN}")));
+        }
+        if self
+            .jitter_minutes
+            .is_some_and(|jitter| jitter > MAX_JITTER_MINUTES)
+        {
+            return Err(invalid(
+                "jitterMinutes",
+                format!("must be at most {MAX_JITTER_MINUTES}"),
+            ));
+        }
+        match &self.frequency {
+            TimeFrequency::Day(task) if task.except.len() > MAX_DATES => {
+                Err(invalid("except", format!("at most {MAX_DATES} dates")))
+            }
+            TimeFrequency::Week(task) if !(1..=7).contains(&task.day_of_week) => {
+                Err(invalid("dayOfWeek", "must be 1 (Monday) to 7 (Sunday)"))
+            }
+            TimeFrequency::Week(task) if task.except.len() > MAX_DATES => {
+                Err(invalid("except", format!("at most {MAX_DATES} dates")))
+            }
+            TimeFrequency::Dates(task) if task.dates.is_empty() || task.dates.len() > MAX_DATES => {
+                Err(invalid("dates", format!("must have 1-{MAX_DATES} dates")))
+            }
+            _ => Result::Ok(()),
         }
     }
-}
 
-impl WeekTask {
-    async fn run<F>(&self, timer_service: EspTimerService<Task>, mut cb: F) -> Result<()>
-    where
-        F: FnMut() -> Result<()>,
-    {
-        let mut async_timer = timer_service.timer_async()?;
-        loop {
-            async_timer.after(Duration::from_secs(60)).await?;
-            if self.timeout()? {
-                cb()?;
+    /// 添加或修改任务前的检查，replace为true时修改同名任务
+    pub fn check(&self, tasks: &[TimeTask], replace: bool) -> Result<(), TaskError> {
+        self.validate()?;
+        let exists = tasks.iter().any(|item| item.name == self.name);
+        match (exists, replace) {
+            (true, false) => Err(TaskError::DuplicateName {
+                name: self.name.clone(),
+            }),
+            (false, true) => Err(TaskError::NotFound {
+                name: self.name.clone(),
+            }),
+            (false, false) if tasks.len() >= MAX_TASKS => {
+                Err(TaskError::TooManyTasks { max: MAX_TASKS })
             }
+            _ => Result::Ok(()),
         }
     }
-}
 
-impl TimeTask {
-    pub async fn run<F>(&self, timer_service: EspTimerService<Task>, cb: F) -> Result<String>
+    /// 按与执行时相同的规则计算下一次执行时间
+    pub fn next_fire(&self, paused: bool, clock: &impl Clock) -> NextFire {
+        let next_fire = if paused || self.expired {
+            None
+        } else {
+            self.frequency.next_fire(clock)
+        };
+        NextFire {
+            name: self.name.clone(),
+            next_fire,
+        }
+    }
+
+    pub async fn run<F>(
+        &self,
+        timer_service: EspTimerService<Task>,
+        clock: &impl Clock,
+        cb: F,
+    ) -> Result<TaskEnd>
     where
         F: FnMut() -> Result<()>,
     {
+        let jitter = self.jitter_minutes.unwrap_or(0);
         match &self.frequency {
-            TimeFrequency::Once(task) => task.run(timer_service, cb).await,
-            TimeFrequency::Day(task) => task.run(timer_service, cb).await,
-            TimeFrequency::Week(task) => task.run(timer_service, cb).await,
+            // 执行后还没来得及从列表中移除就断电了，不再重复执行
+            TimeFrequency::Once(_) if self.last_fired.is_some() => return Ok(TaskEnd::Finished),
+            TimeFrequency::Once(task) => {
+                return run_once(task, timer_service, clock, jitter, cb).await
+            }
+            TimeFrequency::Day(task) => run_repeat(task, timer_service, clock, jitter, cb).await,
+            TimeFrequency::Week(task) => run_repeat(task, timer_service, clock, jitter, cb).await,
+            TimeFrequency::Dates(task) => run_dates(task, timer_service, clock, jitter, cb).await,
+            TimeFrequency::Cron(task) => run_repeat(task, timer_service, clock, jitter, cb).await,
+            TimeFrequency::Countdown(task) => run_countdown(task, timer_service, clock, cb).await,
         }?;
-        Ok(self.name.clone())
+        Ok(TaskEnd::Finished)
     }
 }
@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// 最多统计的场景数，超过后替换使用时间最短的场景
+const MAX_SCENES: usize = 32;
+
+/// 单个场景的使用情况
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SceneUsage {
+    /// 累计点亮时间，单位：秒
+    pub on_secs: u64,
+    /// 切换到该场景的次数
+    pub count: u32,
+}
+
+/// 累计使用统计，用于估算灯珠损耗和耗电
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageStats {
+    /// 累计点亮时间，单位：秒
+    pub on_secs: u64,
+    /// 开灯次数
+    pub switch_count: u32,
+    /// 按场景名称统计
+    pub scenes: BTreeMap<String, SceneUsage>,
+}
+
+impl UsageStats {
+    pub fn scene_mut(&mut self, name: &str) -> &mut SceneUsage {
+        if !self.scenes.contains_key(name) && self.scenes.len() >= MAX_SCENES {
+            let least = self
+                .scenes
+                .iter()
+                .min_by_key(|(_, usage)| usage.on_secs)
+                .map(|(name, _)| name.clone());
+            if let Some(least) = least {
+                self.scenes.remove(&least);
+            }
+        }
+        self.scenes.entry(name.to_string()).or_default()
+    }
+}
@@ -0,0 +1,9 @@
+use serde::{Deserialize, Serialize};
+
+/// Wi-Fi连接配置，ssid为空表示不使用Wi-Fi
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct WifiConfig {
+    pub ssid: String,
+    #[serde(default)]
+    pub password: String,
+}
@@ -0,0 +1,218 @@
+use crate::{
+    light::{LightEvent, LightEventSender},
+    report::{self, ErrorCode, Module},
+    store::{
+        share,
+        sync::{format_mac, parse_mac},
+        Palettes, Scene, SyncConfig,
+    },
+};
+use anyhow::{bail, Result};
+use esp32_nimble::utilities::mutex::Mutex;
+use esp_idf_svc::{
+    espnow::{EspNow, PeerInfo},
+    sys::{esp_mac_type_t_ESP_MAC_WIFI_STA, esp_read_mac, wifi_interface_t_WIFI_IF_STA},
+};
+use rand::random;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc};
+
+/// ESP-NOW单个数据包的最大长度
+const MAX_PAYLOAD: usize = 250;
+
+/// 组内同步的消息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum SyncMessage {
+    Close,
+    /// 开灯，附带场景使组内的灯显示相同效果，场景过大时不附带
+    Open {
+        scene: Option<Scene>,
+        brightness: u8,
+    },
+    /// 将场景应用到整个组，场景为分享字符串，收到后回复`Ack`
+    ApplyScene {
+        id: u32,
+        scene: String,
+    },
+    Ack {
+        id: u32,
+    },
+}
+
+/// 组内应用场景的结果
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupResult {
+    pub id: u32,
+    /// 已确认的灯
+    pub acked: Vec<String>,
+    /// 超时未确认的灯
+    pub missing: Vec<String>,
+}
+
+impl SyncMessage {
+    fn encode(&self) -> Result<Vec<u8>> {
+        let data = serde_json::to_vec(self)?;
+        if data.len() <= MAX_PAYLOAD {
+            return Ok(data);
+        }
+        match self {
+            SyncMessage::Open {
+                scene: Some(_),
+                brightness,
+            } => SyncMessage::Open {
+                scene: None,
+                brightness: *brightness,
+            }
+            .encode(),
+            _ => bail!("Sync message too large"),
+        }
+    }
+}
+
+/// 本机的Wi-Fi MAC地址，其他灯将其加入同步组
+pub fn local_mac() -> String {
+    let mut mac = [0u8; 6];
+    unsafe {
+        esp_read_mac(mac.as_mut_ptr(), esp_mac_type_t_ESP_MAC_WIFI_STA);
+    }
+    format_mac(&mac)
+}
+
+/// 通过ESP-NOW与组内其他灯同步开关和场景，不需要路由器
+#[derive(Clone)]
+pub struct Sync {
+    config: Arc<Mutex<SyncConfig>>,
+    espnow: Arc<Mutex<Option<EspNow<'static>>>>,
+    /// 等待确认的场景应用请求，记录已确认的MAC地址
+    acks: Arc<Mutex<HashMap<u32, Vec<String>>>>,
+}
+
+impl Sync {
+    pub fn new(config: Arc<Mutex<SyncConfig>>) -> Self {
+        Self {
+            config,
+            espnow: Arc::new(Mutex::new(None)),
+            acks: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// 需要在Wi-Fi启动后调用
+    pub fn start(&self, light_sender: LightEventSender) -> Result<()> {
+        let espnow = EspNow::take()?;
+        let config = self.config.clone();
+        let acks = self.acks.clone();
+        espnow.register_recv_cb(move |mac: &[u8], data: &[u8]| {
+            let config = config.lock().clone();
+            let mac = format_mac(mac);
+            // 只接受同步组内的消息
+            if !config.enabled || !config.peers.contains(&mac) {
+                return;
+            }
+            match serde_json::from_slice::<SyncMessage>(data) {
+                // 其他灯发起的请求的确认会广播到整个组，只记录本机发起的
+                Ok(SyncMessage::Ack { id }) => {
+                    if let Some(acked) = acks.lock().get_mut(&id) {
+                        if !acked.contains(&mac) {
+                            acked.push(mac);
+                        }
+                    }
+                }
+                Ok(message) => {
+                    if light_sender.send(LightEvent::Sync(message)).is_err() {
+                        report::error(Module::Sync, ErrorCode::Internal, "sync event error");
+                    }
+                }
+                Err(e) => log::warn!("invalid sync message: {e}"),
+            }
+        })?;
+        *self.espnow.lock() = Some(espnow);
+        self.reload()
+    }
+
+    /// 同步组配置更新后重新注册对端
+    pub fn reload(&self) -> Result<()> {
+        let espnow = self.espnow.lock();
+        let Some(espnow) = espnow.as_ref() else {
+            return Ok(());
+        };
+        let peers = self.config.lock().peer_addrs()?;
+        while let Ok(peer) = espnow.fetch_peer(true) {
+            espnow.del_peer(peer.peer_addr)?;
+        }
+        for peer_addr in peers {
+            espnow.add_peer(PeerInfo {
+                peer_addr,
+                channel: 0,
+                ifidx: wifi_interface_t_WIFI_IF_STA,
+                encrypt: false,
+                ..Default::default()
+            })?;
+        }
+        Ok(())
+    }
+
+    /// 将场景发送到组内所有灯，返回请求ID，稍后通过`take_result`获取确认结果
+    pub fn apply_scene(&self, scene: &Scene, palettes: &Palettes) -> Result<u32> {
+        let config = self.config.lock().clone();
+        if !config.enabled || config.peers.is_empty() {
+            bail!("Sync group is empty");
+        }
+        let id = random::<u32>();
+        let message = SyncMessage::ApplyScene {
+            id,
+            scene: share::encode(scene, palettes)?,
+        };
+        // 压缩后仍然超过单个数据包时无法发送
+        if serde_json::to_vec(&message)?.len() > MAX_PAYLOAD {
+            bail!("Scene too large for sync group");
+        }
+        self.acks.lock().insert(id, vec![]);
+        self.broadcast(&message);
+        Ok(id)
+    }
+
+    /// 应用组内场景后回复确认
+    pub fn ack(&self, id: u32) {
+        self.broadcast(&SyncMessage::Ack { id });
+    }
+
+    /// 结束等待，汇总各灯的确认情况
+    pub fn take_result(&self, id: u32) -> GroupResult {
+        let acked = self.acks.lock().remove(&id).unwrap_or_default();
+        let missing = self
+            .config
+            .lock()
+            .peers
+            .iter()
+            .filter(|peer| !acked.contains(peer))
+            .cloned()
+            .collect();
+        GroupResult { id, acked, missing }
+    }
+
+    /// 向组内所有灯发送消息，从组内收到的事件不再转发，避免循环
+    pub fn broadcast(&self, message: &SyncMessage) {
+        let config = self.config.lock().clone();
+        if !config.enabled {
+            return;
+        }
+        let espnow = self.espnow.lock();
+        let Some(espnow) = espnow.as_ref() else {
+            return;
+        };
+        let data = match message.encode() {
+            Ok(data) => data,
+            Err(e) => {
+                log::warn!("{e}");
+                return;
+            }
+        };
+        for peer in &config.peers {
+            if let Err(e) = parse_mac(peer).and_then(|mac| Ok(espnow.send(mac, &data)?)) {
+                log::warn!("sync to {peer} failed: {e}");
+            }
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use crate::{
+    ble::BleControl,
+    diagnostics,
+    event_bus::{self, SensorEvent},
+    light::{LightEventSender, LightState},
+    report::{self, ErrorCode, Module},
+    store::{thermal::MAX_CELSIUS, ThermalConfig},
+};
+use anyhow::Result;
+use esp_idf_svc::sys::{
+    esp, temperature_sensor_config_t, temperature_sensor_enable, temperature_sensor_get_celsius,
+    temperature_sensor_handle_t, temperature_sensor_install,
+};
+use serde::Serialize;
+use std::{sync::Mutex, time::Duration};
+
+/// 采样间隔
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+/// 温度降到阈值以下这么多才恢复，避免在阈值附近反复切换
+const HYSTERESIS: f32 = 5.0;
+
+/// 过热保护的状态
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ThermalStatus {
+    #[default]
+    Normal,
+    /// 已降低亮度
+    Throttled,
+    /// 已关灯，降温前不能开灯
+    Shutdown,
+}
+
+impl ThermalStatus {
+    /// 根据温度得到新的状态，恢复时需要低于阈值一定温度
+    fn next(self, celsius: f32, config: &ThermalConfig) -> Self {
+        if !config.enabled {
+            return Self::Normal;
+        }
+        let shutdown = config.shutdown_celsius as f32;
+        let throttle = config.throttle_celsius as f32;
+        match self {
+            _ if celsius >= shutdown => Self::Shutdown,
+            Self::Shutdown if celsius >= shutdown - HYSTERESIS => Self::Shutdown,
+            _ if celsius >= throttle => Self::Throttled,
+            Self::Shutdown | Self::Throttled if celsius >= throttle - HYSTERESIS => Self::Throttled,
+            _ => Self::Normal,
+        }
+    }
+}
+
+/// 最近一次读取的芯片温度和保护状态，传感器启动前为None
+static STATE: Mutex<Option<(f32, ThermalStatus)>> = Mutex::new(None);
+
+/// 芯片温度，单位：摄氏度
+pub fn celsius() -> Option<f32> {
+    STATE.lock().unwrap().map(|(celsius, _)| celsius)
+}
+
+pub fn status() -> ThermalStatus {
+    STATE
+        .lock()
+        .unwrap()
+        .map_or(ThermalStatus::Normal, |(_, status)| status)
+}
+
+/// 降温期间的亮度倍率
+pub fn scale(config: &ThermalConfig) -> f32 {
+    match status() {
+        ThermalStatus::Normal => 1.0,
+        _ => config.throttle_brightness as f32 / 100.0,
+    }
+}
+
+/// 定期读取内置温度传感器，过热时降低亮度或关灯，并通过状态特征通知客户端
+pub fn start(ble_control: BleControl, mut light_sender: LightEventSender) -> Result<()> {
+    let mut handle: temperature_sensor_handle_t = std::ptr::null_mut();
+    let config = temperature_sensor_config_t {
+        range_min: 20,
+        range_max: MAX_CELSIUS as i32,
+        ..Default::default()
+    };
+    esp!(unsafe { temperature_sensor_install(&config, &mut handle) })?;
+    esp!(unsafe { temperature_sensor_enable(handle) })?;
+    // 句柄只在采样线程中使用
+    let handle = handle as usize;
+
+    std::thread::Builder::new()
+        .stack_size(4 * 1024)
+        .spawn(move || {
+            diagnostics::track_stack("thermal");
+            loop {
+                let mut celsius = 0f32;
+                match esp!(unsafe {
+                    temperature_sensor_get_celsius(
+                        handle as temperature_sensor_handle_t,
+                        &mut celsius,
+                    )
+                }) {
+                    Ok(_) => {
+                        if let Err(e) = on_update(celsius, &ble_control, &mut light_sender) {
+                            report::error(Module::Thermal, ErrorCode::Internal, e);
+                        }
+                    }
+                    Err(e) => report::error(Module::Thermal, ErrorCode::Hardware, e),
+                }
+                std::thread::sleep(SAMPLE_INTERVAL);
+            }
+        })?;
+    Ok(())
+}
+
+fn on_update(
+    celsius: f32,
+    ble_control: &BleControl,
+    light_sender: &mut LightEventSender,
+) -> Result<()> {
+    event_bus::publish(SensorEvent::Temperature(celsius));
+    let config = ble_control.nvs_store.thermal.lock().clone();
+    let previous = status();
+    let status = previous.next(celsius, &config);
+    *STATE.lock().unwrap() = Some((celsius, status));
+    if status == previous {
+        return Ok(());
+    }
+    log::warn!("chip temperature {celsius:.1}°C, thermal status {status:?}");
+    if status == ThermalStatus::Shutdown && !matches!(ble_control.get_state(), LightState::Closed) {
+        light_sender.close()?;
+    }
+    ble_control.notify_state();
+    Ok(())
+}
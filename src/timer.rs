@@ -1,65 +1,131 @@
 use crate::light::{LightEvent, LightEventSender};
-use crate::{ble::BleControl, store::time_task::TimeTask};
-use anyhow::Result;
+use crate::{
+    ble::BleControl,
+    buzzer::{self, Buzzer},
+    event_bus,
+    executor::Executor,
+    report::{self, ErrorCode, Module},
+    store::{
+        history::Change,
+        time_task::{CountdownTask, TaskEnd, TimeFrequency, TimeTask},
+    },
+};
+use anyhow::{bail, Result};
+use chrono::Utc;
 use esp32_nimble::utilities::mutex::Mutex;
 use esp_idf_svc::timer::{EspTaskTimerService, EspTimerService, Task};
-use futures::executor::ThreadPool;
-use futures::{channel::mpsc, task::SpawnExt, StreamExt};
+use futures::{channel::mpsc::UnboundedReceiver, task::SpawnExt, StreamExt};
 use futures::{future::abortable, stream::AbortHandle};
 use serde::{Deserialize, Serialize};
+use smart_brite_core::hal::SystemClock;
 use std::{collections::HashMap, sync::Arc};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase", tag = "type", content = "data")]
 pub enum TimerEvent {
+    /// 添加任务，已有同名任务时拒绝
     AddTask(TimeTask),
+    /// 修改同名任务
+    UpdateTask(TimeTask),
     RemoveTask(String),
+    /// 一次性任务执行完成，内部使用，删除时不记录撤销历史
+    #[serde(skip)]
+    Finished(String),
+    /// 任务执行了一次，内部使用，记录执行时间
+    #[serde(skip)]
+    Fired(String),
+    /// 一次性任务在断电期间错过，内部使用，标记为过期并通知App
+    #[serde(skip)]
+    Expired(String),
+    /// 时区或系统时间变化后，重新计算所有任务的执行时间
+    Reload,
+    /// 暂停所有定时任务，保留任务定义
+    PauseAll,
+    /// 恢复所有定时任务
+    ResumeAll,
+    /// 把各任务的下一次执行时间更新到下一次执行时间特征中，之后客户端再读取
+    NextFire,
+    /// 倒计时，如“30分钟后关灯”
+    Countdown {
+        name: String,
+        seconds: u32,
+        operation: LightEvent,
+    },
 }
 
-#[derive(Debug, Clone)]
-pub struct TimerEventSender {
-    pub event_tx: mpsc::Sender<TimerEvent>,
-}
+/// 通过事件总线发送定时任务事件
+#[derive(Debug, Clone, Default)]
+pub struct TimerEventSender;
 
 impl TimerEventSender {
-    pub fn new(event_tx: mpsc::Sender<TimerEvent>) -> Self {
-        Self { event_tx }
+    pub fn send(&mut self, event: TimerEvent) -> Result<()> {
+        if event_bus::publish(event) == 0 {
+            bail!("Timer event loop is not running");
+        }
+        Ok(())
     }
 
     pub fn add_task(&mut self, time_task: TimeTask) -> Result<()> {
-        Ok(self.event_tx.try_send(TimerEvent::AddTask(time_task))?)
+        self.send(TimerEvent::AddTask(time_task))
     }
 
     pub fn remove_task(&mut self, name: String) -> Result<()> {
-        Ok(self.event_tx.try_send(TimerEvent::RemoveTask(name))?)
+        self.send(TimerEvent::RemoveTask(name))
+    }
+
+    pub fn reload(&mut self) -> Result<()> {
+        self.send(TimerEvent::Reload)
+    }
+
+    pub fn pause_all(&mut self) -> Result<()> {
+        self.send(TimerEvent::PauseAll)
     }
 
-    pub fn new_pair() -> (TimerEventSender, mpsc::Receiver<TimerEvent>) {
-        let (tx, rx) = mpsc::channel(10);
-        (TimerEventSender::new(tx), rx)
+    pub fn resume_all(&mut self) -> Result<()> {
+        self.send(TimerEvent::ResumeAll)
+    }
+
+    pub fn countdown(&mut self, name: String, seconds: u32, operation: LightEvent) -> Result<()> {
+        self.send(TimerEvent::Countdown {
+            name,
+            seconds,
+            operation,
+        })
+    }
+
+    /// 订阅定时任务事件，需要在发送事件前调用
+    pub fn new_pair() -> (TimerEventSender, UnboundedReceiver<TimerEvent>) {
+        (TimerEventSender, event_bus::subscribe_async())
     }
 }
 
 #[derive(Clone)]
 pub struct TimeTaskManager {
     pub tasks: Arc<Mutex<Vec<TimeTask>>>,
+    pub paused: Arc<Mutex<bool>>,
     pub light_event_sender: LightEventSender,
     pub timer_service: EspTimerService<Task>,
     pub abort_handles: Arc<Mutex<HashMap<String, AbortHandle>>>,
-    pub pool: ThreadPool,
+    pub timer_event_sender: TimerEventSender,
+    pub buzzer: Buzzer,
+    pub pool: Executor,
 }
 
-unsafe impl Send for TimeTaskManager {}
-
 impl TimeTaskManager {
     pub fn new(
         tasks: Arc<Mutex<Vec<TimeTask>>>,
+        paused: Arc<Mutex<bool>>,
         light_event_sender: LightEventSender,
-        pool: ThreadPool,
+        timer_event_sender: TimerEventSender,
+        buzzer: Buzzer,
+        pool: Executor,
     ) -> Self {
         Self {
             light_event_sender,
+            timer_event_sender,
+            buzzer,
             tasks,
+            paused,
             abort_handles: Arc::new(Mutex::new(HashMap::new())),
             timer_service: EspTaskTimerService::new().unwrap(),
             pool,
@@ -67,13 +133,29 @@ impl TimeTaskManager {
     }
 
     pub fn run(&self) -> Result<()> {
+        if *self.paused.lock() {
+            return Ok(());
+        }
         let tasks = self.tasks.lock().clone();
         for time_task in tasks {
-            self.add_task(time_task)?;
+            self.start_task(time_task)?;
         }
         Ok(())
     }
 
+    /// 中断所有正在运行的任务，但不删除任务定义
+    pub fn pause_all(&self) {
+        *self.paused.lock() = true;
+        for (_, abort_handle) in self.abort_handles.lock().drain() {
+            abort_handle.abort();
+        }
+    }
+
+    pub fn resume_all(&self) -> Result<()> {
+        *self.paused.lock() = false;
+        self.run()
+    }
+
     pub fn abort(&self, name: &str) {
         if let Some(abort_handle) = self.abort_handles.lock().remove(name) {
             abort_handle.abort();
@@ -84,7 +166,17 @@ impl TimeTaskManager {
         }
     }
 
-    fn add_task(&self, time_task: TimeTask) -> Result<()> {
+    /// 校验后添加或修改任务，replace为true时修改同名任务
+    fn add_task(&self, time_task: TimeTask, replace: bool) -> Result<()> {
+        time_task.check(&self.tasks.lock(), replace)?;
+        self.start_task(time_task)
+    }
+
+    /// 保存并运行任务，替换同名任务
+    fn start_task(&self, time_task: TimeTask) -> Result<()> {
+        if let Some(melody) = &time_task.melody {
+            buzzer::melody(melody)?;
+        }
         let time_task_name = time_task.name.clone();
         let index = self
             .tasks
@@ -96,33 +188,64 @@ impl TimeTaskManager {
             self.abort(&time_task_name);
         }
         self.tasks.lock().push(time_task.clone());
+        // 暂停期间只保存任务，恢复时再运行；过期的任务等待App修改或删除
+        if *self.paused.lock() || time_task.expired {
+            return Ok(());
+        }
 
         let mut light_event_sender = self.light_event_sender.clone();
         let timer_service = self.timer_service.clone();
         let control = time_task.operation.clone();
+        let buzzer = self.buzzer.clone();
+        let mut timer_event_sender = self.timer_event_sender.clone();
 
         let (future, abort_handle) = abortable(async move {
             time_task
-                .run(timer_service, || match control {
-                    LightEvent::Close => light_event_sender.close(),
-                    LightEvent::Open => light_event_sender.open(),
-                    LightEvent::Reset => unreachable!(),
+                .run(timer_service, &SystemClock, || {
+                    light_event_sender.send(control.clone())?;
+                    // 记录执行时间失败不影响灯光操作
+                    if let Err(e) =
+                        timer_event_sender.send(TimerEvent::Fired(time_task.name.clone()))
+                    {
+                        log::warn!("record task fired failed: {e}");
+                    }
+                    // 旋律播放失败不影响灯光操作
+                    if let Some(melody) = &time_task.melody {
+                        if let Err(e) = buzzer.play(melody) {
+                            log::warn!("play melody failed: {e}");
+                        }
+                    }
+                    Ok(())
                 })
                 .await
         });
 
         self.abort_handles
             .lock()
-            .insert(time_task_name, abort_handle);
-        self.pool.spawn(async {
+            .insert(time_task_name.clone(), abort_handle);
+        let mut timer_event_sender = self.timer_event_sender.clone();
+        self.pool.spawn(async move {
             match future.await {
                 Ok(res) => {
-                    #[cfg(debug_assertions)]
-                    log::info!("Timer task {:?} finished", res);
+                    log::debug!("Timer task {:?} finished", res);
+
+                    // 一次性任务执行完成后移除，错过的任务标记为过期
+                    if let Ok(end) = res {
+                        let event = match end {
+                            TaskEnd::Finished => TimerEvent::Finished(time_task_name),
+                            TaskEnd::Expired => TimerEvent::Expired(time_task_name),
+                        };
+                        if let Err(e) = timer_event_sender.send(event) {
+                            report::error(
+                                Module::Timer,
+                                ErrorCode::Internal,
+                                format!("remove finished task failed: {e}"),
+                            );
+                        }
+                    }
                 }
                 Err(e) => {
-                    #[cfg(debug_assertions)]
-                    log::warn!("Timer task  aborted: {}", e);
+                    log::debug!("Timer task  aborted: {}", e);
                 }
             }
         })?;
@@ -132,29 +255,153 @@ impl TimeTaskManager {
 
     pub fn handle_event(
         &self,
-        mut task_rx: mpsc::Receiver<TimerEvent>,
+        mut task_rx: UnboundedReceiver<TimerEvent>,
         ble_control: BleControl,
     ) -> Result<()> {
         let manager = self.clone();
         self.pool.spawn(async move {
             while let Some(event) = task_rx.next().await {
                 match event {
-                    TimerEvent::AddTask(time_task) => match manager.add_task(time_task) {
+                    TimerEvent::AddTask(time_task) => match manager.add_task(time_task, false) {
                         Ok(_) => {
                             log::info!("add task success");
                         }
                         Err(e) => {
-                            log::error!("add task failed: {}", e);
+                            report::error(
+                                Module::Timer,
+                                ErrorCode::InvalidData,
+                                format!("add task failed: {e}"),
+                            );
                         }
                     },
+                    TimerEvent::UpdateTask(time_task) => {
+                        if let Err(e) = manager.add_task(time_task, true) {
+                            report::error(
+                                Module::Timer,
+                                ErrorCode::InvalidData,
+                                format!("update task failed: {e}"),
+                            );
+                        }
+                    }
                     TimerEvent::RemoveTask(name) => {
+                        let removed = manager
+                            .tasks
+                            .lock()
+                            .iter()
+                            .find(|item| item.name == name)
+                            .cloned();
+                        if let Some(task) = removed {
+                            ble_control.nvs_store.history.record(Change::Task(task));
+                        }
+                        manager.abort(&name);
+                    }
+                    TimerEvent::Finished(name) => {
                         manager.abort(&name);
                     }
+                    TimerEvent::Fired(name) => {
+                        if let Some(task) = manager
+                            .tasks
+                            .lock()
+                            .iter_mut()
+                            .find(|item| item.name == name)
+                        {
+                            task.last_fired = Some(Utc::now());
+                        }
+                    }
+                    TimerEvent::Expired(name) => {
+                        manager.abort_handles.lock().remove(&name);
+                        if let Some(task) = manager
+                            .tasks
+                            .lock()
+                            .iter_mut()
+                            .find(|item| item.name == name)
+                        {
+                            task.expired = true;
+                        }
+                        report::error(
+                            Module::Timer,
+                            ErrorCode::Expired,
+                            format!("task {name} missed while powered off"),
+                        );
+                    }
+                    TimerEvent::PauseAll => {
+                        manager.pause_all();
+                        if let Err(e) = ble_control.nvs_store.write_tasks_paused() {
+                            report::error(Module::Timer, ErrorCode::Storage, e);
+                        }
+                        ble_control.notify_state();
+                    }
+                    TimerEvent::ResumeAll => {
+                        if let Err(e) = manager.resume_all() {
+                            report::error(
+                                Module::Timer,
+                                ErrorCode::Internal,
+                                format!("resume tasks failed: {e}"),
+                            );
+                        }
+                        if let Err(e) = ble_control.nvs_store.write_tasks_paused() {
+                            report::error(Module::Timer, ErrorCode::Storage, e);
+                        }
+                        ble_control.notify_state();
+                    }
+                    TimerEvent::NextFire => {
+                        let paused = *manager.paused.lock();
+                        let next: Vec<_> = manager
+                            .tasks
+                            .lock()
+                            .iter()
+                            .map(|task| task.next_fire(paused, &SystemClock))
+                            .collect();
+                        if let Err(e) = ble_control.set_next_fire(&next) {
+                            report::error(Module::Timer, ErrorCode::Internal, e);
+                        }
+                        continue;
+                    }
+                    TimerEvent::Reload => {
+                        if let Err(e) = manager.run() {
+                            report::error(
+                                Module::Timer,
+                                ErrorCode::Internal,
+                                format!("reload tasks failed: {e}"),
+                            );
+                        }
+                    }
+                    TimerEvent::Countdown {
+                        name,
+                        seconds,
+                        operation,
+                    } => {
+                        let time_task = TimeTask {
+                            name,
+                            operation,
+                            frequency: TimeFrequency::Countdown(CountdownTask::new(
+                                seconds,
+                                &SystemClock,
+                            )),
+                            melody: None,
+                            jitter_minutes: None,
+                            last_fired: None,
+                            expired: false,
+                        };
+                        // 同名倒计时重新开始计时
+                        let replace = manager
+                            .tasks
+                            .lock()
+                            .iter()
+                            .any(|item| item.name == time_task.name);
+                        if let Err(e) = manager.add_task(time_task, replace) {
+                            report::error(
+                                Module::Timer,
+                                ErrorCode::InvalidData,
+                                format!("add countdown failed: {e}"),
+                            );
+                        }
+                    }
                 }
                 match ble_control.set_timer_with_store() {
                     Ok(_) => {}
                     Err(e) => {
-                        log::error!("{}", e);
+                        report::error(Module::Timer, ErrorCode::Storage, e);
                     }
                 }
             }
@@ -1,206 +1,215 @@
-use anyhow::Result;
+use crate::{
+    auth::Auth,
+    event_bus::{self, DataChange},
+    executor::Executor,
+    notify,
+    report::{self, ErrorCode, Module},
+    session,
+};
+use anyhow::{bail, Result};
 use esp32_nimble::{
     utilities::{mutex::Mutex, BleUuid},
     NimbleProperties,
 };
-use futures::{channel::mpsc, executor::ThreadPool, task::SpawnExt, StreamExt};
-use meta_date::{ChunkMetaData, MetaData};
-use msg::{NotifyMessage, ReadMessage};
+use esp_idf_svc::timer::EspTaskTimerService;
+use futures::{channel::mpsc, task::SpawnExt, StreamExt};
+use msg::NotifyMessage;
 use rand::random;
-use std::sync::{Arc, Condvar};
-pub mod meta_date;
-pub mod msg;
-
-trait DataFromBytes
-where
-    Self: Sized,
-{
-    fn from_data(value: &[u8]) -> (Self, &[u8]);
-    fn bytes(&self) -> Vec<u8>;
-}
+pub use smart_brite_core::protocol::{
+    meta_date, msg, session::State, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+use smart_brite_core::protocol::{
+    session::{Sessions, Transport},
+    DataFromBytes,
+};
+use std::{
+    sync::{Arc, Condvar, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
 
-#[derive(Debug, Clone)]
-pub enum State {
-    Reading,
-    Writing,
-}
+/// 传输过程中对端超过该时间无响应，则放弃本次传输
+const TRANSFER_TIMEOUT: Duration = Duration::from_secs(10);
+/// 读取数据时等待传输结束的默认时长，略长于传输超时，失联的传输被放弃后仍能读到数据
+const WAIT_TIMEOUT: Duration = Duration::from_secs(12);
 
 #[derive(Clone)]
 pub struct Transmission {
     pub data: Arc<Mutex<Vec<u8>>>,
     pub characteristic: Arc<Mutex<esp32_nimble::BLECharacteristic>>,
+    /// 所有会话的整体状态，任一会话在写入即为写入，否则任一会话在读取即为读取
     pub state: Arc<std::sync::Mutex<Option<State>>>,
     pub condvar: Arc<Condvar>,
-    pub pool: ThreadPool,
+    /// 传输进行中时固件更新的数据，传输结束后再写入
+    pending: Arc<Mutex<Option<Vec<u8>>>>,
+    /// 写入回调执行期间为Some，记录回调是否请求了数据更新通知
+    ///
+    /// 回调成功后才提交客户端写入的数据，失败时其他会话读到的仍是之前的数据
+    committing: Arc<Mutex<Option<bool>>>,
+    /// 按连接句柄区分的会话
+    sessions: Arc<Mutex<Sessions>>,
+    /// 断开连接时清理回调的标识
+    pub session_key: String,
+    /// 需要鉴权时，每次写入前都要带上令牌
+    auth: Option<Auth>,
+    /// 开启儿童锁时只能读取，拒绝客户端写入
+    child_lock: Option<Arc<Mutex<bool>>>,
+    /// 数据更新时同时发布到事件总线，供WebSocket等推送
+    topic: Option<&'static str>,
+    /// `get_value`等待传输结束的最长时间
+    wait_timeout: Duration,
+    pub pool: Executor,
 }
 
 impl Transmission {
     pub fn new(
         service: Arc<Mutex<esp32_nimble::BLEService>>,
         uuid: BleUuid,
-        pool: ThreadPool,
+        pool: Executor,
     ) -> Self {
         let characteristic = service.lock().create_characteristic(
             uuid,
             NimbleProperties::NOTIFY | NimbleProperties::READ | NimbleProperties::WRITE,
         );
         characteristic.lock().create_2904_descriptor();
+        notify::track(&characteristic, false);
+        let state = Arc::new(std::sync::Mutex::new(None));
+        notify::register(state.clone());
         Self {
             data: Arc::new(Mutex::new(vec![])),
             characteristic,
-            state: Arc::new(std::sync::Mutex::new(None)),
+            state,
             condvar: Arc::new(Condvar::new()),
+            pending: Arc::new(Mutex::new(None)),
+            committing: Arc::new(Mutex::new(None)),
+            sessions: Arc::new(Mutex::new(Sessions::new(random()))),
+            session_key: format!("transmission:{}", uuid),
+            auth: None,
+            child_lock: None,
+            topic: None,
+            wait_timeout: WAIT_TIMEOUT,
             pool,
         }
     }
 
+    /// 写入需要鉴权，需在`init`之前调用
+    pub fn with_auth(mut self, auth: Auth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// 受儿童锁控制，需在`init`之前调用
+    pub fn with_child_lock(mut self, child_lock: Arc<Mutex<bool>>) -> Self {
+        self.child_lock = Some(child_lock);
+        self
+    }
+
+    /// 数据更新时以`topic`发布到事件总线
+    pub fn with_topic(mut self, topic: &'static str) -> Self {
+        self.topic = Some(topic);
+        self
+    }
+
+    fn child_locked(&self) -> bool {
+        self.child_lock
+            .as_ref()
+            .is_some_and(|locked| *locked.lock())
+    }
+
+    /// 修改`get_value`等待传输结束的最长时间
+    pub fn with_wait_timeout(mut self, timeout: Duration) -> Self {
+        self.wait_timeout = timeout;
+        self
+    }
+
+    /// 获取整体状态的锁
+    ///
+    /// 持有锁的线程panic后锁会中毒，状态仍然可用，清除中毒标记继续使用，
+    /// 避免之后所有读写都跟着panic；状态会在下次`update_state`时按会话重新计算
+    fn lock_state(&self) -> MutexGuard<'_, Option<State>> {
+        self.state.lock().unwrap_or_else(|e| {
+            log::warn!("{}: state mutex poisoned, recovering", self.session_key);
+            self.state.clear_poison();
+            e.into_inner()
+        })
+    }
+
     pub fn init<F>(&self, mut on_write_finish: Option<F>)
     where
         F: FnMut(Vec<u8>, &Transmission) -> Result<(), anyhow::Error> + Send + Sync + 'static,
     {
         let transmission = self.clone();
         let transmission2 = self.clone();
+        let transmission3 = self.clone();
+        let transmission4 = self.clone();
 
-        let start = Arc::new(Mutex::new(0));
-        let start2 = start.clone();
-
-        let read_meta_data = Arc::new(Mutex::new(None));
-        let read_meta_data2 = read_meta_data.clone();
-
-        let write_meta_data = Arc::new(Mutex::new(None));
-
-        let (mut tx, mut rx) = mpsc::channel::<Vec<u8>>(10);
-        let write_mtu = Arc::new(Mutex::new(0));
-        let write_mtu2 = write_mtu.clone();
+        let (mut tx, mut rx) = mpsc::channel::<(u16, Vec<u8>)>(10);
 
         self.pool
             .spawn(async move {
-                while let Some(value) = rx.next().await {
-                    let (message, recv_data) = ReadMessage::from_data(&value);
-                    #[cfg(debug_assertions)]
-                    log::info!("read message: {:?}", message);
-                    match message {
-                        ReadMessage::StartRead => {
-                            let id = random::<u32>();
-                            transmission.state.lock().unwrap().replace(State::Reading);
-                            transmission.condvar.notify_one();
-
-                            let meta_data = MetaData {
-                                id,
-                                total_size: transmission.data.lock().len() as u32,
-                            };
-
-                            read_meta_data.lock().replace(meta_data.clone());
-                            transmission
-                                .characteristic
-                                .lock()
-                                .set_value(&NotifyMessage::ReadReady(meta_data).bytes())
-                                .notify();
-                            #[cfg(debug_assertions)]
-                            log::info!("发送通知读取");
-                            *start.lock() = 0;
-                            #[cfg(debug_assertions)]
-                            log::info!("设置start为0");
-                        }
-                        ReadMessage::ReadReceive { next_start } => {
-                            *start.lock() = next_start;
-                        }
-                        ReadMessage::ReadFinish => {
-                            transmission.state.lock().unwrap().take();
-                            transmission.condvar.notify_one();
-                        }
-                        ReadMessage::StartWrite(meta_data) => {
-                            write_meta_data.lock().replace(meta_data);
-                            *transmission.data.lock() = vec![];
-
-                            #[cfg(debug_assertions)]
-                            log::warn!("替换meta_data");
-
-                            transmission
-                                .characteristic
-                                .lock()
-                                .set_value(
-                                    &NotifyMessage::WriteReady {
-                                        mtu: *write_mtu.lock(),
-                                    }
-                                    .bytes(),
-                                )
-                                .notify();
-                            #[cfg(debug_assertions)]
-                            log::warn!("发送通知");
-
-                            transmission.state.lock().unwrap().replace(State::Writing);
-                            transmission.condvar.notify_one();
+                while let Some((conn_handle, value)) = rx.next().await {
+                    // 写入完成的数据，释放会话锁后再回调
+                    let finished = {
+                        let mut sessions = transmission.sessions.lock();
+                        let finished = sessions.handle(
+                            &transmission,
+                            conn_handle,
+                            &value,
+                            transmission.child_locked(),
+                            || transmission.data.lock().clone(),
+                        );
+                        transmission.update_state(&sessions);
+                        finished
+                    };
+                    if let Some(decoded) = finished {
+                        // 回调校验并保存数据，成功后才提交数据并回复完成，失败时只回复错误
+                        *transmission.committing.lock() = Some(false);
+                        let res = decoded.and_then(|data| {
+                            if let Some(on_write) = on_write_finish.as_mut() {
+                                on_write(data.clone(), &transmission)?;
+                            }
+                            Ok(data)
+                        });
+                        let notify = transmission.committing.lock().take() == Some(true);
+                        match res {
+                            Ok(data) => {
+                                if notify {
+                                    *transmission.data.lock() = data;
+                                    transmission.send_update();
+                                }
+                                transmission.reply(conn_handle, NotifyMessage::WriteFinish);
+                            }
+                            Err(e) => {
+                                transmission.reply(conn_handle, NotifyMessage::Error(e.to_string()))
+                            }
                         }
-                        ReadMessage::Write(chunk_meta_data) => {
-                            let state = transmission.state.lock().unwrap().clone();
-                            if let Some(state) = state {
-                                if matches!(state, State::Writing) {
-                                    let write_meta_data = write_meta_data.lock().clone();
-                                    if let Some(write_meta_data) = write_meta_data {
-                                        if write_meta_data.id == chunk_meta_data.id {
-                                            let mut data = transmission.data.lock();
-
-                                            let next_start =
-                                                chunk_meta_data.start + chunk_meta_data.chunk_size;
-
-                                            data.extend(recv_data);
-
-                                            if next_start < write_meta_data.total_size {
-                                                transmission
-                                                    .characteristic
-                                                    .lock()
-                                                    .set_value(
-                                                        &NotifyMessage::WriteReceive { next_start }
-                                                            .bytes(),
-                                                    )
-                                                    .notify();
-                                            } else {
-                                                #[cfg(debug_assertions)]
-                                                log::warn!("写入完成，数据长度：{}", data.len());
-
-                                                let data_clone = data.clone();
-                                                drop(data);
-                                                // 写入完成重置状态
-                                                transmission.state.lock().unwrap().take();
-                                                transmission.condvar.notify_one();
+                    }
+                }
+            })
+            .unwrap();
 
-                                                transmission
-                                                    .characteristic
-                                                    .lock()
-                                                    .set_value(&NotifyMessage::WriteFinish.bytes())
-                                                    .notify();
+        // 传输超时检测，避免对端中途失联导致一直处于读写状态
+        self.pool
+            .spawn(async move {
+                let Ok(mut async_timer) = EspTaskTimerService::new().and_then(|s| s.timer_async())
+                else {
+                    report::error(
+                        Module::Transfer,
+                        ErrorCode::Internal,
+                        "create transfer timer failed",
+                    );
+                    return;
+                };
+                while async_timer.after(Duration::from_secs(1)).await.is_ok() {
+                    let expired = transmission3
+                        .sessions
+                        .lock()
+                        .expired(Instant::now(), TRANSFER_TIMEOUT);
+                    for conn_handle in expired {
+                        if transmission3.cancel(conn_handle) {
+                            log::debug!("传输超时");
 
-                                                // 写入成功回调函数
-                                                if let Some(on_write) = on_write_finish.as_mut() {
-                                                    match on_write(data_clone, &transmission) {
-                                                        Ok(_) => {}
-                                                        Err(e) => {
-                                                            transmission
-                                                                .characteristic
-                                                                .lock()
-                                                                .set_value(
-                                                                    &NotifyMessage::Error(
-                                                                        e.to_string(),
-                                                                    )
-                                                                    .bytes(),
-                                                                )
-                                                                .notify();
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                            continue;
-                                        }
-                                    }
-                                }
-                            }
-                            // 发送错误信息
-                            transmission
-                                .characteristic
-                                .lock()
-                                .set_value(&NotifyMessage::Error("写入失败".into()).bytes())
-                                .notify();
+                            transmission3
+                                .reply(conn_handle, NotifyMessage::Error("传输超时".into()));
                         }
                     }
                 }
@@ -210,68 +219,138 @@ impl Transmission {
         self.characteristic
             .lock()
             .on_write(move |args| {
-                let value = args.recv_data();
-                *write_mtu2.lock() = args.desc().mtu();
-                if tx.try_send(value.to_vec()).is_err() {
-                    #[cfg(debug_assertions)]
-                    log::warn!("发送失败");
+                let value = match &transmission4.auth {
+                    Some(auth) => auth.strip(args.recv_data()),
+                    None => Some(args.recv_data()),
+                };
+                let Some(value) = value else {
+                    log::warn!("reject unauthenticated write");
+                    args.reject();
+                    return;
+                };
+                let conn_handle = args.desc().conn_handle();
+                let mtu = args.desc().mtu();
+                let new_session = transmission4.sessions.lock().connect(conn_handle, mtu);
+                // 连接断开时立即结束该连接的传输并移除会话，避免等待超时
+                if new_session {
+                    let transmission = transmission4.clone();
+                    session::on_disconnect(
+                        conn_handle,
+                        transmission4.session_key.clone(),
+                        move || {
+                            let mut sessions = transmission.sessions.lock();
+                            sessions.remove(conn_handle);
+                            transmission.update_state(&sessions);
+                        },
+                    );
+                }
+                if tx.try_send((conn_handle, value.to_vec())).is_err() {
+                    log::debug!("发送失败");
                     args.reject();
                 }
             })
             .on_read(move |attr, desc| {
-                let state = transmission2.state.lock().unwrap().clone();
-
-                if let Some(state) = state {
-                    if matches!(state, State::Reading) {
-                        let mtu = desc.mtu();
-                        let meta_data = read_meta_data2.lock().clone();
-                        if let Some(meta_data) = meta_data {
-                            let start = *start2.lock();
-                            if start < meta_data.total_size {
-                                let chunk_meta = ChunkMetaData {
-                                    id: meta_data.id,
-                                    start,
-                                    chunk_size: (mtu as u32 - 12).min(meta_data.total_size - start),
-                                };
-                                let mut chunk_meta_bytes = chunk_meta.bytes();
-                                let data = transmission2.data.lock();
-                                let data =
-                                    &data[start as usize..(start + chunk_meta.chunk_size) as usize];
-                                chunk_meta_bytes.extend(data);
-                                attr.set_value(&chunk_meta_bytes);
-                                return;
-                            }
-                        }
-                    }
-                }
-                attr.set_value(&[]);
+                let chunk = transmission2
+                    .sessions
+                    .lock()
+                    .read_chunk(desc.conn_handle(), desc.mtu());
+                attr.set_value(&chunk.unwrap_or_default());
             });
     }
 
+    /// 根据所有会话更新整体状态，全部结束后发送推迟的通知
+    fn update_state(&self, sessions: &Sessions) {
+        let state = sessions.state();
+        let idle = state.is_none();
+        let pending = {
+            let mut guard = self.lock_state();
+            *guard = state;
+            // 持有状态锁时取出，不会漏掉刚保存的数据
+            idle.then(|| self.pending.lock().take()).flatten()
+        };
+        self.condvar.notify_all();
+        if let Some(value) = pending {
+            *self.data.lock() = value;
+            self.send_update();
+        }
+        if idle {
+            notify::flush();
+        }
+    }
+
+    /// 结束连接正在进行的传输，返回是否确实有传输被结束
+    pub fn cancel(&self, conn_handle: u16) -> bool {
+        let mut sessions = self.sessions.lock();
+        let cancelled = sessions.cancel(conn_handle);
+        if cancelled {
+            self.update_state(&sessions);
+        }
+        cancelled
+    }
+
+    /// 等待传输结束后读取数据，超过`wait_timeout`仍未结束则返回错误
+    ///
+    /// 不要在执行线程中调用：处理传输的任务也在该线程中，只能等到超时
     pub fn get_value(&self) -> Result<Vec<u8>> {
-        let mut state = self.state.lock().unwrap();
-        // 如果正在写入，则等待写入完成再读取数据
-        while let Some(_) = &*state {
-            state = self.condvar.wait(state).unwrap();
+        let state = self.lock_state();
+        let (state, result) = self
+            .condvar
+            .wait_timeout_while(state, self.wait_timeout, |state| state.is_some())
+            .unwrap_or_else(PoisonError::into_inner);
+        if result.timed_out() {
+            bail!("Transfer still in progress after {:?}", self.wait_timeout);
         }
+        drop(state);
         Ok(self.data.lock().clone())
     }
 
+    /// 传输进行中时先保存，传输结束后再更新
+    ///
+    /// 不能等待传输结束：调用方和处理传输的任务在同一个执行线程中，等待会导致传输无法结束
     pub fn set_value(&self, value: Vec<u8>) -> Result<()> {
-        let mut state = self.state.lock().unwrap();
-
-        while let Some(_) = &*state {
-            state = self.condvar.wait(state).unwrap();
+        // 写入回调中设置了对外的数据（如去掉密码）时，不再提交客户端写入的数据
+        if let Some(notify) = self.committing.lock().as_mut() {
+            *notify = false;
+        }
+        let state = self.lock_state();
+        if state.is_some() {
+            *self.pending.lock() = Some(value);
+            return Ok(());
         }
         *self.data.lock() = value;
-        self.notify_update();
+        // 释放状态锁后再通知，调度通知时需要检查所有传输的状态
+        drop(state);
+        self.send_update();
         Ok(())
     }
 
+    /// 数据更新通知，写入回调中调用时推迟到提交客户端写入的数据之后
     pub fn notify_update(&self) {
-        self.characteristic
-            .lock()
-            .set_value(&NotifyMessage::DataUpdate.bytes())
-            .notify();
+        if let Some(notify) = self.committing.lock().as_mut() {
+            *notify = true;
+            return;
+        }
+        self.send_update();
+    }
+
+    /// 其他传输进行中时推迟发送
+    fn send_update(&self) {
+        if let Some(topic) = self.topic {
+            event_bus::publish(DataChange {
+                topic,
+                data: self.data.lock().clone(),
+            });
+        }
+        let characteristic = self.characteristic.clone();
+        notify::schedule(self.session_key.clone(), move || {
+            notify::send(&characteristic, &NotifyMessage::DataUpdate.bytes());
+        });
+    }
+}
+
+impl Transport for Transmission {
+    /// 只通知发起传输的连接
+    fn reply(&self, conn_handle: u16, message: NotifyMessage) {
+        notify::send_to(&self.characteristic, conn_handle, &message.bytes());
     }
 }
@@ -0,0 +1,134 @@
+use crate::{
+    executor::Executor,
+    report::{self, ErrorCode, Module},
+    store::{NvsStore, UsageStats},
+};
+use anyhow::Result;
+use esp_idf_svc::timer::EspTaskTimerService;
+use futures::task::SpawnExt;
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// 保存到NVS的间隔，断电最多丢失这段时间的统计
+const PERSIST_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+struct Tracker {
+    stats: UsageStats,
+    /// 灯打开的时间和场景，关灯时为None
+    session: Option<(Instant, String)>,
+    /// 上次保存后是否有变化
+    dirty: bool,
+}
+
+impl Tracker {
+    const fn new() -> Self {
+        Self {
+            stats: UsageStats {
+                on_secs: 0,
+                switch_count: 0,
+                scenes: std::collections::BTreeMap::new(),
+            },
+            session: None,
+            dirty: false,
+        }
+    }
+
+    /// 把进行中的点亮时间计入统计，并从现在重新计时
+    fn settle(&mut self) {
+        let Some((since, scene)) = &mut self.session else {
+            return;
+        };
+        let secs = since.elapsed().as_secs();
+        if secs == 0 {
+            return;
+        }
+        // 只扣除整秒，余下的留到下次计入
+        *since += Duration::from_secs(secs);
+        self.stats.on_secs += secs;
+        self.stats.scene_mut(scene).on_secs += secs;
+        self.dirty = true;
+    }
+}
+
+static TRACKER: Mutex<Tracker> = Mutex::new(Tracker::new());
+
+/// 灯光状态变化时调用，scene为打开时显示的场景，关灯时为None
+pub fn record(scene: Option<&str>) {
+    let Ok(mut tracker) = TRACKER.lock() else {
+        return;
+    };
+    tracker.settle();
+    match (scene, tracker.session.take()) {
+        (Some(scene), Some((since, current))) if scene == current => {
+            tracker.session = Some((since, current));
+        }
+        (Some(scene), previous) => {
+            if previous.is_none() {
+                tracker.stats.switch_count += 1;
+            }
+            tracker.stats.scene_mut(scene).count += 1;
+            tracker.session = Some((Instant::now(), scene.to_string()));
+            tracker.dirty = true;
+        }
+        (None, _) => {}
+    }
+}
+
+/// 包含进行中的点亮时间的统计
+pub fn stats() -> UsageStats {
+    let mut tracker = TRACKER.lock().unwrap();
+    tracker.settle();
+    tracker.stats.clone()
+}
+
+/// 立即保存统计，重启前调用
+pub fn save(nvs_store: &NvsStore) -> Result<()> {
+    let stats = {
+        let mut tracker = TRACKER.lock().unwrap();
+        tracker.settle();
+        tracker.dirty = false;
+        tracker.stats.clone()
+    };
+    nvs_store.write_usage(&stats)
+}
+
+/// 读取保存的统计，并定期保存
+pub fn start(nvs_store: NvsStore, pool: &Executor) -> Result<()> {
+    let stored = nvs_store.read_usage()?;
+    {
+        let mut tracker = TRACKER.lock().unwrap();
+        // 读取前已经开始计时的部分累加到保存的统计上
+        let current = std::mem::replace(&mut tracker.stats, stored);
+        tracker.stats.on_secs += current.on_secs;
+        tracker.stats.switch_count += current.switch_count;
+        for (name, usage) in current.scenes {
+            let scene = tracker.stats.scene_mut(&name);
+            scene.on_secs += usage.on_secs;
+            scene.count += usage.count;
+        }
+    }
+    if nvs_store.safe_mode {
+        return Ok(());
+    }
+
+    let mut async_timer = EspTaskTimerService::new()?.timer_async()?;
+    pool.spawn(async move {
+        while async_timer.after(PERSIST_INTERVAL).await.is_ok() {
+            let stats = {
+                let mut tracker = TRACKER.lock().unwrap();
+                tracker.settle();
+                if !tracker.dirty {
+                    continue;
+                }
+                tracker.dirty = false;
+                tracker.stats.clone()
+            };
+            if let Err(e) = nvs_store.write_usage(&stats) {
+                report::error(Module::Store, ErrorCode::Storage, e);
+            }
+        }
+    })?;
+    Ok(())
+}
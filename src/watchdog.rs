@@ -0,0 +1,155 @@
+use crate::{diagnostics, executor::Executor, led::Led, store::NvsStore};
+use anyhow::Result;
+use esp_idf_svc::{
+    sys::{
+        esp_reset_reason, esp_reset_reason_t, esp_reset_reason_t_ESP_RST_BROWNOUT,
+        esp_reset_reason_t_ESP_RST_DEEPSLEEP, esp_reset_reason_t_ESP_RST_INT_WDT,
+        esp_reset_reason_t_ESP_RST_PANIC, esp_reset_reason_t_ESP_RST_POWERON,
+        esp_reset_reason_t_ESP_RST_SW, esp_reset_reason_t_ESP_RST_TASK_WDT,
+        esp_reset_reason_t_ESP_RST_WDT, esp_restart, esp_task_wdt_add, esp_task_wdt_reset,
+    },
+    timer::EspTaskTimerService,
+};
+use futures::task::SpawnExt;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// 检查心跳的间隔，需小于TWDT的超时时间
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// 执行器心跳的间隔与超时时间
+const POOL_INTERVAL: Duration = Duration::from_secs(1);
+const POOL_TIMEOUT: Duration = Duration::from_secs(10);
+/// 等待灯带锁的超时时间，超过即认为渲染任务死锁
+const LED_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 任务心跳，处理中的任务超过超时时间没有心跳即认为卡死
+///
+/// 空闲等待事件时调用`idle`，不会被误判为卡死
+#[derive(Clone)]
+pub struct Heartbeat {
+    name: &'static str,
+    timeout: Duration,
+    last: Arc<Mutex<Option<Instant>>>,
+}
+
+impl Heartbeat {
+    pub fn beat(&self) {
+        *self.last.lock().unwrap() = Some(Instant::now());
+    }
+
+    pub fn idle(&self) {
+        *self.last.lock().unwrap() = None;
+    }
+
+    fn stuck(&self) -> bool {
+        self.last
+            .lock()
+            .unwrap()
+            .is_some_and(|last| last.elapsed() > self.timeout)
+    }
+}
+
+static HEARTBEATS: Mutex<Vec<Heartbeat>> = Mutex::new(Vec::new());
+static LAST_RESET: Mutex<Option<ResetRecord>> = Mutex::new(None);
+
+/// 登记需要监控的任务，初始为空闲状态
+pub fn register(name: &'static str, timeout: Duration) -> Heartbeat {
+    let heartbeat = Heartbeat {
+        name,
+        timeout,
+        last: Arc::new(Mutex::new(None)),
+    };
+    HEARTBEATS.lock().unwrap().push(heartbeat.clone());
+    heartbeat
+}
+
+/// 上次复位的原因，通过BLE读取
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResetRecord {
+    pub reason: String,
+    /// 因任务卡死而复位时，卡死的任务
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stuck_task: Option<String>,
+}
+
+/// 复位原因的名称
+#[allow(non_upper_case_globals)]
+pub fn reason_name(reason: esp_reset_reason_t) -> &'static str {
+    match reason {
+        esp_reset_reason_t_ESP_RST_POWERON => "powerOn",
+        esp_reset_reason_t_ESP_RST_SW => "software",
+        esp_reset_reason_t_ESP_RST_PANIC => "panic",
+        esp_reset_reason_t_ESP_RST_INT_WDT => "interruptWatchdog",
+        esp_reset_reason_t_ESP_RST_TASK_WDT => "taskWatchdog",
+        esp_reset_reason_t_ESP_RST_WDT => "watchdog",
+        esp_reset_reason_t_ESP_RST_DEEPSLEEP => "deepSleep",
+        esp_reset_reason_t_ESP_RST_BROWNOUT => "brownout",
+        _ => "unknown",
+    }
+}
+
+pub fn last_reset() -> Option<ResetRecord> {
+    LAST_RESET.lock().unwrap().clone()
+}
+
+/// 有任务卡死时记录原因后重启，监控线程本身卡死时由TWDT复位
+pub fn start(nvs_store: NvsStore, led: Arc<Mutex<Led<'static>>>, pool: &Executor) -> Result<()> {
+    let stuck_task = nvs_store.take_stuck_task()?;
+    let reason = unsafe { esp_reset_reason() };
+    let record = ResetRecord {
+        reason: reason_name(reason).into(),
+        // 只有主动重启时记录的任务才是本次复位的原因
+        stuck_task: stuck_task.filter(|_| reason == esp_reset_reason_t_ESP_RST_SW),
+    };
+    log::info!("reset reason: {record:?}");
+    *LAST_RESET.lock().unwrap() = Some(record);
+
+    // 执行器心跳，有任务阻塞执行线程时无法按时执行
+    let pool_heartbeat = register("executor", POOL_TIMEOUT);
+    // 定期尝试获取灯带的锁，锁一直被占用说明渲染任务死锁
+    let led_heartbeat = register("renderer", LED_TIMEOUT);
+    let mut async_timer = EspTaskTimerService::new()?.timer_async()?;
+    pool.spawn(async move {
+        while async_timer.after(POOL_INTERVAL).await.is_ok() {
+            pool_heartbeat.beat();
+            led_heartbeat.beat();
+            drop(led.lock());
+            led_heartbeat.idle();
+        }
+    })?;
+
+    std::thread::Builder::new()
+        .stack_size(4 * 1024)
+        .spawn(move || {
+            diagnostics::track_stack("watchdog");
+            unsafe {
+                esp_task_wdt_add(std::ptr::null_mut());
+            }
+            loop {
+                let stuck = HEARTBEATS
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .find(|heartbeat| heartbeat.stuck())
+                    .map(|heartbeat| heartbeat.name);
+                match stuck {
+                    Some(name) => {
+                        log::error!("task {name} is stuck, restart");
+                        if let Err(e) = nvs_store.write_stuck_task(name) {
+                            log::error!("write stuck task error: {e}");
+                        }
+                        unsafe { esp_restart() };
+                    }
+                    None => unsafe {
+                        esp_task_wdt_reset();
+                    },
+                }
+                std::thread::sleep(CHECK_INTERVAL);
+            }
+        })?;
+    Ok(())
+}
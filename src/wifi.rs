@@ -0,0 +1,165 @@
+use crate::{store::WifiConfig, sync::local_mac};
+use anyhow::{anyhow, Result};
+use esp32_nimble::utilities::mutex::Mutex;
+use esp_idf_svc::{
+    eventloop::EspSystemEventLoop,
+    hal::modem::Modem,
+    nvs::EspDefaultNvsPartition,
+    sys::{esp, esp_wifi_sta_get_ap_info, wifi_ap_record_t},
+    wifi::{
+        AccessPointConfiguration, AuthMethod, BlockingWifi, ClientConfiguration, Configuration,
+        EspWifi,
+    },
+};
+use std::{net::Ipv4Addr, sync::Arc, time::Duration};
+
+/// 检查连接状态的间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// 配网热点名称的前缀，后面加上MAC地址末尾，附近有多盏灯时可以区分
+const AP_PREFIX: &str = "SmartBrite-";
+
+/// 配网热点，不设密码，手机连接后通过配网页面填写路由器信息
+fn access_point() -> Result<AccessPointConfiguration> {
+    let mac = local_mac().replace(':', "").to_uppercase();
+    let ssid = format!("{AP_PREFIX}{}", &mac[mac.len() - 4..]);
+    Ok(AccessPointConfiguration {
+        ssid: ssid
+            .as_str()
+            .try_into()
+            .map_err(|_| anyhow!("Invalid ssid"))?,
+        auth_method: AuthMethod::None,
+        max_connections: 2,
+        ..Default::default()
+    })
+}
+
+#[derive(Clone)]
+pub struct Wifi {
+    pub config: Arc<Mutex<WifiConfig>>,
+    connected: Arc<Mutex<bool>>,
+    /// 连接后路由器分配的地址
+    ip: Arc<Mutex<Option<Ipv4Addr>>>,
+    /// 未配置路由器时是否开启配网热点
+    portal: bool,
+}
+
+impl Wifi {
+    pub fn new(config: Arc<Mutex<WifiConfig>>) -> Self {
+        Self {
+            config,
+            connected: Arc::new(Mutex::new(false)),
+            ip: Arc::new(Mutex::new(None)),
+            portal: false,
+        }
+    }
+
+    /// 未配置路由器时开启配网热点，保存配置后切换为STA模式
+    pub fn with_portal(mut self, enabled: bool) -> Self {
+        self.portal = enabled;
+        self
+    }
+
+    pub fn is_connected(&self) -> bool {
+        *self.connected.lock()
+    }
+
+    pub fn ip(&self) -> Option<Ipv4Addr> {
+        *self.ip.lock()
+    }
+
+    /// 当前连接的路由器信号强度，单位：dBm
+    pub fn rssi(&self) -> Option<i8> {
+        if !self.is_connected() {
+            return None;
+        }
+        let mut info = wifi_ap_record_t::default();
+        esp!(unsafe { esp_wifi_sta_get_ap_info(&mut info) }).ok()?;
+        Some(info.rssi)
+    }
+
+    /// 启动Wi-Fi连接线程，配置变化或断线后自动重连
+    pub fn start(
+        &self,
+        modem: Modem,
+        sys_loop: EspSystemEventLoop,
+        nvs_partition: EspDefaultNvsPartition,
+    ) -> Result<()> {
+        let mut wifi = BlockingWifi::wrap(
+            EspWifi::new(modem, sys_loop.clone(), Some(nvs_partition))?,
+            sys_loop,
+        )?;
+        // 先以未配置的STA模式启动，ESP-NOW需要Wi-Fi驱动处于运行状态
+        wifi.set_configuration(&Configuration::Client(ClientConfiguration::default()))?;
+        wifi.start()?;
+        let this = self.clone();
+
+        std::thread::spawn(move || {
+            let mut applied: Option<WifiConfig> = None;
+            loop {
+                if let Err(e) = this.check(&mut wifi, &mut applied) {
+                    log::warn!("wifi error: {e}");
+                }
+                let connected = wifi.is_connected().unwrap_or(false);
+                *this.connected.lock() = connected;
+                *this.ip.lock() = connected
+                    .then(|| wifi.wifi().sta_netif().get_ip_info().ok())
+                    .flatten()
+                    .map(|info| info.ip)
+                    .filter(|ip| !ip.is_unspecified());
+                std::thread::sleep(CHECK_INTERVAL);
+            }
+        });
+        Ok(())
+    }
+
+    fn check(
+        &self,
+        wifi: &mut BlockingWifi<EspWifi<'static>>,
+        applied: &mut Option<WifiConfig>,
+    ) -> Result<()> {
+        let config = self.config.lock().clone();
+        if applied.as_ref() != Some(&config) {
+            if wifi.is_started()? {
+                wifi.stop()?;
+            }
+            *applied = Some(config.clone());
+            if config.ssid.is_empty() {
+                if self.portal {
+                    wifi.set_configuration(&Configuration::Mixed(
+                        ClientConfiguration::default(),
+                        access_point()?,
+                    ))?;
+                    log::info!("wifi portal started");
+                }
+                wifi.start()?;
+                return Ok(());
+            }
+            wifi.set_configuration(&Configuration::Client(ClientConfiguration {
+                ssid: config
+                    .ssid
+                    .as_str()
+                    .try_into()
+                    .map_err(|_| anyhow!("Invalid ssid"))?,
+                password: config
+                    .password
+                    .as_str()
+                    .try_into()
+                    .map_err(|_| anyhow!("Invalid password"))?,
+                auth_method: if config.password.is_empty() {
+                    AuthMethod::None
+                } else {
+                    AuthMethod::WPA2Personal
+                },
+                ..Default::default()
+            }))?;
+            wifi.start()?;
+        }
+
+        if !config.ssid.is_empty() && !wifi.is_connected()? {
+            wifi.connect()?;
+            wifi.wait_netif_up()?;
+            log::debug!("wifi connected: {}", config.ssid);
+        }
+        Ok(())
+    }
+}